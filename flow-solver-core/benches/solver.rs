@@ -0,0 +1,53 @@
+/// Criterion benchmarks for the solver and the solution counter over the corpus, run with
+/// `cargo bench -p flow-solver-core`. The app's `--bench` mode times the same puzzles without
+/// criterion, for a quick look.
+use criterion::{Criterion, criterion_group, criterion_main};
+use flow_solver_core::flow_corpus::CORPUS;
+use flow_solver_core::flow_count::count_solutions_exact;
+use flow_solver_core::flow_solver::{self, SolveOptions};
+use std::hint::black_box;
+use std::thread;
+
+fn solve(c: &mut Criterion) {
+    let options = SolveOptions::default();
+    for puzzle in &CORPUS {
+        let grid = puzzle.build_grid();
+        c.bench_function(&format!("solve {}", puzzle.name), |b| {
+            b.iter(|| flow_solver::solve_with_options(black_box(&grid), &options))
+        });
+    }
+}
+
+/// splits each puzzle into branches across every core, which is where the solver copies its state
+fn solve_parallel(c: &mut Criterion) {
+    let options = SolveOptions {
+        parallel: true,
+        ..SolveOptions::default()
+    };
+    for puzzle in &CORPUS {
+        let grid = puzzle.build_grid();
+        c.bench_function(&format!("solve in parallel {}", puzzle.name), |b| {
+            b.iter(|| {
+                let mut solve = flow_solver::solve_in_background(black_box(&grid), options.clone());
+                loop {
+                    if let Some(finished) = solve.poll() {
+                        break finished;
+                    }
+                    thread::yield_now();
+                }
+            })
+        });
+    }
+}
+
+fn count(c: &mut Criterion) {
+    for puzzle in &CORPUS {
+        let grid = puzzle.build_grid();
+        c.bench_function(&format!("count {}", puzzle.name), |b| {
+            b.iter(|| count_solutions_exact(black_box(&grid)))
+        });
+    }
+}
+
+criterion_group!(benches, solve, solve_parallel, count);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+//! Loads the input as a saved grid. Anything that loads has passed the grid's own checks, so
+//! saving it and loading that again has to give the same save. Run with
+//! `cargo +nightly fuzz run load_grid` from flow-solver-core.
+#![no_main]
+
+use flow_solver_core::flow_grid::FlowGrid;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    let Ok(grid) = serde_json::from_slice::<FlowGrid>(bytes) else {
+        return;
+    };
+    let json = serde_json::to_string(&grid).expect("grids always serialize");
+    let loaded: FlowGrid = serde_json::from_str(&json).expect("it was just saved");
+    assert_eq!(
+        serde_json::to_string(&loaded).expect("grids always serialize"),
+        json
+    );
+});
@@ -0,0 +1,24 @@
+//! Reads the input as a board, one row per line, and solves whatever parses. Run with
+//! `cargo +nightly fuzz run solve_board` from flow-solver-core.
+#![no_main]
+
+use flow_solver_core::flow_grid::FlowGrid;
+use flow_solver_core::flow_solver::{self, SolveOptions, SolveOutcome};
+use libfuzzer_sys::fuzz_target;
+
+/// keeps each run short, since most boards the fuzzer makes up are unsolvable
+const NODE_LIMIT: u64 = 10_000;
+
+fuzz_target!(|text: &str| {
+    let rows: Vec<&str> = text.lines().collect();
+    let Some(grid) = FlowGrid::from_board(&rows) else {
+        return;
+    };
+    let options = SolveOptions {
+        node_limit: Some(NODE_LIMIT),
+        ..SolveOptions::default()
+    };
+    if let SolveOutcome::Solved(solution) = flow_solver::solve_with_options(&grid, &options) {
+        assert!(solution.is_solved());
+    }
+});
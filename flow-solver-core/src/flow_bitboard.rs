@@ -0,0 +1,270 @@
+/// This file has a compact copy of the grid for the solver's hot path. FlowGrid keeps a whole
+/// FlowCell per cell and recolors pipes with an event per cell, which is what the UI wants, but the
+/// solver only needs to know which sides of each cell are connected and what color it is. Here
+/// that's a byte of flags and a byte of color per cell, so copying and scanning a board is cheap.
+use crate::flow_grid::{CellColor, Direction, FlowGrid, WarpPair};
+use std::sync::Arc;
+
+const SOURCE_FLAG: u8 = 1 << 7;
+/// the color of a cell no colored pipe has reached yet
+const NO_COLOR: u8 = u8::MAX;
+/// The color of a blocked cell. It isn't empty, so the solver never moves into it and doesn't
+/// need to fill it, and no pipe ever has it.
+const BLOCKED: u8 = u8::MAX - 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactGrid {
+    pub width: usize,
+    pub height: usize,
+    is_hex: bool,
+    wraps: bool,
+    /// each pair of warps once. They never change during a search, so copies share them.
+    warps: Arc<Vec<WarpPair>>,
+    /// the low seven bits are the connections, one per direction, and then the source flag
+    flags: Vec<u8>,
+    colors: Vec<u8>,
+}
+
+fn direction_bit(direction: Direction) -> u8 {
+    match direction {
+        Direction::Up => 1,
+        Direction::Down => 1 << 1,
+        Direction::Left => 1 << 2,
+        Direction::Right => 1 << 3,
+        Direction::UpRight => 1 << 4,
+        Direction::DownLeft => 1 << 5,
+        Direction::Warp => 1 << 6,
+    }
+}
+
+impl CompactGrid {
+    /// Copies the grid's sources and pipes. Returns None if there are too many colors to fit in a
+    /// byte, or the grid has bridges, which a byte of flags per cell can't tell apart from a cell
+    /// with four connections.
+    pub fn from_grid(grid: &FlowGrid) -> Option<Self> {
+        if grid.has_bridges() {
+            return None;
+        }
+        let mut flags = Vec::with_capacity(grid.width * grid.height);
+        let mut colors = Vec::with_capacity(grid.width * grid.height);
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                let cell = grid.get(row, col).expect("looping in bounds");
+                let mut cell_flags = if cell.is_source { SOURCE_FLAG } else { 0 };
+                for direction in Direction::ALL {
+                    if cell.is_direction_connected(direction) {
+                        cell_flags |= direction_bit(direction);
+                    }
+                }
+                flags.push(cell_flags);
+                colors.push(match cell.color {
+                    _ if cell.is_blocked => BLOCKED,
+                    CellColor::Colored(color_id) => u8::try_from(color_id)
+                        .ok()
+                        .filter(|&color_id| color_id < BLOCKED)?,
+                    CellColor::Empty(_) => NO_COLOR,
+                });
+            }
+        }
+        Some(CompactGrid {
+            width: grid.width,
+            height: grid.height,
+            is_hex: grid.is_hex(),
+            wraps: grid.wraps(),
+            warps: Arc::new(grid.warps()),
+            flags,
+            colors,
+        })
+    }
+
+    /// Rebuilds a full grid with these pipes. The template supplies everything else, like the
+    /// sources and which color is placed next, so it should be the grid this one was made from.
+    pub fn to_grid(&self, template: &FlowGrid) -> FlowGrid {
+        let mut grid = template.clone();
+        grid.clear_pipes();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                // connecting a warp again from its partner does nothing
+                for direction in [
+                    Direction::Down,
+                    Direction::Right,
+                    Direction::DownLeft,
+                    Direction::Warp,
+                ] {
+                    if self.is_connected(row, col, direction) {
+                        grid.try_connect(row, col, direction);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn index(&self, row: usize, col: usize) -> usize {
+        row * self.width + col
+    }
+
+    pub fn offset_row_col(
+        &self,
+        row: usize,
+        col: usize,
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        if direction == Direction::Warp {
+            return self.warp_partner(row, col);
+        }
+        let (row_step, col_step) = match direction {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+            Direction::UpRight if self.is_hex => (-1, 1),
+            Direction::DownLeft if self.is_hex => (1, -1),
+            Direction::UpRight | Direction::DownLeft | Direction::Warp => return None,
+        };
+        // the same wrapping as FlowGrid's, which only joins sides at least three cells long
+        let wrap = |index: usize, step: isize, len: usize| {
+            let next = index as isize + step;
+            if self.wraps && len >= 3 {
+                Some(next.rem_euclid(len as isize) as usize)
+            } else {
+                usize::try_from(next).ok().filter(|&next| next < len)
+            }
+        };
+        Some((
+            wrap(row, row_step, self.height)?,
+            wrap(col, col_step, self.width)?,
+        ))
+    }
+
+    fn warp_partner(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        self.warps.iter().find_map(|&(first, second)| {
+            if first == (row, col) {
+                Some(second)
+            } else if second == (row, col) {
+                Some(first)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn is_warp(&self, row: usize, col: usize) -> bool {
+        self.warp_partner(row, col).is_some()
+    }
+
+    pub fn is_source(&self, row: usize, col: usize) -> bool {
+        self.flags[self.index(row, col)] & SOURCE_FLAG != 0
+    }
+
+    pub fn is_connected(&self, row: usize, col: usize, direction: Direction) -> bool {
+        self.flags[self.index(row, col)] & direction_bit(direction) != 0
+    }
+
+    pub fn num_connections(&self, row: usize, col: usize) -> usize {
+        (self.flags[self.index(row, col)] & !SOURCE_FLAG).count_ones() as usize
+    }
+
+    /// whether no colored pipe has reached the cell. Uncolored pieces of pipe count as empty.
+    pub fn is_empty(&self, row: usize, col: usize) -> bool {
+        self.colors[self.index(row, col)] == NO_COLOR
+    }
+
+    /// Connects two neighboring cells. Whichever side is colored spreads its color through the
+    /// piece of pipe on the other side. It's up to the caller to only make legal connections.
+    pub fn connect(&mut self, row: usize, col: usize, direction: Direction) {
+        let (next_row, next_col) = self
+            .offset_row_col(row, col, direction)
+            .expect("connections stay inside the grid");
+        let (index, next_index) = (self.index(row, col), self.index(next_row, next_col));
+        self.flags[index] |= direction_bit(direction);
+        self.flags[next_index] |= direction_bit(direction.opposite());
+
+        let color = self.colors[index].min(self.colors[next_index]);
+        for (row, col, new_direction) in [
+            (row, col, direction),
+            (next_row, next_col, direction.opposite()),
+        ] {
+            if self.colors[self.index(row, col)] != color {
+                self.paint(self.piece(row, col, Some(new_direction)).collect(), color);
+            }
+        }
+    }
+
+    /// Disconnects two neighboring cells. Any piece that no longer leads back to a source goes
+    /// back to being uncolored.
+    pub fn disconnect(&mut self, row: usize, col: usize, direction: Direction) {
+        let (next_row, next_col) = self
+            .offset_row_col(row, col, direction)
+            .expect("connections stay inside the grid");
+        let (index, next_index) = (self.index(row, col), self.index(next_row, next_col));
+        self.flags[index] &= !direction_bit(direction);
+        self.flags[next_index] &= !direction_bit(direction.opposite());
+
+        for (row, col) in [(row, col), (next_row, next_col)] {
+            if !self.is_empty(row, col)
+                && !self
+                    .piece(row, col, None)
+                    .any(|(row, col)| self.is_source(row, col))
+            {
+                self.paint(self.piece(row, col, None).collect(), NO_COLOR);
+            }
+        }
+    }
+
+    fn paint(&mut self, cells: Vec<(usize, usize)>, color: u8) {
+        for (row, col) in cells {
+            let index = self.index(row, col);
+            self.colors[index] = color;
+        }
+    }
+
+    /// every cell joined to this one by pipes, including itself, except for what's past the
+    /// skipped direction. Pipes only branch at sources with extra pipes, which this walks
+    /// straight through, but it's only ever asked for pieces without a source or whether a piece
+    /// has one, so walking out to the ends is enough.
+    fn piece(
+        &self,
+        row: usize,
+        col: usize,
+        skipped_direction: Option<Direction>,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let ends = Direction::ALL
+            .into_iter()
+            .filter(move |&direction| {
+                Some(direction) != skipped_direction && self.is_connected(row, col, direction)
+            })
+            .flat_map(move |direction| self.walk(row, col, direction));
+        std::iter::once((row, col)).chain(ends)
+    }
+
+    /// the cells along the pipe leaving (row, col) in the given direction, not including (row, col)
+    fn walk(
+        &self,
+        row: usize,
+        col: usize,
+        direction: Direction,
+    ) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let start = (row, col);
+        let mut step = self
+            .offset_row_col(row, col, direction)
+            .map(|next| (next, direction.opposite()));
+        std::iter::from_fn(move || {
+            let (current, came_from) = step?;
+            if current == start {
+                // went all the way around a loop
+                return None;
+            }
+            step = Direction::ALL
+                .into_iter()
+                .find(|&direction| {
+                    direction != came_from && self.is_connected(current.0, current.1, direction)
+                })
+                .and_then(|direction| {
+                    self.offset_row_col(current.0, current.1, direction)
+                        .map(|next| (next, direction.opposite()))
+                });
+            Some(current)
+        })
+    }
+}
@@ -0,0 +1,581 @@
+/// This file has a corpus of puzzles with known unique solutions, for regression tests and
+/// benchmarks of the engine and the solver. Each one is checked both ways: the solver has to find
+/// exactly the recorded solution, and replaying the recorded moves through try_connect has to
+/// build it.
+///
+/// A few puzzles are there for the other kinds of board, like bridges, hexes and warps. The
+/// solver can't route through bridges yet, so the one with a bridge is only checked by replaying
+/// it and counting its solutions.
+use crate::flow_grid::{Direction, FlowGrid, Move, WarpPair};
+
+/// what kind of board a corpus puzzle is played on, beyond what its rows say
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Square,
+    Hex,
+    /// a square board whose edges join up with the opposite ones
+    Wrapping,
+    /// a square board with these pairs of warps
+    Warps(&'static [WarpPair]),
+}
+
+pub struct CorpusPuzzle {
+    pub name: &'static str,
+    /// one string per row, the way FlowGrid::from_board reads it. Colors are numbered in the
+    /// order their first source appears, reading row by row.
+    pub board: &'static [&'static str],
+    pub layout: Layout,
+    /// for each color, the moves along its pipe from its first source, the way Direction::letter
+    /// writes them. A color with extra sources goes through them on the way.
+    pub solution: &'static [&'static str],
+}
+
+impl CorpusPuzzle {
+    pub fn build_grid(&self) -> FlowGrid {
+        let grid = FlowGrid::from_board(self.board)
+            .unwrap_or_else(|| panic!("{}: every color needs at least two sources", self.name));
+        match self.layout {
+            Layout::Square => grid,
+            Layout::Hex => grid.hexagonal(),
+            Layout::Wrapping => grid.wrapping(),
+            Layout::Warps(warps) => {
+                let mut grid = grid;
+                for &((row, col), (to_row, to_col)) in warps {
+                    assert!(
+                        grid.try_add_warp(row, col, to_row, to_col),
+                        "{}: warps go on empty cells",
+                        self.name
+                    );
+                }
+                grid
+            }
+        }
+    }
+
+    /// every connection in the solution, walking each pipe from its first source
+    pub fn solution_moves(&self) -> Vec<Move> {
+        let grid = self.build_grid();
+        let mut moves = Vec::new();
+        for (color_id, path) in self.solution.iter().enumerate() {
+            let (mut row, mut col) = *grid
+                .get_sources(color_id)
+                .first()
+                .unwrap_or_else(|| panic!("{}: more paths than colors", self.name));
+            for step in path.chars() {
+                let direction = Direction::ALL
+                    .into_iter()
+                    .find(|direction| direction.letter() == step)
+                    .unwrap_or_else(|| panic!("{}: unknown step {step}", self.name));
+                moves.push(Move {
+                    row,
+                    col,
+                    direction,
+                });
+                (row, col) = grid
+                    .get_offset_row_col(row, col, direction)
+                    .unwrap_or_else(|| panic!("{}: a step off the board", self.name));
+            }
+        }
+        moves
+    }
+}
+
+pub const CORPUS: [CorpusPuzzle; 12] = [
+    CorpusPuzzle {
+        name: "Warmup",
+        board: &["R..R", "G.G.", "B...", "...B"],
+        layout: Layout::Square,
+        solution: &["RRR", "RDRRUL", "DRRR"],
+    },
+    CorpusPuzzle {
+        name: "Small square",
+        board: &["R...R", "..G..", "..G.B", "Y....", ".Y..B"],
+        layout: Layout::Square,
+        solution: &["RRRDRU", "LLDRDRDRUUL", "DD", "DR"],
+    },
+    CorpusPuzzle {
+        name: "Medium square",
+        board: &["....R.", "R.....", "..G..G", "B...B.", ".YY...", "...O.O"],
+        layout: Layout::Square,
+        solution: &["RDLLULDLULD", "RRR", "URDRRDRRUL", "LDRRU", "RR"],
+    },
+    CorpusPuzzle {
+        name: "Seven by seven",
+        board: &[
+            ".R...G.", "..G....", "......B", ".....B.", "...Y..O", ".R...Y.", "....O..",
+        ],
+        layout: Layout::Square,
+        solution: &[
+            "LDRDLDRDLDDRU",
+            "RDLDLDLLURURULLD",
+            "DL",
+            "LDDRURURD",
+            "DDLL",
+        ],
+    },
+    CorpusPuzzle {
+        name: "Wide",
+        board: &["........", "R....G..", ".G..R..B", "...B....", "..Y..Y.."],
+        layout: Layout::Square,
+        solution: &["URDRURRRRRDLDLL", "LLDLDLDLUUR", "DDLULLL", "RRR"],
+    },
+    CorpusPuzzle {
+        name: "Many colors",
+        board: &[
+            ".R......", "R...G...", "B.Y..OP.", "Y.......", ".CB.O.G.", "..KM...W", ".....M..",
+            "C...KP.W",
+        ],
+        layout: Layout::Square,
+        solution: &[
+            "LD",
+            "URDRURDDDDL",
+            "RURURDDDDL",
+            "DLL",
+            "LDD",
+            "DLDDRDDL",
+            "LDDD",
+            "LDDRURDR",
+            "RDR",
+            "DD",
+        ],
+    },
+    CorpusPuzzle {
+        name: "Bridge",
+        board: &["....A", ".A...", ".B.+.", ".....", ".C.BC"],
+        layout: Layout::Square,
+        solution: &["DDLLUL", "DRDR", "LUUUURRRDDDRD"],
+    },
+    CorpusPuzzle {
+        name: "Holes",
+        board: &[".A...B", "A..C..", "..###.", ".#D#C.", ".#....", "....BD"],
+        layout: Layout::Square,
+        solution: &["LD", "LLLDLDLDDDRRRR", "RRDDL", "DRRRD"],
+    },
+    CorpusPuzzle {
+        name: "Hex",
+        board: &[".DA..", ".....", ".B...", "CC.D.", ".BA.."],
+        layout: Layout::Hex,
+        solution: &["LDD9RRDD", "RRDDDDLL", "RD1", "D9"],
+    },
+    CorpusPuzzle {
+        name: "Wrapped",
+        board: &["C.A..", "D...C", "B....", "..B..", "A...D"],
+        layout: Layout::Wrapping,
+        solution: &["LLDR", "LUL", "RRDLDLLD", "LLDDLU"],
+    },
+    CorpusPuzzle {
+        name: "Warps",
+        board: &["C...B", ".....", ".#A..", ".#...", "..BAC"],
+        layout: Layout::Warps(&[((0, 3), (2, 3))]),
+        solution: &["RRRWRDD", "DLLLLDDDRR", "DRD"],
+    },
+    CorpusPuzzle {
+        name: "Extra sources",
+        board: &["...AB", ".....", ".AB..", ".C.B.", "....C"],
+        layout: Layout::Square,
+        solution: &["LDLULDDR", "DLDRDLLU", "LDRRRR"],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_grid::CellColor;
+    use crate::flow_solver::{self, SolveOptions, SolveOutcome};
+
+    /// panics at the first cell where the two boards' colors or connections differ
+    fn assert_same_board(name: &str, actual: &FlowGrid, expected: &FlowGrid) {
+        for row in 0..expected.height {
+            for col in 0..expected.width {
+                let actual_cell = actual.get(row, col).expect("boards are the same size");
+                let expected_cell = expected.get(row, col).expect("looping in bounds");
+                assert_eq!(
+                    actual_cell.color, expected_cell.color,
+                    "{name}: color at ({row}, {col})"
+                );
+                for direction in Direction::ALL {
+                    assert_eq!(
+                        actual_cell.is_direction_connected(direction),
+                        expected_cell.is_direction_connected(direction),
+                        "{name}: connection {direction:?} of ({row}, {col})"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Panics if the grid breaks one of the rules try_connect and try_disconnect maintain:
+    /// connections go both ways, connected cells share a color, a cell is only colored while
+    /// it's joined to a source of that color, and the count of filled cells keeps up.
+    fn assert_invariants(name: &str, grid: &FlowGrid) {
+        let num_filled = (0..grid.height)
+            .flat_map(|row| (0..grid.width).map(move |col| (row, col)))
+            .filter(|&(row, col)| grid.get(row, col).is_some_and(|cell| cell.is_filled()))
+            .count();
+        assert_eq!(grid.num_filled_cells(), num_filled, "{name}: filled cells");
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                let cell = grid.get(row, col).expect("looping in bounds");
+                for direction in Direction::ALL {
+                    if !cell.is_direction_connected(direction) {
+                        continue;
+                    }
+                    let neighbor = grid
+                        .offset_get(row, col, direction)
+                        .unwrap_or_else(|| panic!("{name}: ({row}, {col}) connects off the grid"));
+                    assert!(
+                        neighbor.is_direction_connected(direction.opposite()),
+                        "{name}: ({row}, {col}) connects {direction:?} one way"
+                    );
+                    assert_eq!(
+                        neighbor.lane_color(direction.opposite()),
+                        cell.lane_color(direction),
+                        "{name}: ({row}, {col}) and its {direction:?} neighbor differ"
+                    );
+                }
+                let is_sourced = (0..grid.num_colors()).any(|color_id| {
+                    cell.color == CellColor::Colored(color_id)
+                        && grid
+                            .get_sources(color_id)
+                            .iter()
+                            .any(|&(source_row, source_col)| {
+                                grid.are_cells_connected(row, col, source_row, source_col)
+                            })
+                });
+                assert_eq!(
+                    is_sourced,
+                    matches!(cell.color, CellColor::Colored(_)),
+                    "{name}: ({row}, {col}) is {:?}",
+                    cell.color
+                );
+            }
+        }
+    }
+
+    fn replay(puzzle: &CorpusPuzzle, moves: impl IntoIterator<Item = Move>) -> FlowGrid {
+        let mut grid = puzzle.build_grid();
+        for step in moves {
+            assert!(
+                grid.try_connect(step.row, step.col, step.direction),
+                "{}: couldn't make {step:?}",
+                puzzle.name
+            );
+            assert_invariants(puzzle.name, &grid);
+        }
+        grid
+    }
+
+    #[test]
+    fn replaying_the_solution_solves_the_board() {
+        for puzzle in &CORPUS {
+            let num_flows = puzzle.build_grid().num_colors();
+            assert_eq!(
+                puzzle.build_grid().flows_connected(),
+                (0, num_flows),
+                "{}",
+                puzzle.name
+            );
+            let grid = replay(puzzle, puzzle.solution_moves());
+            assert!(grid.is_solved(), "{}", puzzle.name);
+            assert_eq!(
+                grid.flows_connected(),
+                (num_flows, num_flows),
+                "{}",
+                puzzle.name
+            );
+            assert_eq!(
+                grid.num_filled_cells(),
+                grid.width * grid.height,
+                "{}",
+                puzzle.name
+            );
+        }
+    }
+
+    #[test]
+    fn solved_pipes_read_back_as_the_solution() {
+        for puzzle in &CORPUS {
+            let grid = replay(puzzle, puzzle.solution_moves());
+            for (color_id, path) in puzzle.solution.iter().enumerate() {
+                let (_, moves) = grid.pipe_moves(color_id).expect("every color has sources");
+                let letters: String = moves.iter().map(Direction::letter).collect();
+                assert_eq!(&letters, path, "{}", puzzle.name);
+            }
+        }
+    }
+
+    #[test]
+    fn replay_order_doesnt_matter() {
+        for puzzle in &CORPUS {
+            let expected = replay(puzzle, puzzle.solution_moves());
+            // backwards, every pipe grows out of uncolored pieces towards its first source
+            let reversed = replay(puzzle, puzzle.solution_moves().into_iter().rev());
+            assert_same_board(puzzle.name, &reversed, &expected);
+            // and interleaved, so pieces of different pipes get joined in the middle
+            let moves = puzzle.solution_moves();
+            let (even, odd): (Vec<_>, Vec<_>) = moves
+                .iter()
+                .enumerate()
+                .partition(|(index, _)| index % 2 == 0);
+            let interleaved = replay(puzzle, even.into_iter().chain(odd).map(|(_, &step)| step));
+            assert_same_board(puzzle.name, &interleaved, &expected);
+        }
+    }
+
+    #[test]
+    fn the_history_rebuilds_the_board() {
+        for puzzle in &CORPUS {
+            let mut grid = replay(puzzle, puzzle.solution_moves());
+            // a resize and some undone moves, so every kind of event is in there
+            grid.add_col();
+            for step in puzzle.solution_moves().into_iter().step_by(3) {
+                grid.try_disconnect(step.row, step.col, step.direction);
+            }
+            grid.try_remove_col();
+            let (row, col) = grid.get_source_pair(0).expect("every puzzle has a color").0;
+            grid.try_remove_source(row, col);
+            grid.try_set_missing_source(row, col, 0);
+
+            let replayed = grid.replay_history();
+            assert_same_board(puzzle.name, &replayed, &grid);
+            assert_eq!(replayed.history(), grid.history(), "{}", puzzle.name);
+        }
+    }
+
+    #[test]
+    fn saved_boards_load_the_same() {
+        for puzzle in &CORPUS {
+            let mut grid = replay(puzzle, puzzle.solution_moves());
+            let step = puzzle.solution_moves()[0];
+            grid.try_disconnect(step.row, step.col, step.direction);
+            let json = serde_json::to_string(&grid).expect("grids always serialize");
+            let loaded: FlowGrid = serde_json::from_str(&json).expect("it was just saved");
+            assert_same_board(puzzle.name, &loaded, &grid);
+            assert_invariants(puzzle.name, &loaded);
+            assert_eq!(loaded.history(), grid.history(), "{}", puzzle.name);
+            assert_eq!(loaded.next_color(), grid.next_color(), "{}", puzzle.name);
+        }
+    }
+
+    #[test]
+    fn disconnecting_everything_uncolors_the_pipes() {
+        for puzzle in &CORPUS {
+            let mut grid = replay(puzzle, puzzle.solution_moves());
+            for step in puzzle.solution_moves() {
+                assert!(
+                    grid.try_disconnect(step.row, step.col, step.direction),
+                    "{}: couldn't undo {step:?}",
+                    puzzle.name
+                );
+                assert_invariants(puzzle.name, &grid);
+            }
+            for row in 0..grid.height {
+                for col in 0..grid.width {
+                    let cell = grid.get(row, col).expect("looping in bounds");
+                    assert_eq!(cell.num_connections(), 0, "{}", puzzle.name);
+                    if !cell.is_source {
+                        assert!(
+                            matches!(cell.color, CellColor::Empty(_)),
+                            "{}: ({row}, {col}) kept its color",
+                            puzzle.name
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn retracting_keeps_the_side_with_the_source() {
+        for puzzle in &CORPUS {
+            // the first pipe that's still short of its other source after three moves
+            let (color_id, path) = puzzle
+                .solution
+                .iter()
+                .enumerate()
+                .find(|(_, path)| path.len() > 3)
+                .expect("every puzzle has a long pipe");
+            let start: usize = puzzle.solution[..color_id]
+                .iter()
+                .map(|path| path.len())
+                .sum();
+            let moves = &puzzle.solution_moves()[start..start + path.len()];
+            let (source, _) = puzzle
+                .build_grid()
+                .get_source_pair(color_id)
+                .expect("every color has sources");
+            let (row, col) = (moves[1].row, moves[1].col);
+
+            // a pipe that doesn't reach its other source loses its loose end
+            let mut grid = replay(puzzle, moves[..3].iter().copied());
+            assert!(grid.try_retract_to(row, col), "{}", puzzle.name);
+            assert_invariants(puzzle.name, &grid);
+            assert_eq!(grid.get(row, col).unwrap().num_connections(), 1);
+            assert!(grid.are_cells_connected(row, col, source.0, source.1));
+            let loose_end = (moves[2].row, moves[2].col);
+            assert_eq!(
+                grid.get(loose_end.0, loose_end.1)
+                    .unwrap()
+                    .num_connections(),
+                0,
+                "{}",
+                puzzle.name
+            );
+
+            // a finished one loses the shorter side, which here is back to the first source
+            let mut grid = replay(puzzle, puzzle.solution_moves());
+            assert!(grid.try_retract_to(row, col), "{}", puzzle.name);
+            assert_invariants(puzzle.name, &grid);
+            assert!(!grid.are_sources_connected(color_id), "{}", puzzle.name);
+            assert_eq!(grid.get(row, col).unwrap().num_connections(), 1);
+            assert_eq!(
+                grid.get(source.0, source.1).unwrap().num_connections(),
+                0,
+                "{}",
+                puzzle.name
+            );
+        }
+    }
+
+    #[test]
+    fn pipes_cross_on_bridges() {
+        let board = ["A.B", ".+.", "B.A"];
+        let mut grid = FlowGrid::from_board(&board).expect("a valid board");
+        assert_eq!(grid.to_board().expect("few colors"), board);
+        for (row, col, direction) in [
+            (0, 0, Direction::Right),
+            (0, 1, Direction::Down),
+            (1, 1, Direction::Down),
+            (2, 1, Direction::Right),
+            (0, 2, Direction::Down),
+            (1, 2, Direction::Left),
+            (1, 1, Direction::Left),
+            (1, 0, Direction::Down),
+        ] {
+            assert!(grid.try_connect(row, col, direction), "({row}, {col})");
+        }
+        assert!(grid.is_solved());
+        let bridge = grid.get(1, 1).expect("in bounds");
+        assert_eq!(bridge.lane_color(Direction::Up), CellColor::Colored(0));
+        assert_eq!(bridge.lane_color(Direction::Left), CellColor::Colored(1));
+        assert!(!grid.try_remove_bridge(1, 1));
+
+        // cutting one pipe below the bridge leaves the other one whole
+        assert!(grid.try_disconnect(1, 1, Direction::Down));
+        let bridge = grid.get(1, 1).expect("in bounds");
+        assert_eq!(bridge.lane_color(Direction::Up), CellColor::Colored(0));
+        assert_eq!(bridge.lane_color(Direction::Left), CellColor::Colored(1));
+        assert!(grid.are_cells_connected(0, 2, 2, 0));
+        assert!(!grid.is_solved());
+        assert_same_board("bridges", &grid.replay_history(), &grid);
+
+        // pulling the cut pipe back out of the bridge only empties its own lane
+        assert!(grid.remove_tail(0, 0, 1, 1));
+        assert_eq!(grid.get(0, 1).expect("in bounds").num_connections(), 0);
+        let bridge = grid.get(1, 1).expect("in bounds");
+        assert!(matches!(
+            bridge.lane_color(Direction::Up),
+            CellColor::Empty(_)
+        ));
+        assert_eq!(bridge.lane_color(Direction::Left), CellColor::Colored(1));
+    }
+
+    #[test]
+    fn drag_paths_go_around_what_is_in_the_way() {
+        let mut grid = FlowGrid::from_board(&["A.#.A", "..#..", "B...B"]).expect("a valid board");
+        // the only way past the wall is along the bottom row, which B's pipe has started on
+        assert!(grid.try_connect(2, 0, Direction::Right));
+        assert!(grid.drag_path((0, 0), (0, 4)).is_none());
+
+        assert!(grid.try_disconnect(2, 0, Direction::Right));
+        let path = grid
+            .drag_path((0, 0), (0, 4))
+            .expect("the bottom row is free");
+        assert_eq!(path.len(), 8);
+        for step in path {
+            assert!(grid.try_connect(step.row, step.col, step.direction));
+        }
+        assert!(grid.are_sources_connected(0));
+    }
+
+    #[test]
+    fn pipes_dont_change_the_puzzle() {
+        for puzzle in &CORPUS {
+            let grid = replay(puzzle, puzzle.solution_moves());
+            assert!(
+                grid.has_same_puzzle(&puzzle.build_grid()),
+                "{}",
+                puzzle.name
+            );
+
+            let mut moved = puzzle.build_grid();
+            let (row, col) = moved.get_sources(0)[0];
+            assert!(moved.try_remove_source(row, col));
+            assert!(!grid.has_same_puzzle(&moved), "{}", puzzle.name);
+            assert!(moved.try_set_missing_source(row, col, 0));
+            assert!(grid.has_same_puzzle(&moved), "{}", puzzle.name);
+        }
+    }
+
+    #[test]
+    fn solutions_differ_where_they_are_routed_differently() {
+        for puzzle in &CORPUS {
+            let solution = replay(puzzle, puzzle.solution_moves());
+            let path_length: usize = puzzle.solution.iter().map(|path| path.len()).sum();
+            assert_eq!(solution.pipe_length(), path_length, "{}", puzzle.name);
+            assert!(
+                flow_solver::differing_cells(&solution, &solution).is_empty(),
+                "{}",
+                puzzle.name
+            );
+
+            let mut unfinished = solution.clone();
+            let step = puzzle.solution_moves()[0];
+            assert!(unfinished.try_disconnect(step.row, step.col, step.direction));
+            assert_eq!(unfinished.pipe_length(), path_length - 1, "{}", puzzle.name);
+            assert!(
+                flow_solver::differing_cells(&solution, &unfinished)
+                    .contains(&(step.row, step.col)),
+                "{}",
+                puzzle.name
+            );
+        }
+    }
+
+    #[test]
+    fn diverse_solutions_are_all_different() {
+        // one pair on an empty board can snake around in many ways
+        let grid = FlowGrid::from_board(&["R...", "....", "....", "R..."]).expect("valid board");
+        let picked = flow_solver::diverse_solutions(&grid, 4, 100);
+        assert_eq!(picked.len(), 4);
+        for (index, solution) in picked.iter().enumerate() {
+            assert!(solution.is_solved());
+            for other in &picked[index + 1..] {
+                assert!(flow_solver::solution_distance(solution, other) > 0);
+            }
+        }
+        // a puzzle with one solution only has one to pick
+        let picked = flow_solver::diverse_solutions(&CORPUS[0].build_grid(), 4, 100);
+        assert_eq!(picked.len(), 1);
+    }
+
+    #[test]
+    fn solver_finds_exactly_the_recorded_solution() {
+        for puzzle in &CORPUS {
+            let grid = puzzle.build_grid();
+            if grid.has_bridges() {
+                let outcome = flow_solver::solve_with_options(&grid, &SolveOptions::default());
+                assert!(
+                    matches!(outcome, SolveOutcome::Unsupported),
+                    "{}",
+                    puzzle.name
+                );
+                continue;
+            }
+            let expected = replay(puzzle, puzzle.solution_moves());
+            let solutions: Vec<FlowGrid> = flow_solver::solutions(&grid).collect();
+            assert_eq!(solutions.len(), 1, "{}", puzzle.name);
+            assert_same_board(puzzle.name, &solutions[0], &expected);
+        }
+    }
+}
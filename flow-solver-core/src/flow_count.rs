@@ -0,0 +1,297 @@
+/// This file counts solutions exactly without finding them one by one. It sweeps the board a
+/// cell at a time, row by row, and only remembers how the pipes cross the frontier between the
+/// cells it has decided and the ones it hasn't. Every partial board that crosses the frontier the
+/// same way has the same ways to finish, so they're counted together instead of searched apart.
+use crate::flow_grid::{CellColor, FlowCell, FlowGrid};
+use crate::flow_solver;
+use std::collections::HashMap;
+
+/// what crosses one edge of the frontier
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Plug {
+    None,
+    /// a pipe that leads back to a source of this color
+    Colored(usize),
+    /// one end of a piece of pipe that hasn't reached a source yet. Its other end is the only
+    /// other plug with the same id.
+    Piece(usize),
+}
+
+/// The frontier before a cell: one plug per column coming down from the row above, and then the
+/// plug coming in from the cell to the left.
+type Frontier = Vec<Plug>;
+
+/// How many different ways the grid's sources can be connected with every cell filled. Pipes
+/// already on the grid are ignored, like solutions(). A color that is missing a source means
+/// there are none. The frontier only has room for pairs of square cells on a board with edges,
+/// so hex grids, ones that wrap around, ones with warps and ones with colors of more than two
+/// sources are counted by finding every solution instead.
+pub fn count_solutions_exact(grid: &FlowGrid) -> u128 {
+    if (0..grid.num_colors()).any(|color_id| grid.is_missing_source(color_id)) {
+        return 0;
+    }
+    if grid.is_hex() || grid.wraps() || grid.has_warps() || grid.has_extra_sources() {
+        return flow_solver::solutions(grid).count() as u128;
+    }
+
+    let left = grid.width;
+    let mut frontiers: HashMap<Frontier, u128> = HashMap::new();
+    frontiers.insert(vec![Plug::None; grid.width + 1], 1);
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            let cell = grid.get(row, col).expect("looping in bounds");
+            let can_go_right = col + 1 < grid.width;
+            let can_go_down = row + 1 < grid.height;
+
+            let mut next_frontiers: HashMap<Frontier, u128> = HashMap::new();
+            for (frontier, count) in frontiers {
+                for mut next in step(&frontier, col, left, cell) {
+                    let goes_right = next[left] != Plug::None;
+                    let goes_down = next[col] != Plug::None;
+                    if (goes_right && !can_go_right) || (goes_down && !can_go_down) {
+                        continue;
+                    }
+                    normalize(&mut next);
+                    *next_frontiers.entry(next).or_insert(0) += count;
+                }
+            }
+            frontiers = next_frontiers;
+        }
+    }
+    frontiers
+        .into_iter()
+        .filter(|(frontier, _)| frontier.iter().all(|&plug| plug == Plug::None))
+        .map(|(_, count)| count)
+        .sum()
+}
+
+/// Every way to fill in the cell at this column, given the plugs coming into it from above and
+/// from the left. In the returned frontiers, the cell's plugs are the ones it sends down and to
+/// the right.
+fn step(frontier: &Frontier, col: usize, left: usize, cell: &FlowCell) -> Vec<Frontier> {
+    let (from_up, from_left) = (frontier[col], frontier[left]);
+    let mut cleared = frontier.clone();
+    cleared[col] = Plug::None;
+    cleared[left] = Plug::None;
+    let with_outputs = |down: Plug, right: Plug| {
+        let mut next = cleared.clone();
+        next[col] = down;
+        next[left] = right;
+        next
+    };
+
+    // a bridge carries one pipe straight down and another straight across, so it needs both
+    if cell.is_bridge() {
+        return if from_up != Plug::None && from_left != Plug::None {
+            vec![with_outputs(from_up, from_left)]
+        } else {
+            Vec::new()
+        };
+    }
+    // nothing goes in or out of a blocked cell
+    if cell.is_blocked {
+        return if from_up == Plug::None && from_left == Plug::None {
+            vec![with_outputs(Plug::None, Plug::None)]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let source_color = match cell.color {
+        CellColor::Colored(color_id) if cell.is_source => Some(color_id),
+        _ => None,
+    };
+    let incoming: Vec<Plug> = [from_up, from_left]
+        .into_iter()
+        .filter(|&plug| plug != Plug::None)
+        .collect();
+    match (source_color, &incoming[..]) {
+        // a source starts its pipe in one direction
+        (Some(color_id), []) => vec![
+            with_outputs(Plug::Colored(color_id), Plug::None),
+            with_outputs(Plug::None, Plug::Colored(color_id)),
+        ],
+        // or a pipe ends here
+        (Some(color_id), &[plug]) => end_at(&cleared, plug, Plug::Colored(color_id))
+            .into_iter()
+            .collect(),
+        (Some(_), _) => Vec::new(),
+        // an empty cell either starts a new piece going both ways
+        (None, []) => {
+            let id = next_piece_id(frontier);
+            vec![with_outputs(Plug::Piece(id), Plug::Piece(id))]
+        }
+        // carries a pipe through
+        (None, &[plug]) => vec![
+            with_outputs(plug, Plug::None),
+            with_outputs(Plug::None, plug),
+        ],
+        // or joins two pipes
+        (None, &[plug1, plug2]) => end_at(&cleared, plug1, plug2).into_iter().collect(),
+        (None, _) => unreachable!("a cell has at most two plugs coming in"),
+    }
+}
+
+/// Joins two pipe ends, which uses them both up. Returns None if they can't be joined.
+fn end_at(frontier: &Frontier, plug1: Plug, plug2: Plug) -> Option<Frontier> {
+    let mut next = frontier.clone();
+    match (plug1, plug2) {
+        (Plug::Colored(color1), Plug::Colored(color2)) => (color1 == color2).then_some(next),
+        // joining both ends of the same piece would close a loop
+        (Plug::Piece(id1), Plug::Piece(id2)) if id1 == id2 => None,
+        // the other end of the piece takes on whatever it was joined to
+        (Plug::Piece(id), other) | (other, Plug::Piece(id)) => {
+            for plug in next.iter_mut() {
+                if *plug == Plug::Piece(id) {
+                    *plug = other;
+                }
+            }
+            Some(next)
+        }
+        (Plug::None, _) | (_, Plug::None) => unreachable!("only real pipe ends get joined"),
+    }
+}
+
+fn next_piece_id(frontier: &Frontier) -> usize {
+    frontier
+        .iter()
+        .filter_map(|&plug| match plug {
+            Plug::Piece(id) => Some(id + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Renumbers the pieces in the order they appear, so frontiers that only differ in ids are
+/// counted together.
+fn normalize(frontier: &mut Frontier) {
+    let mut renames: Vec<usize> = Vec::new();
+    for plug in frontier.iter_mut() {
+        if let Plug::Piece(id) = *plug {
+            let new_id = match renames.iter().position(|&old_id| old_id == id) {
+                Some(new_id) => new_id,
+                None => {
+                    renames.push(id);
+                    renames.len() - 1
+                }
+            };
+            *plug = Plug::Piece(new_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_corpus::CORPUS;
+
+    #[test]
+    fn corpus_puzzles_have_one_solution() {
+        for puzzle in &CORPUS {
+            assert_eq!(
+                count_solutions_exact(&puzzle.build_grid()),
+                1,
+                "{}",
+                puzzle.name
+            );
+        }
+    }
+
+    #[test]
+    fn bridges_carry_both_pipes_across() {
+        // each pair has to cross the middle, one down and one across, in either order
+        let grid = FlowGrid::from_board(&["A.B", ".+.", "B.A"]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 2);
+    }
+
+    #[test]
+    fn pipes_go_around_blocked_cells() {
+        // the only way to fill the ring is the long way round
+        let grid = FlowGrid::from_board(&["AA.", ".#.", "..."]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 1);
+        let solutions: Vec<FlowGrid> = flow_solver::solutions(&grid).collect();
+        assert_eq!(solutions.len(), 1);
+        assert!(solutions[0].is_solved());
+    }
+
+    #[test]
+    fn hexes_have_diagonal_neighbors() {
+        // opposite corners can't fill a square board, but hexagons zigzag across the diagonal
+        let mut squares = FlowGrid::with_size(2, 2);
+        let mut hexes = FlowGrid::hex_with_size(2, 2);
+        for grid in [&mut squares, &mut hexes] {
+            grid.try_set_missing_source(0, 0, 0);
+            grid.try_set_missing_source(1, 1, 0);
+        }
+        assert_eq!(count_solutions_exact(&squares), 0);
+        assert_eq!(count_solutions_exact(&hexes), 2);
+    }
+
+    #[test]
+    fn pipes_wrap_around_the_edges() {
+        // the outer pair can only meet by going off one end and coming back on the other
+        let edged = FlowGrid::from_board(&["ABBA"]).expect("a valid board");
+        let wrapped = edged.clone().wrapping();
+        assert_eq!(count_solutions_exact(&edged), 0);
+        assert_eq!(count_solutions_exact(&wrapped), 1);
+    }
+
+    #[test]
+    fn pipes_go_through_warps() {
+        // the hole splits the board, and the warps are the only way across
+        let mut grid = FlowGrid::from_board(&["A.#.A"]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 0);
+        assert!(grid.try_add_warp(0, 1, 0, 3));
+        assert_eq!(grid.warps(), [((0, 1), (0, 3))]);
+        assert_eq!(count_solutions_exact(&grid), 1);
+        let solution = flow_solver::solutions(&grid).next().expect("solvable");
+        assert!(solution.get(0, 1).expect("in bounds").is_connected_warp);
+
+        // the pipe has to go through the warps rather than across the cells between them
+        let mut grid = FlowGrid::from_board(&["A..A"]).expect("a valid board");
+        assert!(grid.try_add_warp(0, 1, 0, 2));
+        assert_eq!(count_solutions_exact(&grid), 1);
+    }
+
+    #[test]
+    fn extra_sources_join_into_a_tree() {
+        let grid = FlowGrid::from_board(&["A.A.A"]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 1);
+
+        // each corner can only join the two sources beside it, which closes a loop
+        let grid = FlowGrid::from_board(&[".A.", "A.A", ".A."]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 0);
+        // pipes only branch at sources
+        let grid = FlowGrid::from_board(&["A.A", "#A#"]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 0);
+        // the corner joins two sources, and the third can join either of them
+        let grid = FlowGrid::from_board(&["AA", "A."]).expect("a valid board");
+        assert_eq!(count_solutions_exact(&grid), 2);
+    }
+
+    /// width, height, and the (row, col) of each source, two to a color
+    type TestBoard = (usize, usize, &'static [(usize, usize)]);
+
+    #[test]
+    fn agrees_with_the_solver() {
+        // open boards with lots of solutions, so every kind of join gets exercised
+        let boards: [TestBoard; 4] = [
+            (3, 3, &[(0, 0), (2, 2)]),
+            (4, 4, &[(0, 0), (3, 0), (0, 3), (3, 3)]),
+            (4, 4, &[(0, 0), (0, 1), (3, 3), (2, 3)]),
+            (5, 4, &[(1, 1), (3, 2), (0, 4), (3, 0)]),
+        ];
+        for (width, height, sources) in boards {
+            let mut grid = FlowGrid::with_size(width, height);
+            for (source_index, &(row, col)) in sources.iter().enumerate() {
+                grid.try_set_missing_source(row, col, source_index / 2);
+            }
+            assert_eq!(
+                count_solutions_exact(&grid),
+                flow_solver::solutions(&grid).count() as u128,
+                "{sources:?}"
+            );
+        }
+    }
+}
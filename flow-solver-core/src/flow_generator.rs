@@ -0,0 +1,985 @@
+/// This file makes new puzzles. It lays random pipes over an empty board until every cell is
+/// covered, keeps just their ends as sources, and then asks the solver whether those sources
+/// only fit together one way. Boards with more than one solution are thrown out and it tries again.
+///
+/// Most random pipes leave room for another solution, so the pipes are kept from doubling back
+/// on themselves: no pipe is allowed to cover a whole 2x2 square, since it could always be
+/// rerouted inside one. That makes nearly every board unique. Pipes are joined for as long as
+/// they can be, and the board with the fewest pipes out of a few is kept, since longer pipes make
+/// harder puzzles.
+///
+/// Some boards are unique but not worth playing. A pipe running straight across the whole board
+/// just cuts it in two, so those layouts are thrown out before the solver sees them. A board a
+/// player can fill in without ever guessing is too easy, so it's only kept if nothing harder
+/// turns up.
+///
+/// A style changes which joins are taken, which biases the shape of the pipes: long winding
+/// snakes, coiled spirals, or lots of short maze-like pipes. A color count picks the candidate
+/// with the closest number of colors instead of the fewest. Pipes only join so far, so it's
+/// something to aim for rather than a promise. A ColorTable gives a count for each board size.
+///
+/// Everything random comes from one seed, so a size and a seed are enough to share a puzzle,
+/// written as a code like "7x7 #482913", with the style and color count after it when they're
+/// asked for, like "7x7 #482913 spiral 8 colors".
+///
+/// Boards don't have to be rectangles. Given a mask, pipes are only laid over the cells in it and
+/// everything else is blocked off, which makes L shapes, diamonds and boards with holes. Asking
+/// for walls carves short runs of walls into the inside of the mask first, like a maze, as long
+/// as the open cells stay in one piece, and then lays the pipes around them.
+///
+/// Bridges go on open cells away from the edge before the pipes are laid, and pipes are laid
+/// through each of a bridge's two lanes separately. A layout is only kept if two different pipes
+/// cross at every bridge, so the crossing is part of the puzzle rather than decoration. The
+/// solver can't route through bridges, so boards with them are checked by counting solutions.
+use crate::flow_count::count_solutions_exact;
+use crate::flow_grid::FlowGrid;
+use crate::flow_solver::{self, SolveEvent};
+use web_time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// how many random boards to try before giving up
+const ATTEMPTS: usize = 1000;
+/// how many boards with one solution to choose the puzzle from
+const CANDIDATES: usize = 20;
+/// how many boards with one solution to look through for candidates, since most small boards
+/// turn out too easy
+const MAX_UNIQUE_BOARDS: usize = 2 * CANDIDATES;
+/// new seeds are kept short enough to read out
+const MAX_NEW_SEED: u64 = 1_000_000;
+/// How many moves the solver gets to check a board. A board it can't finish in time is treated
+/// as having more than one solution, since a player would find it too hard anyway.
+const NODE_LIMIT: u64 = 100_000;
+/// a pipe between two touching sources isn't much of a puzzle
+const MIN_PIPE_LENGTH: usize = 3;
+/// walls take about one open cell in this many
+const CELLS_PER_WALL: usize = 6;
+/// the longest run of walls carved at once
+const MAX_WALL_RUN: usize = 3;
+
+/// why a random layout was thrown out before the solver saw it
+enum LayoutProblem {
+    /// a pipe too short to be worth solving
+    ShortPipe,
+    /// a pipe running straight across the whole board
+    StraightPipe,
+    /// a pipe ending on a bridge, where there's no room for a source
+    BridgeEnd,
+    /// a pipe crossing itself at a bridge, so no other pipe needs the bridge
+    SelfCrossing,
+}
+
+/// splitmix64: small, fast, and the same on every platform, which is all the generator needs
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// a number from 0 up to, but not including, the bound
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// how the generator shapes its pipes, by changing which joins it takes as it lays them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PuzzleStyle {
+    /// pipes join wherever they can
+    #[default]
+    Classic,
+    /// long pipes that wind around, with few straight runs
+    Snake,
+    /// pipes that keep turning the same way, so they coil up
+    Spiral,
+    /// lots of short pipes, like the passages of a maze
+    Maze,
+}
+
+impl PuzzleStyle {
+    pub const ALL: [PuzzleStyle; 4] = [
+        PuzzleStyle::Classic,
+        PuzzleStyle::Snake,
+        PuzzleStyle::Spiral,
+        PuzzleStyle::Maze,
+    ];
+
+    /// how the style is written in puzzle codes and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            PuzzleStyle::Classic => "classic",
+            PuzzleStyle::Snake => "snake",
+            PuzzleStyle::Spiral => "spiral",
+            PuzzleStyle::Maze => "maze",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PuzzleStyle::ALL
+            .into_iter()
+            .find(|style| style.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Whether to take a join that's allowed, given which way the pipe turns where it's joined
+    /// and which way it last turned before that, as the sign of the turn with 0 for straight.
+    /// Classic takes every join without touching the rng, so its puzzles don't change.
+    fn accepts_join(
+        self,
+        joined_length: usize,
+        max_maze_length: usize,
+        turn: Option<i64>,
+        last_turn: Option<i64>,
+        rng: &mut Rng,
+    ) -> bool {
+        match self {
+            PuzzleStyle::Classic => true,
+            PuzzleStyle::Snake => turn != Some(0) || rng.below(4) == 0,
+            PuzzleStyle::Spiral => match (turn, last_turn) {
+                (Some(turn), Some(last_turn)) if turn != 0 && turn != last_turn => {
+                    rng.below(8) == 0
+                }
+                _ => true,
+            },
+            PuzzleStyle::Maze => joined_length <= max_maze_length,
+        }
+    }
+}
+
+/// what to generate besides the size and seed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GenerateOptions {
+    pub style: PuzzleStyle,
+    /// how many colors to aim for, or None for as few as possible
+    pub colors: Option<usize>,
+    /// whether to carve a maze of walls into the board before laying the pipes
+    pub walls: bool,
+    /// how many bridges two pipes have to cross at. There may be fewer if there's no room.
+    pub bridges: usize,
+}
+
+/// How many colors to aim for on boards of up to each number of cells, smallest boards first.
+/// Bigger boards than the last row use its count. It's saved as one `cells<TAB>colors` line per
+/// row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColorTable {
+    pub rows: Vec<(usize, usize)>,
+}
+
+impl Default for ColorTable {
+    /// about one color per row of a square board, the way Flow Free's packs go
+    fn default() -> Self {
+        ColorTable {
+            rows: vec![
+                (25, 5),
+                (36, 6),
+                (49, 7),
+                (64, 8),
+                (81, 9),
+                (100, 10),
+                (121, 11),
+                (144, 12),
+                (196, 14),
+                (225, 15),
+            ],
+        }
+    }
+}
+
+impl ColorTable {
+    /// the count for a board this size, or None if the table is empty
+    pub fn colors_for(&self, width: usize, height: usize) -> Option<usize> {
+        let cells = width * height;
+        self.rows
+            .iter()
+            .find(|&&(max_cells, _)| cells <= max_cells)
+            .or(self.rows.last())
+            .map(|&(_, colors)| colors)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|(cells, colors)| format!("{cells}\t{colors}\n"))
+            .collect()
+    }
+
+    /// reads to_text back, or None if it's been mangled. Rows are put back in order.
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut rows: Vec<(usize, usize)> = text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (cells, colors) = line.split_once('\t')?;
+                Some((cells.parse().ok()?, colors.parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        rows.sort();
+        Some(ColorTable { rows })
+    }
+}
+
+/// what happened to the boards the generator tried, to see why a size is hard to generate
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GenerateStats {
+    /// how many random boards were laid out
+    pub attempts: usize,
+    /// boards thrown out for a pipe too short to be worth solving
+    pub short_pipes: usize,
+    /// boards thrown out for a pipe running straight across the whole board
+    pub straight_pipes: usize,
+    /// boards thrown out for a pipe ending on a bridge
+    pub bridge_ends: usize,
+    /// boards thrown out for a pipe crossing itself at a bridge
+    pub self_crossings: usize,
+    /// boards thrown out for having more than one solution
+    pub not_unique: usize,
+    /// boards thrown out because the solver ran out of moves before it could tell
+    pub too_hard: usize,
+    /// boards with one solution that a player never has to guess on, only kept if there are no
+    /// candidates
+    pub too_easy: usize,
+    /// boards with one solution that need a guess, of which the one with the best color count is
+    /// kept
+    pub candidates: usize,
+    pub wall_time: Duration,
+}
+
+/// a seed to generate from when the player didn't ask for one
+pub fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+        % MAX_NEW_SEED
+}
+
+/// Makes a puzzle of this size with only one solution. The same size and seed always make the
+/// same puzzle. Returns None if none turned up, which gets likely on big boards.
+pub fn generate(width: usize, height: usize, seed: u64) -> Option<FlowGrid> {
+    generate_with(width, height, seed, &GenerateOptions::default())
+}
+
+/// Like generate, but in a style. The same size, seed and options always make the same puzzle.
+pub fn generate_with(
+    width: usize,
+    height: usize,
+    seed: u64,
+    options: &GenerateOptions,
+) -> Option<FlowGrid> {
+    generate_shaped_with(&vec![vec![true; width]; height], seed, options)
+}
+
+/// Like generate, but only fills the cells the mask marks, the way FlowGrid::from_mask reads it.
+/// Returns None if the mask isn't a rectangle or no puzzle turned up, which is certain if some
+/// part of the shape is too small to hold a pipe.
+pub fn generate_shaped(mask: &[Vec<bool>], seed: u64) -> Option<FlowGrid> {
+    generate_shaped_with(mask, seed, &GenerateOptions::default())
+}
+
+pub fn generate_shaped_with(
+    mask: &[Vec<bool>],
+    seed: u64,
+    options: &GenerateOptions,
+) -> Option<FlowGrid> {
+    generate_with_stats(mask, seed, options).0
+}
+
+/// Like generate_shaped_with, and also counts what happened to every board it tried.
+pub fn generate_with_stats(
+    mask: &[Vec<bool>],
+    seed: u64,
+    options: &GenerateOptions,
+) -> (Option<FlowGrid>, GenerateStats) {
+    let start = Instant::now();
+    let mut stats = GenerateStats::default();
+    let mut rng = Rng::new(seed);
+    let mask = if options.walls {
+        carve_walls(mask, &mut rng)
+    } else {
+        mask.to_vec()
+    };
+    let Some(mut template) = FlowGrid::from_mask(&mask) else {
+        return (None, stats);
+    };
+    if options.bridges > 0 {
+        place_bridges(&mut template, options.bridges, &mut rng);
+    }
+    let miss = |grid: &FlowGrid| match options.colors {
+        Some(colors) => grid.num_colors().abs_diff(colors),
+        None => grid.num_colors(),
+    };
+    let mut best: Option<FlowGrid> = None;
+    let mut best_easy: Option<FlowGrid> = None;
+    while stats.attempts < ATTEMPTS
+        && stats.candidates < CANDIDATES
+        && stats.candidates + stats.too_easy < MAX_UNIQUE_BOARDS
+    {
+        stats.attempts += 1;
+        let grid = match random_puzzle(&template, options.style, &mut rng) {
+            Ok(grid) => grid,
+            Err(LayoutProblem::ShortPipe) => {
+                stats.short_pipes += 1;
+                continue;
+            }
+            Err(LayoutProblem::StraightPipe) => {
+                stats.straight_pipes += 1;
+                continue;
+            }
+            Err(LayoutProblem::BridgeEnd) => {
+                stats.bridge_ends += 1;
+                continue;
+            }
+            Err(LayoutProblem::SelfCrossing) => {
+                stats.self_crossings += 1;
+                continue;
+            }
+        };
+        match check_solutions(&grid) {
+            SolutionCheck::One => {}
+            SolutionCheck::More => {
+                stats.not_unique += 1;
+                continue;
+            }
+            SolutionCheck::GaveUp => {
+                stats.too_hard += 1;
+                continue;
+            }
+        }
+        let kept = if is_too_easy(&grid) {
+            stats.too_easy += 1;
+            &mut best_easy
+        } else {
+            stats.candidates += 1;
+            &mut best
+        };
+        // ties go to the board found first
+        if kept.as_ref().is_none_or(|kept| miss(&grid) < miss(kept)) {
+            *kept = Some(grid);
+        }
+    }
+    stats.wall_time = start.elapsed();
+    (best.or(best_easy), stats)
+}
+
+/// the code to share a generated puzzle with, like "7x7 #482913", or
+/// "7x7 #482913 spiral walls 2 bridges 8 colors"
+pub fn puzzle_code(width: usize, height: usize, seed: u64, options: &GenerateOptions) -> String {
+    let mut code = format!("{width}x{height} #{seed}");
+    if options.style != PuzzleStyle::Classic {
+        code.push(' ');
+        code.push_str(options.style.name());
+    }
+    if options.walls {
+        code.push_str(" walls");
+    }
+    match options.bridges {
+        0 => {}
+        1 => code.push_str(" 1 bridge"),
+        bridges => code.push_str(&format!(" {bridges} bridges")),
+    }
+    if let Some(colors) = options.colors {
+        code.push_str(&format!(" {colors} colors"));
+    }
+    code
+}
+
+/// reads a code from puzzle_code back into the width, height, seed and options
+pub fn parse_puzzle_code(code: &str) -> Option<(usize, usize, u64, GenerateOptions)> {
+    let (size, rest) = code.trim().split_once('#')?;
+    let (width, height) = size.trim().split_once('x')?;
+    let mut words = rest.split_whitespace();
+    let seed = words.next()?.parse().ok()?;
+    let mut options = GenerateOptions::default();
+    while let Some(word) = words.next() {
+        match word.parse() {
+            Ok(count) => match words.next() {
+                Some("colors") => options.colors = Some(count),
+                Some("bridge" | "bridges") => options.bridges = count,
+                _ => return None,
+            },
+            Err(_) if word == "walls" => options.walls = true,
+            Err(_) => options.style = PuzzleStyle::from_name(word)?,
+        }
+    }
+    Some((
+        width.trim().parse().ok().filter(|&width| width > 0)?,
+        height.trim().parse().ok().filter(|&height| height > 0)?,
+        seed,
+        options,
+    ))
+}
+
+/// Walls off short straight runs of the mask's cells at random, away from its outer edge, until
+/// about one cell in CELLS_PER_WALL is a wall. A cell is only walled if the open cells around it
+/// stay joined up, so no part of the board gets cut off.
+fn carve_walls(mask: &[Vec<bool>], rng: &mut Rng) -> Vec<Vec<bool>> {
+    let mut mask = mask.to_vec();
+    let height = mask.len();
+    let width = mask.first().map_or(0, Vec::len);
+    if width < 3 || height < 3 {
+        return mask;
+    }
+    let open_cells = mask.iter().flatten().filter(|&&is_open| is_open).count();
+    let mut walls_left = open_cells / CELLS_PER_WALL;
+    for _ in 0..width * height * 10 {
+        if walls_left == 0 {
+            break;
+        }
+        let (mut row, mut col) = (1 + rng.below(height - 2), 1 + rng.below(width - 2));
+        let (row_step, col_step) = [(-1, 0), (1, 0), (0, -1), (0, 1)][rng.below(4)];
+        for _ in 0..1 + rng.below(MAX_WALL_RUN) {
+            if walls_left == 0 || row == 0 || col == 0 || row + 1 >= height || col + 1 >= width {
+                break;
+            }
+            if mask[row][col] {
+                mask[row][col] = false;
+                if is_in_one_piece(&mask) {
+                    walls_left -= 1;
+                } else {
+                    mask[row][col] = true;
+                    break;
+                }
+            }
+            row = row.saturating_add_signed(row_step);
+            col = col.saturating_add_signed(col_step);
+        }
+    }
+    mask
+}
+
+/// Turns up to this many open cells into bridges, picked at random from the ones away from the
+/// edge whose four neighbors are open and not bridges themselves.
+fn place_bridges(template: &mut FlowGrid, count: usize, rng: &mut Rng) {
+    let is_open = |grid: &FlowGrid, row: usize, col: usize| {
+        grid.get(row, col)
+            .is_some_and(|cell| !cell.is_blocked && !cell.is_bridge())
+    };
+    for _ in 0..count {
+        let spots: Vec<(usize, usize)> = (1..template.height.saturating_sub(1))
+            .flat_map(|row| (1..template.width.saturating_sub(1)).map(move |col| (row, col)))
+            .filter(|&(row, col)| {
+                is_open(template, row, col)
+                    && is_open(template, row - 1, col)
+                    && is_open(template, row + 1, col)
+                    && is_open(template, row, col - 1)
+                    && is_open(template, row, col + 1)
+            })
+            .collect();
+        if spots.is_empty() {
+            return;
+        }
+        let (row, col) = spots[rng.below(spots.len())];
+        template.try_add_bridge(row, col);
+    }
+}
+
+/// whether every open cell of the mask can reach every other one through open cells
+fn is_in_one_piece(mask: &[Vec<bool>]) -> bool {
+    let cells: Vec<(usize, usize)> = (0..mask.len())
+        .flat_map(|row| (0..mask[row].len()).map(move |col| (row, col)))
+        .filter(|&(row, col)| mask[row][col])
+        .collect();
+    let Some(&start) = cells.first() else {
+        return true;
+    };
+    let mut is_reached = vec![vec![false; mask[0].len()]; mask.len()];
+    is_reached[start.0][start.1] = true;
+    let mut to_visit = vec![start];
+    let mut reached = 1;
+    while let Some((row, col)) = to_visit.pop() {
+        let neighbors = [
+            (row.checked_sub(1), Some(col)),
+            (Some(row + 1), Some(col)),
+            (Some(row), col.checked_sub(1)),
+            (Some(row), Some(col + 1)),
+        ];
+        for (row, col) in neighbors {
+            let (Some(row), Some(col)) = (row, col) else {
+                continue;
+            };
+            if mask.get(row).and_then(|line| line.get(col)) == Some(&true) && !is_reached[row][col]
+            {
+                is_reached[row][col] = true;
+                reached += 1;
+                to_visit.push((row, col));
+            }
+        }
+    }
+    reached == cells.len()
+}
+
+/// Covers the template's open cells with random pipes and keeps their ends, unless some pipe came
+/// out too short, runs straight across the whole board, or doesn't use a bridge properly.
+fn random_puzzle(
+    template: &FlowGrid,
+    style: PuzzleStyle,
+    rng: &mut Rng,
+) -> Result<FlowGrid, LayoutProblem> {
+    let (width, height) = (template.width, template.height);
+    let cell_count = width * height;
+    let is_open: Vec<bool> = template.mask().into_iter().flatten().collect();
+    let is_bridge: Vec<bool> = (0..cell_count)
+        .map(|index| {
+            template
+                .get(index / width, index % width)
+                .is_some_and(|cell| cell.is_bridge())
+        })
+        .collect();
+    let pipes = random_pipes(width, height, &is_open, &is_bridge, style, rng);
+    if pipes.iter().any(|pipe| pipe.len() < MIN_PIPE_LENGTH) {
+        return Err(LayoutProblem::ShortPipe);
+    }
+    let is_across = |pipe: &Vec<usize>| {
+        let cells = || pipe.iter().map(|&node| node % cell_count);
+        (pipe.len() == width && cells().all(|index| index / width == pipe[0] % cell_count / width))
+            || (pipe.len() == height && cells().all(|index| index % width == pipe[0] % width))
+    };
+    if pipes.iter().any(is_across) {
+        return Err(LayoutProblem::StraightPipe);
+    }
+    let is_on_bridge = |node: usize| node >= cell_count || is_bridge[node];
+    if pipes
+        .iter()
+        .any(|pipe| is_on_bridge(pipe[0]) || is_on_bridge(pipe[pipe.len() - 1]))
+    {
+        return Err(LayoutProblem::BridgeEnd);
+    }
+    let crosses_itself = |pipe: &Vec<usize>| {
+        pipe.iter()
+            .any(|&node| node >= cell_count && pipe.contains(&(node - cell_count)))
+    };
+    if pipes.iter().any(crosses_itself) {
+        return Err(LayoutProblem::SelfCrossing);
+    }
+
+    let mut ends: Vec<(usize, usize)> = pipes
+        .iter()
+        .map(|pipe| {
+            let (first, last) = (pipe[0], pipe[pipe.len() - 1]);
+            (first.min(last), first.max(last))
+        })
+        .collect();
+    // colors go in reading order, like a hand-made board
+    ends.sort();
+    let mut grid = template.clone();
+    for (color_id, (start, end)) in ends.into_iter().enumerate() {
+        for index in [start, end] {
+            grid.try_set_missing_source(index / width, index % width, color_id);
+        }
+    }
+    Ok(grid)
+}
+
+/// Starts with every open cell as its own pipe, then joins the ends of neighboring pipes at
+/// random until they mostly can't be joined any more, taking the joins the style likes. Each pipe
+/// is a list of nodes from one end to the other, numbered like FlowGrid's: a cell's index, or
+/// for the up and down lane of a bridge, the number of cells plus its index. A bridge's own index
+/// is its left and right lane.
+fn random_pipes(
+    width: usize,
+    height: usize,
+    is_open: &[bool],
+    is_bridge: &[bool],
+    style: PuzzleStyle,
+    rng: &mut Rng,
+) -> Vec<Vec<usize>> {
+    let cell_count = width * height;
+    let max_maze_length = ((width + height) / 2).max(MIN_PIPE_LENGTH + 1);
+    let is_open_node = |node: usize| {
+        if node < cell_count {
+            is_open[node]
+        } else {
+            is_bridge[node - cell_count]
+        }
+    };
+    // nodes are only picked from the first half unless there are bridges, so boards without
+    // them don't change
+    let picked_nodes = if is_bridge.contains(&true) {
+        2 * cell_count
+    } else {
+        cell_count
+    };
+    // closed nodes get no pipe, but keep their own index as an owner no pipe will ever have
+    let mut pipes: Vec<Vec<usize>> = (0..2 * cell_count)
+        .map(|node| {
+            if is_open_node(node) {
+                vec![node]
+            } else {
+                Vec::new()
+            }
+        })
+        .collect();
+    let mut owners: Vec<usize> = (0..2 * cell_count).collect();
+
+    let is_end = |pipe: &[usize], index: usize| pipe[0] == index || pipe[pipe.len() - 1] == index;
+    for _ in 0..cell_count * 100 {
+        // pipes too short to keep get joined onto something first
+        let short_ends: Vec<usize> = pipes
+            .iter()
+            .filter(|pipe| !pipe.is_empty() && pipe.len() < MIN_PIPE_LENGTH)
+            .flat_map(|pipe| [pipe[0], pipe[pipe.len() - 1]])
+            .collect();
+        let index = if short_ends.is_empty() {
+            rng.below(picked_nodes)
+        } else {
+            short_ends[rng.below(short_ends.len())]
+        };
+        let cell = index % cell_count;
+        let (row, col) = (cell / width, cell % width);
+        let direction = rng.below(4);
+        let neighbor_cell = match direction {
+            0 if row > 0 => cell - width,
+            1 if row + 1 < height => cell + width,
+            2 if col > 0 => cell - 1,
+            3 if col + 1 < width => cell + 1,
+            _ => continue,
+        };
+        let is_vertical = direction < 2;
+        // a bridge's lanes only lead their own way
+        if is_bridge[cell] && (index >= cell_count) != is_vertical {
+            continue;
+        }
+        let neighbor = if is_bridge[neighbor_cell] && is_vertical {
+            neighbor_cell + cell_count
+        } else {
+            neighbor_cell
+        };
+        if !is_open_node(index) || !is_open_node(neighbor) {
+            continue;
+        }
+        let (owner, neighbor_owner) = (owners[index], owners[neighbor]);
+        if owner == neighbor_owner
+            || !is_end(&pipes[owner], index)
+            || !is_end(&pipes[neighbor_owner], neighbor)
+            || would_fill_square(&owners, width, height, owner, &pipes[neighbor_owner])
+        {
+            continue;
+        }
+        if style != PuzzleStyle::Classic {
+            // the pipe's cells walking back from the end being joined
+            let pipe = &pipes[owner];
+            let back: Vec<usize> = if pipe[pipe.len() - 1] == index {
+                pipe.iter().rev().copied().collect()
+            } else {
+                pipe.clone()
+            };
+            let cell_turn = |from: usize, middle: usize, to: usize| {
+                turn(
+                    width,
+                    from % cell_count,
+                    middle % cell_count,
+                    to % cell_count,
+                )
+            };
+            let join_turn = back
+                .get(1)
+                .map(|&previous| cell_turn(previous, index, neighbor));
+            let last_turn = back
+                .windows(3)
+                .map(|nodes| cell_turn(nodes[2], nodes[1], nodes[0]))
+                .find(|&turn| turn != 0);
+            let joined_length = pipe.len() + pipes[neighbor_owner].len();
+            if !style.accepts_join(joined_length, max_maze_length, join_turn, last_turn, rng) {
+                continue;
+            }
+        }
+
+        let mut pipe = std::mem::take(&mut pipes[owner]);
+        let mut neighbor_pipe = std::mem::take(&mut pipes[neighbor_owner]);
+        if pipe[pipe.len() - 1] != index {
+            pipe.reverse();
+        }
+        if neighbor_pipe[0] != neighbor {
+            neighbor_pipe.reverse();
+        }
+        for &cell in &neighbor_pipe {
+            owners[cell] = owner;
+        }
+        pipe.extend(neighbor_pipe);
+        pipes[owner] = pipe;
+    }
+
+    pipes.into_iter().filter(|pipe| !pipe.is_empty()).collect()
+}
+
+/// Which way a path through three touching cells turns at the middle one: 0 if it goes
+/// straight, and 1 or -1 for the two ways it can turn.
+fn turn(width: usize, from: usize, middle: usize, to: usize) -> i64 {
+    let position = |index: usize| ((index / width) as i64, (index % width) as i64);
+    let ((from_row, from_col), (row, col), (to_row, to_col)) =
+        (position(from), position(middle), position(to));
+    ((row - from_row) * (to_col - col) - (col - from_col) * (to_row - row)).signum()
+}
+
+/// Whether joining the other pipe onto this owner's would leave the joined pipe covering a 2x2
+/// square. Neither covers one yet, so any such square has a cell of the other pipe in it.
+fn would_fill_square(
+    owners: &[usize],
+    width: usize,
+    height: usize,
+    owner: usize,
+    other_pipe: &[usize],
+) -> bool {
+    let other_owner = owners[other_pipe[0]];
+    let is_joined = |row: usize, col: usize| {
+        let cell_owner = owners[row * width + col];
+        cell_owner == owner || cell_owner == other_owner
+    };
+    other_pipe.iter().any(|&node| {
+        let index = node % (width * height);
+        let (row, col) = (index / width, index % width);
+        // the squares this cell is the bottom right, bottom left, top right or top left of
+        [
+            (row.checked_sub(1), col.checked_sub(1)),
+            (row.checked_sub(1), Some(col)),
+            (Some(row), col.checked_sub(1)),
+            (Some(row), Some(col)),
+        ]
+        .into_iter()
+        .any(|corner| match corner {
+            (Some(top), Some(left)) if top + 1 < height && left + 1 < width => {
+                is_joined(top, left)
+                    && is_joined(top, left + 1)
+                    && is_joined(top + 1, left)
+                    && is_joined(top + 1, left + 1)
+            }
+            _ => false,
+        })
+    })
+}
+
+/// Whether forced connections alone fill in the board, so a player never has to guess. It's
+/// the same as flow_solver::difficulty finding no guesses, without solving the board again.
+fn is_too_easy(grid: &FlowGrid) -> bool {
+    let mut board = grid.clone();
+    board.apply_forced_moves();
+    board.is_solved()
+}
+
+enum SolutionCheck {
+    One,
+    /// more than one, or none, though a board laid out from pipes always has at least one
+    More,
+    GaveUp,
+}
+
+/// Whether the solver finds exactly one solution within the node limit. Boards with bridges are
+/// counted instead, since the solver can't route through them.
+fn check_solutions(grid: &FlowGrid) -> SolutionCheck {
+    if grid.has_bridges() {
+        return match count_solutions_exact(grid) {
+            1 => SolutionCheck::One,
+            _ => SolutionCheck::More,
+        };
+    }
+    let mut search = flow_solver::solutions(grid);
+    let mut solutions = 0;
+    while search.progress().nodes_explored < NODE_LIMIT {
+        match search.step() {
+            Some(SolveEvent::Solved) => solutions += 1,
+            Some(_) => {}
+            None if solutions == 1 => return SolutionCheck::One,
+            None => return SolutionCheck::More,
+        }
+        if solutions > 1 {
+            return SolutionCheck::More;
+        }
+    }
+    SolutionCheck::GaveUp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_count::count_solutions_exact;
+
+    #[test]
+    fn generated_puzzles_have_one_solution() {
+        for (width, height, seed) in [(5, 5, 1), (6, 6, 2), (7, 5, 3)] {
+            let grid = generate(width, height, seed).expect("small boards are easy to generate");
+            assert_eq!((grid.width, grid.height), (width, height));
+            assert_eq!(count_solutions_exact(&grid), 1, "{}", grid.to_dot());
+        }
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_puzzle() {
+        let (width, height, seed, _) =
+            parse_puzzle_code(&puzzle_code(6, 6, 482913, &GenerateOptions::default()))
+                .expect("valid code");
+        let first = generate(width, height, seed).expect("small boards are easy to generate");
+        let second = generate(6, 6, 482913).expect("small boards are easy to generate");
+        assert_eq!(first.to_dot(), second.to_dot());
+        let other = generate(6, 6, 482914).expect("small boards are easy to generate");
+        assert_ne!(first.to_dot(), other.to_dot());
+    }
+
+    #[test]
+    fn shaped_puzzles_stay_inside_the_mask() {
+        let mask = FlowGrid::parse_mask("###...\n###...\n###...\n......\n......\n......")
+            .expect("the L has cells");
+        let grid = generate_shaped(&mask, 7).expect("small boards are easy to generate");
+        assert_eq!(grid.mask(), mask);
+        let off_board = grid.off_board_cells();
+        assert_eq!(off_board.iter().filter(|&&is_off| is_off).count(), 9);
+        assert_eq!(count_solutions_exact(&grid), 1, "{}", grid.to_dot());
+    }
+
+    #[test]
+    fn styled_puzzles_have_one_solution() {
+        for style in PuzzleStyle::ALL {
+            let options = GenerateOptions {
+                style,
+                ..GenerateOptions::default()
+            };
+            let grid = generate_with(6, 6, 5, &options).expect("small boards are easy to generate");
+            assert_eq!(
+                count_solutions_exact(&grid),
+                1,
+                "{style:?} {}",
+                grid.to_dot()
+            );
+        }
+        // mazes are held to short pipes, so they need more colors
+        let maze = GenerateOptions {
+            style: PuzzleStyle::Maze,
+            ..GenerateOptions::default()
+        };
+        let classic = generate(8, 8, 3).expect("small boards are easy to generate");
+        let maze = generate_with(8, 8, 3, &maze).expect("small boards are easy to generate");
+        assert!(maze.num_colors() > classic.num_colors());
+    }
+
+    #[test]
+    fn puzzle_codes_round_trip() {
+        let classic = GenerateOptions::default();
+        let spiral = GenerateOptions {
+            style: PuzzleStyle::Spiral,
+            colors: Some(8),
+            walls: false,
+            bridges: 0,
+        };
+        let walled = GenerateOptions {
+            walls: true,
+            bridges: 2,
+            ..spiral
+        };
+        assert_eq!(puzzle_code(7, 7, 482913, &classic), "7x7 #482913");
+        assert_eq!(
+            puzzle_code(7, 7, 482913, &spiral),
+            "7x7 #482913 spiral 8 colors"
+        );
+        assert_eq!(
+            parse_puzzle_code(&puzzle_code(7, 7, 482913, &spiral)),
+            Some((7, 7, 482913, spiral))
+        );
+        assert_eq!(
+            puzzle_code(7, 7, 482913, &walled),
+            "7x7 #482913 spiral walls 2 bridges 8 colors"
+        );
+        assert_eq!(
+            parse_puzzle_code(&puzzle_code(7, 7, 482913, &walled)),
+            Some((7, 7, 482913, walled))
+        );
+        assert_eq!(
+            parse_puzzle_code("7x7 #482913"),
+            Some((7, 7, 482913, classic))
+        );
+        assert_eq!(parse_puzzle_code(" 8 x 5 # 12 "), Some((8, 5, 12, classic)));
+        assert_eq!(parse_puzzle_code("7x7"), None);
+        assert_eq!(parse_puzzle_code("0x7 #1"), None);
+        assert_eq!(parse_puzzle_code("7x7 #1 wavy"), None);
+        assert_eq!(parse_puzzle_code("7x7 #1 8"), None);
+    }
+
+    #[test]
+    fn stats_account_for_every_attempt() {
+        let mask = vec![vec![true; 7]; 7];
+        let (grid, stats) = generate_with_stats(&mask, 11, &GenerateOptions::default());
+        assert_eq!(
+            grid.map(|grid| grid.to_dot()),
+            generate(7, 7, 11).map(|grid| grid.to_dot())
+        );
+        assert_eq!(
+            stats.attempts,
+            stats.short_pipes
+                + stats.straight_pipes
+                + stats.bridge_ends
+                + stats.self_crossings
+                + stats.not_unique
+                + stats.too_hard
+                + stats.too_easy
+                + stats.candidates
+        );
+        assert!(stats.candidates > 0);
+    }
+
+    #[test]
+    fn walled_puzzles_have_walls_inside_and_one_solution() {
+        let options = GenerateOptions {
+            walls: true,
+            ..GenerateOptions::default()
+        };
+        let grid = generate_with(7, 7, 9, &options).expect("small boards are easy to generate");
+        let mask = grid.mask();
+        let walls = mask.iter().flatten().filter(|&&is_open| !is_open).count();
+        assert!(walls > 0);
+        assert!(mask[0].iter().chain(&mask[6]).all(|&is_open| is_open));
+        assert!(is_in_one_piece(&mask));
+        assert_eq!(count_solutions_exact(&grid), 1, "{}", grid.to_dot());
+        assert_eq!(
+            generate_with(7, 7, 9, &options).map(|grid| grid.to_dot()),
+            Some(grid.to_dot())
+        );
+    }
+
+    #[test]
+    fn bridged_puzzles_need_two_pipes_to_cross() {
+        let options = GenerateOptions {
+            bridges: 1,
+            ..GenerateOptions::default()
+        };
+        let grid = generate_with(6, 6, 2, &options).expect("small boards are easy to generate");
+        assert!(grid.has_bridges());
+        assert_eq!(count_solutions_exact(&grid), 1, "{}", grid.to_dot());
+        assert_eq!(
+            parse_puzzle_code(&puzzle_code(6, 6, 2, &options)),
+            Some((6, 6, 2, options))
+        );
+    }
+
+    #[test]
+    fn boards_without_a_guess_are_too_easy() {
+        let corridor = FlowGrid::from_board(&["A...A"]).expect("a valid board");
+        assert!(is_too_easy(&corridor));
+        let open = FlowGrid::from_board(&["A..", "...", "..A"]).expect("a valid board");
+        assert!(!is_too_easy(&open));
+    }
+
+    #[test]
+    fn color_counts_aim_for_the_table() {
+        let table = ColorTable::default();
+        assert_eq!(table.colors_for(5, 5), Some(5));
+        assert_eq!(table.colors_for(7, 6), Some(7));
+        assert_eq!(table.colors_for(30, 30), Some(15));
+        assert_eq!(ColorTable { rows: Vec::new() }.colors_for(5, 5), None);
+        assert_eq!(ColorTable::from_text(&table.to_text()), Some(table));
+        assert_eq!(ColorTable::from_text("25 5"), None);
+
+        let fewest = generate(8, 8, 4).expect("small boards are easy to generate");
+        let options = GenerateOptions {
+            colors: Some(fewest.num_colors() + 3),
+            ..GenerateOptions::default()
+        };
+        let more = generate_with(8, 8, 4, &options).expect("small boards are easy to generate");
+        assert!(more.num_colors() > fewest.num_colors());
+    }
+}
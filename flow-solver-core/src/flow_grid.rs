@@ -0,0 +1,2662 @@
+/// This file handles the core data model, abstracted away from any specific UI. you can ask for
+/// various actions, and this will do validation and perform them.
+use crate::flow_rules::{AllCellsFilled, WinCondition};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How many events the history keeps before folding them into its base. Grids are cloned for
+/// undo and compared every frame, so the history can't grow for as long as a board is played.
+const MAX_HISTORY_LEN: usize = 1000;
+
+/// hands out generations, so no two changes to any grids share one
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Saves as a SavedGrid, and loads by rebuilding everything else from it.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "SavedGrid", try_from = "SavedGrid")]
+pub struct FlowGrid {
+    next_color_id: usize,
+    cells: Vec<FlowCell>,
+    /// which piece of pipe each node belongs to, so connectivity checks are a lookup. A piece is
+    /// labeled with one of its nodes, which keeps labels unique without a counter.
+    components: Vec<usize>,
+    pub width: usize,
+    pub height: usize,
+    /// whether cells are hexagons, with the two extra neighbors Direction describes
+    is_hex: bool,
+    /// whether each edge is joined to the one opposite it, so pipes can leave the board on one
+    /// side and come back on the other
+    wraps: bool,
+    /// the cell index of every source of each color, in the order they were placed. Most colors
+    /// have two, but a color can have more, and then they all have to join up into one tree.
+    source_index: Vec<Vec<usize>>,
+    /// what is_solved checks for
+    win_condition: Arc<dyn WinCondition + Send + Sync>,
+    /// how many cells are filled, kept up to date by apply so progress can be shown every frame
+    /// without counting
+    num_filled: usize,
+    /// the size the grid started at, which is where the history replays from
+    history_start: (usize, usize),
+    /// the grid as it was before the oldest event in the history, once older events have been
+    /// folded into it. None while the history goes all the way back to a blank grid.
+    history_base: Option<Arc<SavedGrid>>,
+    /// how many events were folded into the base
+    events_folded: usize,
+    /// every event applied to the grid since the base, oldest first
+    history: Vec<GridEvent>,
+    /// New with every change, and kept by clones, so a grid with the same generation as another
+    /// is a copy of it that hasn't been changed since. Comparing this is how a change gets
+    /// noticed every frame without comparing histories.
+    generation: u64,
+}
+
+/// What gets saved of a grid. The pieces and the source index are worked out from the cells
+/// again on load. The win condition isn't saved, so a loaded grid goes back to filling the board.
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedGrid {
+    width: usize,
+    height: usize,
+    #[serde(default)]
+    is_hex: bool,
+    #[serde(default)]
+    wraps: bool,
+    next_color_id: usize,
+    cells: Vec<FlowCell>,
+    history_start: (usize, usize),
+    #[serde(default)]
+    history_base: Option<Box<SavedGrid>>,
+    #[serde(default)]
+    events_folded: usize,
+    history: Vec<GridEvent>,
+}
+
+impl From<FlowGrid> for SavedGrid {
+    fn from(grid: FlowGrid) -> Self {
+        SavedGrid {
+            width: grid.width,
+            height: grid.height,
+            is_hex: grid.is_hex,
+            wraps: grid.wraps,
+            next_color_id: grid.next_color_id,
+            cells: grid.cells,
+            history_start: grid.history_start,
+            history_base: grid
+                .history_base
+                .map(|base| Box::new(Arc::unwrap_or_clone(base))),
+            events_folded: grid.events_folded,
+            history: grid.history,
+        }
+    }
+}
+
+impl TryFrom<SavedGrid> for FlowGrid {
+    type Error = String;
+
+    fn try_from(saved: SavedGrid) -> Result<Self, Self::Error> {
+        if saved.cells.len() != saved.width * saved.height {
+            return Err(format!(
+                "{} cells don't make a {} x {} grid",
+                saved.cells.len(),
+                saved.width,
+                saved.height
+            ));
+        }
+        let mut grid = FlowGrid::with_size(saved.width, saved.height);
+        grid.is_hex = saved.is_hex;
+        grid.wraps = saved.wraps;
+        grid.next_color_id = saved.next_color_id;
+        grid.cells = saved.cells;
+        grid.history_start = saved.history_start;
+        grid.history_base = saved.history_base.map(|base| Arc::new(*base));
+        grid.events_folded = saved.events_folded;
+        grid.history = saved.history;
+        grid.relabel_components();
+        grid.num_filled = grid.count_filled();
+        // the index starts out empty, so this lists every source in it
+        grid.audit_sources();
+        Ok(grid)
+    }
+}
+
+/// One change to the grid's state. Edits validate first and then apply these through
+/// FlowGrid::apply, which is the only thing that changes the cells, so the history is enough to
+/// rebuild the grid and anything that follows along (undo, saving, another player) can read one
+/// stream of events instead of hooking into every edit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GridEvent {
+    Connect {
+        row: usize,
+        col: usize,
+        direction: Direction,
+    },
+    Disconnect {
+        row: usize,
+        col: usize,
+        direction: Direction,
+    },
+    AddSource {
+        row: usize,
+        col: usize,
+        color_id: usize,
+    },
+    /// the cell keeps its color until Recolor events follow
+    RemoveSource {
+        row: usize,
+        col: usize,
+    },
+    Recolor {
+        row: usize,
+        col: usize,
+        color: CellColor,
+    },
+    /// only empty, unconnected cells that aren't sources become bridges
+    AddBridge {
+        row: usize,
+        col: usize,
+    },
+    RemoveBridge {
+        row: usize,
+        col: usize,
+    },
+    /// recolors the vertical lane of a bridge. Recolor does the horizontal one.
+    RecolorBridge {
+        row: usize,
+        col: usize,
+        color: CellColor,
+    },
+    /// only empty, unconnected cells that aren't sources or bridges get blocked
+    Block {
+        row: usize,
+        col: usize,
+    },
+    Unblock {
+        row: usize,
+        col: usize,
+    },
+    /// links two empty, unconnected cells as a pair of warps
+    AddWarp {
+        row: usize,
+        col: usize,
+        to_row: usize,
+        to_col: usize,
+    },
+    /// unlinks both warps of a pair, once the link between them is disconnected
+    RemoveWarp {
+        row: usize,
+        col: usize,
+    },
+    AddRow,
+    RemoveRow,
+    AddCol,
+    RemoveCol,
+}
+
+/// The ways out of a cell. A square grid only uses the first four. A hex grid is stored the same
+/// way, row by row, but each row sits half a cell further right than the one above it, so a cell
+/// also borders the cell up and to the right of it and the one down and to the left. On a hex
+/// grid Up and Down lead up-left and down-right. Warp isn't a side of the cell at all: it's the
+/// link from a warp cell to its partner, wherever that is on the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpRight,
+    DownLeft,
+    Warp,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 7] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::Warp,
+    ];
+
+    pub fn is_vertical(&self) -> bool {
+        matches!(self, Direction::Up | Direction::Down)
+    }
+
+    pub fn opposite(&self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::Warp => Direction::Warp,
+        }
+    }
+
+    /// U, D, L or R, the way move lists and the corpus write it. The hex grid's extra directions
+    /// are 9 and 1, where they sit on a number pad, and going through a warp is W.
+    pub fn letter(&self) -> char {
+        match self {
+            Direction::Up => 'U',
+            Direction::Down => 'D',
+            Direction::Left => 'L',
+            Direction::Right => 'R',
+            Direction::UpRight => '9',
+            Direction::DownLeft => '1',
+            Direction::Warp => 'W',
+        }
+    }
+
+    pub fn try_from_adjacent(
+        row_from: usize,
+        col_from: usize,
+        row_to: usize,
+        col_to: usize,
+    ) -> Option<Self> {
+        if row_from == row_to {
+            if col_from + 1 == col_to {
+                Some(Direction::Right)
+            } else if col_from == col_to + 1 {
+                Some(Direction::Left)
+            } else {
+                None
+            }
+        } else if col_from == col_to {
+            if row_from + 1 == row_to {
+                Some(Direction::Down)
+            } else if row_from == row_to + 1 {
+                Some(Direction::Up)
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellColor {
+    Empty(usize),
+    Colored(usize),
+}
+
+impl CellColor {
+    pub fn can_colors_connect(color1: &CellColor, color2: &CellColor) -> bool {
+        match (color1, color2) {
+            (CellColor::Colored(id1), CellColor::Colored(id2)) => id1 == id2,
+            _ => true,
+        }
+    }
+}
+
+/// the (row, col) of both cells of a pair of warps
+pub type WarpPair = ((usize, usize), (usize, usize));
+
+/// a single connection between a cell and its neighbor in the given direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub row: usize,
+    pub col: usize,
+    pub direction: Direction,
+}
+
+/// A bridge is a cell two pipes can cross: one going left to right and one going up and down.
+/// Each of those lanes is colored on its own, `color` for the horizontal one and `bridge_color`
+/// for the vertical one, and the rest of the grid treats them as two separate cells, called
+/// nodes. A node is the cell's index, or for the vertical lane of a bridge, the number of cells
+/// plus the index.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct FlowCell {
+    pub color: CellColor,
+    /// the vertical lane's color, if this cell is a bridge
+    #[serde(default)]
+    pub bridge_color: Option<CellColor>,
+    /// a hole in the board, which can't hold a source or a pipe and doesn't need filling
+    #[serde(default)]
+    pub is_blocked: bool,
+    pub is_source: bool,
+    pub is_connected_up: bool,
+    pub is_connected_down: bool,
+    pub is_connected_left: bool,
+    pub is_connected_right: bool,
+    #[serde(default)]
+    pub is_connected_up_right: bool,
+    #[serde(default)]
+    pub is_connected_down_left: bool,
+    /// the other cell of the pair, if this cell is a warp. A pipe that goes into a warp comes
+    /// out of its partner.
+    #[serde(default)]
+    pub warp_to: Option<(usize, usize)>,
+    #[serde(default)]
+    pub is_connected_warp: bool,
+}
+
+impl FlowCell {
+    pub fn empty_with_id(empty_index: usize) -> Self {
+        FlowCell {
+            color: CellColor::Empty(empty_index),
+            bridge_color: None,
+            is_blocked: false,
+            is_source: false,
+            is_connected_up: false,
+            is_connected_down: false,
+            is_connected_left: false,
+            is_connected_right: false,
+            is_connected_up_right: false,
+            is_connected_down_left: false,
+            warp_to: None,
+            is_connected_warp: false,
+        }
+    }
+    pub fn is_direction_connected(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Up => self.is_connected_up,
+            Direction::Down => self.is_connected_down,
+            Direction::Left => self.is_connected_left,
+            Direction::Right => self.is_connected_right,
+            Direction::UpRight => self.is_connected_up_right,
+            Direction::DownLeft => self.is_connected_down_left,
+            Direction::Warp => self.is_connected_warp,
+        }
+    }
+
+    fn add_connection(&mut self, direction: Direction) {
+        match direction {
+            Direction::Up => self.is_connected_up = true,
+            Direction::Down => self.is_connected_down = true,
+            Direction::Left => self.is_connected_left = true,
+            Direction::Right => self.is_connected_right = true,
+            Direction::UpRight => self.is_connected_up_right = true,
+            Direction::DownLeft => self.is_connected_down_left = true,
+            Direction::Warp => self.is_connected_warp = true,
+        }
+    }
+
+    fn remove_connection(&mut self, direction: Direction) {
+        match direction {
+            Direction::Up => self.is_connected_up = false,
+            Direction::Down => self.is_connected_down = false,
+            Direction::Left => self.is_connected_left = false,
+            Direction::Right => self.is_connected_right = false,
+            Direction::UpRight => self.is_connected_up_right = false,
+            Direction::DownLeft => self.is_connected_down_left = false,
+            Direction::Warp => self.is_connected_warp = false,
+        }
+    }
+
+    pub fn num_connections(&self) -> usize {
+        Direction::ALL
+            .into_iter()
+            .filter(|&direction| self.is_direction_connected(direction))
+            .count()
+    }
+
+    pub fn has_open_connections(&self) -> bool {
+        if self.is_blocked || self.num_connections() >= 2 {
+            return false;
+        }
+        if self.is_source && self.num_connections() >= 1 {
+            return false;
+        }
+        true
+    }
+
+    pub fn is_bridge(&self) -> bool {
+        self.bridge_color.is_some()
+    }
+
+    pub fn is_warp(&self) -> bool {
+        self.warp_to.is_some()
+    }
+
+    /// the color of the pipe that leaves the cell in this direction
+    pub fn lane_color(&self, direction: Direction) -> CellColor {
+        match self.bridge_color {
+            Some(bridge_color) if direction.is_vertical() => bridge_color,
+            _ => self.color,
+        }
+    }
+
+    /// the connections of the lane this direction is part of, which is all of them unless the
+    /// cell is a bridge
+    pub fn lane_connections(&self, direction: Direction) -> usize {
+        if self.is_bridge() {
+            [direction, direction.opposite()]
+                .into_iter()
+                .filter(|&lane_direction| self.is_direction_connected(lane_direction))
+                .count()
+        } else {
+            self.num_connections()
+        }
+    }
+
+    /// whether the pipe that would leave in this direction can take another connection. A
+    /// bridge's lanes fill up separately.
+    pub fn is_lane_open(&self, direction: Direction) -> bool {
+        if self.is_bridge() {
+            self.lane_connections(direction) < 2
+        } else if self.is_warp() && direction != Direction::Warp {
+            // a pipe that comes into a warp has to go through it, so only one end can be here
+            self.has_open_connections() && self.num_connections() == self.is_connected_warp as usize
+        } else {
+            self.has_open_connections()
+        }
+    }
+
+    /// whether every lane of the cell has a color. Blocked cells never need one.
+    pub fn is_filled(&self) -> bool {
+        self.is_blocked
+            || (matches!(self.color, CellColor::Colored(_))
+                && !matches!(self.bridge_color, Some(CellColor::Empty(_))))
+    }
+}
+
+impl FlowGrid {
+    pub fn with_size(width: usize, height: usize) -> Self {
+        let mut cells = Vec::with_capacity(width * height);
+        for i in 0..(width * height) {
+            cells.push(FlowCell::empty_with_id(i));
+        }
+        FlowGrid {
+            next_color_id: 0,
+            cells,
+            components: (0..(2 * width * height)).collect(),
+            width,
+            height,
+            is_hex: false,
+            wraps: false,
+            source_index: Vec::new(),
+            win_condition: Arc::new(AllCellsFilled),
+            num_filled: 0,
+            history_start: (width, height),
+            history_base: None,
+            events_folded: 0,
+            history: Vec::new(),
+            generation: next_generation(),
+        }
+    }
+
+    /// an empty hex grid, which has the same number of cells as a square one of this size
+    pub fn hex_with_size(width: usize, height: usize) -> Self {
+        FlowGrid::with_size(width, height).hexagonal()
+    }
+
+    /// The same grid with hexagonal cells, like hex_with_size makes. It's meant for new grids,
+    /// like wrapping, since it changes which cells are neighbors.
+    pub fn hexagonal(mut self) -> Self {
+        self.is_hex = true;
+        self
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.is_hex
+    }
+
+    /// The same grid on a torus, with each edge joined to the one opposite it. It's meant for new
+    /// grids, since it changes which cells are neighbors. Edges only join up along a side that's
+    /// at least three cells long, so two cells are never neighbors twice over.
+    pub fn wrapping(mut self) -> Self {
+        self.wraps = true;
+        self
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wraps
+    }
+
+    /// Builds a puzzle from one string per row, where letters are sources, '.' is an empty cell,
+    /// '+' is a bridge and '#' is blocked. Colors are numbered in the order their first source
+    /// appears, reading row by row. A letter that appears more than twice is a color whose
+    /// sources all join into a tree. Returns None if the rows aren't all the same length or a
+    /// letter appears only once.
+    pub fn from_board(rows: &[&str]) -> Option<FlowGrid> {
+        let width = rows.first()?.chars().count();
+        if width == 0 || rows.iter().any(|row| row.chars().count() != width) {
+            return None;
+        }
+        let mut grid = FlowGrid::with_size(width, rows.len());
+        let mut letters: Vec<(char, usize)> = Vec::new();
+        for (row, line) in rows.iter().enumerate() {
+            for (col, letter) in line.chars().enumerate() {
+                if letter == '.' {
+                    continue;
+                }
+                if letter == '+' {
+                    grid.try_add_bridge(row, col);
+                    continue;
+                }
+                if letter == '#' {
+                    grid.try_block(row, col);
+                    continue;
+                }
+                let color_id = match letters.iter().position(|&(seen, _)| seen == letter) {
+                    Some(color_id) => color_id,
+                    None => {
+                        letters.push((letter, 0));
+                        letters.len() - 1
+                    }
+                };
+                letters[color_id].1 += 1;
+                let is_placed = if letters[color_id].1 > 2 {
+                    grid.try_add_extra_source(row, col, color_id)
+                } else {
+                    grid.try_set_missing_source(row, col, color_id)
+                };
+                if !is_placed {
+                    return None;
+                }
+            }
+        }
+        letters.iter().all(|&(_, count)| count >= 2).then_some(grid)
+    }
+
+    /// The sources as one string per row, the way from_board reads them, with colors lettered
+    /// A to Z and then a to z by color id. Returns None if there are more colors than letters.
+    pub fn to_board(&self) -> Option<Vec<String>> {
+        const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+        (0..self.height)
+            .map(|row| {
+                (0..self.width)
+                    .map(|col| {
+                        let cell = self.get(row, col).expect("looping in bounds");
+                        match cell.color {
+                            CellColor::Colored(color_id) if cell.is_source => {
+                                LETTERS.chars().nth(color_id)
+                            }
+                            _ if cell.is_bridge() => Some('+'),
+                            _ if cell.is_blocked => Some('#'),
+                            _ => Some('.'),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Reads a board shape with one line per row, where '#' or a space is off the board and
+    /// anything else is a cell. Short lines are padded out with cells off the board, so a
+    /// diamond doesn't need trailing spaces. Returns None if there are no cells at all.
+    pub fn parse_mask(text: &str) -> Option<Vec<Vec<bool>>> {
+        let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+        let width = lines.iter().map(|line| line.chars().count()).max()?;
+        let mask: Vec<Vec<bool>> = lines
+            .iter()
+            .map(|line| {
+                let mut row: Vec<bool> = line
+                    .chars()
+                    .map(|letter| letter != '#' && letter != ' ')
+                    .collect();
+                row.resize(width, false);
+                row
+            })
+            .collect();
+        mask.iter()
+            .flatten()
+            .any(|&is_cell| is_cell)
+            .then_some(mask)
+    }
+
+    /// An empty grid in the shape of the mask, with every cell outside it blocked off. Returns
+    /// None if the mask is empty or its rows aren't all the same length.
+    pub fn from_mask(mask: &[Vec<bool>]) -> Option<FlowGrid> {
+        let width = mask.first()?.len();
+        if width == 0 || mask.iter().any(|row| row.len() != width) {
+            return None;
+        }
+        let mut grid = FlowGrid::with_size(width, mask.len());
+        for (row, line) in mask.iter().enumerate() {
+            for (col, &is_cell) in line.iter().enumerate() {
+                if !is_cell {
+                    grid.try_block(row, col);
+                }
+            }
+        }
+        Some(grid)
+    }
+
+    /// which cells aren't blocked, one row at a time, the way from_mask takes them
+    pub fn mask(&self) -> Vec<Vec<bool>> {
+        (0..self.height)
+            .map(|row| {
+                (0..self.width)
+                    .map(|col| !self.cells[row * self.width + col].is_blocked)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Which cells are off the board, by index: blocked cells along the edge, and the blocked
+    /// cells joined to them. These shape the board rather than being holes in it.
+    pub fn off_board_cells(&self) -> Vec<bool> {
+        let mut off_board = vec![false; self.cells.len()];
+        let mut stack: Vec<usize> = (0..self.cells.len())
+            .filter(|&index| {
+                let (row, col) = self.get_row_col(index);
+                row == 0 || col == 0 || row + 1 == self.height || col + 1 == self.width
+            })
+            .collect();
+        while let Some(index) = stack.pop() {
+            if off_board[index] || !self.cells[index].is_blocked {
+                continue;
+            }
+            off_board[index] = true;
+            stack.extend(
+                Direction::ALL
+                    .into_iter()
+                    .filter_map(|direction| self.offset_index(index, direction)),
+            );
+        }
+        off_board
+    }
+
+    /// the events applied since the history was last folded, oldest first
+    pub fn history(&self) -> &[GridEvent] {
+        &self.history
+    }
+
+    /// how many events have been applied since the grid was made, counting folded ones
+    pub fn events_applied(&self) -> usize {
+        self.events_folded + self.history.len()
+    }
+
+    /// The events applied after the first `count`, oldest first, or None if some of them have
+    /// been folded away or the grid hasn't had that many.
+    pub fn events_since(&self, count: usize) -> Option<&[GridEvent]> {
+        self.history.get(count.checked_sub(self.events_folded)?..)
+    }
+
+    /// changes with every change to the grid. See the field.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Starts the history over from the grid as it is now, keeping it as the base to replay
+    /// from.
+    fn fold_history(&mut self) {
+        self.events_folded += self.history.len();
+        self.history.clear();
+        let mut base = self.clone();
+        base.history_base = None;
+        self.history_base = Some(Arc::new(SavedGrid::from(base)));
+    }
+
+    /// Whether the two grids were built the same way: from the same starting size and shape,
+    /// or the same base, with the same events since. Grids built the same way have the same
+    /// cells.
+    pub fn has_same_history(&self, other: &FlowGrid) -> bool {
+        self.history_start == other.history_start
+            && self.is_hex == other.is_hex
+            && self.wraps == other.wraps
+            && self.events_folded == other.events_folded
+            && match (&self.history_base, &other.history_base) {
+                (Some(base), Some(other_base)) => Arc::ptr_eq(base, other_base),
+                (base, other_base) => base.is_none() && other_base.is_none(),
+            }
+            && self.history == other.history
+    }
+
+    /// Whether the two grids are the same puzzle, whatever pipes are drawn on them: the same
+    /// shape, with the same sources, holes, bridges and warps in the same places.
+    pub fn has_same_puzzle(&self, other: &FlowGrid) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.is_hex == other.is_hex
+            && self.wraps == other.wraps
+            && self
+                .cells
+                .iter()
+                .zip(&other.cells)
+                .all(|(cell, other_cell)| {
+                    cell.is_source == other_cell.is_source
+                        && (!cell.is_source || cell.color == other_cell.color)
+                        && cell.is_blocked == other_cell.is_blocked
+                        && cell.is_bridge() == other_cell.is_bridge()
+                        && cell.warp_to == other_cell.warp_to
+                })
+    }
+
+    /// A new grid built by applying this one's history to its base, or to a blank grid of the
+    /// starting size and shape if nothing has been folded. It keeps the win condition, which
+    /// isn't part of the history.
+    pub fn replay_history(&self) -> FlowGrid {
+        let mut grid = match &self.history_base {
+            Some(base) => {
+                let mut grid = FlowGrid::try_from(SavedGrid::clone(base))
+                    .expect("bases are saved from whole grids");
+                grid.history_base = Some(base.clone());
+                grid
+            }
+            None => {
+                let (width, height) = self.history_start;
+                let mut grid = FlowGrid::with_size(width, height);
+                grid.is_hex = self.is_hex;
+                grid.wraps = self.wraps;
+                grid
+            }
+        };
+        grid.win_condition = self.win_condition.clone();
+        for &event in &self.history {
+            grid.apply(event);
+        }
+        grid.debug_audit();
+        grid
+    }
+
+    /// Makes the change an event describes and adds it to the history. This trusts the event:
+    /// the try_ methods are what check that an edit is allowed, and they only apply events that
+    /// keep the grid valid once they've all been applied.
+    pub fn apply(&mut self, event: GridEvent) {
+        if self.history.len() >= MAX_HISTORY_LEN {
+            self.fold_history();
+        }
+        self.generation = next_generation();
+        // the events that can fill or empty a cell only touch the one cell
+        let touched = match event {
+            GridEvent::AddSource { row, col, .. }
+            | GridEvent::Recolor { row, col, .. }
+            | GridEvent::AddBridge { row, col }
+            | GridEvent::RemoveBridge { row, col }
+            | GridEvent::RecolorBridge { row, col, .. }
+            | GridEvent::Block { row, col }
+            | GridEvent::Unblock { row, col } => self
+                .get_index(row, col)
+                .map(|index| (index, self.cells[index].is_filled())),
+            _ => None,
+        };
+        match event {
+            GridEvent::Connect {
+                row,
+                col,
+                direction,
+            } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let other_index = self
+                    .offset_index(index, direction)
+                    .expect("cells cannot be connected to the edge");
+                self.cells[index].add_connection(direction);
+                self.cells[other_index].add_connection(direction.opposite());
+                // the offset side is usually the single cell a pipe was just drawn into, so that's
+                // the cheaper piece to relabel
+                let component = self.components[self.node(index, direction)];
+                let other_node = self.node(other_index, direction.opposite());
+                for piece_node in self.piece(other_node, Some(direction.opposite())) {
+                    self.components[piece_node] = component;
+                }
+            }
+            GridEvent::Disconnect {
+                row,
+                col,
+                direction,
+            } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let other_index = self
+                    .offset_index(index, direction)
+                    .expect("cells cannot be connected to the edge");
+                self.cells[index].remove_connection(direction);
+                self.cells[other_index].remove_connection(direction.opposite());
+                let nodes = [
+                    self.node(index, direction),
+                    self.node(other_index, direction.opposite()),
+                ];
+                for label in nodes {
+                    for piece_node in self.piece(label, None) {
+                        self.components[piece_node] = label;
+                    }
+                }
+            }
+            GridEvent::AddSource { row, col, color_id } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                if color_id >= self.source_index.len() {
+                    self.source_index.resize(color_id + 1, Vec::new());
+                }
+                self.source_index[color_id].push(index);
+                self.skip_full_colors();
+                self.cells[index].is_source = true;
+                self.cells[index].color = CellColor::Colored(color_id);
+            }
+            GridEvent::RemoveSource { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let color_id = if let CellColor::Colored(color_id) = self.cells[index].color {
+                    color_id
+                } else {
+                    panic!("sources should always have an explicit color");
+                };
+                self.cells[index].is_source = false;
+
+                self.source_index
+                    .get_mut(color_id)
+                    .expect("All sources are registered in the index")
+                    .retain(|&source| source != index);
+                if color_id < self.next_color_id && self.source_index[color_id].len() < 2 {
+                    self.next_color_id = color_id;
+                }
+            }
+            GridEvent::Recolor { row, col, color } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                self.cells[index].color = color;
+            }
+            GridEvent::AddBridge { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let node = self.cells.len() + index;
+                self.cells[index].bridge_color = Some(CellColor::Empty(node));
+                self.components[node] = node;
+            }
+            GridEvent::RemoveBridge { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                self.cells[index].bridge_color = None;
+            }
+            GridEvent::RecolorBridge { row, col, color } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                self.cells[index].bridge_color = Some(color);
+            }
+            GridEvent::Block { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                self.cells[index].is_blocked = true;
+            }
+            GridEvent::Unblock { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                self.cells[index].is_blocked = false;
+            }
+            GridEvent::AddWarp {
+                row,
+                col,
+                to_row,
+                to_col,
+            } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let to_index = self
+                    .get_index(to_row, to_col)
+                    .expect("events are in bounds");
+                self.cells[index].warp_to = Some((to_row, to_col));
+                self.cells[to_index].warp_to = Some((row, col));
+            }
+            GridEvent::RemoveWarp { row, col } => {
+                let index = self.get_index(row, col).expect("events are in bounds");
+                let (to_row, to_col) = self.cells[index].warp_to.expect("only warps are removed");
+                let to_index = self
+                    .get_index(to_row, to_col)
+                    .expect("events are in bounds");
+                self.cells[index].warp_to = None;
+                self.cells[to_index].warp_to = None;
+            }
+            GridEvent::AddRow => self.add_row_core(),
+            GridEvent::RemoveRow => self.remove_row_core(),
+            GridEvent::AddCol => self.add_col_core(),
+            GridEvent::RemoveCol => self.remove_col_core(),
+        }
+        match (touched, event) {
+            (Some((index, was_filled)), _) => match (was_filled, self.cells[index].is_filled()) {
+                (false, true) => self.num_filled += 1,
+                (true, false) => self.num_filled -= 1,
+                _ => {}
+            },
+            (
+                None,
+                GridEvent::AddRow | GridEvent::RemoveRow | GridEvent::AddCol | GridEvent::RemoveCol,
+            ) => self.num_filled = self.count_filled(),
+            (None, _) => {}
+        }
+        self.history.push(event);
+    }
+
+    fn count_filled(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.is_filled()).count()
+    }
+
+    /// how many links between cells the pipes make up, with crossings and warps counted too
+    pub fn pipe_length(&self) -> usize {
+        self.cells
+            .iter()
+            .map(FlowCell::num_connections)
+            .sum::<usize>()
+            / 2
+    }
+
+    /// how many cells are filled by pipes, sources or blocks, which the classic rule wants to be
+    /// all of them
+    pub fn num_filled_cells(&self) -> usize {
+        self.num_filled
+    }
+
+    pub fn set_win_condition(&mut self, win_condition: impl WinCondition + Send + Sync + 'static) {
+        self.win_condition = Arc::new(win_condition);
+    }
+
+    pub fn win_condition(&self) -> &dyn WinCondition {
+        self.win_condition.as_ref()
+    }
+
+    /// whether the board meets the active win condition
+    pub fn is_solved(&self) -> bool {
+        self.win_condition.is_met(self)
+    }
+
+    pub fn next_color(&self) -> usize {
+        self.next_color_id
+    }
+
+    /// the number of color ids that have ever had a source. Some of them may have no sources
+    /// anymore, or only one.
+    pub fn num_colors(&self) -> usize {
+        self.source_index.len()
+    }
+
+    /// (row, col) of both sources of the color, if it has exactly two
+    pub fn get_source_pair(&self, color_id: usize) -> Option<((usize, usize), (usize, usize))> {
+        match self.source_index.get(color_id)?[..] {
+            [index1, index2] => Some((self.get_row_col(index1), self.get_row_col(index2))),
+            _ => None,
+        }
+    }
+
+    /// (row, col) of every source of the color, in the order they were placed
+    pub fn get_sources(&self, color_id: usize) -> Vec<(usize, usize)> {
+        self.source_index
+            .get(color_id)
+            .map_or(Vec::new(), |sources| {
+                sources
+                    .iter()
+                    .map(|&index| self.get_row_col(index))
+                    .collect()
+            })
+    }
+
+    /// whether some color has more than two sources
+    pub fn has_extra_sources(&self) -> bool {
+        self.source_index.iter().any(|sources| sources.len() > 2)
+    }
+
+    /// Whether every source of the color is joined to the others by pipes. Colors with only two
+    /// sources call that a pipe, and colors with more a tree.
+    pub fn are_sources_connected(&self, color_id: usize) -> bool {
+        match self.get_sources(color_id).split_first() {
+            Some((&(row, col), others)) if !others.is_empty() => {
+                others.iter().all(|&(other_row, other_col)| {
+                    self.are_cells_connected(row, col, other_row, other_col)
+                })
+            }
+            _ => false,
+        }
+    }
+
+    /// How many colors have all their sources joined, out of the colors that have at least two
+    /// sources to join.
+    pub fn flows_connected(&self) -> (usize, usize) {
+        (0..self.num_colors())
+            .filter(|&color_id| self.source_index[color_id].len() >= 2)
+            .fold((0, 0), |(connected, total), color_id| {
+                (
+                    connected + usize::from(self.are_sources_connected(color_id)),
+                    total + 1,
+                )
+            })
+    }
+
+    /// The fewest drag strokes the board can be solved in, one per color, which is what makes a
+    /// solve perfect.
+    pub fn min_strokes(&self) -> usize {
+        self.source_index
+            .iter()
+            .filter(|sources| sources.len() >= 2)
+            .count()
+    }
+
+    /// The color's first source and the moves along its pipe from there, as far as the pipe
+    /// goes. A color with extra sources is followed through them, taking the first way on where
+    /// its pipes branch. None if the color doesn't have at least two sources.
+    pub fn pipe_moves(&self, color_id: usize) -> Option<((usize, usize), Vec<Direction>)> {
+        let [start, _, ..] = self.get_sources(color_id)[..] else {
+            return None;
+        };
+        let mut node = self
+            .get_index(start.0, start.1)
+            .expect("sources are in bounds");
+        let mut moves = Vec::new();
+        let mut came_from: Option<Direction> = None;
+        loop {
+            let Some(direction) = self
+                .node_directions(node)
+                .iter()
+                .copied()
+                .find(|&direction| {
+                    Some(direction) != came_from && self.is_node_connected(node, direction)
+                })
+            else {
+                return Some((start, moves));
+            };
+            moves.push(direction);
+            node = self
+                .offset_node(node, direction)
+                .expect("cells cannot be connected to the edge");
+            came_from = Some(direction.opposite());
+        }
+    }
+
+    pub fn has_bridges(&self) -> bool {
+        self.cells.iter().any(FlowCell::is_bridge)
+    }
+
+    pub fn has_warps(&self) -> bool {
+        self.cells.iter().any(FlowCell::is_warp)
+    }
+
+    /// each pair of warps once, in reading order of their first cell
+    pub fn warps(&self) -> Vec<WarpPair> {
+        (0..self.cells.len())
+            .map(|index| self.get_row_col(index))
+            .filter_map(|row_col| {
+                let partner = self.cells[row_col.0 * self.width + row_col.1].warp_to?;
+                (row_col < partner).then_some((row_col, partner))
+            })
+            .collect()
+    }
+
+    /// whether the color has exactly one source placed
+    pub fn is_missing_source(&self, color_id: usize) -> bool {
+        self.source_index
+            .get(color_id)
+            .is_some_and(|sources| sources.len() == 1)
+    }
+
+    /// moves the next color on past any that already have both of their sources
+    fn skip_full_colors(&mut self) {
+        while self
+            .source_index
+            .get(self.next_color_id)
+            .is_some_and(|sources| sources.len() >= 2)
+        {
+            self.next_color_id += 1;
+        }
+    }
+
+    fn get_row_col(&self, index: usize) -> (usize, usize) {
+        (index / self.width, index % self.width)
+    }
+
+    /// the node the pipe leaving the cell in this direction goes through
+    fn node(&self, index: usize, direction: Direction) -> usize {
+        if direction.is_vertical() && self.cells[index].is_bridge() {
+            self.cells.len() + index
+        } else {
+            index
+        }
+    }
+
+    /// the index of the cell a node is in
+    fn node_cell(&self, node: usize) -> usize {
+        node % self.cells.len()
+    }
+
+    /// the cell's node, and its bridge's vertical lane if it has one
+    fn cell_nodes(&self, index: usize) -> impl Iterator<Item = usize> + use<> {
+        let bridge_node = self.cells[index]
+            .is_bridge()
+            .then_some(self.cells.len() + index);
+        std::iter::once(index).chain(bridge_node)
+    }
+
+    /// every node, in order of their cells
+    fn nodes(&self) -> Vec<usize> {
+        (0..self.cells.len())
+            .flat_map(|index| self.cell_nodes(index))
+            .collect()
+    }
+
+    /// the directions a pipe can leave a node in
+    fn node_directions(&self, node: usize) -> &'static [Direction] {
+        let is_bridge = self.cells[self.node_cell(node)].is_bridge();
+        if node >= self.cells.len() {
+            if is_bridge {
+                &[Direction::Up, Direction::Down]
+            } else {
+                &[]
+            }
+        } else if is_bridge {
+            &[Direction::Left, Direction::Right]
+        } else {
+            &Direction::ALL
+        }
+    }
+
+    fn is_node_connected(&self, node: usize, direction: Direction) -> bool {
+        self.node_directions(node).contains(&direction)
+            && self.cells[self.node_cell(node)].is_direction_connected(direction)
+    }
+
+    fn node_connections(&self, node: usize) -> usize {
+        self.node_directions(node)
+            .iter()
+            .filter(|&&direction| self.is_node_connected(node, direction))
+            .count()
+    }
+
+    fn node_color(&self, node: usize) -> CellColor {
+        if node < self.cells.len() {
+            self.cells[node].color
+        } else {
+            self.cells[node - self.cells.len()]
+                .bridge_color
+                .expect("only bridges have a second node")
+        }
+    }
+
+    fn recolor_node(&mut self, node: usize, color: CellColor) {
+        let (row, col) = self.get_row_col(self.node_cell(node));
+        self.apply(if node < self.cells.len() {
+            GridEvent::Recolor { row, col, color }
+        } else {
+            GridEvent::RecolorBridge { row, col, color }
+        });
+    }
+
+    /// the node on the other side of a connection leaving this one
+    fn offset_node(&self, node: usize, direction: Direction) -> Option<usize> {
+        let other_index = self.offset_index(self.node_cell(node), direction)?;
+        Some(self.node(other_index, direction.opposite()))
+    }
+
+    fn get_index(&self, row: usize, col: usize) -> Option<usize> {
+        if row < self.height && col < self.width {
+            Some(row * self.width + col)
+        } else {
+            None
+        }
+    }
+    fn get_offset_index(&self, row: usize, col: usize, direction: Direction) -> Option<usize> {
+        let (row, col) = self.get_offset_row_col(row, col, direction)?;
+        self.get_index(row, col)
+    }
+    fn offset_index(&self, index: usize, direction: Direction) -> Option<usize> {
+        if index >= self.cells.len() {
+            return None;
+        }
+        let (row, col) = self.get_row_col(index);
+        self.get_offset_index(row, col, direction)
+    }
+    pub fn get(&self, row: usize, col: usize) -> Option<&FlowCell> {
+        self.cells.get(self.get_index(row, col)?)
+    }
+    pub fn offset_get(&self, row: usize, col: usize, direction: Direction) -> Option<&FlowCell> {
+        self.cells.get(self.get_offset_index(row, col, direction)?)
+    }
+
+    pub fn get_offset_row_col(
+        &self,
+        row: usize,
+        col: usize,
+        direction: Direction,
+    ) -> Option<(usize, usize)> {
+        if direction == Direction::Warp {
+            return self.get(row, col)?.warp_to;
+        }
+        let (next_row, next_col) = self.step(row, col, direction)?;
+        let wrap = |index: isize, len: usize| {
+            if self.wraps && len >= 3 {
+                Some(index.rem_euclid(len as isize) as usize)
+            } else {
+                usize::try_from(index).ok().filter(|&index| index < len)
+            }
+        };
+        Some((wrap(next_row, self.height)?, wrap(next_col, self.width)?))
+    }
+
+    /// where a step from a cell lands before wrapping, which can be off the board. None if the
+    /// cell is off the board or the direction doesn't exist on this grid.
+    fn step(&self, row: usize, col: usize, direction: Direction) -> Option<(isize, isize)> {
+        if row >= self.height || col >= self.width {
+            return None;
+        }
+        let (row_step, col_step) = match direction {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+            Direction::UpRight if self.is_hex => (-1, 1),
+            Direction::DownLeft if self.is_hex => (1, -1),
+            Direction::UpRight | Direction::DownLeft | Direction::Warp => return None,
+        };
+        Some((row as isize + row_step, col as isize + col_step))
+    }
+
+    /// whether a connection in the direction leaves the board and comes back on the other side
+    pub fn crosses_edge(&self, row: usize, col: usize, direction: Direction) -> bool {
+        self.get_offset_row_col(row, col, direction).is_some()
+            && self
+                .step(row, col, direction)
+                .is_some_and(|(next_row, next_col)| {
+                    next_row < 0
+                        || next_col < 0
+                        || next_row as usize >= self.height
+                        || next_col as usize >= self.width
+                })
+    }
+
+    /// the direction that leads from one cell to the other, if they're neighbors
+    pub fn direction_between(
+        &self,
+        row_from: usize,
+        col_from: usize,
+        row_to: usize,
+        col_to: usize,
+    ) -> Option<Direction> {
+        Direction::ALL.into_iter().find(|&direction| {
+            self.get_offset_row_col(row_from, col_from, direction) == Some((row_to, col_to))
+        })
+    }
+
+    /// Disconnects every pipe that wraps around to where the step lands, so growing the grid
+    /// doesn't leave connections between cells that are no longer neighbors.
+    fn cut_edge_crossings(&mut self, is_cut: impl Fn(isize, isize) -> bool) {
+        let mut cuts = Vec::new();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let cell = self.get(row, col).expect("looping in bounds");
+                for direction in Direction::ALL {
+                    if cell.is_direction_connected(direction)
+                        && self.crosses_edge(row, col, direction)
+                        && self
+                            .step(row, col, direction)
+                            .is_some_and(|(next_row, next_col)| is_cut(next_row, next_col))
+                    {
+                        cuts.push((row, col, direction));
+                    }
+                }
+            }
+        }
+        for (row, col, direction) in cuts {
+            // each connection is listed from both ends, and the second is already gone
+            self.try_disconnect(row, col, direction);
+        }
+    }
+
+    pub fn add_row(&mut self) {
+        // the new row goes between the bottom edge and the top one
+        let height = self.height;
+        self.cut_edge_crossings(|row, _| row < 0 || row as usize >= height);
+        self.apply(GridEvent::AddRow);
+        self.debug_audit();
+    }
+
+    fn add_row_core(&mut self) {
+        let old_len = self.cells.len();
+        let width = self.width;
+        self.remap_empty_ids(|id| if id >= old_len { id + width } else { id });
+        self.cells.reserve(self.width);
+
+        for _ in 0..self.width {
+            self.cells.push(FlowCell::empty_with_id(self.cells.len()));
+        }
+        self.height += 1;
+        self.relabel_components();
+    }
+
+    fn can_remove_edge_cell(cell: &FlowCell) -> bool {
+        if cell.is_source || cell.is_bridge() || cell.is_warp() {
+            return false;
+        }
+        if cell.num_connections() > 0 {
+            return false;
+        }
+        true
+    }
+    pub fn can_remove_row(&self) -> bool {
+        if self.height == 1 {
+            return false;
+        }
+        for index in (self.get_index(self.height - 1, 0).expect("Non-empty grid"))..self.cells.len()
+        {
+            if !Self::can_remove_edge_cell(&self.cells[index]) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn try_remove_row(&mut self) -> bool {
+        if !self.can_remove_row() {
+            return false;
+        }
+
+        self.apply(GridEvent::RemoveRow);
+        self.debug_audit();
+        true
+    }
+
+    fn remove_row_core(&mut self) {
+        for _ in 0..self.width {
+            self.cells.pop();
+        }
+        let new_len = self.cells.len();
+        let width = self.width;
+        self.remap_empty_ids(|id| if id >= new_len { id - width } else { id });
+
+        self.height -= 1;
+        self.relabel_components();
+    }
+
+    pub fn add_col(&mut self) {
+        let width = self.width;
+        self.cut_edge_crossings(|_, col| col < 0 || col as usize >= width);
+        self.apply(GridEvent::AddCol);
+        self.debug_audit();
+    }
+
+    fn add_col_core(&mut self) {
+        let old_width = self.width;
+        let old_cells = {
+            // kinda weird operation, but we want to interleave old and new tiles, so we extract the old
+            // ones and put into place a new buffer that will be filled right after this.
+            let mut new_cells = Vec::with_capacity(self.cells.len() + self.height);
+            std::mem::swap(&mut self.cells, &mut new_cells);
+            new_cells
+        };
+
+        for (old_cell_index, cell) in old_cells.into_iter().enumerate() {
+            let old_cell_row = old_cell_index / old_width;
+            let old_cell_col = old_cell_index % old_width;
+
+            self.cells.push(cell);
+
+            if old_cell_col == old_width - 1 {
+                let new_cell_index = old_cell_index + old_cell_row;
+                self.cells.push(FlowCell::empty_with_id(new_cell_index));
+            }
+        }
+        self.remap_source_index(|old_index| old_index + old_index / old_width);
+        // a bridge's vertical lane counts as being a whole grid further down, so the same
+        // arithmetic shifts its ids too
+        self.remap_empty_ids(|old_id| old_id + old_id / old_width);
+
+        self.width += 1;
+        self.relabel_components();
+    }
+
+    pub fn can_remove_col(&self) -> bool {
+        if self.width == 1 {
+            return false;
+        }
+
+        for row in 0..self.height {
+            let cell = self
+                .get(row, self.width - 1)
+                .expect("non-empty grid and height*width = cells.len");
+            if !Self::can_remove_edge_cell(cell) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn try_remove_col(&mut self) -> bool {
+        if !self.can_remove_col() {
+            return false;
+        }
+
+        self.apply(GridEvent::RemoveCol);
+        self.debug_audit();
+        true
+    }
+
+    fn remove_col_core(&mut self) {
+        let old_width = self.width;
+        let old_cells = {
+            // kinda weird operation, but we want to interleave old and new tiles, so we extract the old
+            // ones and put into place a new buffer that will be filled right after this.
+            let mut new_cells = Vec::with_capacity(self.cells.len() + self.height);
+            std::mem::swap(&mut self.cells, &mut new_cells);
+            new_cells
+        };
+        // TODO I can make this in-place if performance becomes a concern. I think this is just easier to read.
+
+        for (old_cell_index, cell) in old_cells.into_iter().enumerate() {
+            let old_cell_col = old_cell_index % old_width;
+
+            if old_cell_col != old_width - 1 {
+                self.cells.push(cell);
+            }
+        }
+        self.remap_source_index(|old_index| old_index - old_index / old_width);
+        self.remap_empty_ids(|old_id| old_id - old_id / old_width);
+
+        self.width -= 1;
+        self.relabel_components();
+    }
+
+    fn remap_source_index(&mut self, remap: impl Fn(usize) -> usize) {
+        for source in self.source_index.iter_mut().flatten() {
+            *source = remap(*source);
+        }
+    }
+
+    /// renumbers the ids of uncolored cells and lanes, for when the nodes they're named after move
+    fn remap_empty_ids(&mut self, remap: impl Fn(usize) -> usize) {
+        for cell in self.cells.iter_mut() {
+            for color in std::iter::once(&mut cell.color).chain(cell.bridge_color.as_mut()) {
+                if let CellColor::Empty(id) = *color {
+                    *color = CellColor::Empty(remap(id));
+                }
+            }
+        }
+    }
+
+    /// Labels every piece from scratch. Only needed when the cells move around, since connecting
+    /// and disconnecting keep the labels up to date themselves.
+    fn relabel_components(&mut self) {
+        self.components = vec![usize::MAX; 2 * self.cells.len()];
+        for node in 0..self.components.len() {
+            if self.components[node] == usize::MAX {
+                for piece_node in self.piece(node, None) {
+                    self.components[piece_node] = node;
+                }
+            }
+        }
+    }
+
+    /// removes every pipe, leaving only the sources
+    pub fn clear_pipes(&mut self) {
+        for index in 0..self.cells.len() {
+            let (row, col) = self.get_row_col(index);
+            // the other end of a warp finds its link already gone
+            for direction in [
+                Direction::Down,
+                Direction::Right,
+                Direction::DownLeft,
+                Direction::Warp,
+            ] {
+                if self.cells[index].is_direction_connected(direction) {
+                    self.apply(GridEvent::Disconnect {
+                        row,
+                        col,
+                        direction,
+                    });
+                }
+            }
+        }
+        for node in self.nodes() {
+            if !self.cells[self.node_cell(node)].is_source
+                && self.node_color(node) != CellColor::Empty(node)
+            {
+                self.recolor_node(node, CellColor::Empty(node));
+            }
+        }
+        self.debug_audit();
+    }
+
+    /// removes every pipe of one color, leaving its sources
+    pub fn clear_color_pipes(&mut self, color_id: usize) {
+        let colored: Vec<usize> = (0..self.cells.len())
+            .filter(|&index| {
+                self.cell_nodes(index)
+                    .any(|node| self.node_color(node) == CellColor::Colored(color_id))
+            })
+            .collect();
+        for index in colored {
+            let (row, col) = self.get_row_col(index);
+            for direction in Direction::ALL {
+                self.try_disconnect(row, col, direction);
+            }
+        }
+    }
+
+    /// Checks that source_index and the cells agree about where every source is, and fixes
+    /// whatever doesn't. The cells win: an entry pointing at a cell that isn't a source of that
+    /// color is dropped, and a source missing from the index is added back. Returns a
+    /// description of each fix, so it's empty when everything was in order. The fixes change the
+    /// cells directly rather than through events, since they repair a grid the history no longer
+    /// describes.
+    pub fn audit_sources(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for color_id in 0..self.source_index.len() {
+            let mut kept: Vec<usize> = Vec::new();
+            for index in std::mem::take(&mut self.source_index[color_id]) {
+                let is_source = self.cells.get(index).is_some_and(|cell| {
+                    cell.is_source && cell.color == CellColor::Colored(color_id)
+                });
+                if !is_source {
+                    let (row, col) = self.get_row_col(index);
+                    problems.push(format!(
+                        "color {color_id} listed ({row}, {col}), which isn't one of its sources"
+                    ));
+                } else if kept.contains(&index) {
+                    problems.push(format!("color {color_id} listed the same source twice"));
+                } else {
+                    kept.push(index);
+                }
+            }
+            self.source_index[color_id] = kept;
+        }
+
+        for index in 0..self.cells.len() {
+            let cell = self.cells[index];
+            if !cell.is_source {
+                continue;
+            }
+            let (row, col) = self.get_row_col(index);
+            let CellColor::Colored(color_id) = cell.color else {
+                problems.push(format!("({row}, {col}) was a source without a color"));
+                self.cells[index].is_source = false;
+                continue;
+            };
+            if color_id >= self.source_index.len() {
+                self.source_index.resize(color_id + 1, Vec::new());
+            }
+            if self.source_index[color_id].contains(&index) {
+                continue;
+            }
+            self.source_index[color_id].push(index);
+            problems.push(format!(
+                "({row}, {col}) wasn't listed as a source of color {color_id}"
+            ));
+        }
+
+        // try_set_new_source expects the next color to have room for a source
+        let next_color_id = self.next_color_id;
+        self.skip_full_colors();
+        if self.next_color_id != next_color_id {
+            problems.push(format!(
+                "the next color, {next_color_id}, already had both sources"
+            ));
+        }
+
+        if !problems.is_empty() {
+            self.generation = next_generation();
+        }
+        problems
+    }
+
+    /// In debug builds, audits the sources after every change, so an engine bug shows up at
+    /// the edit that caused it instead of much later.
+    fn debug_audit(&mut self) {
+        if cfg!(debug_assertions) {
+            let problems = self.audit_sources();
+            assert!(
+                problems.is_empty(),
+                "sources were out of sync: {problems:?}"
+            );
+            assert_eq!(
+                self.num_filled,
+                self.count_filled(),
+                "the filled cell count was out of sync"
+            );
+        }
+    }
+
+    pub fn try_set_new_source(&mut self, row: usize, col: usize) -> bool {
+        self.try_set_missing_source(row, col, self.next_color_id)
+    }
+
+    pub fn try_set_missing_source(&mut self, row: usize, col: usize, color_id: usize) -> bool {
+        if self
+            .source_index
+            .get(color_id)
+            .is_some_and(|sources| sources.len() >= 2)
+        {
+            // more sources make a tree to connect, which try_add_extra_source is for
+            return false;
+        }
+        self.try_add_source(row, col, color_id)
+    }
+
+    /// Gives a color that already has both of its sources another one. Every source of the color
+    /// then has to be joined into one tree, so these sources can take any number of pipes.
+    pub fn try_add_extra_source(&mut self, row: usize, col: usize, color_id: usize) -> bool {
+        if self
+            .source_index
+            .get(color_id)
+            .is_none_or(|sources| sources.len() < 2)
+        {
+            return false;
+        }
+        self.try_add_source(row, col, color_id)
+    }
+
+    fn try_add_source(&mut self, row: usize, col: usize, color_id: usize) -> bool {
+        let (index, cell) = if let Some(index) = self.get_index(row, col) {
+            (index, self.cells[index])
+        } else {
+            return false;
+        };
+
+        if cell.is_source || cell.is_bridge() || cell.is_blocked || cell.is_warp() {
+            return false;
+        }
+
+        if cell.num_connections() > 1 {
+            return false;
+        }
+
+        if !CellColor::can_colors_connect(&cell.color, &CellColor::Colored(color_id)) {
+            return false;
+        }
+
+        self.apply(GridEvent::AddSource { row, col, color_id });
+        if let Some(direction) = Direction::ALL
+            .into_iter()
+            .find(|&direction| cell.is_direction_connected(direction))
+        {
+            self.spread_color(
+                self.offset_node(index, direction)
+                    .expect("cells cannot be connects to the edge"),
+                CellColor::Colored(color_id),
+            );
+        }
+
+        self.debug_audit();
+        true
+    }
+
+    pub fn try_remove_source(&mut self, row: usize, col: usize) -> bool {
+        let (index, cell) = if let Some(index) = self.get_index(row, col) {
+            (index, self.cells[index])
+        } else {
+            return false;
+        };
+
+        if !cell.is_source {
+            return false;
+        }
+
+        let color_id = if let CellColor::Colored(color_id) = cell.color {
+            color_id
+        } else {
+            panic!("sources should always have an explicit color");
+        };
+
+        // the tree joining a color's sources might not hold together without this one, or leave
+        // a pair's source with too many pipes, so the color starts over
+        if self.source_index[color_id].len() > 2 {
+            self.clear_color_pipes(color_id);
+        }
+        let cell = self.cells[index];
+        self.apply(GridEvent::RemoveSource { row, col });
+
+        let should_decolor = cell.num_connections() == 0
+            || !self.source_index[color_id].iter().any(|&other_index| {
+                self.are_cells_connected(
+                    row,
+                    col,
+                    other_index / self.width,
+                    other_index % self.width,
+                )
+            });
+        if should_decolor {
+            self.spread_color(index, CellColor::Empty(index));
+        }
+
+        self.debug_audit();
+        true
+    }
+
+    /// Removes a color's pipes and every one of its sources, which frees its id for the next
+    /// source placed. Returns whether it had any sources.
+    pub fn try_clear_color(&mut self, color_id: usize) -> bool {
+        let sources = self.get_sources(color_id);
+        if sources.is_empty() {
+            return false;
+        }
+        self.clear_color_pipes(color_id);
+        for (row, col) in sources {
+            self.try_remove_source(row, col);
+        }
+        true
+    }
+
+    /// Turns an empty cell into a bridge. Only cells without a source or any pipe can become one,
+    /// and only on square grids.
+    pub fn try_add_bridge(&mut self, row: usize, col: usize) -> bool {
+        let cell = if let Some(cell) = self.get(row, col) {
+            cell
+        } else {
+            return false;
+        };
+        if self.is_hex {
+            return false;
+        }
+        if cell.is_source
+            || cell.is_bridge()
+            || cell.is_blocked
+            || cell.is_warp()
+            || cell.num_connections() > 0
+        {
+            return false;
+        }
+
+        self.apply(GridEvent::AddBridge { row, col });
+        self.debug_audit();
+        true
+    }
+
+    /// Turns a bridge back into an ordinary cell, once no pipe goes through it.
+    pub fn try_remove_bridge(&mut self, row: usize, col: usize) -> bool {
+        let cell = if let Some(cell) = self.get(row, col) {
+            cell
+        } else {
+            return false;
+        };
+        if !cell.is_bridge() || cell.num_connections() > 0 {
+            return false;
+        }
+
+        self.apply(GridEvent::RemoveBridge { row, col });
+        self.debug_audit();
+        true
+    }
+
+    /// Blocks off an empty cell, so the board works without it. Cells with a source, a bridge or
+    /// any pipe can't be blocked.
+    pub fn try_block(&mut self, row: usize, col: usize) -> bool {
+        let cell = if let Some(cell) = self.get(row, col) {
+            cell
+        } else {
+            return false;
+        };
+        if cell.is_source
+            || cell.is_bridge()
+            || cell.is_blocked
+            || cell.is_warp()
+            || cell.num_connections() > 0
+        {
+            return false;
+        }
+
+        self.apply(GridEvent::Block { row, col });
+        self.debug_audit();
+        true
+    }
+
+    pub fn try_unblock(&mut self, row: usize, col: usize) -> bool {
+        if !self.get(row, col).is_some_and(|cell| cell.is_blocked) {
+            return false;
+        }
+
+        self.apply(GridEvent::Unblock { row, col });
+        self.debug_audit();
+        true
+    }
+
+    /// Links two empty cells as a pair of warps. Neither can hold a source, a bridge, a hole or any
+    /// pipe yet.
+    pub fn try_add_warp(&mut self, row: usize, col: usize, to_row: usize, to_col: usize) -> bool {
+        let can_be_warp = |cell: &FlowCell| {
+            !cell.is_source
+                && !cell.is_bridge()
+                && !cell.is_blocked
+                && !cell.is_warp()
+                && cell.num_connections() == 0
+        };
+        if (row, col) == (to_row, to_col)
+            || !self.get(row, col).is_some_and(can_be_warp)
+            || !self.get(to_row, to_col).is_some_and(can_be_warp)
+        {
+            return false;
+        }
+
+        self.apply(GridEvent::AddWarp {
+            row,
+            col,
+            to_row,
+            to_col,
+        });
+        self.debug_audit();
+        true
+    }
+
+    /// Turns a warp and its partner back into plain cells. A pipe through them is cut at the
+    /// link, and whatever it had on each side stays.
+    pub fn try_remove_warp(&mut self, row: usize, col: usize) -> bool {
+        if !self.get(row, col).is_some_and(FlowCell::is_warp) {
+            return false;
+        }
+
+        self.try_disconnect(row, col, Direction::Warp);
+        self.apply(GridEvent::RemoveWarp { row, col });
+        self.debug_audit();
+        true
+    }
+
+    /// Disconnects the pipe from its end in the tail cell back to the base cell, leaving the base
+    /// as the new end.
+    pub fn remove_tail(
+        &mut self,
+        base_row: usize,
+        base_col: usize,
+        tail_row: usize,
+        tail_col: usize,
+    ) -> bool {
+        let (base_index, tail_index) = match (
+            self.get_index(base_row, base_col),
+            self.get_index(tail_row, tail_col),
+        ) {
+            (Some(base_index), Some(tail_index)) => (base_index, tail_index),
+            _ => return false,
+        };
+
+        // the lane the pipe ends in, which for a bridge may only be one of them
+        let Some(mut tail) = self
+            .cell_nodes(tail_index)
+            .find(|&node| self.node_connections(node) == 1)
+        else {
+            return false;
+        };
+        let tail_color = self.node_color(tail);
+        if !self
+            .cell_nodes(base_index)
+            .any(|node| self.node_color(node) == tail_color)
+        {
+            return false;
+        }
+
+        while self.node_cell(tail) != base_index {
+            let Some(direction) = self
+                .node_directions(tail)
+                .iter()
+                .copied()
+                .find(|&direction| self.is_node_connected(tail, direction))
+            else {
+                return false;
+            };
+            let (row, col) = self.get_row_col(self.node_cell(tail));
+            if !self.try_disconnect(row, col, direction) {
+                return false;
+            }
+            tail = self
+                .offset_node(tail, direction)
+                .expect("Grid should not connect to the edges");
+        }
+
+        true
+    }
+
+    pub fn can_disconnect(&self, row: usize, col: usize, direction: Direction) -> bool {
+        let (cell, offset_cell) = match (self.get(row, col), self.offset_get(row, col, direction)) {
+            (Some(cell), Some(offset_cell)) => (cell, offset_cell),
+            _ => return false,
+        };
+
+        if !cell.is_direction_connected(direction) {
+            return false;
+        }
+        if !offset_cell.is_direction_connected(direction.opposite()) {
+            return false;
+        }
+        true
+    }
+
+    /// Cuts a pipe at a cell in its middle and takes away one side of it, so the cell is the end
+    /// of the pipe again. The side that doesn't lead to a source goes, or the shorter one if both
+    /// or neither do.
+    pub fn try_retract_to(&mut self, row: usize, col: usize) -> bool {
+        let Some(index) = self.get_index(row, col) else {
+            return false;
+        };
+        let cell = self.cells[index];
+        if cell.is_source || cell.is_bridge() {
+            return false;
+        }
+        let node = self.node(index, Direction::Up);
+        let directions: Vec<Direction> = self
+            .node_directions(node)
+            .iter()
+            .copied()
+            .filter(|&direction| self.is_node_connected(node, direction))
+            .collect();
+        let [first, second] = directions[..] else {
+            return false;
+        };
+
+        let leads_to_source = |side: &[usize]| {
+            side.iter()
+                .any(|&side_node| self.cells[self.node_cell(side_node)].is_source)
+        };
+        let first_side = self.piece(node, Some(second));
+        let second_side = self.piece(node, Some(first));
+        let (direction, side) = match (leads_to_source(&first_side), leads_to_source(&second_side))
+        {
+            (true, false) => (second, second_side),
+            (false, true) => (first, first_side),
+            _ if first_side.len() <= second_side.len() => (first, first_side),
+            _ => (second, second_side),
+        };
+        if !self.try_disconnect(row, col, direction) {
+            return false;
+        }
+        for side_node in side.into_iter().filter(|&side_node| side_node != node) {
+            let (side_row, side_col) = self.get_row_col(self.node_cell(side_node));
+            for &side_direction in self.node_directions(side_node) {
+                if self.is_node_connected(side_node, side_direction) {
+                    self.try_disconnect(side_row, side_col, side_direction);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn try_disconnect(&mut self, row: usize, col: usize, direction: Direction) -> bool {
+        if !self.can_disconnect(row, col, direction) {
+            return false;
+        }
+        let index = self
+            .get_index(row, col)
+            .expect("previous validation verifies this is a valid index");
+        let node = self.node(index, direction);
+        let other_node = self
+            .offset_node(node, direction)
+            .expect("previous validation verifies this is a valid index");
+
+        self.apply(GridEvent::Disconnect {
+            row,
+            col,
+            direction,
+        });
+        self.uncolor_if_unsourced(node);
+        self.uncolor_if_unsourced(other_node);
+
+        self.debug_audit();
+        true
+    }
+
+    /// A pipe only keeps its color while it leads back to a source, so a piece that got cut off
+    /// goes back to being uncolored.
+    fn uncolor_if_unsourced(&mut self, node: usize) {
+        let piece = self.piece(node, None);
+        if piece
+            .iter()
+            .any(|&piece_node| self.cells[self.node_cell(piece_node)].is_source)
+        {
+            return;
+        }
+        for piece_node in piece {
+            if self.node_color(piece_node) != CellColor::Empty(node) {
+                self.recolor_node(piece_node, CellColor::Empty(node));
+            }
+        }
+    }
+
+    /// every node joined to this one by pipes, including itself, except for what's past the
+    /// skipped direction
+    fn piece(&self, node: usize, skipped_direction: Option<Direction>) -> Vec<usize> {
+        let mut piece = vec![node];
+        let mut walks: Vec<(usize, usize)> = self
+            .node_directions(node)
+            .iter()
+            .filter(|&&direction| {
+                Some(direction) != skipped_direction && self.is_node_connected(node, direction)
+            })
+            .map(|&direction| {
+                let next = self
+                    .offset_node(node, direction)
+                    .expect("cells cannot be connected to the edge");
+                (node, next)
+            })
+            .collect();
+        // Pipes only branch at sources with extra pipes, and never loop back to them, so this
+        // walks out to each end of the piece, or back around a loop.
+        while let Some((mut previous, mut current)) = walks.pop() {
+            while current != node {
+                piece.push(current);
+                let mut nexts = self
+                    .node_directions(current)
+                    .iter()
+                    .filter_map(|&direction| {
+                        self.offset_node(current, direction).filter(|&next| {
+                            next != previous && self.is_node_connected(current, direction)
+                        })
+                    });
+                let Some(next) = nexts.next() else {
+                    break;
+                };
+                walks.extend(nexts.map(|branch| (current, branch)));
+                (previous, current) = (current, next);
+            }
+        }
+        piece
+    }
+
+    pub fn can_connect(&self, row: usize, col: usize, direction: Direction) -> bool {
+        let cell1 = self.get(row, col);
+        let cell2 = self.offset_get(row, col, direction);
+
+        if cell1.is_none() || cell2.is_none() {
+            return false;
+        }
+        let cell1 = cell1.unwrap();
+        let cell2 = cell2.unwrap();
+
+        if !self.is_lane_open(row, col, direction) {
+            return false;
+        }
+        match self.get_offset_row_col(row, col, direction) {
+            Some((row2, col2)) if self.is_lane_open(row2, col2, direction.opposite()) => {}
+            _ => return false,
+        }
+
+        if cell1.is_direction_connected(direction)
+            || cell2.is_direction_connected(direction.opposite())
+        {
+            return false;
+        }
+
+        if !CellColor::can_colors_connect(
+            &cell1.lane_color(direction),
+            &cell2.lane_color(direction.opposite()),
+        ) {
+            return false;
+        }
+        // two pieces of the same color can only join if they aren't already one, which could
+        // only happen through a source with extra pipes, and would close a loop
+        let index = self.get_index(row, col).expect("checked above");
+        let node = self.node(index, direction);
+        let other_node = self.offset_node(node, direction).expect("checked above");
+        !matches!(cell1.lane_color(direction), CellColor::Colored(_))
+            || self.components[node] != self.components[other_node]
+    }
+
+    /// Whether the pipe leaving the cell in this direction can take another connection. Sources
+    /// of a color with more than two can take as many as they have neighbors, since they might
+    /// be where its tree branches.
+    pub fn is_lane_open(&self, row: usize, col: usize, direction: Direction) -> bool {
+        let Some(cell) = self.get(row, col) else {
+            return false;
+        };
+        match cell.color {
+            CellColor::Colored(color_id) if cell.is_source => {
+                cell.is_lane_open(direction)
+                    || (!cell.is_direction_connected(direction)
+                        && self
+                            .source_index
+                            .get(color_id)
+                            .is_some_and(|sources| sources.len() > 2))
+            }
+            _ => cell.is_lane_open(direction),
+        }
+    }
+
+    pub fn try_connect(&mut self, row: usize, col: usize, direction: Direction) -> bool {
+        if !self.can_connect(row, col, direction) {
+            return false;
+        }
+        let index = self
+            .get_index(row, col)
+            .expect("previous validation verifies this is a valid index");
+        let node1 = self.node(index, direction);
+        let node2 = self
+            .offset_node(node1, direction)
+            .expect("previous validation verifies this is a valid index");
+        let (color1, color2) = (self.node_color(node1), self.node_color(node2));
+        // the uncolored side takes on the other side's color
+        let (spread_node, new_color) = if let CellColor::Colored(_) = color1 {
+            (node2, color1)
+        } else {
+            (node1, color2)
+        };
+
+        self.apply(GridEvent::Connect {
+            row,
+            col,
+            direction,
+        });
+        self.spread_color(spread_node, new_color);
+
+        self.debug_audit();
+        true
+    }
+
+    /// Recolors this node and every node joined to it that isn't already the new color.
+    ///
+    /// Colors are kept so that every piece of connected nodes has a single color, and a piece is
+    /// only Colored if it contains a source. Callers keep that true by only ever spreading a
+    /// source's color into uncolored pieces, or an uncolored id into a piece with no source.
+    fn spread_color(&mut self, node: usize, new_color: CellColor) {
+        let mut queue = VecDeque::from([node]);
+        while let Some(current) = queue.pop_front() {
+            if self.node_color(current) == new_color {
+                continue;
+            }
+            self.recolor_node(current, new_color);
+            for &next_direction in self.node_directions(current) {
+                if self.is_node_connected(current, next_direction)
+                    && let Some(next) = self.offset_node(current, next_direction)
+                    && self.node_color(next) != new_color
+                {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// The shortest way a pipe could go from one cell to another through empty cells, for a drag
+    /// that skipped some. The last step isn't checked, since that's where the pipe is headed.
+    pub fn drag_path(&self, from: (usize, usize), to: (usize, usize)) -> Option<Vec<Move>> {
+        let from_index = self.get_index(from.0, from.1)?;
+        let to_index = self.get_index(to.0, to.1)?;
+        let mut came_from: Vec<Option<Move>> = vec![None; self.cells.len()];
+        let mut queue = VecDeque::from([from_index]);
+        while let Some(index) = queue.pop_front() {
+            if index == to_index {
+                let mut moves = Vec::new();
+                let mut current = index;
+                while let Some(step) = came_from[current] {
+                    moves.push(step);
+                    current = self.get_index(step.row, step.col)?;
+                }
+                moves.reverse();
+                return Some(moves);
+            }
+            let (row, col) = self.get_row_col(index);
+            for direction in Direction::ALL {
+                if direction == Direction::Warp {
+                    continue;
+                }
+                let Some(next_index) = self.get_offset_index(row, col, direction) else {
+                    continue;
+                };
+                let next = &self.cells[next_index];
+                let is_free = !next.is_source
+                    && !next.is_blocked
+                    && !next.is_bridge()
+                    && !next.is_warp()
+                    && next.num_connections() == 0;
+                if next_index != from_index
+                    && came_from[next_index].is_none()
+                    && (is_free || next_index == to_index)
+                {
+                    came_from[next_index] = Some(Move {
+                        row,
+                        col,
+                        direction,
+                    });
+                    queue.push_back(next_index);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn are_cells_connected(&self, row1: usize, col1: usize, row2: usize, col2: usize) -> bool {
+        match (self.get_index(row1, col1), self.get_index(row2, col2)) {
+            (Some(index1), Some(index2)) => self.cell_nodes(index1).any(|node1| {
+                self.cell_nodes(index2)
+                    .any(|node2| self.components[node1] == self.components[node2])
+            }),
+            _ => false,
+        }
+    }
+
+    /// Repeatedly makes connections that are the only way to complete the board from here, until
+    /// there are none left. Returns the connections that were made, in order.
+    pub fn apply_forced_moves(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        while let Some(forced_move) = self.find_forced_move() {
+            if !self.try_connect(forced_move.row, forced_move.col, forced_move.direction) {
+                break;
+            }
+            moves.push(forced_move);
+        }
+        moves
+    }
+
+    /// Finds a connection that every completion of the current board has to make:
+    /// - a pipe end (or a source with nothing attached) that only has one way to keep going
+    /// - an empty cell with only two neighbors it could possibly connect to, if the win condition
+    ///   fills the board
+    pub fn find_forced_move(&self) -> Option<Move> {
+        let fills_board = self.win_condition.fills_board();
+        for node in self.nodes() {
+            if !fills_board && matches!(self.node_color(node), CellColor::Empty(_)) {
+                continue;
+            }
+            let connections = self.node_connections(node);
+            let needed_connections = if self.cells[self.node_cell(node)].is_source {
+                1 - connections.min(1)
+            } else {
+                2 - connections.min(2)
+            };
+            if needed_connections == 0 {
+                continue;
+            }
+
+            let mut open_directions = self
+                .node_directions(node)
+                .iter()
+                .copied()
+                .filter(|&direction| self.can_extend(node, direction));
+            let first_direction = open_directions.next();
+            let second_direction = open_directions.next();
+            let is_forced = match (first_direction, second_direction) {
+                (Some(_), None) => needed_connections == 1,
+                (Some(_), Some(_)) => needed_connections == 2 && open_directions.next().is_none(),
+                _ => false,
+            };
+
+            if let Some(direction) = first_direction
+                && is_forced
+            {
+                let (row, col) = self.get_row_col(self.node_cell(node));
+                return Some(Move {
+                    row,
+                    col,
+                    direction,
+                });
+            }
+        }
+        None
+    }
+
+    /// Finds empty cells that can no longer be filled no matter how the rest of the board is
+    /// drawn: cells with too few neighbors left to connect to, and regions of empty cells that
+    /// no unfinished color can pass through, because it can't reach them from both ends. None
+    /// are dead if the win condition lets cells be left empty.
+    pub fn find_dead_cells(&self) -> Vec<(usize, usize)> {
+        if !self.win_condition.fills_board() {
+            return Vec::new();
+        }
+        let is_region_node = |node: usize| {
+            !self.cells[self.node_cell(node)].is_blocked
+                && matches!(self.node_color(node), CellColor::Empty(_))
+        };
+        let mut is_dead = vec![false; 2 * self.cells.len()];
+
+        for node in self.nodes() {
+            let connections = self.node_connections(node);
+            if !is_region_node(node) || connections >= 2 {
+                continue;
+            }
+            let exits = self
+                .node_directions(node)
+                .iter()
+                .filter(|&&direction| self.can_extend(node, direction))
+                .count();
+            if exits + connections < 2 {
+                is_dead[node] = true;
+            }
+        }
+
+        // label regions of empty nodes, where nodes are in the same region if they're already
+        // connected, or could be
+        const NO_REGION: usize = usize::MAX;
+        let mut regions = vec![NO_REGION; 2 * self.cells.len()];
+        let mut region_nodes: Vec<Vec<usize>> = Vec::new();
+        for start_node in self.nodes() {
+            if regions[start_node] != NO_REGION || !is_region_node(start_node) {
+                continue;
+            }
+            let region = region_nodes.len();
+            let mut nodes_in_region = vec![start_node];
+            regions[start_node] = region;
+            let mut stack = vec![start_node];
+            while let Some(node) = stack.pop() {
+                for &direction in self.node_directions(node) {
+                    let next_node = if let Some(next_node) = self.offset_node(node, direction) {
+                        next_node
+                    } else {
+                        continue;
+                    };
+                    if regions[next_node] != NO_REGION || !is_region_node(next_node) {
+                        continue;
+                    }
+                    if self.is_node_connected(node, direction) || self.can_extend(node, direction) {
+                        regions[next_node] = region;
+                        nodes_in_region.push(next_node);
+                        stack.push(next_node);
+                    }
+                }
+            }
+            region_nodes.push(nodes_in_region);
+        }
+
+        let regions_touching = |end_node: usize| {
+            self.node_directions(end_node)
+                .iter()
+                .filter(|&&direction| self.can_extend(end_node, direction))
+                .filter_map(|&direction| self.offset_node(end_node, direction))
+                .map(|next_node| regions[next_node])
+                .filter(|&region| region != NO_REGION)
+                .collect::<Vec<_>>()
+        };
+        let mut is_region_reachable = vec![false; region_nodes.len()];
+        let mut has_unfinished_color = false;
+        for (color_id, sources) in self.source_index.iter().enumerate() {
+            let (source1, source2) = match sources[..] {
+                [source1, source2] => (source1, source2),
+                [_, _, _, ..] if !self.are_sources_connected(color_id) => {
+                    has_unfinished_color = true;
+                    // any of the color's pipes might be the one to pass through, so this is a rough guess
+                    for node in self.nodes() {
+                        if self.node_color(node) == CellColor::Colored(color_id) {
+                            for region in regions_touching(node) {
+                                is_region_reachable[region] = true;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let end1 = self.pipe_end(source1);
+            if end1 == source2 {
+                continue;
+            }
+            has_unfinished_color = true;
+            let end2 = self.pipe_end(source2);
+            let end2_regions = regions_touching(end2);
+            for region in regions_touching(end1) {
+                if end2_regions.contains(&region) {
+                    is_region_reachable[region] = true;
+                }
+            }
+        }
+
+        // while sources are still being placed, nothing has a color to reach it with yet
+        for (region, nodes_in_region) in region_nodes.into_iter().enumerate() {
+            if has_unfinished_color && !is_region_reachable[region] {
+                for node in nodes_in_region {
+                    is_dead[node] = true;
+                }
+            }
+        }
+        (0..self.cells.len())
+            .filter(|&index| self.cell_nodes(index).any(|node| is_dead[node]))
+            .map(|index| self.get_row_col(index))
+            .collect()
+    }
+
+    /// follows the pipe out of a source to wherever it currently ends
+    fn pipe_end(&self, source_index: usize) -> usize {
+        let mut node = source_index;
+        let mut came_from: Option<Direction> = None;
+        loop {
+            let next_direction = self
+                .node_directions(node)
+                .iter()
+                .copied()
+                .find(|&direction| {
+                    Some(direction) != came_from && self.is_node_connected(node, direction)
+                });
+            match next_direction {
+                Some(direction)
+                    if node == source_index || !self.cells[self.node_cell(node)].is_source =>
+                {
+                    node = self
+                        .offset_node(node, direction)
+                        .expect("cells cannot be connected to the edge");
+                    came_from = Some(direction.opposite());
+                }
+                _ => return node,
+            }
+        }
+    }
+
+    /// whether try_connect would succeed without closing a pipe into a loop
+    fn can_extend(&self, node: usize, direction: Direction) -> bool {
+        if !self.node_directions(node).contains(&direction) {
+            return false;
+        }
+        let other_node = if let Some(other_node) = self.offset_node(node, direction) {
+            other_node
+        } else {
+            return false;
+        };
+        let cell = &self.cells[self.node_cell(node)];
+        let (row, col) = self.get_row_col(self.node_cell(node));
+        let (other_row, other_col) = self.get_row_col(self.node_cell(other_node));
+
+        if !self.is_lane_open(row, col, direction)
+            || !self.is_lane_open(other_row, other_col, direction.opposite())
+        {
+            return false;
+        }
+        if cell.is_direction_connected(direction) {
+            return false;
+        }
+        let (color, other_color) = (self.node_color(node), self.node_color(other_node));
+        if !CellColor::can_colors_connect(&color, &other_color) {
+            return false;
+        }
+        match (color, other_color) {
+            (CellColor::Empty(id1), CellColor::Empty(id2)) => id1 != id2,
+            (CellColor::Colored(id1), CellColor::Colored(id2)) if id1 == id2 => {
+                self.components[node] != self.components[other_node]
+            }
+            _ => true,
+        }
+    }
+
+    /// Renders the grid as a Graphviz graph. Every cell is a node, pipes are thick edges in the
+    /// color of their flow, and adjacencies without a pipe are dotted. Colors come from the
+    /// set19 scheme by color id, blocked cells are black, and nodes are pinned in place for
+    /// `neato -n`.
+    pub fn to_dot(&self) -> String {
+        let dot_color = |color: CellColor| match color {
+            CellColor::Colored(color_id) => (color_id % 9 + 1).to_string(),
+            CellColor::Empty(_) => "white".to_string(),
+        };
+
+        let mut dot = String::from("graph flow {\n");
+        dot.push_str("    node [shape=box, style=filled, colorscheme=set19];\n");
+        dot.push_str("    edge [colorscheme=set19];\n");
+        for (index, cell) in self.cells.iter().enumerate() {
+            let (row, col) = self.get_row_col(index);
+            dot.push_str(&format!(
+                "    r{row}c{col} [label=\"{row},{col}\", shape={}, fillcolor=\"{}\", pos=\"{},{}!\"];\n",
+                if cell.is_source { "circle" } else { "box" },
+                if cell.is_blocked {
+                    "black".to_string()
+                } else {
+                    dot_color(cell.color)
+                },
+                // hex rows shift half a cell right each
+                col * 72 + if self.is_hex { row * 36 } else { 0 },
+                (self.height - row) * 72,
+            ));
+        }
+        for (index, cell) in self.cells.iter().enumerate() {
+            let (row, col) = self.get_row_col(index);
+            // a warp's link is drawn from whichever of the pair comes first
+            let warp = cell
+                .warp_to
+                .filter(|&partner| (row, col) < partner)
+                .map(|_| Direction::Warp);
+            for direction in [Direction::Right, Direction::Down, Direction::DownLeft]
+                .into_iter()
+                .chain(warp)
+            {
+                let (other_row, other_col) =
+                    if let Some(other) = self.get_offset_row_col(row, col, direction) {
+                        other
+                    } else {
+                        continue;
+                    };
+                let attributes = if cell.is_direction_connected(direction) {
+                    match cell.lane_color(direction) {
+                        CellColor::Colored(_) => format!(
+                            "color=\"{}\", penwidth=4",
+                            dot_color(cell.lane_color(direction))
+                        ),
+                        CellColor::Empty(_) => "color=black, penwidth=4".to_string(),
+                    }
+                } else {
+                    "color=gray, style=dotted".to_string()
+                };
+                dot.push_str(&format!(
+                    "    r{row}c{col} -- r{other_row}c{other_col} [{attributes}];\n"
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_cells_wait_for_an_unfinished_color() {
+        let mut grid = FlowGrid::with_size(5, 5);
+        assert!(grid.find_dead_cells().is_empty());
+        grid.try_set_missing_source(0, 0, 0);
+        assert!(grid.find_dead_cells().is_empty());
+        grid.try_set_missing_source(0, 4, 0);
+        assert!(grid.find_dead_cells().is_empty());
+
+        // the bottom row is walled off from both of A's pipe ends
+        let grid = FlowGrid::from_board(&["A.A", "###", "..."]).expect("a valid board");
+        assert_eq!(grid.find_dead_cells(), [(2, 0), (2, 1), (2, 2)]);
+        // a dead end is dead whatever the colors are doing
+        let grid = FlowGrid::from_board(&[".#", "#."]).expect("a valid board");
+        assert_eq!(grid.find_dead_cells(), [(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn forced_moves_follow_the_only_way_on() {
+        // a corridor only has one way through it
+        let mut grid = FlowGrid::from_board(&["A...A"]).expect("a valid board");
+        let forced_move = grid.find_forced_move().expect("the corridor is forced");
+        assert!(grid.try_connect(forced_move.row, forced_move.col, forced_move.direction));
+        let moves = grid.apply_forced_moves();
+        assert_eq!(moves.len(), 3);
+        assert!(grid.is_solved());
+        assert!(grid.find_forced_move().is_none());
+
+        // the sources have a choice, but an empty corner has to take both its neighbors
+        let grid = FlowGrid::from_board(&["A..", "...", "..A"]).expect("a valid board");
+        let forced_move = grid.find_forced_move().expect("the corner is forced");
+        assert_eq!((forced_move.row, forced_move.col), (0, 2));
+    }
+
+    #[test]
+    fn empty_cells_are_only_forced_or_dead_when_the_board_has_to_be_filled() {
+        let mut grid = FlowGrid::from_board(&["A..", "...", "..A"]).expect("a valid board");
+        grid.set_win_condition(crate::flow_rules::EndpointsConnected);
+        assert!(grid.find_forced_move().is_none());
+
+        let mut grid = FlowGrid::from_board(&["A.A", "###", "..."]).expect("a valid board");
+        grid.set_win_condition(crate::flow_rules::EndpointsConnected);
+        assert!(grid.find_dead_cells().is_empty());
+        // the sources still have to reach each other
+        let mut grid = FlowGrid::from_board(&["A...A"]).expect("a valid board");
+        grid.set_win_condition(crate::flow_rules::EndpointsConnected);
+        assert!(grid.find_forced_move().is_some());
+    }
+
+    #[test]
+    fn pipes_cant_enter_blocked_cells() {
+        let mut grid = FlowGrid::from_board(&["AA.", ".#.", "..."]).expect("a valid board");
+        assert_eq!(grid.to_board().expect("few colors"), ["AA.", ".#.", "..."]);
+        assert!(!grid.try_connect(0, 1, Direction::Down));
+        assert!(grid.try_connect(0, 1, Direction::Right));
+    }
+
+    #[test]
+    fn hexes_connect_diagonally() {
+        let mut grid = FlowGrid::hex_with_size(2, 2);
+        grid.try_set_missing_source(0, 0, 0);
+        grid.try_set_missing_source(1, 1, 0);
+        assert_eq!(
+            grid.direction_between(0, 1, 1, 0),
+            Some(Direction::DownLeft)
+        );
+        assert!(grid.try_connect(0, 0, Direction::Right));
+        assert!(grid.try_connect(0, 1, Direction::DownLeft));
+        assert!(grid.try_connect(1, 0, Direction::Right));
+        assert!(grid.is_solved());
+    }
+
+    #[test]
+    fn wrapped_pipes_cross_the_edges() {
+        let mut grid = FlowGrid::from_board(&["ABBA"])
+            .expect("a valid board")
+            .wrapping();
+        assert!(grid.crosses_edge(0, 0, Direction::Left));
+        assert!(!grid.crosses_edge(0, 0, Direction::Up));
+        assert!(grid.try_connect(0, 0, Direction::Left));
+        assert!(grid.try_connect(0, 1, Direction::Right));
+        assert!(grid.is_solved());
+
+        // a new column goes between the ends, so the pipe across them is cut
+        grid.add_col();
+        assert!(!grid.get(0, 0).expect("in bounds").is_connected_left);
+        assert!(!grid.is_solved());
+    }
+
+    #[test]
+    fn pipes_go_through_warps() {
+        let mut grid = FlowGrid::from_board(&["A.#.A"]).expect("a valid board");
+        assert!(grid.try_add_warp(0, 1, 0, 3));
+        assert_eq!(grid.warps(), [((0, 1), (0, 3))]);
+        assert!(grid.try_connect(0, 0, Direction::Right));
+        assert!(grid.try_connect(0, 1, Direction::Warp));
+        assert!(grid.try_connect(0, 3, Direction::Right));
+        assert!(grid.is_solved());
+        assert!(grid.try_remove_warp(0, 3));
+        assert!(!grid.has_warps());
+        assert!(!grid.is_solved());
+
+        // a pipe into a warp can't carry on past it
+        let mut grid = FlowGrid::from_board(&["A..A"]).expect("a valid board");
+        assert!(grid.try_add_warp(0, 1, 0, 2));
+        assert!(grid.try_connect(0, 0, Direction::Right));
+        assert!(!grid.try_connect(0, 1, Direction::Right));
+    }
+
+    #[test]
+    fn extra_sources_join_into_one_pipe() {
+        let mut grid = FlowGrid::from_board(&["A.A.A"]).expect("a valid board");
+        assert!(grid.has_extra_sources());
+        assert_eq!(grid.get_sources(0), [(0, 0), (0, 2), (0, 4)]);
+        // the middle source takes both pipes
+        for col in 0..4 {
+            assert!(grid.try_connect(0, col, Direction::Right));
+        }
+        assert!(grid.is_solved());
+        assert!(grid.try_remove_source(0, 4));
+        assert_eq!(grid.get(0, 3).expect("in bounds").num_connections(), 0);
+        assert!(!grid.try_set_missing_source(0, 4, 0));
+        assert!(grid.try_add_extra_source(0, 4, 0));
+    }
+
+    #[test]
+    fn long_histories_fold_into_a_base() {
+        let mut grid = FlowGrid::from_board(&["A.A"]).expect("a valid board");
+        let generation = grid.generation();
+        let copy = grid.clone();
+        assert_eq!(copy.generation(), generation);
+        for _ in 0..MAX_HISTORY_LEN {
+            assert!(grid.try_connect(0, 0, Direction::Right));
+            assert!(grid.try_disconnect(0, 0, Direction::Right));
+        }
+        assert!(grid.try_connect(0, 0, Direction::Right));
+        assert_ne!(grid.generation(), generation);
+        assert!(grid.history().len() <= MAX_HISTORY_LEN);
+        let events_applied = grid.events_applied();
+        assert!(events_applied > 2 * MAX_HISTORY_LEN);
+        assert!(grid.events_since(0).is_none());
+        assert_eq!(
+            grid.events_since(events_applied - 1).map(<[_]>::len),
+            Some(1)
+        );
+
+        let replayed = grid.replay_history();
+        assert!(replayed.has_same_history(&grid));
+        assert!(
+            replayed
+                .get(0, 1)
+                .expect("in bounds")
+                .is_direction_connected(Direction::Left)
+        );
+        let json = serde_json::to_string(&grid).expect("grids save");
+        let loaded: FlowGrid = serde_json::from_str(&json).expect("grids load");
+        assert_eq!(loaded.events_applied(), events_applied);
+        assert_eq!(loaded.replay_history().history(), grid.history());
+    }
+}
@@ -0,0 +1,216 @@
+/// This file helps an author fix a puzzle with more than one solution. It tries every way of
+/// moving one source a short way, and of walling off one empty cell, counts the solutions each
+/// leaves, and ranks the ones that can still be solved by how few solutions they have, so a
+/// change that makes the puzzle unique comes first.
+///
+/// On a plain square board where every cell is filled, sources only move two steps, straight or
+/// diagonally. The pipes cover a fixed number of cells between them, and moving one end of a pipe
+/// to a touching cell changes whether that pipe covers an odd or even number. Nothing else makes
+/// up for it, so those moves never have a solution. A wall takes one cell out of the count the
+/// same way, so walls aren't tried there either. Hex cells, wrapping edges, warps and win
+/// conditions that leave cells empty all break that argument, so on those boards sources move
+/// one step too, and every empty cell is tried as a wall.
+use crate::flow_count::count_solutions_exact;
+use crate::flow_grid::FlowGrid;
+
+/// where a source can move to, as row and column offsets
+const MOVES: [(isize, isize); 8] = [
+    (-2, 0),
+    (2, 0),
+    (0, -2),
+    (0, 2),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+    (1, 1),
+];
+
+/// where a source can also move to when the board isn't plain, as row and column offsets
+const ONE_STEP_MOVES: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// what a repair changes about the puzzle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepairChange {
+    /// one of the color's sources moved a short way
+    MoveSource {
+        color_id: usize,
+        from: (usize, usize),
+        to: (usize, usize),
+    },
+    /// an empty cell walled off
+    AddWall((usize, usize)),
+}
+
+#[derive(Clone)]
+pub struct Repair {
+    pub change: RepairChange,
+    /// how many solutions the puzzle has after the change
+    pub solutions: u128,
+    /// the puzzle after the change, with no pipes
+    pub grid: FlowGrid,
+}
+
+impl Repair {
+    pub fn is_unique(&self) -> bool {
+        self.solutions == 1
+    }
+
+    /// the cell the change ends up at, to point it out on the board
+    pub fn cell(&self) -> (usize, usize) {
+        match self.change {
+            RepairChange::MoveSource { to, .. } => to,
+            RepairChange::AddWall(cell) => cell,
+        }
+    }
+}
+
+/// Whether the board is a square grid with no wrapping or warps, where every cell has to be
+/// filled. Only there do one-step moves and walls always leave it unsolvable.
+fn is_plain(grid: &FlowGrid) -> bool {
+    !grid.is_hex() && !grid.wraps() && !grid.has_warps() && grid.win_condition().fills_board()
+}
+
+/// Every single source move or wall that leaves the puzzle solvable, fewest solutions first.
+/// Changes that tie keep the order they're found in: source moves by color and then cell, then
+/// walls in reading order.
+pub fn propose_repairs(grid: &FlowGrid) -> Vec<Repair> {
+    let mut puzzle = grid.clone();
+    puzzle.clear_pipes();
+    let is_plain = is_plain(&puzzle);
+    let mut moves = MOVES.to_vec();
+    if !is_plain {
+        moves.extend(ONE_STEP_MOVES);
+    }
+
+    let mut repairs = Vec::new();
+    for color_id in 0..puzzle.num_colors() {
+        let Some((source1, source2)) = puzzle.get_source_pair(color_id) else {
+            continue;
+        };
+        for (row, col) in [source1, source2] {
+            for &(row_offset, col_offset) in &moves {
+                let (Some(to_row), Some(to_col)) = (
+                    row.checked_add_signed(row_offset),
+                    col.checked_add_signed(col_offset),
+                ) else {
+                    continue;
+                };
+                if puzzle.get(to_row, to_col).is_none_or(|cell| cell.is_source) {
+                    continue;
+                }
+                let mut moved = puzzle.clone();
+                if !moved.try_remove_source(row, col)
+                    || !moved.try_set_missing_source(to_row, to_col, color_id)
+                {
+                    continue;
+                }
+                let solutions = count_solutions_exact(&moved);
+                if solutions > 0 {
+                    repairs.push(Repair {
+                        change: RepairChange::MoveSource {
+                            color_id,
+                            from: (row, col),
+                            to: (to_row, to_col),
+                        },
+                        solutions,
+                        grid: moved,
+                    });
+                }
+            }
+        }
+    }
+    // on a plain board a wall never leaves a solution, just like a one-step move
+    if !is_plain {
+        for row in 0..puzzle.height {
+            for col in 0..puzzle.width {
+                let mut walled = puzzle.clone();
+                if !walled.try_block(row, col) {
+                    continue;
+                }
+                let solutions = count_solutions_exact(&walled);
+                if solutions > 0 {
+                    repairs.push(Repair {
+                        change: RepairChange::AddWall((row, col)),
+                        solutions,
+                        grid: walled,
+                    });
+                }
+            }
+        }
+    }
+    // sort_by_key is stable, so ties stay in the order they were found
+    repairs.sort_by_key(|repair| repair.solutions);
+    repairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_grid::CellColor;
+
+    /// whether the source at this cell is one of the color's
+    fn is_source_of(grid: &FlowGrid, (row, col): (usize, usize), color_id: usize) -> bool {
+        grid.get(row, col)
+            .is_some_and(|cell| cell.is_source && cell.color == CellColor::Colored(color_id))
+    }
+
+    #[test]
+    fn repairs_move_one_source_and_rank_unique_first() {
+        let grid = FlowGrid::from_board(&["R...", "....", "...R", "..GG"]).expect("valid board");
+        assert_eq!(count_solutions_exact(&grid), 4);
+        let repairs = propose_repairs(&grid);
+        assert!(!repairs.is_empty());
+        assert!(
+            repairs
+                .windows(2)
+                .all(|pair| pair[0].solutions <= pair[1].solutions)
+        );
+        for repair in &repairs {
+            assert_eq!(count_solutions_exact(&repair.grid), repair.solutions);
+            match repair.change {
+                RepairChange::MoveSource { color_id, from, to } => {
+                    assert!(is_source_of(&repair.grid, to, color_id));
+                    assert!(!is_source_of(&repair.grid, from, color_id));
+                }
+                RepairChange::AddWall((row, col)) => {
+                    assert!(
+                        repair
+                            .grid
+                            .get(row, col)
+                            .is_some_and(|cell| cell.is_blocked)
+                    );
+                }
+            }
+        }
+        assert!(repairs[0].is_unique());
+    }
+
+    #[test]
+    fn boards_that_arent_plain_also_get_walls_and_one_step_moves() {
+        let grid = FlowGrid::from_board(&["R...", "....", "...R", "..GG"]).expect("valid board");
+        assert!(is_plain(&grid));
+        assert!(!is_plain(&grid.clone().wrapping()));
+        let mut connect_only = grid.clone();
+        connect_only.set_win_condition(crate::flow_rules::EndpointsConnected);
+        assert!(!is_plain(&connect_only));
+
+        let mut hex = FlowGrid::hex_with_size(3, 3);
+        for (row, col, color_id) in [(0, 0, 0), (2, 2, 0), (2, 0, 1), (2, 1, 1)] {
+            assert!(hex.try_set_missing_source(row, col, color_id));
+        }
+        assert_eq!(count_solutions_exact(&hex), 6);
+        let repairs = propose_repairs(&hex);
+        assert!(repairs.iter().any(|repair| repair.change
+            == RepairChange::MoveSource {
+                color_id: 0,
+                from: (0, 0),
+                to: (0, 1),
+            }));
+        let wall = repairs
+            .iter()
+            .find(|repair| repair.change == RepairChange::AddWall((0, 1)))
+            .expect("a wall is tried");
+        assert!(wall.is_unique());
+        assert!(wall.grid.get(0, 1).is_some_and(|cell| cell.is_blocked));
+    }
+}
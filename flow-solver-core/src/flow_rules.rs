@@ -0,0 +1,117 @@
+/// This file has the rules for when a board counts as finished. The grid keeps whichever rule is
+/// active and asks it from is_solved, so a variant can bring its own objective by implementing
+/// WinCondition instead of changing the grid.
+use crate::flow_grid::{CellColor, FlowGrid};
+
+pub trait WinCondition {
+    /// what the player has to do, short enough for a dropdown
+    fn name(&self) -> String;
+    fn is_met(&self, grid: &FlowGrid) -> bool;
+    /// whether every cell has to be filled to win, so an empty cell that can't be is a mistake
+    fn fills_board(&self) -> bool;
+}
+
+/// The classic rule: every pair is connected and no cell is left empty.
+pub struct AllCellsFilled;
+
+impl WinCondition for AllCellsFilled {
+    fn name(&self) -> String {
+        "Fill the board".to_string()
+    }
+
+    fn is_met(&self, grid: &FlowGrid) -> bool {
+        are_colors_connected(grid)
+            && (0..grid.height).all(|row| {
+                (0..grid.width).all(|col| grid.get(row, col).is_some_and(|cell| cell.is_filled()))
+            })
+    }
+
+    fn fills_board(&self) -> bool {
+        true
+    }
+}
+
+/// Every pair is connected, but cells can be left empty.
+pub struct EndpointsConnected;
+
+impl WinCondition for EndpointsConnected {
+    fn name(&self) -> String {
+        "Connect the pairs".to_string()
+    }
+
+    fn is_met(&self, grid: &FlowGrid) -> bool {
+        are_colors_connected(grid)
+    }
+
+    fn fills_board(&self) -> bool {
+        false
+    }
+}
+
+/// Every pair is connected by a pipe covering exactly the given number of cells, sources
+/// included.
+pub struct ExactLengths {
+    /// indexed by color id. Colors past the end can be any length.
+    pub lengths: Vec<usize>,
+}
+
+impl ExactLengths {
+    pub const NAME: &str = "Match the pipe lengths";
+
+    /// the lengths of each pipe in a finished board
+    pub fn from_solution(solution: &FlowGrid) -> Self {
+        ExactLengths {
+            lengths: (0..solution.num_colors())
+                .map(|color_id| pipe_length(solution, color_id))
+                .collect(),
+        }
+    }
+}
+
+impl WinCondition for ExactLengths {
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn is_met(&self, grid: &FlowGrid) -> bool {
+        are_colors_connected(grid)
+            && self
+                .lengths
+                .iter()
+                .enumerate()
+                .all(|(color_id, &length)| pipe_length(grid, color_id) == length)
+    }
+
+    fn fills_board(&self) -> bool {
+        false
+    }
+}
+
+/// Whether every color that has a source has all of them joined by pipes, which for a color with
+/// more than two is a tree. A board with no sources doesn't count.
+fn are_colors_connected(grid: &FlowGrid) -> bool {
+    let mut has_color = false;
+    for color_id in 0..grid.num_colors() {
+        if grid.get_sources(color_id).is_empty() {
+            continue;
+        }
+        if !grid.are_sources_connected(color_id) {
+            return false;
+        }
+        has_color = true;
+    }
+    has_color
+}
+
+/// how many cells the color covers. Once its sources are connected, that's all one pipe.
+fn pipe_length(grid: &FlowGrid, color_id: usize) -> usize {
+    (0..grid.height)
+        .flat_map(|row| (0..grid.width).map(move |col| (row, col)))
+        .filter(|&(row, col)| {
+            grid.get(row, col).is_some_and(|cell| {
+                cell.color == CellColor::Colored(color_id)
+                    || cell.bridge_color == Some(CellColor::Colored(color_id))
+            })
+        })
+        .count()
+}
@@ -0,0 +1,1121 @@
+/// This file has the solver. It takes a grid with sources placed and does all of the trial and
+/// error for you: it grows one pipe at a time from a source towards its partner, always picking
+/// the pipe with the fewest options, and backs up as soon as the board can't be filled anymore.
+use crate::flow_bitboard::CompactGrid;
+use crate::flow_grid::{Direction, FlowGrid, Move};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc,
+};
+use web_time::{Duration, Instant};
+
+/// how many search steps to take between progress reports and timeout checks
+const STEPS_PER_REPORT: u64 = 10_000;
+
+/// limits on how much work a solve is allowed to do before giving up, and which pipes it routes
+#[derive(Clone, Debug)]
+pub struct SolveOptions {
+    /// checked every few thousand moves, so it can run over slightly
+    pub timeout: Duration,
+    /// how many moves the search may try. Unlike the timeout, this gives up at the same point
+    /// on every machine.
+    pub node_limit: Option<u64>,
+    /// finish the pipes already on the grid instead of starting over from the sources
+    pub keep_pipes: bool,
+    /// split the search across every core. The node limit then applies to each branch.
+    pub parallel: bool,
+    /// only route these colors. Every other pipe is left as it is, and cells can stay empty.
+    pub colors: Option<Vec<usize>>,
+    pub config: SolverConfig,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            timeout: Duration::from_secs(30),
+            node_limit: None,
+            keep_pipes: false,
+            parallel: false,
+            colors: None,
+            config: SolverConfig::default(),
+        }
+    }
+}
+
+/// Heuristics for the order the search tries things in. They only change how quickly a solution
+/// is found, never which solutions exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolverConfig {
+    /// extend the flow with the fewest moves available first
+    pub most_constrained_first: bool,
+    /// among flows that are otherwise tied, extend the one farthest from its target first
+    pub longest_pairs_first: bool,
+    /// try moves into cells with the fewest open neighbors first, like corners and edges
+    pub corners_first: bool,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        SolverConfig {
+            most_constrained_first: true,
+            longest_pairs_first: false,
+            corners_first: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum SolveOutcome {
+    Solved(FlowGrid),
+    /// the whole search space was explored without finding a solution
+    Unsolvable,
+    /// a limit from the SolveOptions was hit before the search finished
+    TimedOut,
+    /// the puzzle has bridges, which the solver can't route through yet
+    Unsupported,
+}
+
+/// Solves the puzzle made up of the grid's sources, giving up once one of the options' limits is
+/// reached. Pipes already on the grid are ignored unless the options keep them. A color that is
+/// missing a source makes the puzzle unsolvable.
+pub fn solve_with_options(grid: &FlowGrid, options: &SolveOptions) -> SolveOutcome {
+    run(&mut search(grid, options), options, |_| true).expect("reporting never stops the search")
+}
+
+fn search(grid: &FlowGrid, options: &SolveOptions) -> Solutions {
+    let solutions = match &options.colors {
+        Some(colors) => {
+            let mut grid = grid.clone();
+            if !options.keep_pipes {
+                for &color_id in colors {
+                    grid.clear_color_pipes(color_id);
+                }
+            }
+            Solutions::new(&grid, Some(colors))
+        }
+        None if options.keep_pipes => completions(grid),
+        None => solutions(grid),
+    };
+    solutions.with_config(options.config)
+}
+
+/// Steps the search until it finds a solution, runs out of options, or hits a limit. Reports
+/// progress every so often, and stops early (returning None) if the report returns false.
+fn run(
+    solutions: &mut Solutions,
+    options: &SolveOptions,
+    mut report: impl FnMut(Progress) -> bool,
+) -> Option<SolveOutcome> {
+    if solutions.is_unsupported {
+        return Some(SolveOutcome::Unsupported);
+    }
+    let start = Instant::now();
+    let mut steps_until_report = STEPS_PER_REPORT;
+    let outcome = loop {
+        match solutions.step() {
+            Some(SolveEvent::Solved) => {
+                let solution = solutions.grid().expect("solved a grid");
+                break Some(SolveOutcome::Solved(solution));
+            }
+            Some(_) => {}
+            None => break Some(SolveOutcome::Unsolvable),
+        }
+
+        if options
+            .node_limit
+            .is_some_and(|node_limit| solutions.nodes_explored >= node_limit)
+        {
+            break Some(SolveOutcome::TimedOut);
+        }
+
+        steps_until_report -= 1;
+        if steps_until_report == 0 {
+            steps_until_report = STEPS_PER_REPORT;
+            if start.elapsed() >= options.timeout {
+                break Some(SolveOutcome::TimedOut);
+            }
+            if !report(solutions.progress()) {
+                break None;
+            }
+        }
+    };
+    solutions.wall_time += start.elapsed();
+    outcome
+}
+
+/// Lazily finds every distinct solution for the grid's sources, one at a time. Any pipes already
+/// on the grid are ignored.
+pub fn solutions(grid: &FlowGrid) -> Solutions {
+    let mut cleared_grid = grid.clone();
+    cleared_grid.clear_pipes();
+    completions(&cleared_grid)
+}
+
+/// Lazily finds every way to finish the grid that keeps all of the pipes already on it.
+pub fn completions(grid: &FlowGrid) -> Solutions {
+    Solutions::new(grid, None)
+}
+
+/// Like solve_with_options, but splits the search into branches and works on them on every core
+/// at once. Returns whichever solution a branch finds first, so on a puzzle with several it may
+/// not be the one solve_with_options would find. Reports the total progress every so often, and
+/// stops early (returning None) if the report returns false.
+fn solve_in_parallel(
+    grid: &FlowGrid,
+    options: &SolveOptions,
+    report: impl Fn(Progress) -> bool + Sync,
+) -> Option<(SolveOutcome, SolveStats)> {
+    let start = Instant::now();
+    let search = search(grid, options);
+    if search.is_unsupported {
+        return Some((SolveOutcome::Unsupported, SolveStats::default()));
+    }
+    let Some(solver) = search.solver else {
+        return Some((SolveOutcome::Unsolvable, SolveStats::default()));
+    };
+    let mut split_stats = SolveStats::default();
+    let branches = split(
+        solver,
+        rayon::current_num_threads() * BRANCHES_PER_THREAD,
+        &mut split_stats,
+    );
+
+    let is_done = AtomicBool::new(false);
+    let is_cancelled = AtomicBool::new(false);
+    let did_time_out = AtomicBool::new(false);
+    let nodes_explored = AtomicU64::new(0);
+    let stats = Mutex::new(split_stats);
+    let solution = branches.into_par_iter().find_map_any(|branch| {
+        // every branch shares the one deadline
+        let branch_options = SolveOptions {
+            timeout: options.timeout.saturating_sub(start.elapsed()),
+            ..options.clone()
+        };
+        let mut solutions = Solutions::from_solver(Some(branch));
+        let mut reported_nodes = 0;
+        let outcome = run(&mut solutions, &branch_options, |progress| {
+            let new_nodes = progress.nodes_explored - reported_nodes;
+            reported_nodes = progress.nodes_explored;
+            let total = nodes_explored.fetch_add(new_nodes, Ordering::Relaxed) + new_nodes;
+            if !report(Progress {
+                nodes_explored: total,
+                depth: progress.depth,
+            }) {
+                is_cancelled.store(true, Ordering::Relaxed);
+            }
+            !is_done.load(Ordering::Relaxed) && !is_cancelled.load(Ordering::Relaxed)
+        });
+
+        let branch_stats = solutions.stats();
+        let mut stats = stats
+            .lock()
+            .expect("branches never panic while holding the stats");
+        stats.nodes_explored += branch_stats.nodes_explored;
+        stats.max_depth = stats.max_depth.max(branch_stats.max_depth);
+        stats.backtracks += branch_stats.backtracks;
+        stats.propagation_steps += branch_stats.propagation_steps;
+        match outcome {
+            Some(SolveOutcome::Solved(solution)) => {
+                is_done.store(true, Ordering::Relaxed);
+                Some(solution)
+            }
+            Some(SolveOutcome::TimedOut) => {
+                did_time_out.store(true, Ordering::Relaxed);
+                None
+            }
+            Some(SolveOutcome::Unsolvable | SolveOutcome::Unsupported) | None => None,
+        }
+    });
+
+    if is_cancelled.load(Ordering::Relaxed) && solution.is_none() {
+        return None;
+    }
+    let mut stats = stats.into_inner().expect("every branch is done");
+    stats.wall_time = start.elapsed();
+    let outcome = match solution {
+        Some(solution) => SolveOutcome::Solved(solution),
+        None if did_time_out.load(Ordering::Relaxed) => SolveOutcome::TimedOut,
+        None => SolveOutcome::Unsolvable,
+    };
+    Some((outcome, stats))
+}
+
+/// how many branches to split a parallel search into for each thread, so a thread that finishes
+/// its branches early has more to pick up
+const BRANCHES_PER_THREAD: usize = 8;
+
+/// Splits a search into independent branches by making its first guesses every possible way,
+/// until there are at least this many branches or nothing is left to guess. Branches that are
+/// already dead ends are dropped. The guesses are counted in the stats.
+fn split(solver: Solver, branch_count: usize, stats: &mut SolveStats) -> Vec<Solver> {
+    let mut branches = vec![solver];
+    while branches.len() < branch_count {
+        let mut next_branches = Vec::new();
+        let mut did_split = false;
+        for branch in branches {
+            stats.propagation_steps += 1;
+            if !branch.is_viable() {
+                did_split = true;
+                continue;
+            }
+            match branch.next_flow() {
+                Some((flow_index, moves)) => {
+                    did_split = true;
+                    for direction in moves {
+                        let mut next_branch = branch.clone();
+                        next_branch.apply_move(flow_index, direction);
+                        stats.nodes_explored += 1;
+                        next_branches.push(next_branch);
+                    }
+                }
+                // already solved
+                None => next_branches.push(branch),
+            }
+        }
+        branches = next_branches;
+        if !did_split {
+            break;
+        }
+    }
+    branches
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    /// how many moves the search has tried so far
+    pub nodes_explored: u64,
+    /// how many moves deep the search currently is
+    pub depth: usize,
+}
+
+/// everything the search counted while it worked
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SolveStats {
+    /// how many moves the search tried
+    pub nodes_explored: u64,
+    /// the most moves deep the search ever went
+    pub max_depth: usize,
+    /// how many moves were taken back after leading to a dead end
+    pub backtracks: u64,
+    /// how many times a board was checked for dead ends, which is how the search prunes
+    pub propagation_steps: u64,
+    /// time spent solving, not counting time the search sat paused between steps
+    pub wall_time: Duration,
+}
+
+enum BackgroundMessage {
+    Progress(Progress),
+    Finished(Box<SolveOutcome>, SolveStats),
+}
+
+/// a solve running on its own thread, so the UI stays responsive while it works
+pub struct BackgroundSolve {
+    receiver: mpsc::Receiver<BackgroundMessage>,
+    is_cancelled: Arc<AtomicBool>,
+    progress: Progress,
+}
+
+/// Starts solving the grid's sources on a separate thread. Poll the result to check on it.
+pub fn solve_in_background(grid: &FlowGrid, options: SolveOptions) -> BackgroundSolve {
+    let (sender, receiver) = mpsc::channel();
+    let is_cancelled = Arc::new(AtomicBool::new(false));
+
+    let grid = grid.clone();
+    let worker_is_cancelled = Arc::clone(&is_cancelled);
+    std::thread::spawn(move || {
+        let report = |progress: Progress| {
+            !worker_is_cancelled.load(Ordering::Relaxed)
+                && sender.send(BackgroundMessage::Progress(progress)).is_ok()
+        };
+        let finished = if options.parallel {
+            solve_in_parallel(&grid, &options, report)
+        } else {
+            let mut solutions = search(&grid, &options);
+            run(&mut solutions, &options, report).map(|outcome| (outcome, solutions.stats()))
+        };
+        if let Some((outcome, stats)) = finished {
+            let _ = sender.send(BackgroundMessage::Finished(Box::new(outcome), stats));
+        }
+    });
+
+    BackgroundSolve {
+        receiver,
+        is_cancelled,
+        progress: Progress::default(),
+    }
+}
+
+impl BackgroundSolve {
+    /// Catches up on messages from the worker. Returns Some once the solve is done.
+    pub fn poll(&mut self) -> Option<(SolveOutcome, SolveStats)> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(BackgroundMessage::Progress(progress)) => self.progress = progress,
+                Ok(BackgroundMessage::Finished(outcome, stats)) => return Some((*outcome, stats)),
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Some((SolveOutcome::TimedOut, SolveStats::default()));
+                }
+            }
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for BackgroundSolve {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Any other slow work running on its own thread, like counting or rating, for when there's no
+/// progress to report along the way. Dropping it doesn't stop the thread, but its answer is
+/// thrown away.
+pub struct BackgroundTask<T> {
+    receiver: mpsc::Receiver<T>,
+}
+
+/// Starts the work on a separate thread. Poll the result to check on it.
+pub fn run_in_background<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> BackgroundTask<T> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(work());
+    });
+    BackgroundTask { receiver }
+}
+
+impl<T> BackgroundTask<T> {
+    /// Returns Some once the work is done, or None while it's still going. Work that panicked is
+    /// never done.
+    pub fn poll(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+pub struct Solutions {
+    solver: Option<Solver>,
+    /// whether the grid has bridges, which is why there's no solver rather than no solution
+    is_unsupported: bool,
+    stack: Vec<Frame>,
+    /// whether we just made a move and have to look at the state it left us in
+    should_expand: bool,
+    nodes_explored: u64,
+    max_depth: usize,
+    backtracks: u64,
+    propagation_steps: u64,
+    /// only counts time spent in run
+    wall_time: Duration,
+}
+
+/// a step of the search: the moves of one flow that haven't been tried yet, and the one that
+/// is currently applied
+struct Frame {
+    flow_index: usize,
+    untried_moves: Vec<Direction>,
+    applied_move: Option<((usize, usize), Direction)>,
+}
+
+/// one unit of work done by the search, so the search can be watched as it happens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolveEvent {
+    /// a flow's pipe was extended by one cell
+    Connect(Move),
+    /// a connection was taken back because it led to a dead end
+    Disconnect(Move),
+    /// the grid is currently a complete solution
+    Solved,
+}
+
+impl Solutions {
+    fn new(grid: &FlowGrid, colors: Option<&[usize]>) -> Self {
+        Solutions {
+            is_unsupported: grid.has_bridges(),
+            ..Solutions::from_solver(Solver::new(grid, colors))
+        }
+    }
+
+    fn from_solver(solver: Option<Solver>) -> Self {
+        Solutions {
+            solver,
+            is_unsupported: false,
+            stack: Vec::new(),
+            should_expand: true,
+            nodes_explored: 0,
+            max_depth: 0,
+            backtracks: 0,
+            propagation_steps: 0,
+            wall_time: Duration::ZERO,
+        }
+    }
+
+    pub fn with_config(mut self, config: SolverConfig) -> Self {
+        if let Some(solver) = &mut self.solver {
+            solver.config = config;
+        }
+        self
+    }
+
+    /// Does the next unit of work in the search. Returns None once every solution has been found.
+    pub fn step(&mut self) -> Option<SolveEvent> {
+        let solver = self.solver.as_mut()?;
+
+        loop {
+            if self.should_expand {
+                self.should_expand = false;
+                self.propagation_steps += 1;
+                if solver.is_viable() {
+                    match solver.next_flow() {
+                        Some((flow_index, mut moves)) => {
+                            moves.reverse();
+                            self.stack.push(Frame {
+                                flow_index,
+                                untried_moves: moves,
+                                applied_move: None,
+                            });
+                        }
+                        None if solver.is_complete() => return Some(SolveEvent::Solved),
+                        None => {}
+                    }
+                }
+            }
+
+            let frame = self.stack.last_mut()?;
+            if let Some(((row, col), direction)) = frame.applied_move.take() {
+                solver.undo_move(frame.flow_index, (row, col), direction);
+                self.backtracks += 1;
+                return Some(SolveEvent::Disconnect(Move {
+                    row,
+                    col,
+                    direction,
+                }));
+            }
+            if let Some(direction) = frame.untried_moves.pop() {
+                let (row, col) = solver.flows[frame.flow_index].head;
+                solver.apply_move(frame.flow_index, direction);
+                frame.applied_move = Some(((row, col), direction));
+                self.should_expand = true;
+                self.nodes_explored += 1;
+                self.max_depth = self.max_depth.max(self.stack.len());
+                return Some(SolveEvent::Connect(Move {
+                    row,
+                    col,
+                    direction,
+                }));
+            } else {
+                self.stack.pop();
+            }
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            nodes_explored: self.nodes_explored,
+            depth: self.stack.len(),
+        }
+    }
+
+    pub fn stats(&self) -> SolveStats {
+        SolveStats {
+            nodes_explored: self.nodes_explored,
+            max_depth: self.max_depth,
+            backtracks: self.backtracks,
+            propagation_steps: self.propagation_steps,
+            wall_time: self.wall_time,
+        }
+    }
+
+    /// Finds the next solution like the iterator does, but gives up once one of the options'
+    /// limits is reached, leaving the search where it stopped so it can carry on later. Only the
+    /// limits are used from the options, and the node limit counts every move this search has
+    /// tried so far.
+    pub fn next_with_options(&mut self, options: &SolveOptions) -> SolveOutcome {
+        run(self, options, |_| true).expect("reporting never stops the search")
+    }
+
+    /// the state the search is currently looking at
+    pub fn grid(&self) -> Option<FlowGrid> {
+        self.solver
+            .as_ref()
+            .map(|solver| solver.grid.to_grid(&solver.template))
+    }
+}
+
+impl Iterator for Solutions {
+    type Item = FlowGrid;
+
+    fn next(&mut self) -> Option<FlowGrid> {
+        loop {
+            if self.step()? == SolveEvent::Solved {
+                return self.grid();
+            }
+        }
+    }
+}
+
+/// The cells two boards of the same size differ in, by color or by connections.
+pub fn differing_cells(first: &FlowGrid, second: &FlowGrid) -> Vec<(usize, usize)> {
+    (0..first.height)
+        .flat_map(|row| (0..first.width).map(move |col| (row, col)))
+        .filter(|&(row, col)| {
+            let (cell1, cell2) = (first.get(row, col), second.get(row, col));
+            match (cell1, cell2) {
+                (Some(cell1), Some(cell2)) => {
+                    cell1.color != cell2.color
+                        || Direction::ALL.into_iter().any(|direction| {
+                            cell1.is_direction_connected(direction)
+                                != cell2.is_direction_connected(direction)
+                        })
+                }
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// how many cells two boards of the same size differ in
+pub fn solution_distance(first: &FlowGrid, second: &FlowGrid) -> usize {
+    differing_cells(first, second).len()
+}
+
+/// Picks up to count solutions that differ from each other as much as possible. The search
+/// finds solutions that are close neighbors of each other one after another, so it gathers a pool
+/// of up to pool_size solutions from searches with every ordering heuristic, which each reach a
+/// different part of the search first. Then it keeps the first one found and repeatedly adds the
+/// one farthest from everything kept so far.
+pub fn diverse_solutions(grid: &FlowGrid, count: usize, pool_size: usize) -> Vec<FlowGrid> {
+    let configs: Vec<SolverConfig> = (0..8)
+        .map(|bits| SolverConfig {
+            most_constrained_first: bits & 1 == 0,
+            longest_pairs_first: bits & 2 != 0,
+            corners_first: bits & 4 != 0,
+        })
+        .collect();
+    let mut pool: Vec<FlowGrid> = Vec::new();
+    for config in &configs {
+        for solution in solutions(grid)
+            .with_config(*config)
+            .take(pool_size.div_ceil(configs.len()))
+        {
+            if pool
+                .iter()
+                .all(|found| solution_distance(found, &solution) > 0)
+            {
+                pool.push(solution);
+            }
+        }
+    }
+
+    let mut picked: Vec<FlowGrid> = Vec::new();
+    // how far each solution in the pool is from the closest one picked so far
+    let mut distances = vec![usize::MAX; pool.len()];
+    while picked.len() < count {
+        let Some((farthest, _)) = distances
+            .iter()
+            .enumerate()
+            .filter(|&(_, &distance)| distance > 0)
+            .max_by_key(|&(index, &distance)| (distance, std::cmp::Reverse(index)))
+        else {
+            break;
+        };
+        let solution = pool[farthest].clone();
+        for (distance, other) in distances.iter_mut().zip(&pool) {
+            *distance = (*distance).min(solution_distance(&solution, other));
+        }
+        picked.push(solution);
+    }
+    picked
+}
+
+/// Finds one connection from a full solution that isn't on the grid yet. Pipes that have already
+/// been started get extended first, then pipes coming out of sources, then anything else.
+pub fn hint(grid: &FlowGrid, options: &SolveOptions) -> Option<Move> {
+    match solve_with_options(grid, options) {
+        SolveOutcome::Solved(solution) => hint_towards(grid, &solution),
+        SolveOutcome::Unsolvable | SolveOutcome::TimedOut | SolveOutcome::Unsupported => None,
+    }
+}
+
+fn hint_towards(grid: &FlowGrid, solution: &FlowGrid) -> Option<Move> {
+    let mut candidates = Vec::new();
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            let cell = grid.get(row, col).expect("looping in bounds");
+            let solved_cell = solution.get(row, col).expect("looping in bounds");
+            let priority = if cell.num_connections() > 0 {
+                0
+            } else if cell.is_source {
+                1
+            } else {
+                2
+            };
+            for direction in Direction::ALL {
+                if solved_cell.is_direction_connected(direction)
+                    && !cell.is_direction_connected(direction)
+                {
+                    candidates.push((
+                        priority,
+                        Move {
+                            row,
+                            col,
+                            direction,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|(priority, _)| *priority);
+
+    candidates
+        .into_iter()
+        .map(|(_, hint)| hint)
+        .find(|hint| grid.clone().try_connect(hint.row, hint.col, hint.direction))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DifficultyRating {
+    /// can be finished by only making connections that are forced
+    Easy,
+    /// needs a few guesses, at most one for every ten connections
+    Medium,
+    Hard,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Difficulty {
+    /// connections a player can make without guessing
+    pub forced_moves: usize,
+    /// times a player runs out of forced connections and has to pick one from the solution
+    pub guesses: usize,
+    /// how many moves the solver tried before finding the solution
+    pub nodes_explored: u64,
+    pub rating: DifficultyRating,
+}
+
+/// Rates how hard the puzzle made up of the grid's sources is for a person. It plays the puzzle
+/// the way a player would, making every forced connection and only taking a connection from the
+/// solution when there are none left. Returns None if the puzzle can't be solved within the
+/// options' limits.
+pub fn difficulty(grid: &FlowGrid, options: &SolveOptions) -> Option<Difficulty> {
+    let mut search = solutions(grid);
+    let solution = match run(&mut search, options, |_| true).expect("reporting never stops") {
+        SolveOutcome::Solved(solution) => solution,
+        SolveOutcome::Unsolvable | SolveOutcome::TimedOut | SolveOutcome::Unsupported => {
+            return None;
+        }
+    };
+
+    let mut board = grid.clone();
+    board.clear_pipes();
+    let mut forced_moves = 0;
+    let mut guesses = 0;
+    loop {
+        forced_moves += board.apply_forced_moves().len();
+        match hint_towards(&board, &solution) {
+            Some(guess) => {
+                board.try_connect(guess.row, guess.col, guess.direction);
+                guesses += 1;
+            }
+            None => break,
+        }
+    }
+
+    let rating = if guesses == 0 {
+        DifficultyRating::Easy
+    } else if guesses * 10 <= forced_moves + guesses {
+        DifficultyRating::Medium
+    } else {
+        DifficultyRating::Hard
+    };
+    Some(Difficulty {
+        forced_moves,
+        guesses,
+        nodes_explored: search.progress().nodes_explored,
+        rating,
+    })
+}
+
+/// Follows the pipe out of a cell to its far end, without going back through the previous cell.
+/// Stops at the first source it reaches after the start.
+fn pipe_end(
+    grid: &CompactGrid,
+    start: (usize, usize),
+    previous: Option<(usize, usize)>,
+) -> (usize, usize) {
+    let (mut current, mut previous) = (start, previous);
+    loop {
+        if grid.is_source(current.0, current.1) && current != start {
+            return current;
+        }
+        let next = Direction::ALL
+            .into_iter()
+            .filter(|&direction| grid.is_connected(current.0, current.1, direction))
+            .filter_map(|direction| grid.offset_row_col(current.0, current.1, direction))
+            .find(|&next| Some(next) != previous);
+        match next {
+            Some(next) if next != start => (previous, current) = (Some(current), next),
+            _ => return current,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Flow {
+    /// the end of the pipe growing out of the first source
+    head: (usize, usize),
+    /// The sources we're growing towards, any of which finishes the pipe. A pair only has the
+    /// one, but in a color with more sources, each one but the first grows a pipe towards any of
+    /// the others. Every tree joining them is then made exactly once: each pipe is the one
+    /// leading from a source towards the first.
+    targets: Vec<(usize, usize)>,
+    is_finished: bool,
+}
+
+#[derive(Clone)]
+struct Solver {
+    grid: CompactGrid,
+    /// The grid the search started from, for turning states back into full grids. It never
+    /// changes, so the branches of a parallel search share it instead of each copying its cells
+    /// and history.
+    template: Arc<FlowGrid>,
+    flows: Vec<Flow>,
+    /// the sources of each color with more than two, which only make a solution once its pipes
+    /// join them all into one tree
+    trees: Vec<Vec<(usize, usize)>>,
+    /// whether every cell has to end up filled. Only false when routing some of the colors.
+    must_fill: bool,
+    config: SolverConfig,
+}
+
+impl Solver {
+    /// Routes the given colors, or all of them and fills the board if there's no list. Returns
+    /// None if the puzzle can't be solved, including when it has more colors than the compact
+    /// grid can hold, or bridges. Colors with more than two sources are routed from scratch,
+    /// since there's no telling which source a pipe already on the board was growing from.
+    fn new(grid: &FlowGrid, colors: Option<&[usize]>) -> Option<Self> {
+        let is_routed = |color_id: usize| colors.is_none_or(|colors| colors.contains(&color_id));
+        let mut start = grid.clone();
+        for color_id in (0..grid.num_colors()).filter(|&color_id| is_routed(color_id)) {
+            if grid.get_sources(color_id).len() > 2 {
+                start.clear_color_pipes(color_id);
+            }
+        }
+        let compact_grid = CompactGrid::from_grid(&start)?;
+
+        let mut flows = Vec::new();
+        let mut trees = Vec::new();
+        for color_id in (0..grid.num_colors()).filter(|&color_id| is_routed(color_id)) {
+            if grid.is_missing_source(color_id) {
+                return None;
+            }
+            let sources = grid.get_sources(color_id);
+            if let [source1, source2] = sources[..] {
+                // pick up from wherever the pipes out of each source already end
+                let head = pipe_end(&compact_grid, source1, None);
+                flows.push(Flow {
+                    head,
+                    targets: vec![pipe_end(&compact_grid, source2, None)],
+                    is_finished: head == source2,
+                });
+            } else if sources.len() > 2 {
+                flows.extend(sources.iter().skip(1).map(|&source| {
+                    Flow {
+                        head: source,
+                        targets: sources
+                            .iter()
+                            .copied()
+                            .filter(|&other| other != source)
+                            .collect(),
+                        is_finished: false,
+                    }
+                }));
+                trees.push(sources);
+            }
+        }
+
+        Some(Solver {
+            grid: compact_grid,
+            template: Arc::new(grid.clone()),
+            flows,
+            trees,
+            must_fill: colors.is_none(),
+            config: SolverConfig::default(),
+        })
+    }
+
+    /// Extends the flow's pipe by one cell. The direction has to come from moves.
+    fn apply_move(&mut self, flow_index: usize, direction: Direction) {
+        let (row, col) = self.flows[flow_index].head;
+        let next_row_col = self
+            .grid
+            .offset_row_col(row, col, direction)
+            .expect("moves only point inside the grid");
+        self.grid.connect(row, col, direction);
+        // stepping onto the end of an uncolored piece of pipe takes the whole piece along
+        let new_head = pipe_end(&self.grid, next_row_col, Some((row, col)));
+        let flow = &mut self.flows[flow_index];
+        if flow.targets.contains(&next_row_col) {
+            flow.is_finished = true;
+        } else {
+            flow.head = new_head;
+        }
+    }
+
+    fn undo_move(
+        &mut self,
+        flow_index: usize,
+        previous_head: (usize, usize),
+        direction: Direction,
+    ) {
+        let flow = &mut self.flows[flow_index];
+        flow.is_finished = false;
+        flow.head = previous_head;
+        self.grid
+            .disconnect(previous_head.0, previous_head.1, direction);
+    }
+
+    /// the unfinished flow to extend next, picked according to the config, along with its moves
+    fn next_flow(&self) -> Option<(usize, Vec<Direction>)> {
+        let mut best: Option<((usize, usize), usize, Vec<Direction>)> = None;
+        for (flow_index, flow) in self.flows.iter().enumerate() {
+            if flow.is_finished {
+                continue;
+            }
+            let moves = self.moves(flow);
+            // smaller goes first
+            let priority = (
+                if self.config.most_constrained_first {
+                    moves.len()
+                } else {
+                    0
+                },
+                if self.config.longest_pairs_first {
+                    usize::MAX
+                        - flow
+                            .targets
+                            .iter()
+                            .map(|target| {
+                                flow.head.0.abs_diff(target.0) + flow.head.1.abs_diff(target.1)
+                            })
+                            .min()
+                            .unwrap_or(0)
+                } else {
+                    0
+                },
+            );
+            if best
+                .as_ref()
+                .is_none_or(|(best_priority, _, _)| priority < *best_priority)
+            {
+                let is_stuck = self.config.most_constrained_first && moves.len() <= 1;
+                best = Some((priority, flow_index, moves));
+                if is_stuck {
+                    break;
+                }
+            }
+        }
+
+        best.map(|(_, flow_index, mut moves)| {
+            if self.config.corners_first {
+                let (row, col) = self.flows[flow_index].head;
+                moves.sort_by_key(|&direction| {
+                    let (next_row, next_col) = self
+                        .grid
+                        .offset_row_col(row, col, direction)
+                        .expect("moves only point inside the grid");
+                    self.neighbors(next_row, next_col)
+                        .filter(|&(row, col)| self.is_empty(row, col))
+                        .count()
+                });
+            }
+            (flow_index, moves)
+        })
+    }
+
+    fn moves(&self, flow: &Flow) -> Vec<Direction> {
+        let (row, col) = flow.head;
+        // a pipe that came into a warp has to go through it
+        let must_warp =
+            self.grid.is_warp(row, col) && !self.grid.is_connected(row, col, Direction::Warp);
+        Direction::ALL
+            .into_iter()
+            .filter(|&direction| !must_warp || direction == Direction::Warp)
+            .filter(
+                |&direction| match self.grid.offset_row_col(row, col, direction) {
+                    Some(next_row_col) if flow.targets.contains(&next_row_col) => {
+                        self.can_enter(next_row_col, direction)
+                    }
+                    Some((next_row, next_col)) => {
+                        self.is_empty(next_row, next_col)
+                            && self.grid.num_connections(next_row, next_col) < 2
+                            && self.can_enter((next_row, next_col), direction)
+                    }
+                    None => false,
+                },
+            )
+            .collect()
+    }
+
+    /// whether a pipe can come into the cell this way. Only one end of a pipe through a warp can
+    /// be on the board side, and the other has to be the link to its partner.
+    fn can_enter(&self, (row, col): (usize, usize), direction: Direction) -> bool {
+        direction == Direction::Warp
+            || !self.grid.is_warp(row, col)
+            || self.grid.num_connections(row, col)
+                == self.grid.is_connected(row, col, Direction::Warp) as usize
+    }
+
+    /// whether nothing colored is in the cell yet. Uncolored pieces of pipe count as empty.
+    fn is_empty(&self, row: usize, col: usize) -> bool {
+        self.grid.is_empty(row, col)
+    }
+
+    fn is_filled(&self) -> bool {
+        (0..self.grid.height).all(|row| (0..self.grid.width).all(|col| !self.is_empty(row, col)))
+    }
+
+    /// whether there's nothing left to route, once every flow is finished
+    fn is_complete(&self) -> bool {
+        (!self.must_fill || self.is_filled())
+            && self
+                .trees
+                .iter()
+                .all(|sources| self.is_tree_joined(sources))
+    }
+
+    /// Whether the pipes join every one of the sources. Each source but the first grew one pipe,
+    /// so they can only all be joined without a loop, but two of them could have grown into each
+    /// other and left the rest apart.
+    fn is_tree_joined(&self, sources: &[(usize, usize)]) -> bool {
+        let width = self.grid.width;
+        let mut is_reached = vec![false; width * self.grid.height];
+        let mut stack = vec![sources[0]];
+        is_reached[sources[0].0 * width + sources[0].1] = true;
+        while let Some((row, col)) = stack.pop() {
+            for direction in Direction::ALL {
+                if !self.grid.is_connected(row, col, direction) {
+                    continue;
+                }
+                if let Some((next_row, next_col)) = self.grid.offset_row_col(row, col, direction)
+                    && !is_reached[next_row * width + next_col]
+                {
+                    is_reached[next_row * width + next_col] = true;
+                    stack.push((next_row, next_col));
+                }
+            }
+        }
+        sources
+            .iter()
+            .all(|&(row, col)| is_reached[row * width + col])
+    }
+
+    fn neighbors(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        Direction::ALL
+            .into_iter()
+            .filter_map(move |direction| self.grid.offset_row_col(row, col, direction))
+    }
+
+    /// Cheap checks that the current state can still be completed. It can have false positives,
+    /// but never false negatives.
+    fn is_viable(&self) -> bool {
+        let width = self.grid.width;
+        let mut open_ends = vec![false; width * self.grid.height];
+        for flow in self.flows.iter().filter(|flow| !flow.is_finished) {
+            open_ends[flow.head.0 * width + flow.head.1] = true;
+            for target in &flow.targets {
+                open_ends[target.0 * width + target.1] = true;
+            }
+        }
+
+        // every empty cell needs a way in and a way out, unless it's allowed to stay empty
+        if self.must_fill {
+            for row in 0..self.grid.height {
+                for col in 0..width {
+                    if !self.is_empty(row, col) {
+                        continue;
+                    }
+                    let exits = self
+                        .neighbors(row, col)
+                        .filter(|&(next_row, next_col)| {
+                            self.is_empty(next_row, next_col)
+                                || open_ends[next_row * width + next_col]
+                        })
+                        .count();
+                    if exits < 2 {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        // label each connected region of empty cells
+        const NO_REGION: usize = usize::MAX;
+        let mut regions = vec![NO_REGION; width * self.grid.height];
+        let mut num_regions = 0;
+        for row in 0..self.grid.height {
+            for col in 0..width {
+                if regions[row * width + col] != NO_REGION || !self.is_empty(row, col) {
+                    continue;
+                }
+                let mut stack = vec![(row, col)];
+                regions[row * width + col] = num_regions;
+                while let Some((cur_row, cur_col)) = stack.pop() {
+                    for (next_row, next_col) in self.neighbors(cur_row, cur_col) {
+                        if regions[next_row * width + next_col] == NO_REGION
+                            && self.is_empty(next_row, next_col)
+                        {
+                            regions[next_row * width + next_col] = num_regions;
+                            stack.push((next_row, next_col));
+                        }
+                    }
+                }
+                num_regions += 1;
+            }
+        }
+
+        // every flow must be able to reach its target, and every region needs a flow passing
+        // through it, or it can never be filled
+        let mut is_region_used = vec![false; num_regions];
+        for flow in self.flows.iter().filter(|flow| !flow.is_finished) {
+            let touching_regions = |(row, col): (usize, usize)| {
+                self.neighbors(row, col)
+                    .map(|(next_row, next_col)| regions[next_row * width + next_col])
+                    .filter(|&region| region != NO_REGION)
+                    .collect::<Vec<_>>()
+            };
+            let head_regions = touching_regions(flow.head);
+            let target_regions: Vec<usize> = flow
+                .targets
+                .iter()
+                .flat_map(|&target| touching_regions(target))
+                .collect();
+
+            let mut can_reach = self
+                .neighbors(flow.head.0, flow.head.1)
+                .any(|next_row_col| flow.targets.contains(&next_row_col));
+            for region in head_regions {
+                if target_regions.contains(&region) {
+                    is_region_used[region] = true;
+                    can_reach = true;
+                }
+            }
+            if !can_reach {
+                return false;
+            }
+        }
+
+        !self.must_fill || is_region_used.into_iter().all(|is_used| is_used)
+    }
+}
@@ -0,0 +1,14 @@
+/// This crate is the algorithmic part of the project: the grid, the solver and its rules, the
+/// solution counter and the puzzle generator, with no egui anywhere in its dependencies, so bots,
+/// websites and research code can use it on its own. The app depends on it through the flow crate.
+///
+/// The public API follows semver: anything that changes or removes a public item bumps the minor
+/// version while this is 0.x, and the major version after that.
+pub mod flow_bitboard;
+pub mod flow_corpus;
+pub mod flow_count;
+pub mod flow_generator;
+pub mod flow_grid;
+pub mod flow_repair;
+pub mod flow_rules;
+pub mod flow_solver;
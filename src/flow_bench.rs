@@ -0,0 +1,162 @@
+/// This file has the benchmark mode, run with `cargo run --release -- --bench`. It puts every
+/// puzzle in the corpus through the solver and through the grid's connect and disconnect a number
+/// of times, and prints how long each took, so a slowdown shows up as a number. With
+/// `--format json` it prints a BenchReport instead, so CI can keep the numbers.
+use crate::flow_solver::{self, SolveOptions, SolveOutcome};
+use crate::{flow_cli, flow_corpus::CORPUS};
+use serde::Serialize;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// how many times each thing is timed. The median is reported, so one slow run doesn't count.
+const RUNS: usize = 21;
+
+fn median_time(mut action: impl FnMut()) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            action();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn format_time(time: Duration) -> String {
+    format!("{:.1} us", microseconds(time))
+}
+
+fn microseconds(time: Duration) -> f64 {
+    time.as_secs_f64() * 1_000_000.0
+}
+
+/// What `--bench --format json` prints.
+#[derive(Serialize)]
+struct BenchReport {
+    schema: u32,
+    /// grid times from a debug build are slow, because the grid audits itself after every edit
+    debug_build: bool,
+    puzzles: Vec<BenchPuzzle>,
+    moves_per_second: f64,
+}
+
+/// the median times for one corpus puzzle, in microseconds
+#[derive(Serialize)]
+struct BenchPuzzle {
+    name: &'static str,
+    puzzle_hash: String,
+    width: usize,
+    height: usize,
+    /// how many moves the solver tried
+    moves: u64,
+    /// null for puzzles the solver can't handle yet, like ones with bridges
+    solve_us: Option<f64>,
+    connect_us: f64,
+    disconnect_us: f64,
+}
+
+pub fn run(format: flow_cli::Format) {
+    let is_text = format == flow_cli::Format::Text;
+    if is_text && cfg!(debug_assertions) {
+        println!("Debug build: the grid audits itself after every edit, so grid times are slow.");
+    }
+    if is_text {
+        println!(
+            "{:<16} {:>6} {:>8} {:>12} {:>12} {:>12}",
+            "Puzzle", "Size", "Moves", "Solve", "Connect", "Disconnect"
+        );
+    }
+
+    let mut puzzles = Vec::new();
+    let mut total_nodes = 0;
+    let (mut total_solve, mut total_connect, mut total_disconnect) =
+        (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+    for puzzle in &CORPUS {
+        let grid = puzzle.build_grid();
+        let moves = puzzle.solution_moves();
+
+        let mut nodes = 0;
+        let mut is_supported = true;
+        let solve = median_time(|| {
+            let mut solutions = flow_solver::solutions(&grid);
+            match solutions.next_with_options(&SolveOptions::default()) {
+                SolveOutcome::Solved(solution) => {
+                    black_box(solution);
+                }
+                SolveOutcome::Unsupported => is_supported = false,
+                SolveOutcome::Unsolvable | SolveOutcome::TimedOut => {
+                    panic!("{}: corpus puzzles are solvable", puzzle.name)
+                }
+            }
+            nodes = solutions.stats().nodes_explored;
+        });
+        let solve = is_supported.then_some(solve);
+        let connect = median_time(|| {
+            let mut grid = grid.clone();
+            for step in &moves {
+                grid.try_connect(step.row, step.col, step.direction);
+            }
+            black_box(grid);
+        });
+        let mut solved = grid.clone();
+        for step in &moves {
+            solved.try_connect(step.row, step.col, step.direction);
+        }
+        let disconnect = median_time(|| {
+            let mut grid = solved.clone();
+            for step in &moves {
+                grid.try_disconnect(step.row, step.col, step.direction);
+            }
+            black_box(grid);
+        });
+
+        if is_text {
+            println!(
+                "{:<16} {:>6} {:>8} {:>12} {:>12} {:>12}",
+                puzzle.name,
+                format!("{}x{}", grid.width, grid.height),
+                nodes,
+                solve.map_or("-".to_string(), format_time),
+                format_time(connect),
+                format_time(disconnect),
+            );
+        }
+        puzzles.push(BenchPuzzle {
+            name: puzzle.name,
+            puzzle_hash: flow_cli::puzzle_hash(&grid),
+            width: grid.width,
+            height: grid.height,
+            moves: nodes,
+            solve_us: solve.map(microseconds),
+            connect_us: microseconds(connect),
+            disconnect_us: microseconds(disconnect),
+        });
+        total_nodes += nodes;
+        total_solve += solve.unwrap_or_default();
+        total_connect += connect;
+        total_disconnect += disconnect;
+    }
+
+    let moves_per_second = total_nodes as f64 / total_solve.as_secs_f64();
+    match format {
+        flow_cli::Format::Text => {
+            println!(
+                "{:<16} {:>6} {:>8} {:>12} {:>12} {:>12}",
+                "Total",
+                "",
+                total_nodes,
+                format_time(total_solve),
+                format_time(total_connect),
+                format_time(total_disconnect),
+            );
+            println!("Solver speed: {moves_per_second:.0} moves per second");
+        }
+        flow_cli::Format::Json => flow_cli::print_json(&BenchReport {
+            schema: flow_cli::SCHEMA_VERSION,
+            debug_build: cfg!(debug_assertions),
+            puzzles,
+            moves_per_second,
+        }),
+    }
+}
@@ -0,0 +1,134 @@
+/// This file has blitz mode: generated puzzles one after another against the clock. Each round
+/// gets less time than the last, and running out of time ends the run. Solving a puzzle with at
+/// least half its time left keeps a combo going, and the combo multiplies the points a solve is
+/// worth, so a run rewards staying quick rather than just getting through.
+///
+/// A run is seeded like any generated puzzle, so the same seed plays the same puzzles in the
+/// same order.
+use crate::flow_generator::{self, GenerateOptions};
+
+/// one run of blitz, from the first puzzle until time runs out
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlitzRun {
+    seed: u64,
+    pub score: u64,
+    /// fast solves in a row
+    pub combo: usize,
+    pub best_combo: usize,
+    /// how long each solved puzzle took, in seconds, in order
+    pub solve_times: Vec<f64>,
+}
+
+/// every blitz puzzle is this wide and tall, small enough to generate between rounds
+pub const BLITZ_SIZE: usize = 6;
+
+/// how long the first round lasts
+const FIRST_ROUND_SECONDS: f64 = 60.0;
+/// each round lasts this much of the one before
+const ROUND_SHRINK: f64 = 0.9;
+/// rounds never get shorter than this
+const MIN_ROUND_SECONDS: f64 = 15.0;
+/// a solve keeps the combo going if it leaves at least this much of the round's time
+const COMBO_TIME_LEFT: f64 = 0.5;
+/// what any solve is worth before the time bonus and combo
+const SOLVE_POINTS: u64 = 100;
+/// the bonus for each whole second left when the puzzle is solved
+const POINTS_PER_SECOND_LEFT: u64 = 10;
+
+impl BlitzRun {
+    pub fn new(seed: u64) -> Self {
+        BlitzRun {
+            seed,
+            score: 0,
+            combo: 0,
+            best_combo: 0,
+            solve_times: Vec::new(),
+        }
+    }
+
+    /// the round being played, counting from 0, which is how many puzzles were solved
+    pub fn round(&self) -> usize {
+        self.solve_times.len()
+    }
+
+    /// the seed the round's puzzle is generated from
+    pub fn round_seed(&self) -> u64 {
+        self.seed + self.round() as u64
+    }
+
+    /// the code of the round's puzzle, to pick it out when it's generated
+    pub fn round_code(&self) -> String {
+        flow_generator::puzzle_code(
+            BLITZ_SIZE,
+            BLITZ_SIZE,
+            self.round_seed(),
+            &GenerateOptions::default(),
+        )
+    }
+
+    /// how many seconds the round's puzzle gets
+    pub fn time_limit(&self) -> f64 {
+        (FIRST_ROUND_SECONDS * ROUND_SHRINK.powi(self.round() as i32)).max(MIN_ROUND_SECONDS)
+    }
+
+    /// Counts the round's puzzle as solved after this many seconds, and moves on to the next
+    /// round. Returns the points the solve was worth.
+    pub fn solve(&mut self, seconds: f64) -> u64 {
+        let time_left = (self.time_limit() - seconds).max(0.0);
+        if time_left >= COMBO_TIME_LEFT * self.time_limit() {
+            self.combo += 1;
+            self.best_combo = self.best_combo.max(self.combo);
+        } else {
+            self.combo = 0;
+        }
+        let points =
+            (SOLVE_POINTS + time_left as u64 * POINTS_PER_SECOND_LEFT) * (1 + self.combo as u64);
+        self.score += points;
+        self.solve_times.push(seconds);
+        points
+    }
+
+    /// the average time a solved puzzle took, or None if none were
+    pub fn average_time(&self) -> Option<f64> {
+        (!self.solve_times.is_empty())
+            .then(|| self.solve_times.iter().sum::<f64>() / self.solve_times.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_get_shorter_down_to_a_floor() {
+        let mut run = BlitzRun::new(7);
+        assert_eq!(run.round_seed(), 7);
+        let mut last_limit = run.time_limit();
+        for _ in 0..30 {
+            run.solve(run.time_limit());
+            assert!(run.time_limit() <= last_limit);
+            last_limit = run.time_limit();
+        }
+        assert_eq!(run.round(), 30);
+        assert_eq!(run.round_seed(), 37);
+        assert_eq!(run.time_limit(), MIN_ROUND_SECONDS);
+    }
+
+    #[test]
+    fn fast_solves_build_a_combo_that_multiplies_points() {
+        let mut run = BlitzRun::new(0);
+        // half the time left exactly still counts as fast
+        assert_eq!(run.solve(30.0), (100 + 300) * 2);
+        assert_eq!(run.combo, 1);
+        let fast = run.solve(1.0);
+        assert_eq!(run.combo, 2);
+        assert!(fast > 3 * SOLVE_POINTS);
+        // a slow solve breaks the combo and is only worth its time bonus
+        assert_eq!(run.solve(run.time_limit()), SOLVE_POINTS);
+        assert_eq!(run.combo, 0);
+        assert_eq!(run.best_combo, 2);
+        assert_eq!(run.score, 800 + fast + SOLVE_POINTS);
+        assert_eq!(BlitzRun::new(0).average_time(), None);
+        assert!(run.average_time().is_some_and(|average| average > 10.0));
+    }
+}
@@ -2,95 +2,824 @@
 /// 1. taking user input and interpretting it as commands for the underlying data model in flow_grid
 /// 2. interpretting the data from flow_grid and displaying it to the user
 use crate::{
-    CELL_SIZE, COLOR_INDEX, GRID_BORDER_WIDTH, PIPE_INSET_DIST, PIPE_LENGTH, PIPE_WIDTH,
-    SOURCE_RADIUS,
-    flow_grid::{self, CellColor, Direction},
+    flow_grid::{self, CellColor, Direction, Move},
+    flow_palette::Palette,
+    flow_solver,
 };
 use eframe::egui::{
-    self, Color32, Context, CornerRadius, Painter, Pos2, Rect, Response, Sense, Vec2, Widget,
+    self, Align2, Color32, Context, CornerRadius, FontId, Key, Painter, PointerButton, Pos2, Rect,
+    Response, Sense, Shape, Stroke, StrokeKind, Vec2, Widget,
 };
+use std::cmp::Ordering;
 
-pub struct FlowCanvas {
+/// how big a cell is in exported pictures, and the size the canvas scales from, in points
+pub const DEFAULT_CELL_SIZE: f32 = 75.0;
+/// The canvas sizes cells to fit the board in the space it's given, but no smaller than this.
+/// Past that, the board is clipped and can be zoomed and dragged around instead.
+const MIN_CELL_SIZE: f32 = 16.0;
+/// and no bigger than this, so a small board doesn't fill a big window
+const MAX_CELL_SIZE: f32 = 2.0 * DEFAULT_CELL_SIZE;
+/// How hard a pen has to press, from 0 to 1, before it draws. Lighter touches are treated like
+/// hovering, so resting the pen on the screen doesn't lay pipe.
+const MIN_STYLUS_PRESSURE: f32 = 0.1;
+/// How far past the edge of its cell a finger has to move before a drag goes into the next one,
+/// as a fraction of the cell size. Fingertips are wide and roll as they move.
+const TOUCH_HIT_SLOP: f32 = 0.2;
+/// how long a cell shakes after a drag into it was refused, in seconds
+const REJECTION_SECONDS: f64 = 0.3;
+/// how far a refused cell shakes to each side, as a fraction of the cell size
+const REJECTION_SHAKE: f32 = 0.08;
+/// how long a new connection takes to grow from one cell into the next, in seconds
+const PIPE_GROW_SECONDS: f64 = 0.1;
+/// how long the countdown runs before a timed board comes back after the window had been in
+/// the background, in seconds
+const RESUME_COUNTDOWN_SECONDS: f64 = 3.0;
+
+/// why a timed board is hidden and its clock stopped
+#[derive(Clone, Copy)]
+enum Pause {
+    /// the window doesn't have focus
+    InBackground,
+    /// the window is back in focus, and the board comes back after this many seconds
+    CountingDown(f64),
+}
+/// how many straight pieces a pipe's turn is drawn with
+const ELBOW_SEGMENTS: usize = 12;
+/// how far other colors fade toward the empty cells while one is being drawn, from 0 to 1
+const INACTIVE_COLOR_FADE: f32 = 0.35;
+/// how strongly the cells of the color being drawn are lit up, as the opacity of the glow
+const ACTIVE_COLOR_GLOW: f32 = 0.25;
+/// how far the ghost solution's pipes are faded towards an empty cell
+const GHOST_FADE: f32 = 0.75;
+/// outlines the cells a board is routed differently in from the one it's compared to
+const DIFFERENCE_COLOR: Color32 = Color32::from_rgb(255, 0, 255);
+/// zooming only goes in from the size that fits, since there's nothing more to see further out
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 4.0;
+/// how much one point of scrolling zooms by, as a power of e, so a notch of the wheel (about 50
+/// points) zooms by about a fifth
+const ZOOM_PER_SCROLL_POINT: f32 = 0.004;
+/// the least room the board takes when it doesn't fit, in points, so it stays usable at the
+/// bottom of a crowded panel
+const MIN_VIEW_SIZE: f32 = 200.0;
+/// how much pipes have to stand out from the empty cells under them, as a WCAG contrast ratio.
+/// 3 is what WCAG asks of graphics.
+const MIN_CONTRAST: f32 = 3.0;
+/// the luminance where black and white contrast equally with a color, so anything brighter
+/// goes with black and anything darker with white
+const MID_LUMINANCE: f32 = 0.18;
+
+/// Every size the board is drawn with, in points. They're worked out from the zoom and the
+/// screen's scale each frame, and rounded to whole physical pixels so lines stay crisp at
+/// fractional scale factors.
+#[derive(Clone, Copy, Debug)]
+pub struct BoardMetrics {
+    pub pixels_per_point: f32,
+    pub cell_size: f32,
+    pub grid_border_width: f32,
+    pub source_radius: f32,
+    pub pipe_width: f32,
+    /// how far a pipe reaches from the edge of its cell, past the middle
+    pub pipe_length: f32,
+    /// how far a pipe is from the edge of its cell, across the middle
+    pub pipe_inset_dist: f32,
+    /// Lays cells out as hexagons, pointy side up, with each row half a cell right of the one
+    /// above. cell_size is then the distance across a hexagon from one flat side to the other.
+    pub is_hex: bool,
+}
+
+impl BoardMetrics {
+    pub fn new(cell_size: f32, pixels_per_point: f32) -> Self {
+        let snap = |points: f32| (points * pixels_per_point).round().max(1.0) / pixels_per_point;
+        let cell_size = snap(cell_size);
+        let grid_border_width = snap(cell_size / 35.0);
+        let pipe_width = snap(cell_size * 2.0 / 7.0);
+        BoardMetrics {
+            pixels_per_point,
+            cell_size,
+            grid_border_width,
+            source_radius: cell_size / 3.0,
+            pipe_width,
+            pipe_length: (cell_size + pipe_width) / 2.0 + grid_border_width,
+            pipe_inset_dist: (cell_size - pipe_width) / 2.0 + grid_border_width,
+            is_hex: false,
+        }
+    }
+
+    pub fn with_hex(mut self, is_hex: bool) -> Self {
+        self.is_hex = is_hex;
+        self
+    }
+
+    /// the distance between the middles of two rows of hexagons
+    fn hex_row_pitch(&self) -> f32 {
+        self.cell_pitch() * 3f32.sqrt() / 2.0
+    }
+
+    /// the distance from the middle of a hexagon, including its border, to a corner
+    fn hex_radius(&self) -> f32 {
+        self.cell_pitch() / 3f32.sqrt()
+    }
+
+    /// the distance from the start of one cell to the start of the next
+    pub fn cell_pitch(&self) -> f32 {
+        self.cell_size + self.grid_border_width
+    }
+
+    pub fn board_size(&self, width: usize, height: usize) -> Vec2 {
+        if self.is_hex {
+            let extra_rows = height.saturating_sub(1) as f32;
+            return Vec2::new(
+                self.grid_border_width + self.cell_pitch() * (width as f32 + extra_rows / 2.0),
+                self.grid_border_width
+                    + 2.0 * self.hex_radius()
+                    + self.hex_row_pitch() * extra_rows,
+            );
+        }
+        Vec2::new(
+            self.grid_border_width + self.cell_pitch() * width as f32,
+            self.grid_border_width + self.cell_pitch() * height as f32,
+        )
+    }
+
+    /// the top left corner of a cell, inside its borders
+    pub fn cell_min(&self, origin: Pos2, row: usize, col: usize) -> Pos2 {
+        Pos2::new(
+            origin.x + self.grid_border_width + col as f32 * self.cell_pitch(),
+            origin.y + self.grid_border_width + row as f32 * self.cell_pitch(),
+        )
+    }
+
+    /// the middle of a cell
+    pub fn cell_center(&self, origin: Pos2, row: usize, col: usize) -> Pos2 {
+        if self.is_hex {
+            Pos2::new(
+                origin.x
+                    + self.grid_border_width / 2.0
+                    + self.cell_pitch() * (col as f32 + 0.5 + row as f32 / 2.0),
+                origin.y
+                    + self.grid_border_width / 2.0
+                    + self.hex_radius()
+                    + self.hex_row_pitch() * row as f32,
+            )
+        } else {
+            self.cell_min(origin, row, col) + Vec2::splat(self.cell_size / 2.0)
+        }
+    }
+
+    /// the way from the middle of a cell to the middle of its neighbor in the direction. A warp's
+    /// partner can be anywhere, so its link doesn't go anywhere on the board.
+    pub fn neighbor_offset(&self, direction: Direction) -> Vec2 {
+        let pitch = self.cell_pitch();
+        let (row_step, col_step) = if self.is_hex {
+            (self.hex_row_pitch(), pitch / 2.0)
+        } else {
+            (pitch, 0.0)
+        };
+        match direction {
+            Direction::Up => Vec2::new(-col_step, -row_step),
+            Direction::Down => Vec2::new(col_step, row_step),
+            Direction::Left => Vec2::new(-pitch, 0.0),
+            Direction::Right => Vec2::new(pitch, 0.0),
+            Direction::UpRight => Vec2::new(pitch - col_step, -row_step),
+            Direction::DownLeft => Vec2::new(col_step - pitch, row_step),
+            Direction::Warp => Vec2::ZERO,
+        }
+    }
+
+    /// the corners of a cell inside its borders, clockwise
+    pub fn cell_corners(&self, center: Pos2) -> Vec<Pos2> {
+        if self.is_hex {
+            let radius = self.cell_size / 3f32.sqrt();
+            (0..6)
+                .map(|corner| {
+                    let angle = (60.0 * corner as f32 - 90.0).to_radians();
+                    center + radius * Vec2::angled(angle)
+                })
+                .collect()
+        } else {
+            let half = self.cell_size / 2.0;
+            [(-half, -half), (half, -half), (half, half), (-half, half)]
+                .into_iter()
+                .map(|(x, y)| center + Vec2::new(x, y))
+                .collect()
+        }
+    }
+
+    /// the corners of the middle of a cell's border, where it meets its neighbors, clockwise
+    pub fn cell_outline(&self, center: Pos2) -> Vec<Pos2> {
+        let scale = self.cell_pitch() / self.cell_size;
+        self.cell_corners(center)
+            .into_iter()
+            .map(|corner| center + (corner - center) * scale)
+            .collect()
+    }
+
+    /// the cell under a point relative to the board's top left corner
+    pub fn cell_at(&self, local_pos: Vec2) -> Option<(usize, usize)> {
+        let (row, col) = self.signed_cell_at(local_pos);
+        Some((usize::try_from(row).ok()?, usize::try_from(col).ok()?))
+    }
+
+    /// the cell under a point on a board whose edges join up, where a point past one edge is
+    /// over the cells along the opposite one
+    pub fn wrapped_cell_at(&self, local_pos: Vec2, width: usize, height: usize) -> (usize, usize) {
+        let (row, col) = self.signed_cell_at(local_pos);
+        (
+            row.rem_euclid(height as isize) as usize,
+            col.rem_euclid(width as isize) as usize,
+        )
+    }
+
+    /// the row and column under a point, which are negative above or left of the board
+    fn signed_cell_at(&self, local_pos: Vec2) -> (isize, isize) {
+        if self.is_hex {
+            return self.hex_at(local_pos);
+        }
+        (
+            (local_pos.y / self.cell_pitch()).floor() as isize,
+            (local_pos.x / self.cell_pitch()).floor() as isize,
+        )
+    }
+
+    /// the hexagon whose middle is nearest the point, found by rounding its fractional row and
+    /// column in cube coordinates, where the three axes always add up to zero
+    fn hex_at(&self, local_pos: Vec2) -> (isize, isize) {
+        let row =
+            (local_pos.y - self.grid_border_width / 2.0 - self.hex_radius()) / self.hex_row_pitch();
+        let col =
+            (local_pos.x - self.grid_border_width / 2.0) / self.cell_pitch() - 0.5 - row / 2.0;
+        let third = -row - col;
+        let (mut rounded_row, mut rounded_col, rounded_third) =
+            (row.round(), col.round(), third.round());
+        let row_error = (rounded_row - row).abs();
+        let col_error = (rounded_col - col).abs();
+        let third_error = (rounded_third - third).abs();
+        // the axis that rounded the furthest is the one to fix up
+        if row_error > col_error && row_error > third_error {
+            rounded_row = -rounded_col - rounded_third;
+        } else if col_error > third_error {
+            rounded_col = -rounded_row - rounded_third;
+        }
+        (rounded_row as isize, rounded_col as isize)
+    }
+
+    /// moves a point onto the nearest physical pixel
+    pub fn snap_pos(&self, pos: Pos2) -> Pos2 {
+        Pos2::new(
+            (pos.x * self.pixels_per_point).round() / self.pixels_per_point,
+            (pos.y * self.pixels_per_point).round() / self.pixels_per_point,
+        )
+    }
+}
+
+impl Default for BoardMetrics {
+    fn default() -> Self {
+        BoardMetrics::new(DEFAULT_CELL_SIZE, 1.0)
+    }
+}
+
+/// The colors the canvas draws with, so whatever is embedding the canvas can match its own look.
+#[derive(Clone, Debug)]
+pub struct BoardStyle {
+    /// source and pipe colors, indexed by color id
+    pub palette: Vec<Color32>,
+    /// for pipes that aren't connected to a source yet, and colors missing from the palette.
+    /// None follows the egui theme.
+    pub uncolored_pipe_color: Option<Color32>,
+    /// Fills the cells behind the pipes on the canvas. Exported pictures leave them clear. None
+    /// follows the egui theme.
+    pub empty_cell_color: Option<Color32>,
+    /// None follows the egui theme
+    pub grid_line_color: Option<Color32>,
+    /// None follows the egui theme
+    pub highlight_color: Option<Color32>,
+    /// for sources that haven't been found yet in hard mode
+    pub hidden_source_color: Color32,
+    /// fills blocked cells
+    pub blocked_color: Color32,
+    /// for the rings around warps, and the numbers that pair them up
+    pub warp_color: Color32,
+}
+
+impl BoardStyle {
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette
+            .colors
+            .iter()
+            .map(|color| Color32::from_rgb(color.rgb[0], color.rgb[1], color.rgb[2]))
+            .collect();
+    }
+
+    pub fn cell_color(&self, color: CellColor) -> Color32 {
+        let uncolored_pipe_color = self.uncolored_pipe_color.unwrap_or(Color32::GRAY);
+        match color {
+            CellColor::Colored(color_id) => self
+                .palette
+                .get(color_id)
+                .copied()
+                .unwrap_or(uncolored_pipe_color),
+            CellColor::Empty(_) => uncolored_pipe_color,
+        }
+    }
+
+    /// This style as it's drawn under these visuals: everything left to the theme is filled in
+    /// from them, and the colors pipes and sources are drawn in are darkened or lightened until
+    /// they stand out from the empty cells.
+    pub fn for_visuals(&self, visuals: &egui::Visuals) -> BoardStyle {
+        let empty_cell_color = self.empty_cell_color.unwrap_or(visuals.extreme_bg_color);
+        let readable = |color| with_contrast(color, empty_cell_color);
+        BoardStyle {
+            palette: self.palette.iter().copied().map(readable).collect(),
+            uncolored_pipe_color: Some(
+                self.uncolored_pipe_color
+                    .unwrap_or(visuals.strong_text_color()),
+            ),
+            empty_cell_color: Some(empty_cell_color),
+            grid_line_color: Some(
+                self.grid_line_color
+                    .unwrap_or(visuals.window_stroke().color),
+            ),
+            highlight_color: Some(self.highlight_color.unwrap_or(visuals.strong_text_color())),
+            hidden_source_color: readable(self.hidden_source_color),
+            blocked_color: self.blocked_color,
+            warp_color: readable(self.warp_color),
+        }
+    }
+}
+
+/// how bright a color looks, from 0 for black to 1 for white, as WCAG defines it
+fn relative_luminance(color: Color32) -> f32 {
+    let linear = |channel: u8| {
+        let channel = channel as f32 / 255.0;
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r()) + 0.7152 * linear(color.g()) + 0.0722 * linear(color.b())
+}
+
+fn contrast_ratio(a: Color32, b: Color32) -> f32 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The color, moved toward black on a light background or white on a dark one, just far enough
+/// to reach MIN_CONTRAST against the background. Colors that already stand out are kept as is.
+fn with_contrast(color: Color32, background: Color32) -> Color32 {
+    let target = if relative_luminance(background) > MID_LUMINANCE {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    };
+    (0..=10)
+        .map(|step| color.lerp_to_gamma(target, step as f32 / 10.0))
+        .find(|candidate| contrast_ratio(*candidate, background) >= MIN_CONTRAST)
+        .unwrap_or(target)
+}
+
+impl Default for BoardStyle {
+    fn default() -> Self {
+        let mut style = BoardStyle {
+            palette: Vec::new(),
+            uncolored_pipe_color: None,
+            empty_cell_color: None,
+            grid_line_color: None,
+            highlight_color: None,
+            hidden_source_color: Color32::GRAY,
+            blocked_color: Color32::from_gray(40),
+            warp_color: Color32::from_rgb(170, 110, 255),
+        };
+        style.set_palette(&Palette::default());
+        style
+    }
+}
+
+/// what clicking a cell places or removes while editing a puzzle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditTool {
+    Sources,
+    /// cells two pipes can cross
+    Bridges,
+    /// blocked cells, which pipes go around
+    Holes,
+    /// pairs of warps, placed by clicking one cell and then the other
+    Warps,
+    /// more sources for a color that has both, placed by clicking one of its sources and then
+    /// the cells to add
+    ExtraSources,
+}
+
+/// what dragging across the board does while playing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DragTool {
+    Draw,
+    /// takes every connection out of the cells dragged over, leaving the sources
+    Erase,
+}
+
+pub struct FlowBoardWidget {
     pub grid: flow_grid::FlowGrid,
     have_laid_pipe: bool,
     previous_row_col: Option<(usize, usize)>,
+    /// the color the drag is laying once it has one, which it sticks to
+    stroke_color: Option<usize>,
+    pub drag_tool: DragTool,
+    /// where the pointer was last frame while erasing, relative to the board, so a quick drag
+    /// doesn't skip cells
+    erase_pos: Option<Vec2>,
     pub can_edit_sources: bool,
+    /// what clicking a cell places or removes, while sources can be edited
+    pub edit_tool: EditTool,
+    /// the color the Sources tool places, or None for the next color that needs sources
+    pub source_color: Option<usize>,
+    /// the source the last click removed and its color, so double-clicking it clears the rest
+    removed_source: Option<((usize, usize), usize)>,
+    /// the first cell of a pair of warps being placed
+    warp_start: Option<(usize, usize)>,
+    /// the color extra sources are being added to
+    extra_source_color: Option<usize>,
+    /// when false, the board is only drawn and ignores all input
+    pub is_interactive: bool,
+    pub style: BoardStyle,
+    /// style as resolved against the egui theme this frame, which is what gets drawn
+    drawn_style: BoardStyle,
+    /// a cell to draw an outline around, e.g. the one the solver just changed
+    pub highlighted_cell: Option<(usize, usize)>,
+    /// tint cells that can no longer be filled, so mistakes show up while drawing
+    pub show_dead_ends: bool,
+    /// after each stroke, fill in any connections the board now forces
+    pub auto_complete: bool,
+    /// hard mode: sources are drawn gray until a pipe has been connected to them
+    pub hide_sources: bool,
+    /// number each color's sources and pipes, for when the colors themselves are hard to tell
+    /// apart
+    pub show_color_labels: bool,
+    /// a solution to draw faintly under the pipes, for following along while drawing them
+    pub ghost: Option<flow_grid::FlowGrid>,
+    /// another routing of the same board, whose cells that are routed differently get outlined
+    pub compare_to: Option<flow_grid::FlowGrid>,
+    /// draw compare_to's pipes faintly under the board's, the way the ghost is drawn
+    pub show_compared_pipes: bool,
+    revealed_sources: Vec<(usize, usize)>,
+    /// the cell size that fits the board in the space the canvas was last given, before zooming
+    fitted_cell_size: f32,
+    /// 1.0 draws cells at fitted_cell_size
+    pub zoom: f32,
+    /// how far the board has been dragged from the canvas's top left corner, which is never
+    /// positive, so the board can only be moved to show parts that don't fit
+    pan: Vec2,
+    /// whether the current drag is moving the board around rather than drawing
+    is_panning: bool,
+    stylus: Stylus,
+    /// Whether a finger is what's being used. A finger can't hover, and a quick tap is easy to
+    /// make by accident, so sources are placed and removed with a long press instead.
+    is_touch: bool,
+    /// whether the pointer was long-pressed last frame, so a long press only acts once
+    was_long_touched: bool,
+    /// the pipe end where the last pen stroke finished, which the elastic band stretches from
+    stylus_anchor: Option<(usize, usize)>,
+    /// the last cell a drag couldn't connect into, and when, by the input clock
+    rejected_cell: Option<((usize, usize), f64)>,
+    /// drag strokes that laid pipe since the board was put on the canvas
+    pub strokes: usize,
+    /// Whether the solver has changed the board since it was put on the canvas, by solving it,
+    /// a hint, Assist or showing a solution. A board solved with help isn't the player's win.
+    pub is_assisted: bool,
+    /// how long the board has been played, in seconds, which stops once it's solved and while
+    /// the board is paused
+    pub elapsed_seconds: f64,
+    /// by the input clock, to time the board between one frame and the next. None while the
+    /// window doesn't have focus, so that time isn't counted.
+    last_frame_time: Option<f64>,
+    /// whether the window had focus last frame, to tell when it comes back
+    was_focused: bool,
+    /// When the countdown after the window came back into focus ends, by the input clock. Until
+    /// then a timed board stays covered, like it is while the window is in the background, so
+    /// it can't be studied off the clock.
+    resume_at: Option<f64>,
+    /// the color a drag is drawing this frame, which stands out from the rest
+    active_color: Option<usize>,
+    /// connections made recently enough that they're still growing
+    growing_pipes: Vec<GrowingPipe>,
+    /// how many of the grid's events have been checked for new connections
+    seen_events: usize,
+}
+
+/// A connection that's being drawn growing out of the cell it was made from, first across that
+/// cell's half and then across the next cell's half.
+struct GrowingPipe {
+    row: usize,
+    col: usize,
+    direction: Direction,
+    /// by the input clock
+    started_at: f64,
+    /// from 0 when it's made to 1 when it's all drawn, as of this frame
+    progress: f32,
+}
+
+/// A pen shows up as touch events with a pressure, next to the pointer events egui makes from
+/// them. A hovering pen only moves the pointer, like a mouse would, so the canvas remembers that
+/// a pen is in use until a mouse button is pressed without any touches.
+#[derive(Clone, Copy, Default)]
+struct Stylus {
+    is_active: bool,
+    /// how hard the pen is pressing, or None while it's hovering
+    pressure: Option<f32>,
+    /// the hardest the pen pressed during the current or last contact
+    peak_pressure: f32,
+}
+
+impl Stylus {
+    fn update(&mut self, events: &[egui::Event]) {
+        let mut saw_touch = false;
+        for event in events {
+            match event {
+                egui::Event::Touch {
+                    phase,
+                    force: Some(force),
+                    ..
+                } => {
+                    saw_touch = true;
+                    self.is_active = true;
+                    match phase {
+                        egui::TouchPhase::Start => {
+                            self.pressure = Some(*force);
+                            self.peak_pressure = *force;
+                        }
+                        egui::TouchPhase::Move => {
+                            self.pressure = Some(*force);
+                            self.peak_pressure = self.peak_pressure.max(*force);
+                        }
+                        egui::TouchPhase::End | egui::TouchPhase::Cancel => self.pressure = None,
+                    }
+                }
+                egui::Event::PointerButton { pressed: true, .. } if !saw_touch => {
+                    *self = Stylus::default();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn is_hovering(&self) -> bool {
+        self.is_active && self.pressure.is_none()
+    }
+
+    /// whether the current or last contact was firm enough to count as drawing
+    fn is_pressed_firmly(&self) -> bool {
+        self.peak_pressure >= MIN_STYLUS_PRESSURE
+    }
 }
 
-impl Widget for &mut FlowCanvas {
+impl Widget for &mut FlowBoardWidget {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        // the board is scaled to fit the space it's given, so it follows the window as it's
+        // resized. Zoomed in, or too big to fit even with the smallest cells, it's clipped and
+        // can be dragged around to see the rest.
+        let room = ui.available_size().max(Vec2::splat(MIN_VIEW_SIZE));
+        self.fit_cells(room);
+        let pause = self.update_pause(ui.ctx());
+        let is_paused = pause.is_some();
+        // is_interactive is set again every frame, so this only lasts while paused
+        self.is_interactive &= !is_paused;
         let (canvas_rect, response) = ui.allocate_exact_size(
-            Vec2::new(
-                GRID_BORDER_WIDTH + (CELL_SIZE + GRID_BORDER_WIDTH) * self.grid.width as f32,
-                GRID_BORDER_WIDTH + (CELL_SIZE + GRID_BORDER_WIDTH) * self.grid.height as f32,
-            ),
-            Sense::click_and_drag(),
+            self.metrics(ui.ctx().pixels_per_point())
+                .board_size(self.grid.width, self.grid.height)
+                .min(room),
+            if self.is_interactive {
+                Sense::click_and_drag()
+            } else {
+                Sense::hover()
+            },
+        );
+        let is_moving_view = self.is_interactive && self.handle_view_controls(ui, &response);
+        let metrics = self.metrics(ui.ctx().pixels_per_point());
+        let overflow = metrics.board_size(self.grid.width, self.grid.height) - canvas_rect.size();
+        self.pan = Vec2::new(
+            self.pan.x.clamp(-overflow.x.max(0.0), 0.0),
+            self.pan.y.clamp(-overflow.y.max(0.0), 0.0),
         );
 
         let painter = ui.painter_at(canvas_rect);
+        let origin = metrics.snap_pos(canvas_rect.min + self.pan);
+        self.drawn_style = self.style.for_visuals(ui.visuals());
+        self.update_growing_pipes(ui.ctx());
+        let (now, is_focused) = ui.input(|input| (input.time, input.focused));
+        if let Some(last_frame_time) = self.last_frame_time
+            && !self.grid.is_solved()
+            && !is_paused
+        {
+            self.elapsed_seconds += now - last_frame_time;
+        }
+        self.last_frame_time = is_focused.then_some(now);
+        self.active_color =
+            if response.dragged() && !self.is_panning && self.drag_tool == DragTool::Draw {
+                self.previous_row_col
+                    .and_then(|(row, col)| match self.grid.get(row, col)?.color {
+                        CellColor::Colored(color_id) => Some(color_id),
+                        CellColor::Empty(_) => None,
+                    })
+            } else {
+                None
+            };
+        let grid_line_color = self
+            .drawn_style
+            .grid_line_color
+            .expect("filled in from the theme");
 
-        self.draw_grid_lines(&painter, &canvas_rect, ui.visuals().window_stroke().color);
+        // a shaped board outlines just its own cells, and leaves the rest of the rectangle empty
+        let off_board = self.grid.off_board_cells();
+        let is_shaped = off_board.contains(&true);
+        if !self.grid.is_hex() && !is_shaped {
+            self.draw_grid_lines(&painter, origin, &metrics, grid_line_color);
+        }
+
+        if self.hide_sources {
+            for row in 0..self.grid.height {
+                for col in 0..self.grid.width {
+                    let cell = self.grid.get(row, col).expect("looping in bounds");
+                    if cell.is_source
+                        && cell.num_connections() > 0
+                        && !self.revealed_sources.contains(&(row, col))
+                    {
+                        self.revealed_sources.push((row, col));
+                    }
+                }
+            }
+        } else {
+            self.revealed_sources.clear();
+        }
 
+        let empty_cell_color = self
+            .drawn_style
+            .empty_cell_color
+            .expect("filled in from the theme");
         for row in 0..self.grid.height {
             for col in 0..self.grid.width {
+                if !off_board[row * self.grid.width + col] {
+                    painter.add(Shape::convex_polygon(
+                        metrics.cell_corners(metrics.cell_center(origin, row, col)),
+                        empty_cell_color,
+                        Stroke::NONE,
+                    ));
+                }
+            }
+        }
+
+        if let Some(color_id) = self.active_color {
+            let glow = self
+                .drawn_style
+                .cell_color(CellColor::Colored(color_id))
+                .gamma_multiply(ACTIVE_COLOR_GLOW);
+            for row in 0..self.grid.height {
+                for col in 0..self.grid.width {
+                    let cell = self.grid.get(row, col).expect("looping in bounds");
+                    if cell.color == CellColor::Colored(color_id)
+                        && (cell.is_source || cell.num_connections() > 0)
+                        && !self.is_hidden_source(row, col)
+                    {
+                        painter.add(Shape::convex_polygon(
+                            metrics.cell_corners(metrics.cell_center(origin, row, col)),
+                            glow,
+                            Stroke::NONE,
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(ghost) = &self.ghost {
+            self.draw_faint_pipes(&painter, origin, &metrics, ghost);
+        }
+        if let Some(compare_to) = &self.compare_to
+            && self.show_compared_pipes
+        {
+            self.draw_faint_pipes(&painter, origin, &metrics, compare_to);
+        }
+
+        // which cells are dead gives away which sources pair up, so hard mode goes without
+        if self.show_dead_ends && !self.hide_sources {
+            for (row, col) in self.grid.find_dead_cells() {
+                painter.add(Shape::convex_polygon(
+                    metrics.cell_corners(metrics.cell_center(origin, row, col)),
+                    Color32::from_rgba_unmultiplied(255, 0, 0, 60),
+                    Stroke::NONE,
+                ));
+            }
+        }
+
+        for row in 0..self.grid.height {
+            for col in 0..self.grid.width {
+                if off_board[row * self.grid.width + col] {
+                    continue;
+                }
+                if self.grid.is_hex() {
+                    self.draw_hex_cell(&painter, origin, &metrics, row, col, grid_line_color);
+                    continue;
+                }
+                if is_shaped {
+                    painter.add(Shape::closed_line(
+                        metrics.cell_outline(metrics.cell_center(origin, row, col)),
+                        Stroke::new(metrics.grid_border_width, grid_line_color),
+                    ));
+                }
                 // TODO maybe could be better to get an iterator from grid? idk.
-                let x0 = col as f32 * (CELL_SIZE + GRID_BORDER_WIDTH)
-                    + canvas_rect.min.x
-                    + GRID_BORDER_WIDTH;
-                let y0 = row as f32 * (CELL_SIZE + GRID_BORDER_WIDTH)
-                    + canvas_rect.min.y
-                    + GRID_BORDER_WIDTH;
+                let Pos2 { x: x0, y: y0 } = metrics.cell_min(origin, row, col);
                 let cell = self.grid.get(row, col).expect("looping in bounds");
 
-                let color = interpret_cell_color(cell.color);
+                let color = self.displayed_color(row, col);
+                let vertical_color = self.displayed_lane_color(row, col, Direction::Up);
 
+                if cell.is_blocked {
+                    painter.rect_filled(
+                        Rect::from_min_size(Pos2::from([x0, y0]), Vec2::splat(metrics.cell_size)),
+                        0,
+                        self.drawn_style.blocked_color,
+                    );
+                }
                 if cell.is_source {
                     painter.circle_filled(
-                        Pos2::from([x0 + CELL_SIZE / 2.0, y0 + CELL_SIZE / 2.0]),
-                        SOURCE_RADIUS,
+                        Pos2::from([x0 + metrics.cell_size / 2.0, y0 + metrics.cell_size / 2.0]),
+                        metrics.source_radius,
                         color,
                     );
                 }
+                if cell.is_bridge() {
+                    // a frame around the crossing, under the pipes, with the horizontal lane
+                    // drawn last so it passes over the vertical one
+                    painter.rect_stroke(
+                        Rect::from_min_size(Pos2::from([x0, y0]), Vec2::splat(metrics.cell_size))
+                            .shrink(metrics.pipe_inset_dist / 2.0),
+                        (metrics.pipe_width / 2.0) as u8,
+                        Stroke::new(metrics.grid_border_width, grid_line_color),
+                        StrokeKind::Inside,
+                    );
+                }
+                if let Some(elbow) = self.elbow(&metrics, Pos2::new(x0, y0), row, col) {
+                    painter.add(Shape::line(elbow, Stroke::new(metrics.pipe_width, color)));
+                    continue;
+                }
                 if cell.is_connected_up {
                     painter.rect_filled(
-                        Rect::from_min_size(
-                            Pos2::from([x0 + PIPE_INSET_DIST, y0]),
-                            Vec2::from([PIPE_WIDTH, PIPE_LENGTH]),
+                        self.grown_half_pipe(
+                            Rect::from_min_size(
+                                Pos2::from([x0 + metrics.pipe_inset_dist, y0]),
+                                Vec2::from([metrics.pipe_width, metrics.pipe_length]),
+                            ),
+                            row,
+                            col,
+                            Direction::Up,
                         ),
                         CornerRadius {
                             ne: 0,
                             nw: 0,
-                            se: PIPE_WIDTH as u8 / 2,
-                            sw: PIPE_WIDTH as u8 / 2,
+                            se: (metrics.pipe_width / 2.0) as u8,
+                            sw: (metrics.pipe_width / 2.0) as u8,
                         },
-                        color,
+                        vertical_color,
                     );
                 }
                 if cell.is_connected_down {
                     painter.rect_filled(
-                        Rect::from_min_size(
-                            Pos2::from([x0 + PIPE_INSET_DIST, y0 + PIPE_INSET_DIST]),
-                            Vec2::from([PIPE_WIDTH, PIPE_LENGTH]),
+                        self.grown_half_pipe(
+                            Rect::from_min_size(
+                                Pos2::from([
+                                    x0 + metrics.pipe_inset_dist,
+                                    y0 + metrics.pipe_inset_dist,
+                                ]),
+                                Vec2::from([metrics.pipe_width, metrics.pipe_length]),
+                            ),
+                            row,
+                            col,
+                            Direction::Down,
                         ),
                         CornerRadius {
-                            ne: PIPE_WIDTH as u8 / 2,
-                            nw: PIPE_WIDTH as u8 / 2,
+                            ne: (metrics.pipe_width / 2.0) as u8,
+                            nw: (metrics.pipe_width / 2.0) as u8,
                             se: 0,
                             sw: 0,
                         },
-                        color,
+                        vertical_color,
                     );
                 }
                 if cell.is_connected_left {
                     painter.rect_filled(
-                        Rect::from_min_size(
-                            Pos2::from([x0, y0 + PIPE_INSET_DIST]),
-                            Vec2::from([PIPE_LENGTH, PIPE_WIDTH]),
+                        self.grown_half_pipe(
+                            Rect::from_min_size(
+                                Pos2::from([x0, y0 + metrics.pipe_inset_dist]),
+                                Vec2::from([metrics.pipe_length, metrics.pipe_width]),
+                            ),
+                            row,
+                            col,
+                            Direction::Left,
                         ),
                         CornerRadius {
-                            ne: PIPE_WIDTH as u8 / 2,
+                            ne: (metrics.pipe_width / 2.0) as u8,
                             nw: 0,
-                            se: PIPE_WIDTH as u8 / 2,
+                            se: (metrics.pipe_width / 2.0) as u8,
                             sw: 0,
                         },
                         color,
@@ -98,15 +827,23 @@ impl Widget for &mut FlowCanvas {
                 }
                 if cell.is_connected_right {
                     painter.rect_filled(
-                        Rect::from_min_size(
-                            Pos2::from([x0 + PIPE_INSET_DIST, y0 + PIPE_INSET_DIST]),
-                            Vec2::from([PIPE_LENGTH, PIPE_WIDTH]),
+                        self.grown_half_pipe(
+                            Rect::from_min_size(
+                                Pos2::from([
+                                    x0 + metrics.pipe_inset_dist,
+                                    y0 + metrics.pipe_inset_dist,
+                                ]),
+                                Vec2::from([metrics.pipe_length, metrics.pipe_width]),
+                            ),
+                            row,
+                            col,
+                            Direction::Right,
                         ),
                         CornerRadius {
                             ne: 0,
-                            nw: PIPE_WIDTH as u8 / 2,
+                            nw: (metrics.pipe_width / 2.0) as u8,
                             se: 0,
-                            sw: PIPE_WIDTH as u8 / 2,
+                            sw: (metrics.pipe_width / 2.0) as u8,
                         },
                         color,
                     );
@@ -114,39 +851,269 @@ impl Widget for &mut FlowCanvas {
             }
         }
 
-        self.handle_interactions(&response, ui.ctx(), &canvas_rect);
+        if self.grid.wraps() {
+            self.draw_edge_crossings(&painter, origin, &metrics);
+        }
+        self.draw_warps(&painter, origin, &metrics);
+        if self.show_color_labels {
+            self.draw_color_labels(&painter, origin, &metrics);
+        }
+        if self.can_edit_sources
+            && self.edit_tool == EditTool::ExtraSources
+            && let Some(color_id) = self.extra_source_color
+        {
+            for (row, col) in self.grid.get_sources(color_id) {
+                painter.circle_stroke(
+                    metrics.cell_center(origin, row, col),
+                    metrics.source_radius * 1.4,
+                    Stroke::new(metrics.grid_border_width * 2.0, grid_line_color),
+                );
+            }
+        }
+
+        if self.is_interactive {
+            let events = ui.ctx().input(|input| input.events.clone());
+            self.stylus.update(&events);
+            self.update_touch(&events);
+        }
+        // a finger leaves the pointer where it lifted, which isn't hovering
+        if self.is_interactive
+            && !self.is_touch
+            && let Some(hover_pos) = response.hover_pos()
+            && let Some((row, col)) = metrics.cell_at(hover_pos - origin)
+        {
+            self.draw_hover_preview(&painter, origin, &metrics, row, col);
+        }
+        if self.is_interactive
+            && self.stylus.is_hovering()
+            && let Some(anchor) = self.stylus_anchor
+            && let Some(hover_pos) = response.hover_pos()
+            && let Some(target) = metrics.cell_at(hover_pos - origin)
+            && self.elastic_band(anchor, target).is_some()
+        {
+            let center = |(row, col)| metrics.cell_center(origin, row, col);
+            painter.line_segment(
+                [center(anchor), center(target)],
+                Stroke::new(
+                    metrics.pipe_width,
+                    self.displayed_color(anchor.0, anchor.1).gamma_multiply(0.5),
+                ),
+            );
+        }
+
+        if let Some(((row, col), rejected_at)) = self.rejected_cell {
+            let age = ui.ctx().input(|input| input.time) - rejected_at;
+            if age < REJECTION_SECONDS {
+                let fade = 1.0 - (age / REJECTION_SECONDS) as f32;
+                let shake = (age as f32 * 60.0).sin() * fade * REJECTION_SHAKE * metrics.cell_size;
+                painter.add(Shape::convex_polygon(
+                    metrics.cell_corners(
+                        metrics.cell_center(origin, row, col) + Vec2::new(shake, 0.0),
+                    ),
+                    Color32::from_rgba_unmultiplied(255, 0, 0, (120.0 * fade) as u8),
+                    Stroke::NONE,
+                ));
+                ui.ctx().request_repaint();
+            } else {
+                self.rejected_cell = None;
+            }
+        }
+
+        if let Some((row, col)) = self.highlighted_cell {
+            painter.add(Shape::closed_line(
+                metrics.cell_corners(metrics.cell_center(origin, row, col)),
+                Stroke::new(
+                    metrics.grid_border_width * 2.0,
+                    self.drawn_style
+                        .highlight_color
+                        .expect("filled in from the theme"),
+                ),
+            ));
+        }
+        if let Some(compare_to) = &self.compare_to {
+            for (row, col) in flow_solver::differing_cells(&self.grid, compare_to) {
+                painter.add(Shape::closed_line(
+                    metrics.cell_corners(metrics.cell_center(origin, row, col)),
+                    Stroke::new(metrics.grid_border_width * 2.0, DIFFERENCE_COLOR),
+                ));
+            }
+        }
+
+        if self.is_interactive && !is_moving_view {
+            self.handle_interactions(&response, ui.ctx(), origin, &metrics);
+        }
+
+        if let Some(pause) = pause {
+            self.draw_pause_cover(&painter, canvas_rect, ui.visuals(), pause);
+        }
 
         response
     }
 }
-impl FlowCanvas {
+impl FlowBoardWidget {
     pub fn with_size(width: usize, height: usize) -> Self {
-        FlowCanvas {
-            grid: flow_grid::FlowGrid::with_size(width, height),
+        Self::with_grid(flow_grid::FlowGrid::with_size(width, height))
+    }
+
+    pub fn with_grid(grid: flow_grid::FlowGrid) -> Self {
+        // a board's connections are drawn in full when it's first shown
+        let seen_events = grid.events_applied();
+        FlowBoardWidget {
+            grid,
             have_laid_pipe: false,
             previous_row_col: None,
+            stroke_color: None,
+            drag_tool: DragTool::Draw,
+            erase_pos: None,
             can_edit_sources: true,
+            edit_tool: EditTool::Sources,
+            source_color: None,
+            removed_source: None,
+            warp_start: None,
+            extra_source_color: None,
+            is_interactive: true,
+            style: BoardStyle::default(),
+            drawn_style: BoardStyle::default(),
+            highlighted_cell: None,
+            show_dead_ends: true,
+            auto_complete: false,
+            hide_sources: false,
+            show_color_labels: false,
+            ghost: None,
+            compare_to: None,
+            show_compared_pipes: false,
+            revealed_sources: Vec::new(),
+            fitted_cell_size: DEFAULT_CELL_SIZE,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            is_panning: false,
+            stylus: Stylus::default(),
+            is_touch: false,
+            was_long_touched: false,
+            stylus_anchor: None,
+            rejected_cell: None,
+            strokes: 0,
+            is_assisted: false,
+            elapsed_seconds: 0.0,
+            last_frame_time: None,
+            was_focused: true,
+            resume_at: None,
+            active_color: None,
+            growing_pipes: Vec::new(),
+            seen_events,
+        }
+    }
+
+    /// a board that is only drawn, and ignores all input
+    pub fn read_only(mut self) -> Self {
+        self.is_interactive = false;
+        self
+    }
+
+    /// a board where pipes can be drawn, but sources can't be placed or removed
+    pub fn sources_locked(mut self) -> Self {
+        self.can_edit_sources = false;
+        self
+    }
+
+    pub fn with_style(mut self, style: BoardStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn metrics(&self, pixels_per_point: f32) -> BoardMetrics {
+        BoardMetrics::new(self.fitted_cell_size * self.zoom, pixels_per_point)
+            .with_hex(self.grid.is_hex())
+    }
+
+    /// Picks the biggest cell size that fits the whole board in this much room. Every other size
+    /// in BoardMetrics grows in step with the cell size, so the board's size at the default
+    /// cells scales straight to any other. Unbounded room, like inside a scroll area, gets the
+    /// default size.
+    fn fit_cells(&mut self, room: Vec2) {
+        let default_size = BoardMetrics::default()
+            .with_hex(self.grid.is_hex())
+            .board_size(self.grid.width, self.grid.height);
+        let scale = (room.x / default_size.x).min(room.y / default_size.y);
+        self.fitted_cell_size = if scale.is_finite() {
+            (DEFAULT_CELL_SIZE * scale).clamp(MIN_CELL_SIZE, MAX_CELL_SIZE)
+        } else {
+            DEFAULT_CELL_SIZE
+        };
+    }
+
+    /// the color a bridge's lane is drawn with, or for any other cell, the color it's drawn with
+    fn displayed_lane_color(&self, row: usize, col: usize, direction: Direction) -> Color32 {
+        let cell = self
+            .grid
+            .get(row, col)
+            .expect("only asked about cells in bounds");
+        if cell.is_bridge() {
+            let color = cell.lane_color(direction);
+            self.faded_unless_active(color, self.drawn_style.cell_color(color))
+        } else {
+            self.displayed_color(row, col)
+        }
+    }
+
+    /// the color a cell is drawn with, which hides sources that haven't been found in hard mode
+    fn displayed_color(&self, row: usize, col: usize) -> Color32 {
+        let cell = self
+            .grid
+            .get(row, col)
+            .expect("only asked about cells in bounds");
+        if self.is_hidden_source(row, col) {
+            self.drawn_style.hidden_source_color
+        } else {
+            self.faded_unless_active(cell.color, self.drawn_style.cell_color(cell.color))
+        }
+    }
+
+    /// whether a cell is a source hard mode is keeping gray
+    fn is_hidden_source(&self, row: usize, col: usize) -> bool {
+        self.hide_sources
+            && self.grid.get(row, col).is_some_and(|cell| cell.is_source)
+            && !self.revealed_sources.contains(&(row, col))
+    }
+
+    /// while a drag is drawing one color, every other color is drawn faded
+    fn faded_unless_active(&self, color: CellColor, drawn: Color32) -> Color32 {
+        match self.active_color {
+            Some(color_id) if color != CellColor::Colored(color_id) => drawn.lerp_to_gamma(
+                self.drawn_style
+                    .empty_cell_color
+                    .expect("filled in from the theme"),
+                INACTIVE_COLOR_FADE,
+            ),
+            _ => drawn,
         }
     }
 
-    fn draw_grid_lines(&self, painter: &Painter, canvas_rect: &Rect, color: Color32) {
+    fn draw_grid_lines(
+        &self,
+        painter: &Painter,
+        origin: Pos2,
+        metrics: &BoardMetrics,
+        color: Color32,
+    ) {
+        let max = origin + metrics.board_size(self.grid.width, self.grid.height);
         for row in 0..=self.grid.height {
-            let y = row as f32 * (CELL_SIZE + GRID_BORDER_WIDTH) + canvas_rect.min.y;
+            let y = row as f32 * metrics.cell_pitch() + origin.y;
             painter.rect_filled(
                 Rect::from_two_pos(
-                    Pos2::new(canvas_rect.min.x, y),
-                    Pos2::new(canvas_rect.max.x, y + GRID_BORDER_WIDTH),
+                    Pos2::new(origin.x, y),
+                    Pos2::new(max.x, y + metrics.grid_border_width),
                 ),
                 0,
                 color,
             );
         }
         for col in 0..=self.grid.width {
-            let x = col as f32 * (CELL_SIZE + GRID_BORDER_WIDTH) + canvas_rect.min.x;
+            let x = col as f32 * metrics.cell_pitch() + origin.x;
             painter.rect_filled(
                 Rect::from_two_pos(
-                    Pos2::new(x, canvas_rect.min.y),
-                    Pos2::new(x + GRID_BORDER_WIDTH, canvas_rect.max.y),
+                    Pos2::new(x, origin.y),
+                    Pos2::new(x + metrics.grid_border_width, max.y),
                 ),
                 0,
                 color,
@@ -154,82 +1121,829 @@ impl FlowCanvas {
         }
     }
 
-    fn handle_interactions(&mut self, response: &Response, ctx: &Context, canvas_rect: &Rect) {
+    /// Draws one cell of a hex grid with its outline. Pipes there run from the middle of the cell
+    /// towards each neighbor they connect to, with a dot in the middle to round off the bend.
+    fn draw_hex_cell(
+        &self,
+        painter: &Painter,
+        origin: Pos2,
+        metrics: &BoardMetrics,
+        row: usize,
+        col: usize,
+        grid_line_color: Color32,
+    ) {
+        let cell = self.grid.get(row, col).expect("looping in bounds");
+        let center = metrics.cell_center(origin, row, col);
+        let color = self.displayed_color(row, col);
+
+        painter.add(Shape::closed_line(
+            metrics.cell_outline(center),
+            Stroke::new(metrics.grid_border_width, grid_line_color),
+        ));
+        if cell.is_blocked {
+            painter.add(Shape::convex_polygon(
+                metrics.cell_corners(center),
+                self.drawn_style.blocked_color,
+                Stroke::NONE,
+            ));
+        }
+        if cell.is_source {
+            painter.circle_filled(center, metrics.source_radius, color);
+        } else if cell.num_connections() > 0 {
+            painter.circle_filled(center, metrics.pipe_width / 2.0, color);
+        }
+        for direction in Direction::ALL {
+            if cell.is_direction_connected(direction) {
+                let (start, end) = self.pipe_extent(row, col, direction);
+                let half = metrics.neighbor_offset(direction) / 2.0;
+                painter.line_segment(
+                    [center + half * start, center + half * end],
+                    Stroke::new(metrics.pipe_width, color),
+                );
+            }
+        }
+    }
+
+    /// Draws another board's pipes as faded lines from the middle of each cell to its edges,
+    /// under everything but the empty cells.
+    fn draw_faint_pipes(
+        &self,
+        painter: &Painter,
+        origin: Pos2,
+        metrics: &BoardMetrics,
+        ghost: &flow_grid::FlowGrid,
+    ) {
+        let empty_cell_color = self
+            .drawn_style
+            .empty_cell_color
+            .expect("filled in from the theme");
+        for row in 0..ghost.height {
+            for col in 0..ghost.width {
+                let cell = ghost.get(row, col).expect("looping in bounds");
+                let center = metrics.cell_center(origin, row, col);
+                for direction in Direction::ALL {
+                    if direction == Direction::Warp || !cell.is_direction_connected(direction) {
+                        continue;
+                    }
+                    let color = self
+                        .drawn_style
+                        .cell_color(cell.lane_color(direction))
+                        .lerp_to_gamma(empty_cell_color, GHOST_FADE);
+                    painter.line_segment(
+                        [center, center + metrics.neighbor_offset(direction) / 2.0],
+                        Stroke::new(metrics.pipe_width, color),
+                    );
+                    painter.circle_filled(center, metrics.pipe_width / 2.0, color);
+                }
+            }
+        }
+    }
+
+    /// Carries pipes that wrap around over the board's outer border, so they visibly leave the
+    /// board on one side and come back on the other, instead of stopping at the edge.
+    fn draw_edge_crossings(&self, painter: &Painter, origin: Pos2, metrics: &BoardMetrics) {
+        for row in 0..self.grid.height {
+            for col in 0..self.grid.width {
+                let cell = self.grid.get(row, col).expect("looping in bounds");
+                for direction in Direction::ALL {
+                    if !cell.is_direction_connected(direction)
+                        || !self.grid.crosses_edge(row, col, direction)
+                    {
+                        continue;
+                    }
+                    let offset = metrics.neighbor_offset(direction);
+                    let border = metrics.cell_center(origin, row, col) + offset / 2.0;
+                    let across = offset.normalized() * metrics.grid_border_width;
+                    painter.line_segment(
+                        [border - across, border + across],
+                        Stroke::new(
+                            metrics.pipe_width,
+                            self.displayed_lane_color(row, col, direction),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Starts growing any connections made since the last frame, and works out how far along
+    /// each growing one is. A grid that's been swapped for one with a shorter history, like by
+    /// undo, has nothing new to grow.
+    fn update_growing_pipes(&mut self, ctx: &Context) {
+        let now = ctx.input(|input| input.time);
+        if let Some(events) = self.grid.events_since(self.seen_events) {
+            for event in events {
+                if let flow_grid::GridEvent::Connect {
+                    row,
+                    col,
+                    direction,
+                } = *event
+                {
+                    self.growing_pipes.push(GrowingPipe {
+                        row,
+                        col,
+                        direction,
+                        started_at: now,
+                        progress: 0.0,
+                    });
+                }
+            }
+        }
+        self.seen_events = self.grid.events_applied();
+        self.growing_pipes
+            .retain(|pipe| now - pipe.started_at < PIPE_GROW_SECONDS);
+        for pipe in &mut self.growing_pipes {
+            pipe.progress = ((now - pipe.started_at) / PIPE_GROW_SECONDS) as f32;
+        }
+        if !self.growing_pipes.is_empty() {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Whether a timed board is paused, which it is while the window is in the background and
+    /// for the countdown once it's back. Only puzzles being played and not yet solved are timed.
+    fn update_pause(&mut self, ctx: &Context) -> Option<Pause> {
+        let (now, is_focused) = ctx.input(|input| (input.time, input.focused));
+        let is_timed = !self.can_edit_sources && !self.grid.is_solved();
+        if is_focused && !self.was_focused && is_timed {
+            self.resume_at = Some(now + RESUME_COUNTDOWN_SECONDS);
+        }
+        self.was_focused = is_focused;
+        self.resume_at = self
+            .resume_at
+            .filter(|&resume_at| is_timed && resume_at > now);
+        if !is_timed {
+            None
+        } else if !is_focused {
+            Some(Pause::InBackground)
+        } else if let Some(resume_at) = self.resume_at {
+            ctx.request_repaint();
+            Some(Pause::CountingDown(resume_at - now))
+        } else {
+            None
+        }
+    }
+
+    /// hides the board behind a cover while it's paused, with the countdown on it once the
+    /// window is back in focus
+    fn draw_pause_cover(
+        &self,
+        painter: &Painter,
+        rect: Rect,
+        visuals: &egui::Visuals,
+        pause: Pause,
+    ) {
+        painter.rect_filled(rect, CornerRadius::ZERO, visuals.extreme_bg_color);
+        let (text, size) = match pause {
+            Pause::CountingDown(seconds_left) => (
+                format!("{}", seconds_left.ceil() as u32),
+                rect.height().min(rect.width()) * 0.4,
+            ),
+            Pause::InBackground => ("Paused".to_string(), rect.height().min(rect.width()) * 0.12),
+        };
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            text,
+            FontId::proportional(size),
+            visuals.strong_text_color(),
+        );
+    }
+
+    /// How much of a cell's half of a connection to draw, as the stretch from start to end,
+    /// where 0 is the middle of the cell and 1 is its edge. A growing connection fills the half
+    /// it was made from outward, and then the other half inward.
+    fn pipe_extent(&self, row: usize, col: usize, direction: Direction) -> (f32, f32) {
+        for pipe in &self.growing_pipes {
+            if (pipe.row, pipe.col, pipe.direction) == (row, col, direction) {
+                return (0.0, (pipe.progress * 2.0).min(1.0));
+            }
+            if direction == pipe.direction.opposite()
+                && self
+                    .grid
+                    .get_offset_row_col(pipe.row, pipe.col, pipe.direction)
+                    == Some((row, col))
+            {
+                return (1.0 - (pipe.progress * 2.0 - 1.0).clamp(0.0, 1.0), 1.0);
+            }
+        }
+        (0.0, 1.0)
+    }
+
+    /// A pipe turning through a square cell, as the quarter circle around the corner it turns
+    /// about, from the middle of one edge to the middle of the other. None for cells the pipe
+    /// goes straight through, and while either connection is still growing.
+    fn elbow(
+        &self,
+        metrics: &BoardMetrics,
+        cell_min: Pos2,
+        row: usize,
+        col: usize,
+    ) -> Option<Vec<Pos2>> {
+        let cell = self.grid.get(row, col)?;
+        if cell.is_source || cell.is_bridge() || cell.num_connections() != 2 {
+            return None;
+        }
+        let vertical = match (cell.is_connected_up, cell.is_connected_down) {
+            (true, false) => Direction::Up,
+            (false, true) => Direction::Down,
+            _ => return None,
+        };
+        let horizontal = match (cell.is_connected_left, cell.is_connected_right) {
+            (true, false) => Direction::Left,
+            (false, true) => Direction::Right,
+            _ => return None,
+        };
+        if [vertical, horizontal]
+            .into_iter()
+            .any(|direction| self.pipe_extent(row, col, direction) != (0.0, 1.0))
+        {
+            return None;
+        }
+
+        let radius = metrics.cell_size / 2.0;
+        let (to_vertical_edge, to_horizontal_edge) = match (vertical, horizontal) {
+            (Direction::Up, Direction::Left) => (Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)),
+            (Direction::Up, _) => (Vec2::new(-1.0, 0.0), Vec2::new(0.0, 1.0)),
+            (_, Direction::Left) => (Vec2::new(1.0, 0.0), Vec2::new(0.0, -1.0)),
+            _ => (Vec2::new(-1.0, 0.0), Vec2::new(0.0, -1.0)),
+        };
+        // the corner between the two edges the pipe leaves through
+        let corner = cell_min
+            + Vec2::new(
+                if horizontal == Direction::Right {
+                    metrics.cell_size
+                } else {
+                    0.0
+                },
+                if vertical == Direction::Down {
+                    metrics.cell_size
+                } else {
+                    0.0
+                },
+            );
+        Some(
+            (0..=ELBOW_SEGMENTS)
+                .map(|step| {
+                    let angle = step as f32 / ELBOW_SEGMENTS as f32 * std::f32::consts::FRAC_PI_2;
+                    corner
+                        + radius
+                            * (to_vertical_edge * angle.cos() + to_horizontal_edge * angle.sin())
+                })
+                .collect(),
+        )
+    }
+
+    /// the part of a square cell's half pipe, running from the middle of the cell to its edge
+    /// in the direction, that pipe_extent says to draw
+    fn grown_half_pipe(&self, rect: Rect, row: usize, col: usize, direction: Direction) -> Rect {
+        let (start, end) = self.pipe_extent(row, col, direction);
+        let (width, height) = (rect.width(), rect.height());
+        match direction {
+            Direction::Up => Rect::from_min_max(
+                Pos2::new(rect.min.x, rect.max.y - height * end),
+                Pos2::new(rect.max.x, rect.max.y - height * start),
+            ),
+            Direction::Down => Rect::from_min_max(
+                Pos2::new(rect.min.x, rect.min.y + height * start),
+                Pos2::new(rect.max.x, rect.min.y + height * end),
+            ),
+            Direction::Left => Rect::from_min_max(
+                Pos2::new(rect.max.x - width * end, rect.min.y),
+                Pos2::new(rect.max.x - width * start, rect.max.y),
+            ),
+            Direction::Right => Rect::from_min_max(
+                Pos2::new(rect.min.x + width * start, rect.min.y),
+                Pos2::new(rect.min.x + width * end, rect.max.y),
+            ),
+            _ => rect,
+        }
+    }
+
+    /// Writes each color's number on its sources, and smaller on the cells its pipes pass
+    /// through, so colors can be told apart without telling the colors apart. Bridges are left
+    /// out, since their two lanes would need two numbers, and so are sources hard mode hides.
+    fn draw_color_labels(&self, painter: &Painter, origin: Pos2, metrics: &BoardMetrics) {
+        for row in 0..self.grid.height {
+            for col in 0..self.grid.width {
+                let cell = self.grid.get(row, col).expect("looping in bounds");
+                let CellColor::Colored(color_id) = cell.color else {
+                    continue;
+                };
+                if self.is_hidden_source(row, col)
+                    || cell.is_bridge()
+                    || (!cell.is_source && cell.num_connections() == 0)
+                {
+                    continue;
+                }
+                let size = if cell.is_source {
+                    metrics.source_radius
+                } else {
+                    metrics.pipe_width * 0.8
+                };
+                let text_color =
+                    if relative_luminance(self.displayed_color(row, col)) > MID_LUMINANCE {
+                        Color32::BLACK
+                    } else {
+                        Color32::WHITE
+                    };
+                painter.text(
+                    metrics.cell_center(origin, row, col),
+                    Align2::CENTER_CENTER,
+                    (color_id + 1).to_string(),
+                    FontId::monospace(size),
+                    text_color,
+                );
+            }
+        }
+    }
+
+    /// Draws a ring around each warp, numbered by pair so it's clear which ones lead to each
+    /// other, with the end of the pipe in the middle once it goes through.
+    fn draw_warps(&self, painter: &Painter, origin: Pos2, metrics: &BoardMetrics) {
+        let ring = Stroke::new(metrics.grid_border_width * 2.0, self.drawn_style.warp_color);
+        for (pair, ends) in self.grid.warps().into_iter().enumerate() {
+            for (row, col) in [ends.0, ends.1] {
+                let center = metrics.cell_center(origin, row, col);
+                let cell = self.grid.get(row, col).expect("warps are in bounds");
+                if cell.is_connected_warp {
+                    painter.circle_filled(
+                        center,
+                        metrics.source_radius,
+                        self.displayed_color(row, col),
+                    );
+                }
+                painter.circle_stroke(center, metrics.source_radius, ring);
+                painter.text(
+                    center - Vec2::splat(metrics.cell_size * 0.3),
+                    Align2::CENTER_CENTER,
+                    (pair + 1).to_string(),
+                    FontId::proportional(metrics.cell_size * 0.2),
+                    self.drawn_style.warp_color,
+                );
+            }
+        }
+        if self.can_edit_sources
+            && self.edit_tool == EditTool::Warps
+            && let Some((row, col)) = self.warp_start
+        {
+            painter.circle_stroke(
+                metrics.cell_center(origin, row, col),
+                metrics.source_radius,
+                Stroke::new(ring.width, ring.color.gamma_multiply(0.5)),
+            );
+        }
+    }
+
+    /// Faintly draws the pipes that could be extended into an empty cell, to teach the rules.
+    fn draw_hover_preview(
+        &self,
+        painter: &Painter,
+        origin: Pos2,
+        metrics: &BoardMetrics,
+        row: usize,
+        col: usize,
+    ) {
+        let cell = if let Some(cell) = self.grid.get(row, col) {
+            cell
+        } else {
+            return;
+        };
+        if cell.is_source {
+            return;
+        }
+
+        let center = metrics.cell_center(origin, row, col);
+        for direction in Direction::ALL {
+            let (neighbor_row, neighbor_col) =
+                if let Some(row_col) = self.grid.get_offset_row_col(row, col, direction) {
+                    row_col
+                } else {
+                    continue;
+                };
+            let neighbor = self
+                .grid
+                .get(neighbor_row, neighbor_col)
+                .expect("offsets stay in bounds");
+            let can_extend = cell.lane_connections(direction) == 0
+                && (neighbor.is_source || neighbor.lane_connections(direction) > 0)
+                && self
+                    .grid
+                    .is_lane_open(neighbor_row, neighbor_col, direction.opposite())
+                && CellColor::can_colors_connect(
+                    &neighbor.lane_color(direction),
+                    &cell.lane_color(direction),
+                );
+            if !can_extend {
+                continue;
+            }
+            painter.line_segment(
+                [center, center + metrics.neighbor_offset(direction) / 2.0],
+                Stroke::new(
+                    metrics.pipe_width,
+                    self.displayed_lane_color(neighbor_row, neighbor_col, direction)
+                        .gamma_multiply(0.35),
+                ),
+            );
+        }
+    }
+
+    /// The straight run of connections from the anchor to the target, if the anchor is still an
+    /// open pipe end and nothing is in the way. This is what a hovering pen's elastic band shows,
+    /// and what touching the pen down commits.
+    fn elastic_band(&self, anchor: (usize, usize), target: (usize, usize)) -> Option<Vec<Move>> {
+        let anchor_cell = self.grid.get(anchor.0, anchor.1)?;
+        let direction = match (target.0.cmp(&anchor.0), target.1.cmp(&anchor.1)) {
+            (Ordering::Less, Ordering::Equal) => Direction::Up,
+            (Ordering::Greater, Ordering::Equal) => Direction::Down,
+            (Ordering::Equal, Ordering::Less) => Direction::Left,
+            (Ordering::Equal, Ordering::Greater) => Direction::Right,
+            (Ordering::Less, Ordering::Greater)
+                if self.grid.is_hex() && anchor.0 - target.0 == target.1 - anchor.1 =>
+            {
+                Direction::UpRight
+            }
+            (Ordering::Greater, Ordering::Less)
+                if self.grid.is_hex() && target.0 - anchor.0 == anchor.1 - target.1 =>
+            {
+                Direction::DownLeft
+            }
+            _ => return None,
+        };
+        if !(anchor_cell.is_source || anchor_cell.lane_connections(direction) > 0)
+            || !self.grid.is_lane_open(anchor.0, anchor.1, direction)
+        {
+            return None;
+        }
+
+        let mut moves = Vec::new();
+        let (mut row, mut col) = anchor;
+        while (row, col) != target {
+            let (next_row, next_col) = self.grid.get_offset_row_col(row, col, direction)?;
+            let next = self.grid.get(next_row, next_col)?;
+            // it can cross a bridge whose lane in this direction is free
+            let is_free = !next.is_source && next.lane_connections(direction) == 0;
+            // the band can end on the other source of the pipe's color
+            let is_partner = (next_row, next_col) == target
+                && next.is_source
+                && next.num_connections() == 0
+                && next.color == anchor_cell.lane_color(direction);
+            if !is_free && !is_partner {
+                return None;
+            }
+            moves.push(Move {
+                row,
+                col,
+                direction,
+            });
+            (row, col) = (next_row, next_col);
+        }
+        Some(moves)
+    }
+
+    /// Zooms in and out with the scroll wheel or a pinch, keeping the spot under the pointer
+    /// still, and moves the board while it's dragged with the middle button or with space held.
+    /// Returns whether the pointer is moving the board, so it shouldn't also draw.
+    fn handle_view_controls(&mut self, ui: &egui::Ui, response: &Response) -> bool {
+        if let Some(hover_pos) = response.hover_pos() {
+            // the wheel is used up here, so a scroll area around the canvas doesn't scroll too
+            let (scroll, pinch) = ui.input_mut(|input| {
+                let scroll = input.raw_scroll_delta.y;
+                input.raw_scroll_delta = Vec2::ZERO;
+                input.smooth_scroll_delta = Vec2::ZERO;
+                (scroll, input.zoom_delta())
+            });
+            let zoom = (self.zoom * pinch * (scroll * ZOOM_PER_SCROLL_POINT).exp())
+                .clamp(MIN_ZOOM, MAX_ZOOM);
+            if zoom != self.zoom {
+                let anchor = hover_pos - response.rect.min;
+                self.pan = anchor - (anchor - self.pan) * (zoom / self.zoom);
+                self.zoom = zoom;
+                ui.ctx().request_repaint();
+            }
+        }
+        let is_space_down = ui.input(|input| input.key_down(Key::Space));
+        if response.drag_started() {
+            self.is_panning = is_space_down || response.dragged_by(PointerButton::Middle);
+        }
+        let is_panning = self.is_panning;
+        if is_panning {
+            self.pan += response.drag_delta();
+        }
+        if response.drag_stopped() {
+            self.is_panning = false;
+        }
+        is_panning || is_space_down
+    }
+
+    /// Notices when a finger starts being used, and when a mouse takes over again. A pen is
+    /// handled by Stylus, since it reports how hard it's pressing.
+    fn update_touch(&mut self, events: &[egui::Event]) {
+        let mut saw_touch = false;
+        for event in events {
+            match event {
+                egui::Event::Touch { force: None, .. } => {
+                    saw_touch = true;
+                    self.is_touch = true;
+                }
+                egui::Event::PointerButton { pressed: true, .. } if !saw_touch => {
+                    self.is_touch = false;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// the cell at a spot relative to the board, if it's on the board
+    fn board_cell_at(&self, local_pos: Vec2, metrics: &BoardMetrics) -> Option<(usize, usize)> {
+        let (row, col) = if self.grid.wraps() {
+            // dragging off one edge carries on from the opposite one
+            Some(metrics.wrapped_cell_at(local_pos, self.grid.width, self.grid.height))
+        } else {
+            metrics.cell_at(local_pos)
+        }?;
+        (row < self.grid.height && col < self.grid.width).then_some((row, col))
+    }
+
+    /// Erases every cell on the line between two spots relative to the board, checking often
+    /// enough that no cell it crosses is missed.
+    fn erase_along(&mut self, from: Vec2, to: Vec2, metrics: &BoardMetrics) {
+        let num_steps = ((to - from).length() / (metrics.cell_size / 2.0)).ceil() as usize;
+        for step in 0..=num_steps {
+            let pos = from + (to - from) * (step as f32 / num_steps.max(1) as f32);
+            let Some((row, col)) = self.board_cell_at(pos, metrics) else {
+                continue;
+            };
+            let Some(&cell) = self.grid.get(row, col) else {
+                continue;
+            };
+            for direction in Direction::ALL {
+                if cell.is_direction_connected(direction) {
+                    self.grid.try_disconnect(row, col, direction);
+                }
+            }
+        }
+    }
+
+    fn handle_interactions(
+        &mut self,
+        response: &Response,
+        ctx: &Context,
+        origin: Pos2,
+        metrics: &BoardMetrics,
+    ) {
         let local_pos = if let Some(pointer_pos) = ctx.pointer_interact_pos() {
-            pointer_pos - canvas_rect.min
+            pointer_pos - origin
+        } else {
+            return;
+        };
+        let (row, col) = if let Some(row_col) = self.board_cell_at(local_pos, metrics) {
+            row_col
         } else {
             return;
         };
-        if local_pos.x < 0.0 || local_pos.y < 0.0 {
+        if self.stylus.is_active && !self.stylus.is_pressed_firmly() {
             return;
         }
-        let row = (local_pos.y / CELL_SIZE).floor() as usize;
-        let col = (local_pos.x / CELL_SIZE).floor() as usize;
-        if row >= self.grid.height || col >= self.grid.width {
+        if self.drag_tool == DragTool::Erase {
+            if response.clicked() || response.dragged() {
+                let from = self.erase_pos.unwrap_or(local_pos);
+                self.erase_along(from, local_pos, metrics);
+            }
+            self.erase_pos = response.dragged().then_some(local_pos);
             return;
         }
+        // a finger dragging near the edge of a cell stays in it until it's clearly left
+        let (row, col) = match self.previous_row_col {
+            Some((prev_row, prev_col))
+                if self.is_touch
+                    && response.dragged()
+                    && (origin + local_pos)
+                        .distance(metrics.cell_center(origin, prev_row, prev_col))
+                        < (0.5 + TOUCH_HIT_SLOP) * metrics.cell_size =>
+            {
+                (prev_row, prev_col)
+            }
+            _ => (row, col),
+        };
+        let is_long_touched = response.long_touched();
+        if self.is_touch && is_long_touched && !self.was_long_touched {
+            self.handle_clicked(row, col);
+        }
+        self.was_long_touched = is_long_touched;
 
-        response.clicked().then(|| self.handle_clicked(row, col));
+        if response.secondary_clicked() {
+            self.clear_pipe_at(row, col);
+        }
+        if response.double_clicked()
+            && !self.is_touch
+            && let Some((removed_at, color_id)) = self.removed_source.take()
+            && removed_at == (row, col)
+        {
+            // the first click already took away this source
+            self.grid.try_clear_color(color_id);
+        } else if response.clicked() {
+            // a tap that lays the elastic band is a stroke of its own. One that starts a drag
+            // is counted with the drag when it ends.
+            if self.try_commit_band(row, col) {
+                self.strokes += 1;
+            } else if !self.is_touch {
+                self.handle_clicked(row, col);
+            }
+        }
         response
             .drag_started()
             .then(|| self.handle_drag_start(row, col));
-        response.dragged().then(|| self.handle_dragged(row, col));
+        if response.dragged() && !self.handle_dragged(row, col) {
+            self.rejected_cell = Some(((row, col), ctx.input(|input| input.time)));
+        }
         response
             .drag_stopped()
             .then(|| self.handle_drag_stopped(row, col));
     }
 
+    /// Removes the whole pipe running through a cell, keeping its sources. A bridge clears its
+    /// horizontal pipe, or the vertical one if only that is laid.
+    fn clear_pipe_at(&mut self, row: usize, col: usize) {
+        let Some(cell) = self.grid.get(row, col) else {
+            return;
+        };
+        let color_id = [Direction::Right, Direction::Down]
+            .into_iter()
+            .find_map(|direction| match cell.lane_color(direction) {
+                CellColor::Colored(color_id) => Some(color_id),
+                CellColor::Empty(_) => None,
+            });
+        if let Some(color_id) = color_id {
+            self.grid.clear_color_pipes(color_id);
+        }
+    }
+
+    /// Lays the pipe the elastic band was showing, when the pen touches down at its end. Returns
+    /// whether there was one.
+    fn try_commit_band(&mut self, row: usize, col: usize) -> bool {
+        let band = if self.stylus.is_active
+            && let Some(anchor) = self.stylus_anchor
+        {
+            self.elastic_band(anchor, (row, col))
+        } else {
+            None
+        };
+        let Some(band) = band else {
+            return false;
+        };
+        for step in band {
+            self.grid.try_connect(step.row, step.col, step.direction);
+        }
+        self.stylus_anchor = Some((row, col));
+        self.previous_row_col = Some((row, col));
+        self.have_laid_pipe = true;
+        true
+    }
+
     fn handle_drag_start(&mut self, row: usize, col: usize) {
-        if self.grid.get(row, col).unwrap().num_connections() > 1 {
-            println!("TODO Started dragging in the middle of the pipe. Idk what I want to do.");
-            // TODO if one end is connected to the source, disconnect the other end
-            // if both ends connected or if neither end is connected, take the shortest path,
-            // otherwise, just pick one, who cares.
+        if self.try_commit_band(row, col) {
             return;
         }
+        let cell = self.grid.get(row, col).unwrap();
+        // a source with extra pipes can start another from where it branches, and a bridge
+        // can't tell which of its pipes was meant
+        if cell.num_connections() > 1 && !cell.is_source {
+            // grabbing the middle of a pipe draws on from there
+            if !self.grid.try_retract_to(row, col) {
+                return;
+            }
+        }
         self.previous_row_col = Some((row, col));
         self.have_laid_pipe = false;
+        self.stroke_color = self
+            .grid
+            .get(row, col)
+            .filter(|cell| !cell.is_bridge())
+            .and_then(|cell| match cell.color {
+                CellColor::Colored(color_id) => Some(color_id),
+                CellColor::Empty(_) => None,
+            });
     }
 
-    fn handle_dragged(&mut self, row: usize, col: usize) {
+    /// Returns false if the drag tried to connect into this cell and the grid refused, e.g. a
+    /// different color's pipe is already through it.
+    fn handle_dragged(&mut self, row: usize, col: usize) -> bool {
+        let mut is_accepted = true;
         if let Some((prev_row, prev_col)) = self.previous_row_col {
             if prev_row == row && prev_col == col {
-                return;
+                return true;
             }
-            if let Some(direction) = Direction::try_from_adjacent(prev_row, prev_col, row, col) {
-                let from_cell = self
-                    .grid
-                    .get(prev_row, prev_col)
-                    .expect("we should only have stored cells that are valid");
-                let to_cell = self
-                    .grid
-                    .get(row, col)
-                    .expect("previously bounds checked indexes");
-
-                if from_cell.is_direction_connected(direction) {
-                    self.grid.try_disconnect(prev_row, prev_col, direction);
-                } else if from_cell.color != to_cell.color {
-                    // TODO add some logic that you can't switch colors mid-drag.
-                    // For example, if you have . . .-.-. . . and then if you drag
-                    // that entire width, you'd end up with .-.-. . .-.-.
-                    self.grid.try_connect(prev_row, prev_col, direction);
-                } else if self.grid.are_cells_connected(prev_row, prev_col, row, col) {
+            if let Some(direction) = self.grid.direction_between(prev_row, prev_col, row, col) {
+                is_accepted = self.drag_step(prev_row, prev_col, row, col, direction);
+            } else if self.grid.are_cells_connected(prev_row, prev_col, row, col) {
+                // dragged back along the pipe faster than a cell a frame, if it's the stroke's own
+                let is_own_pipe = self.stroke_color.is_none_or(|color_id| {
+                    self.grid.get(row, col).map(|cell| cell.color)
+                        == Some(CellColor::Colored(color_id))
+                });
+                if is_own_pipe {
                     self.grid.remove_tail(row, col, prev_row, prev_col);
                 } else {
-                    self.grid.try_connect(prev_row, prev_col, direction);
+                    is_accepted = false;
+                }
+            } else if let Some(path) = self.grid.drag_path((prev_row, prev_col), (row, col)) {
+                // the pointer skipped some cells, so the pipe takes the shortest free way there
+                for step in path {
+                    let (next_row, next_col) = self
+                        .grid
+                        .get_offset_row_col(step.row, step.col, step.direction)
+                        .expect("paths stay on the grid");
+                    if !self.drag_step(step.row, step.col, next_row, next_col, step.direction) {
+                        is_accepted = false;
+                        break;
+                    }
                 }
             } else {
-                println!("TODO pathfinding");
-                // TODO handle diagonals or fast mouse movements
+                is_accepted = false;
             }
             self.have_laid_pipe = true;
         }
         self.previous_row_col = Some((row, col));
+        is_accepted
+    }
+
+    /// Connects, disconnects or retracts the pipe between two neighboring cells, whichever the
+    /// drag means. Returns false if the grid refused the connection.
+    fn drag_step(
+        &mut self,
+        prev_row: usize,
+        prev_col: usize,
+        row: usize,
+        col: usize,
+        direction: Direction,
+    ) -> bool {
+        let mut is_accepted = true;
+        let from_cell = self
+            .grid
+            .get(prev_row, prev_col)
+            .expect("we should only have stored cells that are valid");
+        let to_cell = self
+            .grid
+            .get(row, col)
+            .expect("previously bounds checked indexes");
+        if let Some(color_id) = self.stroke_color {
+            // after a refused step the drag is over someone else's pipe, which it leaves alone
+            let stroke_color = CellColor::Colored(color_id);
+            if from_cell.lane_color(direction) != stroke_color
+                || !CellColor::can_colors_connect(
+                    &to_cell.lane_color(direction.opposite()),
+                    &stroke_color,
+                )
+            {
+                return false;
+            }
+        }
+
+        if from_cell.is_direction_connected(direction) {
+            self.grid.try_disconnect(prev_row, prev_col, direction);
+        } else if from_cell.lane_color(direction) != to_cell.lane_color(direction.opposite()) {
+            is_accepted = self.grid.try_connect(prev_row, prev_col, direction);
+        } else if self.grid.are_cells_connected(prev_row, prev_col, row, col) {
+            self.grid.remove_tail(row, col, prev_row, prev_col);
+        } else {
+            is_accepted = self.grid.try_connect(prev_row, prev_col, direction);
+        }
+        if self
+            .grid
+            .get(row, col)
+            .is_some_and(|cell| cell.is_warp() && cell.is_direction_connected(direction.opposite()))
+        {
+            // the pipe comes out of the partner, where the next drag can pick it up
+            self.grid.try_connect(row, col, Direction::Warp);
+        }
+        // a drag that started on an empty cell takes the color of the first pipe it joins
+        if is_accepted
+            && self.stroke_color.is_none()
+            && let Some(cell) = self.grid.get(row, col)
+            && let CellColor::Colored(color_id) = cell.lane_color(direction.opposite())
+        {
+            self.stroke_color = Some(color_id);
+        }
+        is_accepted
     }
 
     fn handle_drag_stopped(&mut self, row: usize, col: usize) {
+        self.stylus_anchor = self
+            .stylus
+            .is_active
+            .then_some(self.previous_row_col)
+            .flatten();
         if !self.have_laid_pipe {
-            self.handle_clicked(row, col)
+            // a finger held still is a long press, which has already been handled
+            if !self.is_touch {
+                self.handle_clicked(row, col);
+            }
+            return;
+        }
+        self.strokes += 1;
+        if self.auto_complete && !self.grid.apply_forced_moves().is_empty() {
+            self.is_assisted = true;
         }
     }
 
@@ -243,23 +1957,41 @@ impl FlowCanvas {
             return;
         };
 
-        if cell.is_source {
-            self.grid.try_remove_source(row, col);
-        } else {
-            self.grid.try_set_new_source(row, col);
-        }
-    }
-}
-
-fn interpret_cell_color(color: CellColor) -> Color32 {
-    match color {
-        CellColor::Colored(color_id) => {
-            if color_id < COLOR_INDEX.len() {
-                COLOR_INDEX[color_id].1
-            } else {
-                Color32::BLACK
+        self.removed_source = None;
+        match self.edit_tool {
+            EditTool::Sources if cell.is_source => {
+                if let CellColor::Colored(color_id) = cell.color {
+                    self.removed_source = Some(((row, col), color_id));
+                }
+                self.grid.try_remove_source(row, col)
             }
-        }
-        CellColor::Empty(_) => Color32::from_rgb(0, 0, 0),
+            EditTool::Sources => match self.source_color {
+                Some(color_id) => self.grid.try_set_missing_source(row, col, color_id),
+                None => self.grid.try_set_new_source(row, col),
+            },
+            EditTool::Bridges if cell.is_bridge() => self.grid.try_remove_bridge(row, col),
+            EditTool::Bridges => self.grid.try_add_bridge(row, col),
+            EditTool::Holes if cell.is_blocked => self.grid.try_unblock(row, col),
+            EditTool::Holes => self.grid.try_block(row, col),
+            EditTool::Warps if cell.is_warp() => self.grid.try_remove_warp(row, col),
+            EditTool::Warps => match self.warp_start.take() {
+                Some((start_row, start_col)) => {
+                    self.grid.try_add_warp(start_row, start_col, row, col)
+                }
+                None => {
+                    self.warp_start = Some((row, col));
+                    true
+                }
+            },
+            EditTool::ExtraSources if cell.is_source => {
+                if let CellColor::Colored(color_id) = cell.color {
+                    self.extra_source_color = Some(color_id);
+                }
+                true
+            }
+            EditTool::ExtraSources => self
+                .extra_source_color
+                .is_some_and(|color_id| self.grid.try_add_extra_source(row, col, color_id)),
+        };
     }
 }
@@ -0,0 +1,327 @@
+/// This file has the command line solver, so puzzles can go through the solver from a shell:
+///
+/// ```text
+/// cargo run --release -- --solve puzzle.txt
+/// cat puzzle.txt | flow --solve --timeout 5 > solution.txt
+/// ```
+///
+/// The puzzle comes from the file, or from stdin when no file is given, in the corpus format:
+/// one line per row, letters for sources and '.' for empty cells. The solution goes to stdout
+/// the way the corpus records it, as the U, D, L and R moves from each color's first source to
+/// its second, one color per line. Anything meant for a person goes to stderr, so stdout can be
+/// piped on, and the exit code says how it went, one of the EXIT_ codes below.
+///
+/// `--dot [<file>]` reads a board the same way and prints it as a Graphviz graph instead, for
+/// `flow --dot puzzle.txt | neato -n -Tpng > puzzle.png`.
+///
+/// Every command (--solve, --pack, --bench and --gym) also takes `--format json`, which prints
+/// one JSON object to stdout instead of text, for checking puzzles in CI. Each object has a
+/// "schema" field, which is SCHEMA_VERSION, and boards are named by their puzzle_hash. The
+/// fields of each command's object are documented on its report struct, like SolveReport here.
+/// Fields are only ever added within a schema version, never renamed or removed.
+use crate::flow_grid::{Direction, FlowGrid};
+use crate::flow_solver::{self, SolveOptions, SolveOutcome, SolverConfig};
+use serde::Serialize;
+use std::io::Read;
+use web_time::{Duration, Instant};
+
+/// bumped whenever a field of a JSON report is renamed or removed, or changes meaning
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// the puzzle was solved, or the command did what it was asked
+pub const EXIT_SOLVED: i32 = 0;
+/// the arguments were wrong, or a file couldn't be read or written
+pub const EXIT_USAGE: i32 = 1;
+/// the input isn't a puzzle, like rows of different lengths or a letter that appears once
+pub const EXIT_INVALID: i32 = 2;
+/// the solver searched everywhere and found no solution
+pub const EXIT_UNSOLVABLE: i32 = 3;
+/// the solver ran out of time before it finished
+pub const EXIT_TIMED_OUT: i32 = 4;
+/// the puzzle has bridges, which the solver can't route through yet
+pub const EXIT_UNSUPPORTED: i32 = 5;
+
+/// how a command prints its results
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// for people, in a table or a few lines
+    #[default]
+    Text,
+    /// one JSON object, for scripts
+    Json,
+}
+
+/// Takes `--format text|json` out of the args, or None if it names some other format.
+pub fn take_format(args: &mut Vec<String>) -> Option<Format> {
+    match take_flag(args, "--format").as_deref() {
+        None | Some("text") => Some(Format::Text),
+        Some("json") => Some(Format::Json),
+        Some(_) => None,
+    }
+}
+
+/// Prints a report as JSON on one line.
+pub fn print_json(report: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string(report).expect("reports are plain data")
+    );
+}
+
+/// A name for the board that stays the same from run to run and version to version: the
+/// 64-bit FNV-1a hash of its rows as to_board writes them, joined by newlines, in hex. Boards
+/// that only differ in which letters they use hash the same.
+pub fn puzzle_hash(grid: &FlowGrid) -> String {
+    let board = grid.to_board().unwrap_or_default().join("\n");
+    let hash = board.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// What `--solve --format json` prints.
+#[derive(Serialize)]
+pub struct SolveReport {
+    pub schema: u32,
+    /// None if the input wasn't a puzzle
+    pub puzzle_hash: Option<String>,
+    /// "solved", "invalid", "unsolvable", "timed_out" or "unsupported", matching the exit code
+    pub status: &'static str,
+    pub solved: bool,
+    /// each color's moves from its first source, like the text output. Empty unless solved.
+    pub paths: Vec<String>,
+    pub timings: SolveTimings,
+    pub strategy: SolveStrategy,
+}
+
+#[derive(Serialize)]
+pub struct SolveTimings {
+    /// how long reading the board took, in milliseconds
+    pub parse_ms: f64,
+    /// how long the solver ran, in milliseconds
+    pub solve_ms: f64,
+}
+
+/// how the solver was asked to search
+#[derive(Serialize)]
+pub struct SolveStrategy {
+    pub timeout_seconds: f64,
+    pub parallel: bool,
+    pub config: SolverConfig,
+}
+
+/// Reads a board in the corpus format. Blank lines and spaces around rows are ignored, so a
+/// file with a trailing newline reads the same as one without.
+pub fn read_board(text: &str) -> Option<FlowGrid> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    FlowGrid::from_board(&rows)
+}
+
+/// each color's pipe as the moves from its first source, in color order, like the corpus
+pub fn pipe_paths(grid: &FlowGrid) -> Vec<String> {
+    (0..grid.num_colors())
+        .map(|color_id| {
+            grid.pipe_moves(color_id)
+                .map(|(_, moves)| moves.iter().map(Direction::letter).collect())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Takes a flag and the value after it out of the args, and returns the value. A flag with
+/// nothing after it has an empty value.
+pub fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(position + 1).cloned().unwrap_or_default();
+    args.drain(position..(position + 2).min(args.len()));
+    Some(value)
+}
+
+/// Reads the file, or stdin when there's no file, or says what went wrong.
+fn read_input(path: Option<&String>) -> Result<String, String> {
+    match path {
+        Some(path) => {
+            std::fs::read_to_string(path).map_err(|error| format!("Couldn't read {path}: {error}"))
+        }
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map(|_| text)
+                .map_err(|error| format!("Couldn't read stdin: {error}"))
+        }
+    }
+}
+
+/// Runs `--dot` with the args after it, printing the board as a Graphviz graph, and returns the
+/// exit code.
+pub fn run_dot(args: &[String]) -> i32 {
+    if args.len() > 1 || args.iter().any(|arg| arg.starts_with("--")) {
+        eprintln!("Usage: --dot [<file>]");
+        return EXIT_USAGE;
+    }
+    let text = match read_input(args.first()) {
+        Ok(text) => text,
+        Err(message) => {
+            eprintln!("{message}");
+            return EXIT_USAGE;
+        }
+    };
+    match read_board(&text) {
+        Some(grid) => {
+            print!("{}", grid.to_dot());
+            EXIT_SOLVED
+        }
+        None => {
+            eprintln!(
+                "Not a puzzle: rows must be the same length, and every letter must appear twice"
+            );
+            EXIT_INVALID
+        }
+    }
+}
+
+/// Runs `--solve` with the args after it, printing in the format, and returns the exit code.
+pub fn run_solve(args: &[String], format: Format) -> i32 {
+    let usage = "Usage: --solve [<file>] [--timeout <seconds>] [--format text|json]";
+    let mut args = args.to_vec();
+    let mut options = SolveOptions::default();
+    if let Some(seconds) = take_flag(&mut args, "--timeout") {
+        let Some(timeout) = seconds
+            .parse()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        else {
+            eprintln!("{usage}");
+            return EXIT_USAGE;
+        };
+        options.timeout = timeout;
+    }
+    if args.len() > 1 || args.iter().any(|arg| arg.starts_with("--")) {
+        eprintln!("{usage}");
+        return EXIT_USAGE;
+    }
+
+    let text = match read_input(args.first()) {
+        Ok(text) => text,
+        Err(message) => {
+            eprintln!("{message}");
+            return EXIT_USAGE;
+        }
+    };
+
+    let start = Instant::now();
+    let grid = read_board(&text);
+    let parse_time = start.elapsed();
+    let start = Instant::now();
+    let outcome = grid
+        .as_ref()
+        .map(|grid| flow_solver::solve_with_options(grid, &options));
+    let solve_time = start.elapsed();
+
+    let (status, exit_code, paths) = match &outcome {
+        None => {
+            eprintln!(
+                "Not a puzzle: rows must be the same length, and every letter must appear twice"
+            );
+            ("invalid", EXIT_INVALID, Vec::new())
+        }
+        Some(SolveOutcome::Solved(solution)) => {
+            eprintln!("Solved in {:.1} ms", solve_time.as_secs_f64() * 1000.0);
+            ("solved", EXIT_SOLVED, pipe_paths(solution))
+        }
+        Some(SolveOutcome::Unsolvable) => {
+            eprintln!("The puzzle has no solution");
+            ("unsolvable", EXIT_UNSOLVABLE, Vec::new())
+        }
+        Some(SolveOutcome::TimedOut) => {
+            eprintln!(
+                "The solver ran out of time after {:.1} s",
+                solve_time.as_secs_f64()
+            );
+            ("timed_out", EXIT_TIMED_OUT, Vec::new())
+        }
+        Some(SolveOutcome::Unsupported) => {
+            eprintln!("The solver can't handle bridges yet");
+            ("unsupported", EXIT_UNSUPPORTED, Vec::new())
+        }
+    };
+    match format {
+        Format::Text => {
+            for path in &paths {
+                println!("{path}");
+            }
+        }
+        Format::Json => print_json(&SolveReport {
+            schema: SCHEMA_VERSION,
+            puzzle_hash: grid.as_ref().map(puzzle_hash),
+            status,
+            solved: exit_code == EXIT_SOLVED,
+            paths,
+            timings: SolveTimings {
+                parse_ms: parse_time.as_secs_f64() * 1000.0,
+                solve_ms: solve_time.as_secs_f64() * 1000.0,
+            },
+            strategy: SolveStrategy {
+                timeout_seconds: options.timeout.as_secs_f64(),
+                parallel: options.parallel,
+                config: options.config,
+            },
+        }),
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_corpus::{CORPUS, Layout};
+
+    #[test]
+    fn corpus_boards_solve_to_their_recorded_paths() {
+        // hexes, wrapping and warps can't be written in the corpus format
+        for puzzle in CORPUS
+            .iter()
+            .filter(|puzzle| puzzle.layout == Layout::Square)
+        {
+            let text = format!("{}\n\n", puzzle.board.join("\n"));
+            let grid = read_board(&text).expect("corpus boards are valid");
+            match flow_solver::solve_with_options(&grid, &SolveOptions::default()) {
+                SolveOutcome::Solved(solution) => {
+                    assert_eq!(pipe_paths(&solution), puzzle.solution, "{}", puzzle.name);
+                }
+                SolveOutcome::Unsupported => assert!(grid.has_bridges(), "{}", puzzle.name),
+                SolveOutcome::Unsolvable | SolveOutcome::TimedOut => {
+                    panic!("{}: corpus puzzles are solvable", puzzle.name)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ragged_or_unpaired_boards_are_not_puzzles() {
+        assert!(read_board("R.R\nG.").is_none());
+        assert!(read_board("R..\n...").is_none());
+        assert!(read_board("").is_none());
+    }
+
+    #[test]
+    fn puzzle_hashes_ignore_which_letters_are_used() {
+        let hash = |text| puzzle_hash(&read_board(text).expect("a valid board"));
+        assert_eq!(hash("R..R\nG.G."), hash("A..A\nB.B."));
+        assert_ne!(hash("R..R\nG.G."), hash("R.R.\nG..G"));
+        assert_eq!(hash("R..R\nG.G.").len(), 16);
+    }
+
+    #[test]
+    fn take_flag_removes_the_flag_and_its_value() {
+        let mut args: Vec<String> = ["a.txt", "--timeout", "5", "b"].map(String::from).to_vec();
+        assert_eq!(take_flag(&mut args, "--timeout"), Some("5".to_string()));
+        assert_eq!(args, ["a.txt", "b"]);
+        assert_eq!(take_flag(&mut args, "--timeout"), None);
+    }
+}
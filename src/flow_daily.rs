@@ -0,0 +1,158 @@
+/// This file has the daily puzzle: everyone gets the same generated puzzle each day, seeded from
+/// the date, and the days the player finished are remembered for a streak and a calendar. Days
+/// roll over at midnight UTC, so the puzzle changes at the same moment for everyone.
+use std::collections::BTreeSet;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// every daily puzzle is this wide and tall
+pub const DAILY_SIZE: usize = 7;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// the days since 1970-01-01, which is how days are counted everywhere here
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() / SECONDS_PER_DAY)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Date {
+    pub year: u64,
+    /// 1 for January
+    pub month: u64,
+    /// 1 for the first of the month
+    pub day: u64,
+}
+
+impl Date {
+    /// the calendar date of a day number, from Howard Hinnant's civil_from_days
+    pub fn of(day_number: u64) -> Self {
+        let days = day_number + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // months counted from March, so the leap day is at the end
+        let march_month = (5 * day_of_year + 2) / 153;
+        let month = if march_month < 10 {
+            march_month + 3
+        } else {
+            march_month - 9
+        };
+        Date {
+            year: year_of_era + era * 400 + u64::from(month <= 2),
+            month,
+            day: day_of_year - (153 * march_month + 2) / 5 + 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// 0 for Monday up to 6 for Sunday. 1970-01-01 was a Thursday.
+pub fn weekday(day_number: u64) -> u64 {
+    (day_number + 3) % 7
+}
+
+/// The seed a day's puzzle is generated from. It's the date written as digits, so the puzzle
+/// code reads like "7x7 #20261016".
+pub fn seed(day_number: u64) -> u64 {
+    let date = Date::of(day_number);
+    date.year * 10_000 + date.month * 100 + date.day
+}
+
+/// which daily puzzles the player has finished
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DailyRecord {
+    completed: BTreeSet<u64>,
+}
+
+impl DailyRecord {
+    pub fn complete(&mut self, day_number: u64) {
+        self.completed.insert(day_number);
+    }
+
+    pub fn is_completed(&self, day_number: u64) -> bool {
+        self.completed.contains(&day_number)
+    }
+
+    /// How many days in a row were finished, up to today. Today not being done yet doesn't break
+    /// the streak, since there's still time.
+    pub fn streak(&self, today: u64) -> usize {
+        let last = if self.is_completed(today) {
+            today
+        } else {
+            today.saturating_sub(1)
+        };
+        (0..=last)
+            .rev()
+            .take_while(|&day_number| self.is_completed(day_number))
+            .count()
+    }
+
+    /// the finished days as day numbers separated by commas, for saving
+    pub fn to_text(&self) -> String {
+        self.completed
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// reads to_text back, or None if it's been mangled
+    pub fn from_text(text: &str) -> Option<Self> {
+        let completed = text
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(|part| part.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        Some(DailyRecord { completed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_numbers_become_dates() {
+        assert_eq!(Date::of(0).to_string(), "1970-01-01");
+        assert_eq!(Date::of(11_016).to_string(), "2000-02-29");
+        assert_eq!(Date::of(20_742).to_string(), "2026-10-16");
+        assert_eq!(weekday(20_742), 4, "a Friday");
+        assert_eq!(seed(20_742), 20_261_016);
+    }
+
+    #[test]
+    fn streaks_count_back_from_today() {
+        let mut record = DailyRecord::default();
+        assert_eq!(record.streak(100), 0);
+        for day_number in [95, 97, 98, 99] {
+            record.complete(day_number);
+        }
+        assert_eq!(record.streak(100), 3, "today isn't over yet");
+        record.complete(100);
+        assert_eq!(record.streak(100), 4);
+        assert_eq!(record.streak(102), 0);
+    }
+
+    #[test]
+    fn records_round_trip_through_text() {
+        let mut record = DailyRecord::default();
+        assert_eq!(
+            DailyRecord::from_text(&record.to_text()),
+            Some(record.clone())
+        );
+        record.complete(20_742);
+        record.complete(3);
+        assert_eq!(record.to_text(), "3,20742");
+        assert_eq!(DailyRecord::from_text(&record.to_text()), Some(record));
+        assert_eq!(DailyRecord::from_text("3,x"), None);
+    }
+}
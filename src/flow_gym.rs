@@ -0,0 +1,311 @@
+/// This file wraps the grid as an environment for reinforcement learning, shaped like the usual
+/// gym API: reset to a puzzle, then step with one action at a time and get back what the board
+/// looks like now, a reward, and whether the episode is over. Run `cargo run --release -- --gym`
+/// to watch a random agent play, which is the baseline a trained agent has to beat.
+use crate::flow_cli;
+use crate::flow_corpus::CORPUS;
+use crate::flow_generator::Rng;
+use crate::flow_grid::{CellColor, Direction, FlowGrid, Move};
+use serde::Serialize;
+
+/// for solving the board
+const SOLVED_REWARD: f32 = 1.0;
+/// for every step, so shorter solutions score higher
+const STEP_REWARD: f32 = -0.01;
+/// for an action that isn't legal, on top of the step. The board is left as it was.
+const ILLEGAL_REWARD: f32 = -0.1;
+/// how many steps an episode gets per cell before it's cut off
+const STEPS_PER_CELL: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Connect(Move),
+    Disconnect(Move),
+}
+
+/// What the agent sees: the board, one entry per cell, row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    /// the cell's color id plus one, or 0 for a cell no pipe has colored
+    pub colors: Vec<usize>,
+    pub sources: Vec<bool>,
+    /// whether the cell connects in each of Direction::ALL, where UpRight and DownLeft are only
+    /// used by hex grids and the last is the link through a warp
+    pub connections: Vec<[bool; 7]>,
+}
+
+impl Observation {
+    fn of(grid: &FlowGrid) -> Self {
+        let mut observation = Observation {
+            width: grid.width,
+            height: grid.height,
+            colors: Vec::with_capacity(grid.width * grid.height),
+            sources: Vec::with_capacity(grid.width * grid.height),
+            connections: Vec::with_capacity(grid.width * grid.height),
+        };
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                let cell = grid.get(row, col).expect("looping in bounds");
+                observation.colors.push(match cell.color {
+                    CellColor::Colored(color_id) => color_id + 1,
+                    CellColor::Empty(_) => 0,
+                });
+                observation.sources.push(cell.is_source);
+                observation
+                    .connections
+                    .push(Direction::ALL.map(|direction| cell.is_direction_connected(direction)));
+            }
+        }
+        observation
+    }
+}
+
+/// what one step returns
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: f32,
+    /// the board is solved, or the episode ran out of steps
+    pub done: bool,
+}
+
+pub struct FlowEnv {
+    grid: FlowGrid,
+    steps: usize,
+    max_steps: usize,
+}
+
+impl FlowEnv {
+    /// an environment on the first corpus puzzle. Call reset to pick another.
+    pub fn new() -> Self {
+        let mut env = FlowEnv {
+            grid: FlowGrid::with_size(1, 1),
+            steps: 0,
+            max_steps: 0,
+        };
+        env.reset(0);
+        env
+    }
+
+    /// Starts a new episode on the puzzle the seed picks. The same seed always gives the same
+    /// puzzle.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let puzzle = &CORPUS[(seed % CORPUS.len() as u64) as usize];
+        self.grid = puzzle.build_grid();
+        self.steps = 0;
+        self.max_steps = self.grid.width * self.grid.height * STEPS_PER_CELL;
+        Observation::of(&self.grid)
+    }
+
+    pub fn step(&mut self, action: Action) -> Step {
+        let is_legal = match action {
+            Action::Connect(Move {
+                row,
+                col,
+                direction,
+            }) => self.grid.try_connect(row, col, direction),
+            Action::Disconnect(Move {
+                row,
+                col,
+                direction,
+            }) => self.grid.try_disconnect(row, col, direction),
+        };
+        self.steps += 1;
+
+        let is_solved = self.grid.is_solved();
+        let mut reward = STEP_REWARD;
+        if !is_legal {
+            reward += ILLEGAL_REWARD;
+        }
+        if is_solved {
+            reward += SOLVED_REWARD;
+        }
+        Step {
+            observation: Observation::of(&self.grid),
+            reward,
+            done: is_solved || self.steps >= self.max_steps,
+        }
+    }
+
+    /// Every action step would accept right now. Each connection is only listed once, from the
+    /// cell above or to the left of it, or on a hex grid, up and to the right of it. A link
+    /// through a warp is listed from whichever of the pair comes first.
+    pub fn legal_actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for row in 0..self.grid.height {
+            for col in 0..self.grid.width {
+                let warp = self
+                    .grid
+                    .get(row, col)
+                    .and_then(|cell| cell.warp_to)
+                    .filter(|&partner| (row, col) < partner)
+                    .map(|_| Direction::Warp);
+                for direction in [Direction::Down, Direction::Right, Direction::DownLeft]
+                    .into_iter()
+                    .chain(warp)
+                {
+                    let step = Move {
+                        row,
+                        col,
+                        direction,
+                    };
+                    if self.grid.can_connect(row, col, direction) {
+                        actions.push(Action::Connect(step));
+                    } else if self.grid.can_disconnect(row, col, direction) {
+                        actions.push(Action::Disconnect(step));
+                    }
+                }
+            }
+        }
+        actions
+    }
+
+    pub fn grid(&self) -> &FlowGrid {
+        &self.grid
+    }
+}
+
+impl Default for FlowEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What `--gym --format json` prints.
+#[derive(Serialize)]
+pub struct GymReport {
+    pub schema: u32,
+    pub episodes: Vec<GymEpisode>,
+}
+
+/// how the random agent did on one corpus puzzle
+#[derive(Serialize)]
+pub struct GymEpisode {
+    pub name: &'static str,
+    pub puzzle_hash: String,
+    pub steps: usize,
+    pub reward: f32,
+    /// how many cells a pipe colored by the end, out of all the cells
+    pub filled: usize,
+    pub cells: usize,
+    pub solved: bool,
+}
+
+/// Plays an episode on every corpus puzzle with an agent that picks uniformly from the legal
+/// actions, and prints how it did in the format.
+pub fn run_random_agent(format: flow_cli::Format) {
+    if format == flow_cli::Format::Text {
+        println!(
+            "{:<16} {:>8} {:>10} {:>8} {:>8}",
+            "Puzzle", "Steps", "Reward", "Filled", "Solved"
+        );
+    }
+    let mut episodes = Vec::new();
+    let mut env = FlowEnv::new();
+    let mut rng = Rng::new(0);
+    for (seed, puzzle) in CORPUS.iter().enumerate() {
+        env.reset(seed as u64);
+        let (mut steps, mut total_reward) = (0, 0.0);
+        let observation = loop {
+            let actions = env.legal_actions();
+            let action = actions[rng.below(actions.len())];
+            let step = env.step(action);
+            steps += 1;
+            total_reward += step.reward;
+            if step.done {
+                break step.observation;
+            }
+        };
+        let episode = GymEpisode {
+            name: puzzle.name,
+            puzzle_hash: flow_cli::puzzle_hash(env.grid()),
+            steps,
+            reward: total_reward,
+            filled: observation
+                .colors
+                .iter()
+                .filter(|&&color| color != 0)
+                .count(),
+            cells: observation.width * observation.height,
+            solved: env.grid().is_solved(),
+        };
+        if format == flow_cli::Format::Text {
+            println!(
+                "{:<16} {:>8} {:>10.2} {:>8} {:>8}",
+                episode.name,
+                episode.steps,
+                episode.reward,
+                format!("{}/{}", episode.filled, episode.cells),
+                episode.solved
+            );
+        }
+        episodes.push(episode);
+    }
+    if format == flow_cli::Format::Json {
+        flow_cli::print_json(&GymReport {
+            schema: flow_cli::SCHEMA_VERSION,
+            episodes,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playing_the_solution_wins() {
+        for (seed, puzzle) in CORPUS.iter().enumerate() {
+            let mut env = FlowEnv::new();
+            env.reset(seed as u64);
+            let moves = puzzle.solution_moves();
+            for (move_index, &step) in moves.iter().enumerate() {
+                let result = env.step(Action::Connect(step));
+                let is_last = move_index + 1 == moves.len();
+                assert_eq!(result.done, is_last, "{}", puzzle.name);
+                let expected = if is_last {
+                    STEP_REWARD + SOLVED_REWARD
+                } else {
+                    STEP_REWARD
+                };
+                assert_eq!(result.reward, expected, "{}", puzzle.name);
+            }
+        }
+    }
+
+    #[test]
+    fn illegal_actions_are_penalized_and_change_nothing() {
+        let mut env = FlowEnv::new();
+        let before = env.reset(0);
+        // nothing is connected yet, so there's nothing to disconnect
+        let result = env.step(Action::Disconnect(Move {
+            row: 0,
+            col: 0,
+            direction: Direction::Right,
+        }));
+        assert_eq!(result.reward, STEP_REWARD + ILLEGAL_REWARD);
+        assert_eq!(result.observation, before);
+    }
+
+    #[test]
+    fn legal_actions_are_all_accepted() {
+        let mut env = FlowEnv::new();
+        for seed in 0..CORPUS.len() as u64 {
+            env.reset(seed);
+            for action in env.legal_actions() {
+                let mut copy = FlowEnv {
+                    grid: env.grid.clone(),
+                    steps: 0,
+                    max_steps: usize::MAX,
+                };
+                assert_ne!(
+                    copy.step(action).reward,
+                    STEP_REWARD + ILLEGAL_REWARD,
+                    "{action:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use eframe::egui::{Color32, Pos2, Vec2};
+/// This file draws the board into a PNG, for sharing. It has its own small rasterizer rather than
+/// going through egui, so it works at any resolution and without a window, and it draws the same
+/// shapes as the spectator's SVG: grid lines, or an outline for each cell of a hex grid, a filled
+/// shape for each blocked cell, a circle for each source, a ring for each warp and a round-capped
+/// line for each half of a pipe. The background is left transparent.
+use flow::flow_canvas::{BoardMetrics, BoardStyle};
+use flow::flow_grid::{Direction, FlowGrid};
+
+/// an RGBA image being drawn into, with straight alpha
+struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Raster {
+    fn new(width: usize, height: usize) -> Self {
+        Raster {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+        }
+    }
+
+    /// Paints every pixel by how much of it the shape covers, from 0 to 1, given the distance
+    /// from the pixel's center to the shape's edge, negative inside. Only pixels within the
+    /// bounds are looked at.
+    fn fill(
+        &mut self,
+        (min_x, min_y, max_x, max_y): (f32, f32, f32, f32),
+        color: Color32,
+        signed_distance: impl Fn(f32, f32) -> f32,
+    ) {
+        let clamp_x = |x: f32| (x.max(0.0) as usize).min(self.width);
+        let clamp_y = |y: f32| (y.max(0.0) as usize).min(self.height);
+        let source = [color.r(), color.g(), color.b()].map(|channel| channel as f32 / 255.0);
+        for y in clamp_y(min_y.floor())..clamp_y(max_y.ceil() + 1.0) {
+            for x in clamp_x(min_x.floor())..clamp_x(max_x.ceil() + 1.0) {
+                let coverage =
+                    (0.5 - signed_distance(x as f32 + 0.5, y as f32 + 0.5)).clamp(0.0, 1.0);
+                let alpha = coverage * color.a() as f32 / 255.0;
+                if alpha == 0.0 {
+                    continue;
+                }
+                // source over, with straight alpha
+                let pixel = &mut self.pixels[y * self.width + x];
+                let old_alpha = pixel[3];
+                let out_alpha = alpha + old_alpha * (1.0 - alpha);
+                for (channel, source) in pixel.iter_mut().zip(source) {
+                    *channel = (source * alpha + *channel * old_alpha * (1.0 - alpha)) / out_alpha;
+                }
+                pixel[3] = out_alpha;
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, min: Pos2, max: Pos2, color: Color32) {
+        self.fill((min.x, min.y, max.x, max.y), color, |x, y| {
+            (min.x - x).max(x - max.x).max(min.y - y).max(y - max.y)
+        });
+    }
+
+    fn fill_circle(&mut self, center: Pos2, radius: f32, color: Color32) {
+        self.fill(
+            (
+                center.x - radius,
+                center.y - radius,
+                center.x + radius,
+                center.y + radius,
+            ),
+            color,
+            |x, y| ((x - center.x).powi(2) + (y - center.y).powi(2)).sqrt() - radius,
+        );
+    }
+
+    fn fill_ring(&mut self, center: Pos2, radius: f32, width: f32, color: Color32) {
+        let outer = radius + width / 2.0;
+        self.fill(
+            (
+                center.x - outer,
+                center.y - outer,
+                center.x + outer,
+                center.y + outer,
+            ),
+            color,
+            |x, y| {
+                (((x - center.x).powi(2) + (y - center.y).powi(2)).sqrt() - radius).abs()
+                    - width / 2.0
+            },
+        );
+    }
+
+    /// a convex polygon whose corners go clockwise
+    fn fill_polygon(&mut self, corners: &[Pos2], color: Color32) {
+        let bounds = corners.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(min_x, min_y, max_x, max_y), corner| {
+                (
+                    min_x.min(corner.x),
+                    min_y.min(corner.y),
+                    max_x.max(corner.x),
+                    max_y.max(corner.y),
+                )
+            },
+        );
+        // with y pointing down, a quarter turn points a clockwise side into the shape
+        let sides: Vec<(Pos2, Vec2)> = corners
+            .iter()
+            .zip(corners.iter().cycle().skip(1))
+            .map(|(&start, &end)| (start, -(end - start).rot90().normalized()))
+            .collect();
+        self.fill(bounds, color, |x, y| {
+            sides
+                .iter()
+                .map(|(start, outwards)| (Pos2::new(x, y) - *start).dot(*outwards))
+                .fold(f32::MIN, f32::max)
+        });
+    }
+
+    /// a line with round ends
+    fn fill_line(&mut self, start: Pos2, end: Pos2, width: f32, color: Color32) {
+        let radius = width / 2.0;
+        let (dx, dy) = (end.x - start.x, end.y - start.y);
+        let length_squared = (dx * dx + dy * dy).max(f32::EPSILON);
+        self.fill(
+            (
+                start.x.min(end.x) - radius,
+                start.y.min(end.y) - radius,
+                start.x.max(end.x) + radius,
+                start.y.max(end.y) + radius,
+            ),
+            color,
+            |x, y| {
+                let along =
+                    (((x - start.x) * dx + (y - start.y) * dy) / length_squared).clamp(0.0, 1.0);
+                let (nearest_x, nearest_y) = (start.x + dx * along, start.y + dy * along);
+                ((x - nearest_x).powi(2) + (y - nearest_y).powi(2)).sqrt() - radius
+            },
+        );
+    }
+
+    fn to_rgba8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.map(|channel| (channel * 255.0).round() as u8))
+            .collect()
+    }
+}
+
+/// Draws the board with cells of the given size in pixels and encodes it as a PNG.
+pub fn board_to_png(
+    grid: &FlowGrid,
+    style: &BoardStyle,
+    cell_size: f32,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let metrics = BoardMetrics::new(cell_size, 1.0).with_hex(grid.is_hex());
+    let cell_pitch = metrics.cell_pitch();
+    let size = metrics.board_size(grid.width, grid.height);
+    let (width, height) = (size.x.ceil() as usize, size.y.ceil() as usize);
+    let grid_line_color = style.grid_line_color.unwrap_or(Color32::GRAY);
+
+    let mut raster = Raster::new(width, height);
+    // hex grids and shaped boards outline each cell instead
+    let off_board = grid.off_board_cells();
+    let is_shaped = off_board.contains(&true);
+    if !grid.is_hex() && !is_shaped {
+        for row in 0..=grid.height {
+            let y = row as f32 * cell_pitch;
+            raster.fill_rect(
+                Pos2::new(0.0, y),
+                Pos2::new(size.x, y + metrics.grid_border_width),
+                grid_line_color,
+            );
+        }
+        for col in 0..=grid.width {
+            let x = col as f32 * cell_pitch;
+            raster.fill_rect(
+                Pos2::new(x, 0.0),
+                Pos2::new(x + metrics.grid_border_width, size.y),
+                grid_line_color,
+            );
+        }
+    }
+
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            if off_board[row * grid.width + col] {
+                continue;
+            }
+            let cell = grid.get(row, col).expect("looping in bounds");
+            let color = style.cell_color(cell.color);
+            let center = metrics.cell_center(Pos2::ZERO, row, col);
+
+            if grid.is_hex() || is_shaped {
+                let outline = metrics.cell_outline(center);
+                for (&start, &end) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+                    raster.fill_line(start, end, metrics.grid_border_width, grid_line_color);
+                }
+            }
+            if cell.is_blocked {
+                raster.fill_polygon(&metrics.cell_corners(center), style.blocked_color);
+            }
+            if cell.is_source {
+                raster.fill_circle(center, metrics.source_radius, color);
+            }
+            // each half of a pipe runs from the middle of the cell to the shared border, in the
+            // color of its lane, so a bridge's crossing pipes keep their own colors
+            for direction in Direction::ALL {
+                if !cell.is_direction_connected(direction) {
+                    continue;
+                }
+                raster.fill_line(
+                    center,
+                    center + metrics.neighbor_offset(direction) / 2.0,
+                    metrics.pipe_width,
+                    style.cell_color(cell.lane_color(direction)),
+                );
+            }
+            if cell.is_warp() {
+                // a pipe through a warp ends in the middle of the ring
+                if cell.is_connected_warp {
+                    raster.fill_circle(center, metrics.source_radius, color);
+                }
+                raster.fill_ring(
+                    center,
+                    metrics.source_radius,
+                    metrics.grid_border_width * 2.0,
+                    style.warp_color,
+                );
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&raster.to_rgba8())?;
+    Ok(bytes)
+}
@@ -0,0 +1,102 @@
+/// This file has the strategy course. Each lesson is a small hand-picked board with a unique
+/// solution that hinges on one technique, and we check the player's pipe for the lesson's color
+/// against the path that technique forces.
+use crate::flow_grid::{CellColor, Direction, FlowGrid};
+
+/// the (row, col) of both sources of a color
+pub type SourcePair = ((usize, usize), (usize, usize));
+
+pub struct Lesson {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub width: usize,
+    pub height: usize,
+    /// indexed by color id
+    pub sources: &'static [SourcePair],
+    pub focus_color: usize,
+    /// every cell the focus color passes through, from one source to the other
+    pub focus_path: &'static [(usize, usize)],
+}
+
+impl Lesson {
+    pub fn build_grid(&self) -> FlowGrid {
+        let mut grid = FlowGrid::with_size(self.width, self.height);
+        for (color_id, &((row1, col1), (row2, col2))) in self.sources.iter().enumerate() {
+            grid.try_set_missing_source(row1, col1, color_id);
+            grid.try_set_missing_source(row2, col2, color_id);
+        }
+        grid
+    }
+
+    pub fn is_technique_performed(&self, grid: &FlowGrid) -> bool {
+        self.focus_path.windows(2).all(|step| {
+            let (row_from, col_from) = step[0];
+            let (row_to, col_to) = step[1];
+            let direction = if let Some(direction) =
+                Direction::try_from_adjacent(row_from, col_from, row_to, col_to)
+            {
+                direction
+            } else {
+                return false;
+            };
+            grid.get(row_from, col_from).is_some_and(|cell| {
+                cell.color == CellColor::Colored(self.focus_color)
+                    && cell.is_direction_connected(direction)
+            })
+        })
+    }
+}
+
+pub const COURSE: [Lesson; 3] = [
+    Lesson {
+        name: "Hugging the wall",
+        description: "When both sources of a color sit on the same edge, try running the pipe \
+            along the wall first. Nothing else can use those edge cells without getting trapped, \
+            so the wall route is almost always right. Connect Blue along the top of the board.",
+        width: 5,
+        height: 5,
+        sources: &[
+            ((2, 4), (4, 4)),
+            ((3, 1), (3, 2)),
+            ((1, 4), (1, 0)),
+            ((4, 2), (3, 3)),
+        ],
+        focus_color: 2,
+        focus_path: &[(1, 4), (0, 4), (0, 3), (0, 2), (0, 1), (0, 0), (1, 0)],
+    },
+    Lesson {
+        name: "Region parity",
+        description: "Color the board like a checkerboard. A pipe between two neighboring \
+            sources always covers an even number of cells, so if a pocket of the board can only \
+            be reached by one color, that color has to detour through all of it. Red is the only \
+            color that can reach the pocket beside it.",
+        width: 5,
+        height: 5,
+        sources: &[
+            ((1, 4), (0, 4)),
+            ((0, 2), (2, 1)),
+            ((4, 0), (4, 2)),
+            ((1, 1), (3, 4)),
+            ((3, 0), (3, 1)),
+        ],
+        focus_color: 0,
+        focus_path: &[(1, 4), (2, 4), (2, 3), (1, 3), (0, 3), (0, 4)],
+    },
+    Lesson {
+        name: "Corridor forcing",
+        description: "A cell walled in by other colors' sources is a corridor: whichever pipe \
+            enters it can only leave one way. Follow the corridor and the route draws itself. \
+            Orange has to squeeze between the sources around it.",
+        width: 5,
+        height: 5,
+        sources: &[
+            ((1, 0), (0, 0)),
+            ((4, 0), (3, 4)),
+            ((2, 2), (3, 1)),
+            ((0, 1), (3, 3)),
+            ((3, 0), (1, 1)),
+        ],
+        focus_color: 4,
+        focus_path: &[(3, 0), (2, 0), (2, 1), (1, 1)],
+    },
+];
@@ -0,0 +1,396 @@
+/// This file has level packs: a plain text file of puzzles, each a name line followed by its
+/// board, one row per line in the same letters-and-dots form as the corpus, with a blank line
+/// between puzzles. Run `cargo run --release -- --pack practice.txt 50 7x7 9x9` to generate a
+/// pack of 50 puzzles of each size offline. Add `--style spiral` for a pack in one style, and
+/// `--colors 8`, or `--colors auto` to go by the board size, to aim for a number of colors.
+///
+/// A few packs are bundled into the app from assets/packs, made with the --pack command, and
+/// which of their levels are done is kept in a LevelProgress.
+///
+/// It also reads the community level packs made for Flow Free, which put one level on each line
+/// as `WxH;id;path;path;...`. Each path is the cell indexes of one pipe in the solution, row by
+/// row and separated by commas, and its two ends are the sources.
+use crate::flow_cli::{self, take_flag};
+use crate::flow_generator;
+use crate::flow_grid::FlowGrid;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// how many seeds to try per puzzle asked for before giving up on a size
+const SEEDS_PER_PUZZLE: u64 = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackPuzzle {
+    /// generated puzzles are named by their puzzle code, so they can be shared on their own
+    pub name: String,
+    pub board: Vec<String>,
+}
+
+impl PackPuzzle {
+    pub fn build_grid(&self) -> Option<FlowGrid> {
+        let rows: Vec<&str> = self.board.iter().map(String::as_str).collect();
+        FlowGrid::from_board(&rows)
+    }
+}
+
+/// a pack that ships inside the app
+pub struct BundledPack {
+    pub name: &'static str,
+    text: &'static str,
+}
+
+impl BundledPack {
+    pub fn puzzles(&self) -> Vec<PackPuzzle> {
+        pack_from_text(self.text).expect("bundled packs are checked by the tests")
+    }
+}
+
+pub const BUNDLED_PACKS: [BundledPack; 3] = [
+    BundledPack {
+        name: "Starter",
+        text: include_str!("../assets/packs/starter.txt"),
+    },
+    BundledPack {
+        name: "Classic",
+        text: include_str!("../assets/packs/classic.txt"),
+    },
+    BundledPack {
+        name: "Challenge",
+        text: include_str!("../assets/packs/challenge.txt"),
+    },
+];
+
+/// which levels of the bundled packs the player has finished, by pack and level name, and
+/// which of those were perfect: one stroke per color
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelProgress {
+    completed: BTreeSet<(String, String)>,
+    perfect: BTreeSet<(String, String)>,
+}
+
+/// marks a perfect level in to_text, after its name
+const PERFECT_MARK: &str = "\t*";
+
+impl LevelProgress {
+    /// A perfect finish stays perfect, even if the level is finished again with more strokes.
+    pub fn complete(&mut self, pack: &str, level: &str, is_perfect: bool) {
+        let key = (pack.to_string(), level.to_string());
+        if is_perfect {
+            self.perfect.insert(key.clone());
+        }
+        self.completed.insert(key);
+    }
+
+    pub fn is_completed(&self, pack: &str, level: &str) -> bool {
+        self.completed
+            .contains(&(pack.to_string(), level.to_string()))
+    }
+
+    pub fn is_perfect(&self, pack: &str, level: &str) -> bool {
+        self.perfect
+            .contains(&(pack.to_string(), level.to_string()))
+    }
+
+    /// One finished level per line, as the pack name and level name separated by a tab. Perfect
+    /// levels have another tab and a star after the name.
+    pub fn to_text(&self) -> String {
+        self.completed
+            .iter()
+            .map(|key| {
+                let mark = if self.perfect.contains(key) {
+                    PERFECT_MARK
+                } else {
+                    ""
+                };
+                format!("{}\t{}{mark}\n", key.0, key.1)
+            })
+            .collect()
+    }
+
+    /// reads to_text back, or None if it's been mangled
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut progress = LevelProgress::default();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let (line, is_perfect) = match line.strip_suffix(PERFECT_MARK) {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            let (pack, level) = line.split_once('\t')?;
+            progress.complete(pack, level, is_perfect);
+        }
+        Some(progress)
+    }
+}
+
+/// Generates count puzzles of each size, trying seeds upwards from first_seed. Seeds that don't
+/// give a puzzle are skipped, so a size that's too hard to generate can come up short.
+pub fn generate_pack(
+    sizes: &[(usize, usize)],
+    count: usize,
+    first_seed: u64,
+    options: &flow_generator::GenerateOptions,
+) -> Vec<PackPuzzle> {
+    let mut pack = Vec::new();
+    for &(width, height) in sizes {
+        let mut puzzles = Vec::new();
+        let mut next_seed = first_seed;
+        while puzzles.len() < count && next_seed < first_seed + count as u64 * SEEDS_PER_PUZZLE {
+            let seeds: Vec<u64> = (next_seed..next_seed + (count - puzzles.len()) as u64).collect();
+            next_seed += seeds.len() as u64;
+            let batch: Vec<Option<PackPuzzle>> = seeds
+                .into_par_iter()
+                .map(|seed| {
+                    Some(PackPuzzle {
+                        name: flow_generator::puzzle_code(width, height, seed, options),
+                        board: flow_generator::generate_with(width, height, seed, options)?
+                            .to_board()?,
+                    })
+                })
+                .collect();
+            puzzles.extend(batch.into_iter().flatten());
+        }
+        pack.extend(puzzles);
+    }
+    pack
+}
+
+/// Reads a Flow Free level pack, or None if a line isn't a valid level. Blank lines are skipped.
+pub fn parse_flow_free(text: &str) -> Option<Vec<PackPuzzle>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.split(';');
+            let (width, height) = fields.next()?.trim().split_once('x')?;
+            let (width, height): (usize, usize) =
+                (width.trim().parse().ok()?, height.trim().parse().ok()?);
+            let id = fields.next()?.trim();
+            let mut grid = FlowGrid::with_size(width, height);
+            for (color_id, path) in fields.enumerate() {
+                let cells = path
+                    .split(',')
+                    .map(|index| index.trim().parse().ok())
+                    .collect::<Option<Vec<usize>>>()?;
+                let (&first, &last) = (cells.first()?, cells.last()?);
+                for index in [first, last] {
+                    if index >= width * height
+                        || !grid.try_set_missing_source(index / width, index % width, color_id)
+                    {
+                        return None;
+                    }
+                }
+            }
+            Some(PackPuzzle {
+                name: format!("Level {id}"),
+                board: grid.to_board()?,
+            })
+        })
+        .collect()
+}
+
+pub fn pack_to_text(pack: &[PackPuzzle]) -> String {
+    pack.iter()
+        .map(|puzzle| format!("{}\n{}\n", puzzle.name, puzzle.board.join("\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// reads pack_to_text back, or None if a puzzle has no board
+pub fn pack_from_text(text: &str) -> Option<Vec<PackPuzzle>> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    lines
+        .split(|line| line.is_empty())
+        .filter(|block| !block.is_empty())
+        .map(|block| {
+            let (name, board) = block.split_first()?;
+            (!board.is_empty()).then(|| PackPuzzle {
+                name: name.to_string(),
+                board: board.iter().map(|row| row.to_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+/// What `--pack --format json` prints.
+#[derive(Serialize)]
+pub struct PackReport {
+    pub schema: u32,
+    /// where the pack was written
+    pub file: String,
+    pub puzzles: Vec<PackReportPuzzle>,
+}
+
+#[derive(Serialize)]
+pub struct PackReportPuzzle {
+    /// the puzzle code it was generated from
+    pub name: String,
+    pub puzzle_hash: String,
+    pub width: usize,
+    pub height: usize,
+    pub colors: usize,
+}
+
+impl PackReportPuzzle {
+    fn of(puzzle: &PackPuzzle) -> Option<Self> {
+        let grid = puzzle.build_grid()?;
+        Some(PackReportPuzzle {
+            name: puzzle.name.clone(),
+            puzzle_hash: flow_cli::puzzle_hash(&grid),
+            width: grid.width,
+            height: grid.height,
+            colors: grid.num_colors(),
+        })
+    }
+}
+
+/// The --pack command: takes the file, how many of each size, and the sizes, like
+/// `practice.txt 50 7x7 9x9`, and writes the pack. `--style <style>` and `--colors <count>`
+/// anywhere pick the style and color count. Prints what it wrote in the format, and returns the
+/// exit code.
+pub fn run(args: &[String], format: flow_cli::Format) -> i32 {
+    let usage = "Usage: --pack <file> <count> <width>x<height>... \
+        [--style classic|snake|spiral|maze] [--colors <count>|auto] [--format text|json]";
+    let mut args = args.to_vec();
+    let mut options = flow_generator::GenerateOptions::default();
+    if let Some(name) = take_flag(&mut args, "--style") {
+        let Some(style) = flow_generator::PuzzleStyle::from_name(&name) else {
+            eprintln!("{usage}");
+            return flow_cli::EXIT_USAGE;
+        };
+        options.style = style;
+    }
+    let colors = take_flag(&mut args, "--colors");
+    let is_auto_colors = colors.as_deref() == Some("auto");
+    if let Some(colors) = colors.filter(|_| !is_auto_colors) {
+        let Ok(colors) = colors.parse() else {
+            eprintln!("{usage}");
+            return flow_cli::EXIT_USAGE;
+        };
+        options.colors = Some(colors);
+    }
+    let (Some(path), Some(count)) = (
+        args.first(),
+        args.get(1).and_then(|count| count.parse().ok()),
+    ) else {
+        eprintln!("{usage}");
+        return flow_cli::EXIT_USAGE;
+    };
+    let Some(sizes) = args[2..]
+        .iter()
+        .map(|size| {
+            let (width, height) = size.split_once('x')?;
+            Some((width.parse().ok()?, height.parse().ok()?))
+        })
+        .collect::<Option<Vec<(usize, usize)>>>()
+        .filter(|sizes| !sizes.is_empty())
+    else {
+        eprintln!("{usage}");
+        return flow_cli::EXIT_USAGE;
+    };
+
+    let seed = flow_generator::new_seed();
+    let pack = if is_auto_colors {
+        let table = flow_generator::ColorTable::default();
+        sizes
+            .iter()
+            .flat_map(|&(width, height)| {
+                let options = flow_generator::GenerateOptions {
+                    colors: table.colors_for(width, height),
+                    ..options
+                };
+                generate_pack(&[(width, height)], count, seed, &options)
+            })
+            .collect()
+    } else {
+        generate_pack(&sizes, count, seed, &options)
+    };
+    match std::fs::write(path, pack_to_text(&pack)) {
+        Ok(()) => {
+            eprintln!("Wrote {} puzzles to {path}", pack.len());
+            if format == flow_cli::Format::Json {
+                flow_cli::print_json(&PackReport {
+                    schema: flow_cli::SCHEMA_VERSION,
+                    file: path.clone(),
+                    puzzles: pack.iter().filter_map(PackReportPuzzle::of).collect(),
+                });
+            }
+            flow_cli::EXIT_SOLVED
+        }
+        Err(error) => {
+            eprintln!("Couldn't write {path}: {error}");
+            flow_cli::EXIT_USAGE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_count::count_solutions_exact;
+
+    #[test]
+    fn packs_round_trip_through_text() {
+        let pack = generate_pack(
+            &[(5, 5), (6, 4)],
+            2,
+            7,
+            &flow_generator::GenerateOptions::default(),
+        );
+        assert_eq!(pack.len(), 4);
+        assert_eq!(pack_from_text(&pack_to_text(&pack)), Some(pack.clone()));
+        for puzzle in &pack {
+            let grid = puzzle.build_grid().expect("generated boards are valid");
+            assert_eq!(count_solutions_exact(&grid), 1, "{}", puzzle.name);
+        }
+        assert_eq!(pack_from_text("5x5 #1\n\n"), None);
+    }
+
+    #[test]
+    fn bundled_packs_are_valid() {
+        for pack in &BUNDLED_PACKS {
+            let puzzles = pack.puzzles();
+            assert!(!puzzles.is_empty(), "{}", pack.name);
+            for puzzle in &puzzles {
+                let grid = puzzle.build_grid().expect("bundled boards are valid");
+                assert_eq!(
+                    count_solutions_exact(&grid),
+                    1,
+                    "{} {}",
+                    pack.name,
+                    puzzle.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn level_progress_round_trips_through_text() {
+        let mut progress = LevelProgress::default();
+        progress.complete("Starter", "5x5 #1", false);
+        progress.complete("Classic", "7x7 #2", true);
+        progress.complete("Classic", "7x7 #2", false);
+        assert!(progress.is_completed("Starter", "5x5 #1"));
+        assert!(!progress.is_completed("Classic", "5x5 #1"));
+        assert!(!progress.is_perfect("Starter", "5x5 #1"));
+        assert!(progress.is_perfect("Classic", "7x7 #2"));
+        assert_eq!(
+            LevelProgress::from_text(&progress.to_text()),
+            Some(progress)
+        );
+        assert_eq!(LevelProgress::from_text("no tab here"), None);
+    }
+
+    #[test]
+    fn flow_free_levels_keep_the_path_ends() {
+        let text = "3x2;7;0,1,2;3,4,5\n\n2x2;8;0,2;1,3\n";
+        let pack = parse_flow_free(text).expect("valid pack");
+        assert_eq!(pack[0].name, "Level 7");
+        assert_eq!(pack[0].board, ["A.A", "B.B"]);
+        assert_eq!(pack[1].board, ["AB", "AB"]);
+        assert!(pack[0].build_grid().is_some());
+        // a path off the board, and a path that ends where it starts
+        assert_eq!(parse_flow_free("2x2;1;0,4"), None);
+        assert_eq!(parse_flow_free("2x2;1;0"), None);
+    }
+}
@@ -0,0 +1,107 @@
+/// This file has the colors pipes and sources are drawn in. Color ids index into the palette, so
+/// the first color placed is drawn in its first color, and ids past the end of it are drawn as
+/// uncolored pipes. The palette can be edited, and is saved as one `name<TAB>r g b` line per
+/// color.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaletteColor {
+    pub name: String,
+    pub rgb: [u8; 3],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: Vec<PaletteColor>,
+}
+
+/// the colors the app starts with, in order of color id
+const DEFAULT_COLORS: [(&str, [u8; 3]); 9] = [
+    ("Red", [255, 0, 0]),
+    ("Green", [0, 200, 0]),
+    ("Blue", [0, 0, 255]),
+    ("Yellow", [255, 255, 0]),
+    ("Orange", [255, 165, 0]),
+    ("Purple", [128, 0, 128]),
+    ("Cyan", [0, 255, 255]),
+    ("Pink", [255, 192, 203]),
+    ("Dark Red", [128, 0, 0]),
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: DEFAULT_COLORS
+                .iter()
+                .map(|&(name, rgb)| PaletteColor {
+                    name: name.to_string(),
+                    rgb,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Palette {
+    /// the color's name, or its number when the palette runs out
+    pub fn name(&self, color_id: usize) -> String {
+        self.colors
+            .get(color_id)
+            .map_or(format!("Color {}", color_id + 1), |color| {
+                color.name.clone()
+            })
+    }
+
+    pub fn to_text(&self) -> String {
+        self.colors
+            .iter()
+            .map(|color| {
+                let [r, g, b] = color.rgb;
+                format!("{}\t{r} {g} {b}\n", color.name)
+            })
+            .collect()
+    }
+
+    /// reads to_text back, or None if it's been mangled
+    pub fn from_text(text: &str) -> Option<Self> {
+        let colors = text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (name, rgb) = line.split_once('\t')?;
+                let channels: Vec<u8> = rgb
+                    .split(' ')
+                    .map(|channel| channel.parse().ok())
+                    .collect::<Option<_>>()?;
+                Some(PaletteColor {
+                    name: name.to_string(),
+                    rgb: channels.try_into().ok()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Palette { colors })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_round_trip_through_text() {
+        let mut palette = Palette::default();
+        palette.colors.swap(0, 2);
+        palette.colors.push(PaletteColor {
+            name: "Sea Green".to_string(),
+            rgb: [46, 139, 87],
+        });
+        assert_eq!(
+            Palette::from_text(&palette.to_text()),
+            Some(palette.clone())
+        );
+        assert_eq!(palette.name(0), "Blue");
+        assert_eq!(palette.name(9), "Sea Green");
+        assert_eq!(palette.name(10), "Color 11");
+        assert_eq!(Palette::from_text("Red\t255 0"), None);
+        assert_eq!(Palette::from_text("Red\t255 0 256"), None);
+    }
+}
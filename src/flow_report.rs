@@ -0,0 +1,55 @@
+/// This file builds bug reports: one JSON file with the board, every edit made to it, the app
+/// version and the settings, so an issue can come with everything needed to reproduce it. The
+/// same privacy summary is shown before saving and written into the file.
+use flow::flow_grid::FlowGrid;
+use flow::flow_solver::SolverConfig;
+use serde::Serialize;
+
+/// what a report holds, and what it leaves out
+pub const PRIVACY_SUMMARY: &str = "A bug report holds the board, every edit made to it since \
+    it was started, the app version, your operating system's name (like \"windows\") and your \
+    solver and board settings. It doesn't hold file names, your name or anything else from your \
+    computer. Nothing is sent anywhere: the report is saved where you pick, for you to attach to \
+    an issue.";
+
+/// the settings that can change how the board behaves
+#[derive(Serialize)]
+pub struct ReportSettings {
+    pub solver_timeout_seconds: f64,
+    pub node_limit: Option<u64>,
+    pub parallel_solve: bool,
+    pub solver_config: SolverConfig,
+    pub show_dead_ends: bool,
+    pub auto_complete: bool,
+    pub hide_sources: bool,
+    pub can_edit_sources: bool,
+    pub lesson: Option<usize>,
+    pub daily_day: Option<u64>,
+    pub puzzle_code: String,
+}
+
+#[derive(Serialize)]
+pub struct BugReport<'a> {
+    app_version: &'static str,
+    os: &'static str,
+    privacy: &'static str,
+    settings: ReportSettings,
+    /// saved with its history, which is the list of edits
+    grid: &'a FlowGrid,
+}
+
+impl<'a> BugReport<'a> {
+    pub fn new(grid: &'a FlowGrid, settings: ReportSettings) -> Self {
+        BugReport {
+            app_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            privacy: PRIVACY_SUMMARY,
+            settings,
+            grid,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
@@ -0,0 +1,230 @@
+/// This file reads a puzzle out of a screenshot, of Flow Free or of a board exported from here,
+/// so it doesn't have to be copied in by hand. It only handles the easy case: the board's size
+/// has to be known, and the screenshot cropped so the board sits on a plain background. The
+/// board is taken to fill everything that isn't the color of the top left pixel, and is split
+/// evenly into cells.
+///
+/// A cell holds a source if the middle of the cell and four points around it, a fifth of a cell
+/// out diagonally, are all the same color. Source dots are wide enough to cover all of them,
+/// while pipes are too thin to reach the diagonals, so only the sources are read and any pipes
+/// are left out.
+use crate::flow_grid::FlowGrid;
+
+/// how far apart two colors' channels can be and still count as the same color
+const COLOR_TOLERANCE: u8 = 48;
+
+/// where a source dot is checked for around the middle of a cell, as fractions of a cell
+const DOT_SAMPLES: [(f32, f32); 4] = [(-0.2, -0.2), (0.2, -0.2), (-0.2, 0.2), (0.2, 0.2)];
+
+/// a source color found on the board, and the (row, col) of each source dot in it
+type SeenColor = ([u8; 4], Vec<(usize, usize)>);
+
+/// a decoded image, with one RGBA pixel per entry, row by row
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+fn is_similar(first: [u8; 4], second: [u8; 4]) -> bool {
+    first
+        .into_iter()
+        .zip(second)
+        .all(|(channel1, channel2)| channel1.abs_diff(channel2) <= COLOR_TOLERANCE)
+}
+
+impl Screenshot {
+    /// None if there aren't width times height pixels
+    pub fn new(width: usize, height: usize, pixels: Vec<[u8; 4]>) -> Option<Self> {
+        (pixels.len() == width * height && !pixels.is_empty()).then_some(Screenshot {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// decodes a PNG, or None if it isn't one
+    pub fn from_png(bytes: &[u8]) -> Option<Self> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().ok()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).ok()?;
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            // the transformations expand palettes, so this never comes up
+            png::ColorType::Indexed => return None,
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        let pixels = buffer
+            .chunks(info.line_size)
+            .take(height)
+            .flat_map(|line| line[..width * channels].chunks(channels))
+            .map(|pixel| match *pixel {
+                [gray] => [gray, gray, gray, u8::MAX],
+                [gray, alpha] => [gray, gray, gray, alpha],
+                [red, green, blue] => [red, green, blue, u8::MAX],
+                [red, green, blue, alpha] => [red, green, blue, alpha],
+                _ => unreachable!("pixels are one to four channels"),
+            })
+            .collect();
+        Screenshot::new(width, height, pixels)
+    }
+
+    /// the pixel under a point, clamped to the image
+    fn pixel(&self, x: f32, y: f32) -> [u8; 4] {
+        let x = (x.max(0.0) as usize).min(self.width - 1);
+        let y = (y.max(0.0) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+
+    /// The smallest box around everything that isn't the background, as the left, top, right
+    /// and bottom edges, the last two exclusive. None if it's all background.
+    fn board_bounds(&self, background: [u8; 4]) -> Option<(usize, usize, usize, usize)> {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if is_similar(self.pixels[y * self.width + x], background) {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    Some((left, top, right, bottom)) => {
+                        (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1))
+                    }
+                    None => (x, y, x + 1, y + 1),
+                });
+            }
+        }
+        bounds
+    }
+}
+
+/// Reads the sources of a board of the given size out of a screenshot. Colors are numbered in
+/// the order they're first seen, row by row. None if there are no sources, or a color doesn't
+/// have exactly two.
+pub fn grid_from_screenshot(
+    screenshot: &Screenshot,
+    width: usize,
+    height: usize,
+) -> Option<FlowGrid> {
+    let background = screenshot.pixel(0.0, 0.0);
+    let (left, top, right, bottom) = screenshot.board_bounds(background)?;
+    let cell_width = (right - left) as f32 / width as f32;
+    let cell_height = (bottom - top) as f32 / height as f32;
+
+    let mut colors: Vec<SeenColor> = Vec::new();
+    for row in 0..height {
+        for col in 0..width {
+            let center_x = left as f32 + (col as f32 + 0.5) * cell_width;
+            let center_y = top as f32 + (row as f32 + 0.5) * cell_height;
+            let color = screenshot.pixel(center_x, center_y);
+            // exported boards leave empty cells transparent
+            if is_similar(color, background) || color[3] < u8::MAX / 2 {
+                continue;
+            }
+            let is_source = DOT_SAMPLES.iter().all(|&(x_offset, y_offset)| {
+                let sample = screenshot.pixel(
+                    center_x + x_offset * cell_width,
+                    center_y + y_offset * cell_height,
+                );
+                is_similar(sample, color)
+            });
+            if !is_source {
+                continue;
+            }
+            match colors
+                .iter_mut()
+                .find(|(known_color, _)| is_similar(*known_color, color))
+            {
+                Some((_, sources)) => sources.push((row, col)),
+                None => colors.push((color, vec![(row, col)])),
+            }
+        }
+    }
+
+    if colors.is_empty() {
+        return None;
+    }
+    let mut grid = FlowGrid::with_size(width, height);
+    for (color_id, (_, sources)) in colors.into_iter().enumerate() {
+        if sources.len() != 2 {
+            return None;
+        }
+        for (row, col) in sources {
+            if !grid.try_set_missing_source(row, col, color_id) {
+                return None;
+            }
+        }
+    }
+    Some(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL_SIZE: usize = 30;
+    /// how much background there is around the board
+    const MARGIN: usize = 7;
+    const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+    const GRID_LINE: [u8; 4] = [120, 110, 40, 255];
+
+    fn letter_color(letter: char) -> [u8; 4] {
+        match letter {
+            'R' => [230, 20, 20, 255],
+            'G' => [0, 140, 0, 255],
+            'B' => [20, 40, 250, 255],
+            _ => [230, 220, 0, 255],
+        }
+    }
+
+    /// Draws a board the way Flow Free does: a frame around it, a dot for each source and, for
+    /// each lowercase letter, a piece of pipe running across the cell.
+    fn draw(board: &[&str]) -> Screenshot {
+        let (width, height) = (board[0].len(), board.len());
+        let image_width = width * CELL_SIZE + 2 * MARGIN;
+        let image_height = height * CELL_SIZE + 2 * MARGIN;
+        let mut pixels = vec![BACKGROUND; image_width * image_height];
+        let (board_width, board_height) = (width * CELL_SIZE, height * CELL_SIZE);
+        for y in 0..board_height {
+            for x in 0..board_width {
+                let pixel = &mut pixels[(y + MARGIN) * image_width + x + MARGIN];
+                if x == 0 || y == 0 || x + 1 == board_width || y + 1 == board_height {
+                    *pixel = GRID_LINE;
+                    continue;
+                }
+                let letter = board[y / CELL_SIZE].as_bytes()[x / CELL_SIZE] as char;
+                let offset_x = (x % CELL_SIZE) as f32 + 0.5 - CELL_SIZE as f32 / 2.0;
+                let offset_y = (y % CELL_SIZE) as f32 + 0.5 - CELL_SIZE as f32 / 2.0;
+                let is_drawn = if letter.is_ascii_uppercase() {
+                    offset_x.hypot(offset_y) < CELL_SIZE as f32 / 3.0
+                } else {
+                    letter != '.' && offset_y.abs() < CELL_SIZE as f32 / 7.0
+                };
+                if is_drawn {
+                    *pixel = letter_color(letter.to_ascii_uppercase());
+                }
+            }
+        }
+        Screenshot::new(image_width, image_height, pixels).expect("the right number of pixels")
+    }
+
+    #[test]
+    fn sources_are_read_and_pipes_left_out() {
+        let screenshot = draw(&["R..R", "GrrG", "B...", "...B"]);
+        let grid = grid_from_screenshot(&screenshot, 4, 4).expect("a readable board");
+        assert_eq!(
+            grid.to_board().expect("few colors"),
+            ["A..A", "B..B", "C...", "...C"]
+        );
+    }
+
+    #[test]
+    fn unpaired_sources_are_rejected() {
+        assert!(grid_from_screenshot(&draw(&["R.R", "..R"]), 3, 2).is_none());
+        assert!(grid_from_screenshot(&draw(&["r..", "..."]), 3, 2).is_none());
+    }
+}
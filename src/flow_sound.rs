@@ -0,0 +1,110 @@
+/// This file plays short sounds for what happens on the board: laying pipe, breaking it,
+/// finishing a flow and solving the puzzle. The tones are made up as they're played, so there are
+/// no sound files to ship, and the output device isn't opened until the first sound. Without a
+/// device to play on, the app just stays quiet.
+use crate::flow_grid::{FlowGrid, GridEvent};
+use rodio::{OutputStream, OutputStreamHandle, Source, source::SineWave};
+use std::time::Duration;
+
+/// how loud the tones are, from 0 to 1
+const VOLUME: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    LayPipe,
+    BreakPipe,
+    CompleteFlow,
+    Solve,
+}
+
+impl Sound {
+    /// each note's pitch in hertz and length in milliseconds, played one after the other
+    fn notes(self) -> &'static [(f32, u64)] {
+        match self {
+            Sound::LayPipe => &[(660.0, 30)],
+            Sound::BreakPipe => &[(220.0, 40)],
+            Sound::CompleteFlow => &[(523.3, 60), (784.0, 90)],
+            Sound::Solve => &[(523.3, 90), (659.3, 90), (784.0, 90), (1046.5, 220)],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SoundPlayer {
+    pub is_muted: bool,
+    /// None until the first sound is played, and then the stream and a handle to play on it, if
+    /// a device could be opened
+    output: Option<Option<(OutputStream, OutputStreamHandle)>>,
+    /// How many of the board's events have been heard, and the last of them, which tells when a
+    /// different board has been put in its place. None before the first frame.
+    seen_history: Option<(usize, Option<GridEvent>)>,
+    /// how many flows were connected as of the last frame
+    flows_connected: usize,
+}
+
+impl SoundPlayer {
+    pub fn play(&mut self, sound: Sound) {
+        if self.is_muted {
+            return;
+        }
+        let output = self
+            .output
+            .get_or_insert_with(|| OutputStream::try_default().ok());
+        let Some((_, handle)) = output else {
+            return;
+        };
+        let mut start = Duration::ZERO;
+        for &(frequency, millis) in sound.notes() {
+            let length = Duration::from_millis(millis);
+            let note = SineWave::new(frequency)
+                .take_duration(length)
+                .amplify(VOLUME)
+                .delay(start);
+            // a note the device won't take is just skipped
+            let _ = handle.play_raw(note);
+            start += length;
+        }
+    }
+
+    /// Plays one sound for what's changed on the board since the last frame, picking finishing a
+    /// flow over laying pipe over breaking it. A board put in place of the last one, or one that
+    /// was stepped back to, is taken as it is.
+    pub fn follow(&mut self, grid: &FlowGrid) {
+        let (flows_connected, _) = grid.flows_connected();
+        // the last event heard can only be checked until the history is folded past it
+        if let Some((seen_len, last_seen_event)) = self.seen_history
+            && let Some(new_events) = grid.events_since(seen_len)
+            && seen_len
+                .checked_sub(1)
+                .and_then(|index| grid.events_since(index))
+                .is_none_or(|events| events.first().copied() == last_seen_event)
+        {
+            let sound = if flows_connected > self.flows_connected {
+                Some(Sound::CompleteFlow)
+            } else if new_events
+                .iter()
+                .any(|event| matches!(event, GridEvent::Connect { .. }))
+            {
+                Some(Sound::LayPipe)
+            } else if new_events
+                .iter()
+                .any(|event| matches!(event, GridEvent::Disconnect { .. }))
+            {
+                Some(Sound::BreakPipe)
+            } else {
+                None
+            };
+            if let Some(sound) = sound {
+                self.play(sound);
+            }
+        }
+        self.catch_up(grid);
+    }
+
+    /// takes the board as it is now without playing anything, for changes that aren't the
+    /// player's
+    pub fn catch_up(&mut self, grid: &FlowGrid) {
+        self.seen_history = Some((grid.events_applied(), grid.history().last().copied()));
+        self.flows_connected = grid.flows_connected().0;
+    }
+}
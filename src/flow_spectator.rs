@@ -0,0 +1,227 @@
+/// This file lets viewers follow along. It draws the board as an SVG and serves it over a tiny
+/// local HTTP server, so a stream can add http://127.0.0.1:<port> as a browser source and watch
+/// the board update live.
+use crate::{
+    flow_canvas::{BoardMetrics, BoardStyle},
+    flow_grid::{Direction, FlowGrid},
+};
+use eframe::egui::{Color32, Pos2};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::Duration;
+
+pub const SPECTATOR_PORT: u16 = 8787;
+
+/// the page a browser source opens. It just keeps reloading the board.
+const SPECTATOR_PAGE: &str = "<!DOCTYPE html>\
+<html><body style=\"margin:0;background:transparent\">\
+<img id=\"board\" src=\"/board.svg\">\
+<script>setInterval(() => { document.getElementById('board').src = '/board.svg?' + Date.now(); }, 250);</script>\
+</body></html>";
+
+/// Draws the board the same way the canvas does, with a transparent background.
+pub fn board_to_svg(grid: &FlowGrid, style: &BoardStyle) -> String {
+    let metrics = BoardMetrics::default().with_hex(grid.is_hex());
+    let cell_pitch = metrics.cell_pitch();
+    let border_width = metrics.grid_border_width;
+    let size = metrics.board_size(grid.width, grid.height);
+    let (width, height) = (size.x, size.y);
+    let grid_line_color = style.grid_line_color.unwrap_or(Color32::GRAY);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+        viewBox=\"0 0 {width} {height}\">\n"
+    );
+    // hex grids and shaped boards outline each cell instead
+    let off_board = grid.off_board_cells();
+    let is_shaped = off_board.contains(&true);
+    if !grid.is_hex() && !is_shaped {
+        for row in 0..=grid.height {
+            svg.push_str(&format!(
+                "<rect x=\"0\" y=\"{}\" width=\"{width}\" height=\"{border_width}\" fill=\"{}\"/>\n",
+                row as f32 * cell_pitch,
+                to_hex(grid_line_color),
+            ));
+        }
+        for col in 0..=grid.width {
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"0\" width=\"{border_width}\" height=\"{height}\" fill=\"{}\"/>\n",
+                col as f32 * cell_pitch,
+                to_hex(grid_line_color),
+            ));
+        }
+    }
+
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            if off_board[row * grid.width + col] {
+                continue;
+            }
+            let cell = grid.get(row, col).expect("looping in bounds");
+            let color = style.cell_color(cell.color);
+            let center = metrics.cell_center(Pos2::ZERO, row, col);
+            let (center_x, center_y) = (center.x, center.y);
+
+            if grid.is_hex() || is_shaped {
+                svg.push_str(&format!(
+                    "<polygon points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{border_width}\"/>\n",
+                    to_points(&metrics.cell_outline(center)),
+                    to_hex(grid_line_color),
+                ));
+            }
+            if cell.is_blocked {
+                svg.push_str(&format!(
+                    "<polygon points=\"{}\" fill=\"{}\"/>\n",
+                    to_points(&metrics.cell_corners(center)),
+                    to_hex(style.blocked_color),
+                ));
+            }
+            if cell.is_source {
+                svg.push_str(&format!(
+                    "<circle cx=\"{center_x}\" cy=\"{center_y}\" r=\"{}\" fill=\"{}\"/>\n",
+                    metrics.source_radius,
+                    to_hex(color),
+                ));
+            }
+            // each half of a pipe runs from the middle of the cell to the shared border
+            for direction in Direction::ALL {
+                if !cell.is_direction_connected(direction) {
+                    continue;
+                }
+                // bridges color their two lanes separately
+                let Pos2 { x: end_x, y: end_y } = center + metrics.neighbor_offset(direction) / 2.0;
+                svg.push_str(&format!(
+                    "<line x1=\"{center_x}\" y1=\"{center_y}\" x2=\"{end_x}\" y2=\"{end_y}\" \
+                    stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"/>\n",
+                    to_hex(style.cell_color(cell.lane_color(direction))),
+                    metrics.pipe_width,
+                ));
+            }
+            if cell.is_warp() {
+                svg.push_str(&format!(
+                    "<circle cx=\"{center_x}\" cy=\"{center_y}\" r=\"{}\" fill=\"{}\" \
+                    stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                    metrics.source_radius,
+                    if cell.is_connected_warp {
+                        to_hex(color)
+                    } else {
+                        "none".to_string()
+                    },
+                    to_hex(style.warp_color),
+                    border_width * 2.0,
+                ));
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// the corners of a polygon, as an SVG points list
+fn to_points(corners: &[Pos2]) -> String {
+    corners
+        .iter()
+        .map(|corner| format!("{},{}", corner.x, corner.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn to_hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+/// Serves the latest board until it's dropped.
+pub struct SpectatorServer {
+    pub address: SocketAddr,
+    board_svg: Arc<Mutex<String>>,
+    is_stopped: Arc<AtomicBool>,
+}
+
+impl SpectatorServer {
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let board_svg = Arc::new(Mutex::new(String::new()));
+        let is_stopped = Arc::new(AtomicBool::new(false));
+
+        let thread_board_svg = Arc::clone(&board_svg);
+        let thread_is_stopped = Arc::clone(&is_stopped);
+        std::thread::spawn(move || {
+            while !thread_is_stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // a viewer that hangs up early isn't our problem
+                        let _ = respond(stream, &thread_board_svg);
+                    }
+                    Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(SpectatorServer {
+            address,
+            board_svg,
+            is_stopped,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn update(&self, board_svg: String) {
+        *self
+            .board_svg
+            .lock()
+            .expect("the server thread never panics") = board_svg;
+    }
+}
+
+impl Drop for SpectatorServer {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+fn respond(stream: TcpStream, board_svg: &Mutex<String>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // skip the headers, we only care about the path
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = match path.split('?').next() {
+        Some("/") => ("200 OK", "text/html", SPECTATOR_PAGE.to_string()),
+        Some("/board.svg") => (
+            "200 OK",
+            "image/svg+xml",
+            board_svg
+                .lock()
+                .expect("the app thread never panics while holding the board")
+                .clone(),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+        Cache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,103 @@
+/// This file has undo and redo for the board. Instead of working out how to reverse each edit,
+/// it keeps a copy of the board after every change and steps back and forth between them. Every
+/// edit goes through the grid's history and gives the grid a new generation, so any change,
+/// including clearing, loading or generating a board, shows up as a generation that's moved. The app
+/// records the board once a frame while nothing is being dragged, so a whole stroke comes back
+/// in one step.
+use crate::flow_grid::FlowGrid;
+
+/// how many changes back undo can go, since each one keeps a whole copy of the board
+const MAX_UNDO_STEPS: usize = 100;
+
+pub struct UndoHistory {
+    /// the boards before the current one, oldest first
+    undo_steps: Vec<FlowGrid>,
+    /// the boards undone since the last change, most recently undone last
+    redo_steps: Vec<FlowGrid>,
+    /// the board as of the last record, undo or redo
+    current: FlowGrid,
+}
+
+impl UndoHistory {
+    pub fn new(grid: &FlowGrid) -> Self {
+        UndoHistory {
+            undo_steps: Vec::new(),
+            redo_steps: Vec::new(),
+            current: grid.clone(),
+        }
+    }
+
+    /// Makes the grid a step to come back to, if it changed since the last one. A change
+    /// forgets whatever was undone before it. Returns whether there was a change.
+    pub fn record(&mut self, grid: &FlowGrid) -> bool {
+        if grid.generation() == self.current.generation() {
+            return false;
+        }
+        self.undo_steps
+            .push(std::mem::replace(&mut self.current, grid.clone()));
+        if self.undo_steps.len() > MAX_UNDO_STEPS {
+            self.undo_steps.remove(0);
+        }
+        self.redo_steps.clear();
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_steps.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_steps.is_empty()
+    }
+
+    /// the board from before the last change, to put back in place of the current one
+    pub fn undo(&mut self) -> Option<FlowGrid> {
+        let previous = self.undo_steps.pop()?;
+        self.redo_steps
+            .push(std::mem::replace(&mut self.current, previous.clone()));
+        Some(previous)
+    }
+
+    /// the board from before the last undo
+    pub fn redo(&mut self) -> Option<FlowGrid> {
+        let next = self.redo_steps.pop()?;
+        self.undo_steps
+            .push(std::mem::replace(&mut self.current, next.clone()));
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_grid::Direction;
+
+    #[test]
+    fn steps_back_and_forth_through_changes() {
+        let mut grid = FlowGrid::from_board(&["A.A"]).expect("a valid board");
+        let mut history = UndoHistory::new(&grid);
+        assert!(!history.record(&grid));
+        assert!(!history.can_undo());
+
+        assert!(grid.try_connect(0, 0, Direction::Right));
+        assert!(history.record(&grid));
+        assert!(grid.try_connect(0, 1, Direction::Right));
+        assert!(history.record(&grid));
+        assert!(grid.is_solved());
+
+        let undone = history.undo().expect("two changes to undo");
+        assert!(!undone.is_solved());
+        assert_eq!(undone.get(0, 0).expect("in bounds").num_connections(), 1);
+        let undone = history.undo().expect("one change left to undo");
+        assert_eq!(undone.get(0, 0).expect("in bounds").num_connections(), 0);
+        assert!(history.undo().is_none());
+
+        let redone = history.redo().expect("two changes to redo");
+        assert_eq!(redone.get(0, 0).expect("in bounds").num_connections(), 1);
+        // a new change, like starting over, drops the rest of the redo steps
+        assert!(history.record(&FlowGrid::with_size(3, 1)));
+        assert!(!history.can_redo());
+        let undone = history.undo().expect("the board from before starting over");
+        assert!(undone.has_same_history(&redone));
+    }
+}
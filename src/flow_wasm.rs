@@ -0,0 +1,63 @@
+/// This file is the JavaScript API for the wasm build, made with wasm-bindgen. Build it with
+/// `wasm-pack build --target web`, then from JavaScript:
+///
+/// ```js
+/// const puzzle = new Puzzle("R..R\nG.G.\nB...\n...B");
+/// puzzle.solve(5000); // ["RRR", "RDRRUL", "DRRR"]
+/// ```
+///
+/// Boards use the corpus format: one line per row, letters for sources and '.' for empty cells.
+/// Paths come back the same way the corpus records them, as the U, D, L and R moves from each
+/// color's first source to its second, in the order the colors first appear.
+use crate::flow_cli;
+use crate::flow_grid::FlowGrid;
+use crate::flow_solver::{self, SolveOptions, SolveOutcome};
+use wasm_bindgen::prelude::*;
+use web_time::Duration;
+
+#[wasm_bindgen]
+pub struct Puzzle {
+    grid: FlowGrid,
+}
+
+#[wasm_bindgen]
+impl Puzzle {
+    #[wasm_bindgen(constructor)]
+    pub fn new(board: &str) -> Result<Puzzle, String> {
+        flow_cli::read_board(board)
+            .map(|grid| Puzzle { grid })
+            .ok_or_else(|| {
+                "rows must be the same length, and every letter must appear exactly twice"
+                    .to_string()
+            })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.grid.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.grid.height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn colors(&self) -> usize {
+        self.grid.num_colors()
+    }
+
+    /// the path of every color in a solution, giving up after the timeout
+    pub fn solve(&self, timeout_ms: u32) -> Result<Vec<String>, String> {
+        let options = SolveOptions {
+            timeout: Duration::from_millis(timeout_ms.into()),
+            ..SolveOptions::default()
+        };
+        match flow_solver::solve_with_options(&self.grid, &options) {
+            SolveOutcome::Solved(solution) => Ok(flow_cli::pipe_paths(&solution)),
+            SolveOutcome::Unsolvable => Err("the puzzle has no solution".to_string()),
+            SolveOutcome::TimedOut => Err("the solver ran out of time".to_string()),
+            SolveOutcome::Unsupported => Err("the solver can't handle bridges yet".to_string()),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/// This file has zen mode: a playlist of level packs, played one level after another with no
+/// clock and no score. The levels come in pack order or shuffled, and the playlist remembers how
+/// far the player got, so zen mode picks up at the same level next time.
+///
+/// Only the pack names, the shuffle seed and the position are kept, and the order is worked out
+/// again from those, so the same seed always shuffles the same packs the same way.
+use crate::flow_generator::Rng;
+
+/// the packs to play, how they're ordered, and how far into them the player is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZenPlaylist {
+    /// the packs picked, by name, in the order they're played when not shuffled
+    pub packs: Vec<String>,
+    /// the seed the levels are shuffled with, or None to play them in order
+    pub shuffle_seed: Option<u64>,
+    /// how many levels of the playlist have been played
+    pub position: usize,
+}
+
+/// stands for no shuffle seed in to_text
+const IN_ORDER: &str = "in order";
+
+impl ZenPlaylist {
+    pub fn new(packs: Vec<String>, shuffle_seed: Option<u64>) -> Self {
+        ZenPlaylist {
+            packs,
+            shuffle_seed,
+            position: 0,
+        }
+    }
+
+    /// Every level of the playlist as its pack name and level index, in the order they're
+    /// played. pack_len gives how many levels a pack has, or 0 for one that isn't around.
+    pub fn levels(&self, pack_len: impl Fn(&str) -> usize) -> Vec<(String, usize)> {
+        let mut levels: Vec<(String, usize)> = self
+            .packs
+            .iter()
+            .flat_map(|pack| (0..pack_len(pack)).map(move |level| (pack.clone(), level)))
+            .collect();
+        if let Some(seed) = self.shuffle_seed {
+            let mut rng = Rng::new(seed);
+            for index in (1..levels.len()).rev() {
+                levels.swap(index, rng.below(index + 1));
+            }
+        }
+        levels
+    }
+
+    /// the level being played, or None once the whole playlist has been
+    pub fn current(&self, pack_len: impl Fn(&str) -> usize) -> Option<(String, usize)> {
+        self.levels(pack_len).into_iter().nth(self.position)
+    }
+
+    /// The position, then the shuffle seed or "in order", then one pack name per line.
+    pub fn to_text(&self) -> String {
+        let seed = self
+            .shuffle_seed
+            .map_or(IN_ORDER.to_string(), |seed| seed.to_string());
+        let mut text = format!("{}\n{seed}\n", self.position);
+        for pack in &self.packs {
+            text.push_str(pack);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// reads to_text back, or None if it's been mangled
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        let position = lines.next()?.parse().ok()?;
+        let shuffle_seed = match lines.next()? {
+            IN_ORDER => None,
+            seed => Some(seed.parse().ok()?),
+        };
+        let packs: Vec<String> = lines
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        (!packs.is_empty()).then_some(ZenPlaylist {
+            packs,
+            shuffle_seed,
+            position,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack_len(pack: &str) -> usize {
+        match pack {
+            "Starter" => 3,
+            "Classic" => 4,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn playlists_go_through_every_level_once_in_order_or_shuffled() {
+        let packs = vec!["Classic".to_string(), "Starter".to_string()];
+        let in_order = ZenPlaylist::new(packs.clone(), None).levels(pack_len);
+        assert_eq!(in_order.len(), 7);
+        assert_eq!(in_order[0], ("Classic".to_string(), 0));
+        assert_eq!(in_order[4], ("Starter".to_string(), 0));
+
+        let shuffled = ZenPlaylist::new(packs.clone(), Some(5)).levels(pack_len);
+        assert_ne!(shuffled, in_order);
+        assert_eq!(shuffled, ZenPlaylist::new(packs, Some(5)).levels(pack_len));
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        let mut expected = in_order.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn playlists_end_after_the_last_level_and_read_back_from_text() {
+        let mut playlist = ZenPlaylist::new(vec!["Starter".to_string(), "Gone".to_string()], None);
+        playlist.position = 2;
+        assert_eq!(playlist.current(pack_len), Some(("Starter".to_string(), 2)));
+        playlist.position = 3;
+        assert_eq!(playlist.current(pack_len), None);
+
+        assert_eq!(ZenPlaylist::from_text(&playlist.to_text()), Some(playlist));
+        let shuffled = ZenPlaylist::new(vec!["Classic".to_string()], Some(u64::MAX));
+        assert_eq!(ZenPlaylist::from_text(&shuffled.to_text()), Some(shuffled));
+        assert_eq!(ZenPlaylist::from_text("1\nin order\n"), None);
+        assert_eq!(ZenPlaylist::from_text("x\nin order\nStarter"), None);
+    }
+}
@@ -0,0 +1,23 @@
+/// This file is the library side of the app: the parts that don't need a window but aren't
+/// general enough for flow-solver-core, like the board widget, the daily puzzle, blitz and zen
+/// mode, lessons, level packs, undo and the command line solver.
+/// The core modules are re-exported here so the app keeps finding them under flow::. On wasm32 it
+/// also exports a small JavaScript API from flow_wasm, so web pages can call the solver directly.
+pub use flow_solver_core::{
+    flow_bitboard, flow_corpus, flow_count, flow_generator, flow_grid, flow_repair, flow_rules,
+    flow_solver,
+};
+
+pub mod flow_blitz;
+pub mod flow_canvas;
+pub mod flow_cli;
+pub mod flow_daily;
+pub mod flow_gym;
+pub mod flow_lessons;
+pub mod flow_pack;
+pub mod flow_palette;
+pub mod flow_screenshot;
+pub mod flow_undo;
+#[cfg(target_arch = "wasm32")]
+pub mod flow_wasm;
+pub mod flow_zen;
@@ -1,50 +1,2473 @@
 /// This file has the basic, overall UI layout. All of the harder UI interactions have been
-/// extracted into flow_canvas, and the core data model is in flow_grid.
-mod flow_canvas;
-mod flow_grid;
+/// extracted into flow_canvas, and the core data model is in flow_grid, both in the library.
+mod flow_bench;
+mod flow_image;
+mod flow_report;
+mod flow_sound;
+mod flow_spectator;
+
+use flow::{
+    flow_blitz, flow_canvas, flow_cli, flow_corpus, flow_count, flow_daily, flow_generator,
+    flow_grid, flow_gym, flow_lessons, flow_pack, flow_palette, flow_repair, flow_rules,
+    flow_screenshot, flow_solver, flow_undo, flow_zen,
+};
 
 use eframe::{
     App, NativeOptions,
-    egui::{self, CentralPanel, Color32, TopBottomPanel, ViewportBuilder},
+    egui::{self, CentralPanel, Color32, SidePanel, TopBottomPanel, ViewportBuilder},
     icon_data, run_native,
 };
+use flow_rules::WinCondition;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
 
-const CELL_SIZE: f32 = 75.0;
-const SOURCE_RADIUS: f32 = CELL_SIZE / 3.0;
-const PIPE_WIDTH: f32 = CELL_SIZE * 2.0 / 7.0;
-const GRID_BORDER_WIDTH: f32 = CELL_SIZE / 35.0;
-const PIPE_LENGTH: f32 = (CELL_SIZE + PIPE_WIDTH) / 2.0 + GRID_BORDER_WIDTH;
-const PIPE_INSET_DIST: f32 = (CELL_SIZE - PIPE_WIDTH) / 2.0 + GRID_BORDER_WIDTH;
-
-const COLOR_INDEX: [(&str, Color32); 9] = [
-    ("Red", Color32::from_rgb(255, 0, 0)),
-    ("Green", Color32::from_rgb(0, 200, 0)),
-    ("Blue", Color32::from_rgb(0, 0, 255)),
-    ("Yellow", Color32::from_rgb(255, 255, 0)),
-    ("Orange", Color32::from_rgb(255, 165, 0)),
-    ("Purple", Color32::from_rgb(128, 0, 128)),
-    ("Cyan", Color32::from_rgb(0, 255, 255)),
-    ("Pink", Color32::from_rgb(255, 192, 203)),
-    ("Dark Red", Color32::from_rgb(128, 0, 0)),
-];
+/// Each color's pipe as its first source and the moves from there, one color per line, like
+/// "Red: (0,0) R R D D".
+fn move_list(grid: &flow_grid::FlowGrid, palette: &flow_palette::Palette) -> String {
+    (0..grid.num_colors())
+        .filter_map(|color_id| {
+            let ((row, col), moves) = grid.pipe_moves(color_id)?;
+            let name = palette.name(color_id);
+            let moves: Vec<String> = moves.iter().map(|step| step.letter().to_string()).collect();
+            Some(format!("{name}: ({row},{col}) {}", moves.join(" ")))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// seconds as minutes and seconds, like "1:05"
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
 
 struct FlowSolverApp {
-    flow_canvas: flow_canvas::FlowCanvas,
+    flow_canvas: flow_canvas::FlowBoardWidget,
+    show_lessons: bool,
+    active_lesson: Option<usize>,
+    completed_lessons: [bool; flow_lessons::COURSE.len()],
+    solver_message: Option<String>,
+    solution_browser: Option<SolutionBrowser>,
+    comparison: Option<Comparison>,
+    solve_animation: Option<SolveAnimation>,
+    /// whether the board was solved as of the last frame, so the win overlay only comes up when
+    /// it becomes solved
+    was_solved: bool,
+    show_win_overlay: bool,
+    /// the board after each change, so edits and resets can be stepped back through
+    undo_history: flow_undo::UndoHistory,
+    background_solve: Option<flow_solver::BackgroundSolve>,
+    /// whatever else the controls asked to work out, like a hint or a count
+    job: Option<Job>,
+    solve_options: flow_solver::SolveOptions,
+    /// from the last solve that finished
+    solve_stats: Option<flow_solver::SolveStats>,
+    /// draw a solution faintly under the player's pipes
+    show_ghost: bool,
+    /// the board the ghost solution was worked out for, so it can be redone when the puzzle
+    /// changes
+    ghost_puzzle: Option<flow_grid::FlowGrid>,
+    ghost_solve: Option<flow_solver::BackgroundSolve>,
+    /// None while it's being worked out, or if the puzzle has no solution
+    ghost: Option<flow_grid::FlowGrid>,
+    /// these are carried over to the canvas each frame, since the canvas is rebuilt on clear
+    /// and lesson changes
+    show_dead_ends: bool,
+    auto_complete: bool,
+    hide_sources: bool,
+    show_color_labels: bool,
+    sounds: flow_sound::SoundPlayer,
+    /// only the board is shown, and it ignores all input
+    is_viewing: bool,
+    spectator_server: Option<flow_spectator::SpectatorServer>,
+    show_diagnostics: bool,
+    /// whether the diagnostics are in their own window instead of a side panel
+    is_diagnostics_detached: bool,
+    repair_wizard: Option<RepairWizard>,
+    /// the levels of an imported pack, listed in a side panel
+    imported_pack: Option<Vec<flow_pack::PackPuzzle>>,
+    /// the last level opened from the imported pack
+    pack_level: Option<usize>,
+    /// how many pixels wide each cell is in an exported image
+    export_cell_size: f32,
+    /// the code of the last generated puzzle, or one typed in to open
+    puzzle_code: String,
+    /// what Generate and Fill shape make
+    generate_options: flow_generator::GenerateOptions,
+    color_count: ColorCount,
+    /// from the last puzzle generated, for the diagnostics
+    generate_stats: Option<flow_generator::GenerateStats>,
+    /// the color counts Auto picks from, which are kept between runs
+    color_table: flow_generator::ColorTable,
+    /// the day whose daily puzzle is on the board, if one is
+    daily_day: Option<u64>,
+    daily_record: flow_daily::DailyRecord,
+    /// the blitz run being played, if one is
+    blitz: Option<Blitz>,
+    /// a blitz run that's over, shown until its results are closed
+    blitz_results: Option<flow_blitz::BlitzRun>,
+    /// the best blitz score so far, which is kept between runs
+    blitz_best_score: u64,
+    /// the zen playlist, kept between runs so zen mode picks up where it left off
+    zen_playlist: Option<flow_zen::ZenPlaylist>,
+    /// whether the board is serving the zen playlist's levels
+    is_zen: bool,
+    /// when the zen level on the board was solved, by egui's clock, while it's being celebrated
+    zen_solved_at: Option<f64>,
+    show_zen_setup: bool,
+    /// the packs ticked in the zen setup, by name
+    zen_picked_packs: BTreeSet<String>,
+    zen_shuffle: bool,
+    /// whether the bug report window is open
+    show_bug_report: bool,
+    show_level_browser: bool,
+    /// the levels of each of flow_pack::BUNDLED_PACKS, read once at startup
+    bundled_packs: Vec<Vec<flow_pack::PackPuzzle>>,
+    /// the pack and level from the level browser that's on the board, if one is
+    browsed_level: Option<(usize, usize)>,
+    level_progress: flow_pack::LevelProgress,
+    /// the colors the board is drawn in, which are carried over to the canvas each frame
+    palette: flow_palette::Palette,
+    show_palette_editor: bool,
+    /// the size New grid makes, which can differ from the board's until it's pressed
+    new_width: usize,
+    new_height: usize,
+    /// every open board, in the order their tabs are shown
+    tabs: Vec<BoardTab>,
+    /// the tab whose board is on the canvas
+    active_tab: usize,
+}
+
+/// A board open in a tab. The board on the canvas lives in FlowSolverApp's own fields, so while
+/// its tab is active, its entry here only holds its name.
+struct BoardTab {
+    name: String,
+    canvas: flow_canvas::FlowBoardWidget,
+    undo_history: flow_undo::UndoHistory,
+    active_lesson: Option<usize>,
+    daily_day: Option<u64>,
+    browsed_level: Option<(usize, usize)>,
+    pack_level: Option<usize>,
+}
+
+impl BoardTab {
+    fn new(name: String, width: usize, height: usize) -> Self {
+        BoardTab {
+            name,
+            canvas: flow_canvas::FlowBoardWidget::with_size(width, height),
+            undo_history: flow_undo::UndoHistory::new(&flow_grid::FlowGrid::with_size(
+                width, height,
+            )),
+            active_lesson: None,
+            daily_day: None,
+            browsed_level: None,
+            pack_level: None,
+        }
+    }
+}
+
+/// where the daily record is kept between runs
+const DAILY_RECORD_KEY: &str = "daily_record";
+/// where the best blitz score is kept between runs
+const BLITZ_BEST_SCORE_KEY: &str = "blitz_best_score";
+/// where the zen playlist is kept between runs
+const ZEN_PLAYLIST_KEY: &str = "zen_playlist";
+/// what zen mode calls the imported pack, which isn't kept between runs
+const IMPORTED_PACK_NAME: &str = "Imported pack";
+/// how long a solved level is celebrated before zen mode moves on
+const ZEN_CELEBRATION_SECONDS: f64 = 1.5;
+/// where the finished bundled levels are kept between runs
+const LEVEL_PROGRESS_KEY: &str = "level_progress";
+/// where the edited palette is kept between runs
+const PALETTE_KEY: &str = "palette";
+/// where the edited table of color counts is kept between runs
+const COLOR_TABLE_KEY: &str = "color_table";
+/// where the board is kept between runs
+const SESSION_KEY: &str = "session";
+
+/// the board as it was left, so closing the app doesn't lose it
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    grid: flow_grid::FlowGrid,
+    can_edit_sources: bool,
+    /// kept with the board so someone who needs the labels doesn't have to turn them on every
+    /// time; sessions saved before it existed have them off
+    #[serde(default)]
+    show_color_labels: bool,
+    #[serde(default)]
+    is_muted: bool,
+}
+
+/// how many steps the solve animation remembers, so it can step back through them
+const MAX_REWIND_STEPS: usize = 1000;
+
+/// plays back the solver's search on the canvas a few steps at a time
+struct SolveAnimation {
+    steps: flow_solver::Solutions,
+    is_paused: bool,
+    steps_per_second: f32,
+    /// time that has passed but hasn't been spent on steps yet, measured in steps
+    pending_steps: f32,
+    /// each step so far with the board it left, oldest first, starting from the board before any
+    history: VecDeque<(Option<flow_solver::SolveEvent>, flow_grid::FlowGrid)>,
+    /// how many steps back from the newest one the board is showing
+    rewound: usize,
+    is_finished: bool,
+    /// the board as it was before the animation took it over, put back when it's stopped
+    board_before: flow_grid::FlowGrid,
+}
+
+impl SolveAnimation {
+    fn new(grid: &flow_grid::FlowGrid) -> Self {
+        let steps = flow_solver::solutions(grid);
+        SolveAnimation {
+            history: steps.grid().map(|grid| (None, grid)).into_iter().collect(),
+            steps,
+            is_paused: false,
+            steps_per_second: 10.0,
+            pending_steps: 0.0,
+            rewound: 0,
+            is_finished: false,
+            board_before: grid.clone(),
+        }
+    }
+
+    /// the step on show and the board it left
+    fn current(&self) -> Option<&(Option<flow_solver::SolveEvent>, flow_grid::FlowGrid)> {
+        self.history
+            .len()
+            .checked_sub(self.rewound + 1)
+            .map(|index| &self.history[index])
+    }
+
+    /// moves forward through the remembered steps, then on to new ones from the solver
+    fn step(&mut self) {
+        if self.rewound > 0 {
+            self.rewound -= 1;
+        } else {
+            match self.steps.step() {
+                Some(event) => {
+                    if let Some(grid) = self.steps.grid() {
+                        self.history.push_back((Some(event), grid));
+                        if self.history.len() > MAX_REWIND_STEPS {
+                            self.history.pop_front();
+                        }
+                    }
+                }
+                None => {
+                    self.is_finished = true;
+                    self.is_paused = true;
+                    return;
+                }
+            }
+        }
+        if self.last_event() == Some(flow_solver::SolveEvent::Solved) {
+            self.is_paused = true;
+        }
+    }
+
+    fn step_back(&mut self) {
+        if self.rewound + 1 < self.history.len() {
+            self.rewound += 1;
+        }
+    }
+
+    fn advance(&mut self, seconds: f32) {
+        if self.is_paused {
+            self.pending_steps = 0.0;
+            return;
+        }
+        self.pending_steps += seconds * self.steps_per_second;
+        while self.pending_steps >= 1.0 && !self.is_paused {
+            self.pending_steps -= 1.0;
+            self.step();
+        }
+    }
+
+    fn last_event(&self) -> Option<flow_solver::SolveEvent> {
+        self.current()?.0
+    }
+
+    /// the cell the last step changed
+    fn changed_cell(&self) -> Option<(usize, usize)> {
+        let (event, grid) = self.current()?;
+        match (*event)? {
+            flow_solver::SolveEvent::Connect(step) => {
+                grid.get_offset_row_col(step.row, step.col, step.direction)
+            }
+            flow_solver::SolveEvent::Disconnect(step) => Some((step.row, step.col)),
+            flow_solver::SolveEvent::Solved => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.is_finished && self.rewound == 0 {
+            return "Search finished".to_string();
+        }
+        let description = match self.last_event() {
+            Some(flow_solver::SolveEvent::Connect(step)) => {
+                format!("Extended ({}, {}) {:?}", step.row, step.col, step.direction)
+            }
+            Some(flow_solver::SolveEvent::Disconnect(step)) => {
+                format!("Backed up to ({}, {})", step.row, step.col)
+            }
+            Some(flow_solver::SolveEvent::Solved) => "Found a solution".to_string(),
+            None => "Starting".to_string(),
+        };
+        match self.rewound {
+            0 => description,
+            rewound => format!("{description} ({rewound} back)"),
+        }
+    }
+}
+
+/// walks an author through making a puzzle unique by previewing source moves on the board
+struct RepairWizard {
+    /// the board before any preview, to go back to on cancel
+    original: flow_grid::FlowGrid,
+    /// how many solutions the original has
+    solutions: u128,
+    repairs: Vec<flow_repair::Repair>,
+    /// the repair on show
+    selected: Option<usize>,
+}
+
+impl RepairWizard {
+    fn describe(repair: &flow_repair::Repair, palette: &flow_palette::Palette) -> String {
+        let change = match repair.change {
+            flow_repair::RepairChange::MoveSource { color_id, from, to } => format!(
+                "{} ({}, {}) to ({}, {})",
+                palette.name(color_id),
+                from.0,
+                from.1,
+                to.0,
+                to.1
+            ),
+            flow_repair::RepairChange::AddWall((row, col)) => format!("Wall at ({row}, {col})"),
+        };
+        format!(
+            "{change}: {}",
+            if repair.is_unique() {
+                "unique ✔".to_string()
+            } else {
+                format!("{} solutions", repair.solutions)
+            }
+        )
+    }
+}
+
+/// shown on the solver's buttons while they're off for a board with bridges, which the search
+/// can't route through yet and so would call unsolvable
+const NO_BRIDGES_TEXT: &str = "The solver can't handle bridges yet";
+
+/// how many solutions Diverse solutions shows
+const DIVERSE_SOLUTIONS: usize = 5;
+/// how many solutions Diverse solutions picks them from
+const DIVERSE_POOL_SIZE: usize = 400;
+
+/// the player's routing of a board held up against a solution the solver finds for it
+struct Comparison {
+    solve: Option<flow_solver::BackgroundSolve>,
+    /// the solver's solution, once it's found, drawn on its own next to the board when they're
+    /// side by side
+    reference: Option<flow_canvas::FlowBoardWidget>,
+    /// whether the solver turned the puzzle down for its bridges
+    is_unsupported: bool,
+    is_side_by_side: bool,
+}
+
+impl Comparison {
+    fn new(grid: &flow_grid::FlowGrid, options: &flow_solver::SolveOptions) -> Self {
+        Comparison {
+            solve: Some(flow_solver::solve_in_background(
+                grid,
+                flow_solver::SolveOptions {
+                    keep_pipes: false,
+                    colors: None,
+                    ..options.clone()
+                },
+            )),
+            reference: None,
+            is_unsupported: false,
+            is_side_by_side: false,
+        }
+    }
+}
+
+/// how many colors Generate aims for
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ColorCount {
+    /// as few as the generator can manage, which makes the longest pipes
+    Fewest,
+    /// whatever the color table says for the board's size
+    Auto,
+    Exactly(usize),
+}
+
+/// what a job hands back once it's done
+enum JobResult {
+    Hint(Option<flow_grid::Move>),
+    Rating(Option<flow_solver::Difficulty>),
+    Count(u128),
+    /// how many solutions the puzzle has, and the source moves that narrow them down
+    Repairs(u128, Vec<flow_repair::Repair>),
+    Diverse(Vec<flow_grid::FlowGrid>),
+    /// a solution to take the Exact lengths rule's lengths from
+    Lengths(flow_solver::SolveOutcome),
+    /// the browser's search, handed back, and what it found next
+    NextSolution(Box<flow_solver::Solutions>, flow_solver::SolveOutcome),
+    /// a new puzzle, or None if none could be made, where it came from, and how making it went
+    Generated(
+        Option<Box<flow_grid::FlowGrid>>,
+        NewPuzzle,
+        flow_generator::GenerateStats,
+    ),
+}
+
+/// where a generated puzzle came from, to go with it onto the board
+struct NewPuzzle {
+    /// the code to share it with, or empty if it was made in a shape a code can't describe
+    code: String,
+    /// the day it's the daily puzzle for, if it is one
+    daily_day: Option<u64>,
+}
+
+/// a blitz run and the puzzle its round is played on
+struct Blitz {
+    run: flow_blitz::BlitzRun,
+    /// the code of the round's puzzle, to pick it out when it's generated
+    code: String,
+    /// the round's puzzle once it's on the board, or None while it's being generated
+    puzzle: Option<flow_grid::FlowGrid>,
+}
+
+/// work for one of the buttons, done on another thread so the window keeps drawing
+struct Job {
+    /// what the job is doing, shown while it runs
+    activity: &'static str,
+    /// the board the job started from. If the puzzle on the board changes, the answer is dropped.
+    puzzle: flow_grid::FlowGrid,
+    task: flow_solver::BackgroundTask<JobResult>,
+}
+
+/// pages through the solutions of a puzzle, only searching for more as they're asked for
+struct SolutionBrowser {
+    /// the search for more solutions, or None while a job is carrying it on
+    solutions: Option<flow_solver::Solutions>,
+    found: Vec<flow_grid::FlowGrid>,
+    current: Option<usize>,
+    is_exhausted: bool,
+}
+
+impl SolutionBrowser {
+    fn new(grid: &flow_grid::FlowGrid) -> Self {
+        SolutionBrowser {
+            solutions: Some(flow_solver::solutions(grid)),
+            found: Vec::new(),
+            current: None,
+            is_exhausted: false,
+        }
+    }
+
+    /// a browser over solutions that were already found, like the ones diverse_solutions picks
+    fn with_solutions(found: Vec<flow_grid::FlowGrid>) -> Self {
+        SolutionBrowser {
+            solutions: None,
+            found,
+            current: None,
+            is_exhausted: true,
+        }
+    }
+
+    /// whether stepping forward needs the search to find another solution first
+    fn needs_search(&self) -> bool {
+        let next_index = self.current.map_or(0, |current| current + 1);
+        next_index == self.found.len() && !self.is_exhausted
+    }
+
+    fn step_forward(&mut self) -> Option<&flow_grid::FlowGrid> {
+        let next_index = self.current.map_or(0, |current| current + 1);
+        if next_index < self.found.len() {
+            self.current = Some(next_index);
+        }
+        self.current.map(|current| &self.found[current])
+    }
+
+    fn step_back(&mut self) -> Option<&flow_grid::FlowGrid> {
+        if let Some(current) = self.current
+            && current > 0
+        {
+            self.current = Some(current - 1);
+        }
+        self.current.map(|current| &self.found[current])
+    }
+
+    fn describe(&self) -> String {
+        match self.current {
+            Some(current) => format!(
+                "Solution {} of {}{}",
+                current + 1,
+                self.found.len(),
+                if self.is_exhausted { "" } else { "+" }
+            ),
+            None => "No solutions".to_string(),
+        }
+    }
 }
 
 impl FlowSolverApp {
     pub fn with_size(width: usize, height: usize) -> Self {
         FlowSolverApp {
-            flow_canvas: flow_canvas::FlowCanvas::with_size(width, height),
+            flow_canvas: flow_canvas::FlowBoardWidget::with_size(width, height),
+            show_lessons: false,
+            active_lesson: None,
+            completed_lessons: [false; flow_lessons::COURSE.len()],
+            solver_message: None,
+            solution_browser: None,
+            comparison: None,
+            solve_animation: None,
+            was_solved: false,
+            show_win_overlay: false,
+            undo_history: flow_undo::UndoHistory::new(&flow_grid::FlowGrid::with_size(
+                width, height,
+            )),
+            background_solve: None,
+            job: None,
+            solve_options: flow_solver::SolveOptions::default(),
+            solve_stats: None,
+            show_ghost: false,
+            ghost_puzzle: None,
+            ghost_solve: None,
+            ghost: None,
+            show_dead_ends: true,
+            is_viewing: false,
+            auto_complete: false,
+            hide_sources: false,
+            show_color_labels: false,
+            sounds: flow_sound::SoundPlayer::default(),
+            spectator_server: None,
+            show_diagnostics: false,
+            is_diagnostics_detached: false,
+            repair_wizard: None,
+            imported_pack: None,
+            pack_level: None,
+            export_cell_size: flow_canvas::DEFAULT_CELL_SIZE,
+            puzzle_code: String::new(),
+            generate_options: flow_generator::GenerateOptions::default(),
+            color_count: ColorCount::Fewest,
+            generate_stats: None,
+            color_table: flow_generator::ColorTable::default(),
+            daily_day: None,
+            daily_record: flow_daily::DailyRecord::default(),
+            blitz: None,
+            blitz_results: None,
+            blitz_best_score: 0,
+            zen_playlist: None,
+            is_zen: false,
+            zen_solved_at: None,
+            show_zen_setup: false,
+            zen_picked_packs: BTreeSet::new(),
+            zen_shuffle: false,
+            show_bug_report: false,
+            show_level_browser: false,
+            bundled_packs: flow_pack::BUNDLED_PACKS
+                .iter()
+                .map(flow_pack::BundledPack::puzzles)
+                .collect(),
+            browsed_level: None,
+            level_progress: flow_pack::LevelProgress::default(),
+            palette: flow_palette::Palette::default(),
+            show_palette_editor: false,
+            new_width: width,
+            new_height: height,
+            tabs: vec![BoardTab::new("Board 1".to_string(), width, height)],
+            active_tab: 0,
         }
     }
+
+    /// picks up the board, daily record and level progress saved by the last run, if there are
+    /// any
+    fn with_storage(mut self, storage: Option<&dyn eframe::Storage>) -> Self {
+        if let Some(session) = storage
+            .and_then(|storage| storage.get_string(SESSION_KEY))
+            .and_then(|json| serde_json::from_str::<SavedSession>(&json).ok())
+        {
+            self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(session.grid);
+            self.flow_canvas.can_edit_sources = session.can_edit_sources;
+            self.show_color_labels = session.show_color_labels;
+            self.sounds.is_muted = session.is_muted;
+            self.undo_history = flow_undo::UndoHistory::new(&self.flow_canvas.grid);
+        }
+        if let Some(record) = storage
+            .and_then(|storage| storage.get_string(DAILY_RECORD_KEY))
+            .and_then(|text| flow_daily::DailyRecord::from_text(&text))
+        {
+            self.daily_record = record;
+        }
+        if let Some(score) = storage
+            .and_then(|storage| storage.get_string(BLITZ_BEST_SCORE_KEY))
+            .and_then(|text| text.parse().ok())
+        {
+            self.blitz_best_score = score;
+        }
+        if let Some(playlist) = storage
+            .and_then(|storage| storage.get_string(ZEN_PLAYLIST_KEY))
+            .and_then(|text| flow_zen::ZenPlaylist::from_text(&text))
+        {
+            self.zen_picked_packs = playlist.packs.iter().cloned().collect();
+            self.zen_shuffle = playlist.shuffle_seed.is_some();
+            self.zen_playlist = Some(playlist);
+        }
+        if let Some(progress) = storage
+            .and_then(|storage| storage.get_string(LEVEL_PROGRESS_KEY))
+            .and_then(|text| flow_pack::LevelProgress::from_text(&text))
+        {
+            self.level_progress = progress;
+        }
+        if let Some(palette) = storage
+            .and_then(|storage| storage.get_string(PALETTE_KEY))
+            .and_then(|text| flow_palette::Palette::from_text(&text))
+        {
+            self.palette = palette;
+        }
+        if let Some(color_table) = storage
+            .and_then(|storage| storage.get_string(COLOR_TABLE_KEY))
+            .and_then(|text| flow_generator::ColorTable::from_text(&text))
+        {
+            self.color_table = color_table;
+        }
+        self
+    }
+
+    fn start_lesson(&mut self, lesson_index: usize) {
+        self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(
+            flow_lessons::COURSE[lesson_index].build_grid(),
+        )
+        .sources_locked();
+        self.active_lesson = Some(lesson_index);
+        self.daily_day = None;
+        self.browsed_level = None;
+    }
+
+    /// lets the solver be limited to some of the colors, leaving the rest to the player
+    fn solve_colors_ui(&mut self, ui: &mut egui::Ui) {
+        let mut is_limited = self.solve_options.colors.is_some();
+        if ui
+            .checkbox(&mut is_limited, "Only route some colors")
+            .on_hover_text("Leave every other pipe as it is, and let cells stay empty")
+            .changed()
+        {
+            self.solve_options.colors = is_limited.then(Vec::new);
+        }
+        let Some(colors) = &mut self.solve_options.colors else {
+            return;
+        };
+        let grid = &self.flow_canvas.grid;
+        ui.horizontal_wrapped(|ui| {
+            for color_id in 0..grid.num_colors() {
+                if grid.get_sources(color_id).len() < 2 {
+                    continue;
+                }
+                let name = self.palette.name(color_id);
+                let mut is_selected = colors.contains(&color_id);
+                if ui.checkbox(&mut is_selected, name).changed() {
+                    if is_selected {
+                        colors.push(color_id);
+                    } else {
+                        colors.retain(|&selected| selected != color_id);
+                    }
+                }
+            }
+        });
+    }
+
+    fn win_condition_ui(&mut self, ui: &mut egui::Ui) {
+        let grid = &mut self.flow_canvas.grid;
+        let current = grid.win_condition().name();
+        if ui
+            .selectable_label(
+                current == flow_rules::AllCellsFilled.name(),
+                flow_rules::AllCellsFilled.name(),
+            )
+            .clicked()
+        {
+            grid.set_win_condition(flow_rules::AllCellsFilled);
+        }
+        if ui
+            .selectable_label(
+                current == flow_rules::EndpointsConnected.name(),
+                flow_rules::EndpointsConnected.name(),
+            )
+            .clicked()
+        {
+            grid.set_win_condition(flow_rules::EndpointsConnected);
+        }
+        if ui
+            .add_enabled(
+                !grid.has_bridges(),
+                egui::SelectableLabel::new(
+                    current == flow_rules::ExactLengths::NAME,
+                    flow_rules::ExactLengths::NAME,
+                ),
+            )
+            .on_hover_text("Each pipe has to be as long as it is in the solution")
+            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+            .clicked()
+        {
+            // the lengths come from a solution for the sources alone, whatever the solver
+            // settings say about keeping pipes or limiting colors
+            let options = flow_solver::SolveOptions {
+                keep_pipes: false,
+                colors: None,
+                ..self.solve_options.clone()
+            };
+            self.start_job("Finding the pipe lengths", move |grid| {
+                JobResult::Lengths(flow_solver::solve_with_options(&grid, &options))
+            });
+        }
+    }
+
+    fn diagnostics_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Diagnostics");
+            let detach_label = if self.is_diagnostics_detached {
+                "Attach"
+            } else {
+                "Detach"
+            };
+            ui.button(detach_label)
+                .on_hover_text("Move the diagnostics between a side panel and their own window")
+                .clicked()
+                .then(|| self.is_diagnostics_detached = !self.is_diagnostics_detached);
+        });
+        let grid = &self.flow_canvas.grid;
+        ui.label(format!(
+            "Board: {} x {}, {} colors",
+            grid.width,
+            grid.height,
+            grid.num_colors()
+        ));
+        ui.label(format!("Dead cells: {}", grid.find_dead_cells().len()));
+        ui.label(format!("Events: {}", grid.events_applied()));
+        ui.label(match grid.find_forced_move() {
+            Some(forced_move) => format!(
+                "Forced move: {:?} from ({}, {})",
+                forced_move.direction, forced_move.row, forced_move.col
+            ),
+            None => "Forced move: none".to_string(),
+        });
+        if ui
+            .button("Audit sources")
+            .on_hover_text("Check where the board thinks each source is, and fix any mix-ups")
+            .clicked()
+        {
+            let problems = self.flow_canvas.grid.audit_sources();
+            self.solver_message = Some(if problems.is_empty() {
+                "The sources are all in order.".to_string()
+            } else {
+                format!("Fixed: {}", problems.join("; "))
+            });
+        }
+        if ui
+            .button("Replay events")
+            .on_hover_text("Rebuild the board from its history of events alone")
+            .clicked()
+        {
+            let grid = self.flow_canvas.grid.replay_history();
+            self.solver_message = Some(format!("Replayed {} events.", grid.history().len()));
+            self.flow_canvas.grid = grid;
+        }
+        if let Some(stats) = &self.generate_stats {
+            ui.separator();
+            ui.label(format!(
+                "Last generated: {} boards tried in {:.2} s",
+                stats.attempts,
+                stats.wall_time.as_secs_f64()
+            ));
+            ui.label(format!("Pipes too short: {}", stats.short_pipes))
+                .on_hover_text("A pipe between sources this close isn't much of a puzzle");
+            ui.label(format!("Straight across: {}", stats.straight_pipes))
+                .on_hover_text("A pipe straight across the board just cuts it in two");
+            if stats.bridge_ends + stats.self_crossings > 0 {
+                ui.label(format!("Pipes ending on a bridge: {}", stats.bridge_ends))
+                    .on_hover_text("There's no room for a source on a bridge");
+                ui.label(format!(
+                    "Pipes crossing themselves: {}",
+                    stats.self_crossings
+                ))
+                .on_hover_text("A bridge only one pipe uses isn't much of a crossing");
+            }
+            ui.label(format!("More than one solution: {}", stats.not_unique));
+            ui.label(format!("Too hard to check: {}", stats.too_hard))
+                .on_hover_text("The solver gave up before it could tell");
+            ui.label(format!("Too easy: {}", stats.too_easy))
+                .on_hover_text("Solvable without a guess, so only kept if nothing harder turns up");
+            ui.label(format!("Candidates: {}", stats.candidates))
+                .on_hover_text(
+                    "Boards with one solution, that the best color count is picked from",
+                );
+        }
+        ui.separator();
+        let progress = if let Some(background_solve) = &self.background_solve {
+            Some(("Solving", background_solve.progress()))
+        } else {
+            self.solve_animation
+                .as_ref()
+                .map(|animation| ("Animating", animation.steps.progress()))
+        };
+        match progress {
+            Some((activity, progress)) => {
+                ui.label(activity);
+                ui.label(format!("Moves explored: {}", progress.nodes_explored));
+                ui.label(format!("Search depth: {}", progress.depth));
+            }
+            None => {
+                ui.label("Solver idle");
+            }
+        }
+    }
+
+    /// Starts generating the puzzle from this size and seed, in the chosen style, which replaces
+    /// the board once it's made. Big boards take seconds, so it's done in the background.
+    fn generate_puzzle(&mut self, width: usize, height: usize, seed: u64) {
+        let options = self.options_for(width, height);
+        self.start_generating(width, height, seed, options, None);
+    }
+
+    /// the generate options with the color count worked out for a board this size
+    fn options_for(&self, width: usize, height: usize) -> flow_generator::GenerateOptions {
+        flow_generator::GenerateOptions {
+            colors: match self.color_count {
+                ColorCount::Fewest => None,
+                ColorCount::Auto => self.color_table.colors_for(width, height),
+                ColorCount::Exactly(colors) => Some(colors),
+            },
+            ..self.generate_options
+        }
+    }
+
+    /// the daily puzzle is always classic, so it's the same for everyone
+    fn start_daily(&mut self) {
+        let today = flow_daily::today();
+        let size = flow_daily::DAILY_SIZE;
+        self.start_generating(
+            size,
+            size,
+            flow_daily::seed(today),
+            flow_generator::GenerateOptions::default(),
+            Some(today),
+        );
+    }
+
+    fn start_generating(
+        &mut self,
+        width: usize,
+        height: usize,
+        seed: u64,
+        options: flow_generator::GenerateOptions,
+        daily_day: Option<u64>,
+    ) {
+        let new_puzzle = NewPuzzle {
+            code: flow_generator::puzzle_code(width, height, seed, &options),
+            daily_day,
+        };
+        self.start_job("Generating", move |_| {
+            let (grid, stats) = flow_generator::generate_with_stats(
+                &vec![vec![true; width]; height],
+                seed,
+                &options,
+            );
+            JobResult::Generated(grid.map(Box::new), new_puzzle, stats)
+        });
+    }
+
+    /// How many colors Generate aims for, and the table Auto reads them from, by how many cells
+    /// the board has.
+    fn generator_settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Colors:");
+            ui.selectable_value(&mut self.color_count, ColorCount::Fewest, "Fewest")
+                .on_hover_text("As few as possible, for the longest pipes");
+            ui.selectable_value(&mut self.color_count, ColorCount::Auto, "Auto")
+                .on_hover_text("Pick a count from the board's size with the table below");
+            let colors = self
+                .color_table
+                .colors_for(self.flow_canvas.grid.width, self.flow_canvas.grid.height)
+                .unwrap_or(5);
+            if ui
+                .selectable_label(
+                    matches!(self.color_count, ColorCount::Exactly(_)),
+                    "Exactly",
+                )
+                .clicked()
+            {
+                self.color_count = ColorCount::Exactly(colors);
+            }
+            if let ColorCount::Exactly(colors) = &mut self.color_count {
+                ui.add(egui::DragValue::new(colors).range(2..=30));
+            }
+        });
+        ui.label("Auto colors, by board size:");
+        let mut removed = None;
+        egui::Grid::new("color_table").show(ui, |ui| {
+            for (row_index, (cells, colors)) in self.color_table.rows.iter_mut().enumerate() {
+                ui.add(
+                    egui::DragValue::new(cells)
+                        .range(1..=MAX_GRID_SIZE * MAX_GRID_SIZE)
+                        .prefix("up to ")
+                        .suffix(" cells"),
+                );
+                ui.add(egui::DragValue::new(colors).range(2..=30).suffix(" colors"));
+                ui.small_button("×")
+                    .clicked()
+                    .then(|| removed = Some(row_index));
+                ui.end_row();
+            }
+        });
+        if let Some(row_index) = removed {
+            self.color_table.rows.remove(row_index);
+        }
+        // bigger boards go further down, so the first row that fits is the right one
+        self.color_table.rows.sort();
+        ui.horizontal(|ui| {
+            if ui.button("Add size").clicked() {
+                let (cells, colors) = self.color_table.rows.last().copied().unwrap_or((25, 5));
+                self.color_table.rows.push((cells * 2, colors + 2));
+            }
+            ui.button("Reset")
+                .clicked()
+                .then(|| self.color_table = flow_generator::ColorTable::default());
+        });
+    }
+
+    /// picks the style Generate and Fill shape make puzzles in
+    fn generate_style_ui(&mut self, ui: &mut egui::Ui) {
+        let style = &mut self.generate_options.style;
+        egui::ComboBox::from_id_salt("generate_style")
+            .selected_text(style.name())
+            .show_ui(ui, |ui| {
+                for option in flow_generator::PuzzleStyle::ALL {
+                    ui.selectable_value(style, option, option.name());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Snake makes long winding pipes, spiral coils them up, and maze makes lots of \
+                short ones",
+            );
+        ui.checkbox(&mut self.generate_options.walls, "Walls")
+            .on_hover_text("Carve a maze of walls into the board for the pipes to go around");
+        ui.add(
+            egui::DragValue::new(&mut self.generate_options.bridges)
+                .range(0..=4)
+                .suffix(" bridges"),
+        )
+        .on_hover_text("Bridges two pipes have to cross at");
+    }
+
+    /// Replaces the board with a generated puzzle. If none could be made, the board is left alone.
+    fn open_generated(&mut self, grid: Option<Box<flow_grid::FlowGrid>>, new_puzzle: NewPuzzle) {
+        if let Some(blitz) = &mut self.blitz
+            && blitz.puzzle.is_none()
+            && blitz.code == new_puzzle.code
+        {
+            match &grid {
+                Some(grid) => blitz.puzzle = Some(grid.as_ref().clone()),
+                None => self.end_blitz(),
+            }
+        }
+        let Some(grid) = grid else {
+            self.solver_message = Some(if new_puzzle.code.is_empty() {
+                "Couldn't make a puzzle in this shape. Try again, or make the narrow parts wider."
+                    .to_string()
+            } else {
+                format!(
+                    "Couldn't make a puzzle from {}. Try another seed or a smaller board.",
+                    new_puzzle.code
+                )
+            });
+            return;
+        };
+        self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(*grid).sources_locked();
+        self.active_lesson = None;
+        self.daily_day = new_puzzle.daily_day;
+        self.browsed_level = None;
+        self.pack_level = None;
+        self.puzzle_code = new_puzzle.code;
+        self.solver_message = None;
+    }
+
+    /// starts a blitz run from a new seed, with its first puzzle
+    fn start_blitz(&mut self) {
+        self.blitz_results = None;
+        self.blitz = Some(Blitz {
+            run: flow_blitz::BlitzRun::new(flow_generator::new_seed()),
+            code: String::new(),
+            puzzle: None,
+        });
+        self.start_blitz_round();
+    }
+
+    /// starts generating the puzzle for the blitz run's round
+    fn start_blitz_round(&mut self) {
+        let Some(blitz) = &mut self.blitz else {
+            return;
+        };
+        let size = flow_blitz::BLITZ_SIZE;
+        let seed = blitz.run.round_seed();
+        blitz.code = blitz.run.round_code();
+        blitz.puzzle = None;
+        let options = flow_generator::GenerateOptions::default();
+        self.start_generating(size, size, seed, options, None);
+    }
+
+    /// ends the blitz run, if there is one, and shows how it went
+    fn end_blitz(&mut self) {
+        if let Some(blitz) = self.blitz.take() {
+            self.blitz_best_score = self.blitz_best_score.max(blitz.run.score);
+            self.blitz_results = Some(blitz.run);
+        }
+    }
+
+    /// Moves the blitz run on: to the next round once the puzzle is solved, or to the results
+    /// once time runs out. The solver's help or a different puzzle on the board ends the run too.
+    fn update_blitz(&mut self, ctx: &egui::Context) {
+        let Some(blitz) = &mut self.blitz else {
+            return;
+        };
+        let Some(puzzle) = &blitz.puzzle else {
+            // the round's puzzle was being generated, but something else took its place
+            if self.job.is_none() {
+                self.end_blitz();
+            }
+            return;
+        };
+        let seconds = self.flow_canvas.elapsed_seconds;
+        if !puzzle.has_same_puzzle(&self.flow_canvas.grid) || self.flow_canvas.is_assisted {
+            self.end_blitz();
+        } else if self.flow_canvas.grid.is_solved() {
+            blitz.run.solve(seconds);
+            self.sounds.play(flow_sound::Sound::Solve);
+            self.start_blitz_round();
+        } else if seconds >= blitz.run.time_limit() {
+            self.end_blitz();
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+    }
+
+    /// how a finished blitz run went, and a way to play another
+    fn blitz_results_ui(&mut self, ctx: &egui::Context) {
+        let Some(run) = &self.blitz_results else {
+            return;
+        };
+        let mut is_open = true;
+        let mut is_again_picked = false;
+        egui::Window::new("Blitz over")
+            .open(&mut is_open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.heading(format!("Score: {}", run.score));
+                if run.score > 0 && run.score == self.blitz_best_score {
+                    ui.label("★ New best");
+                } else {
+                    ui.label(format!("Best: {}", self.blitz_best_score));
+                }
+                ui.label(format!("Puzzles solved: {}", run.round()));
+                ui.label(format!("Best combo: {}", run.best_combo));
+                if let Some(average) = run.average_time() {
+                    ui.label(format!("Average time: {}", format_time(average)));
+                }
+                is_again_picked = ui.button("Play again").clicked();
+            });
+        if !is_open {
+            self.blitz_results = None;
+        }
+        if is_again_picked {
+            self.start_blitz();
+        }
+    }
+
+    /// the streak, and this month with the finished days ticked off
+    fn daily_calendar_ui(&self, ui: &mut egui::Ui) {
+        let today = flow_daily::today();
+        let date = flow_daily::Date::of(today);
+        ui.collapsing(
+            format!("🔥 {} day streak", self.daily_record.streak(today)),
+            |ui| {
+                ui.label(format!("{}-{:02}", date.year, date.month));
+                let first = today - (date.day - 1);
+                egui::Grid::new("daily_calendar").show(ui, |ui| {
+                    for name in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+                        ui.small(name);
+                    }
+                    ui.end_row();
+                    for _ in 0..flow_daily::weekday(first) {
+                        ui.label("");
+                    }
+                    let month_days =
+                        (first..).take_while(|&day| flow_daily::Date::of(day).month == date.month);
+                    for day in month_days {
+                        let mut text = if self.daily_record.is_completed(day) {
+                            egui::RichText::new("✔")
+                        } else {
+                            egui::RichText::new(flow_daily::Date::of(day).day.to_string())
+                        };
+                        if day == today {
+                            text = text.strong();
+                        } else if day > today {
+                            text = text.weak();
+                        }
+                        ui.label(text);
+                        if flow_daily::weekday(day) == 6 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            },
+        );
+    }
+
+    /// asks where to save the board, and writes it there as JSON
+    fn save_board(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Flow board", &["json"])
+            .set_file_name("board.json")
+            .save_file()
+        else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(&self.flow_canvas.grid)
+            .map_err(|error| error.to_string())
+            .and_then(|json| std::fs::write(&path, json).map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            self.solver_message = Some(format!("Couldn't save the board: {error}"));
+        }
+    }
+
+    /// asks where to save a bug report for the board, and writes it there
+    fn save_bug_report(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Bug report", &["json"])
+            .set_file_name("flow-bug-report.json")
+            .save_file()
+        else {
+            return;
+        };
+        let settings = flow_report::ReportSettings {
+            solver_timeout_seconds: self.solve_options.timeout.as_secs_f64(),
+            node_limit: self.solve_options.node_limit,
+            parallel_solve: self.solve_options.parallel,
+            solver_config: self.solve_options.config,
+            show_dead_ends: self.show_dead_ends,
+            auto_complete: self.auto_complete,
+            hide_sources: self.hide_sources,
+            can_edit_sources: self.flow_canvas.can_edit_sources,
+            lesson: self.active_lesson,
+            daily_day: self.daily_day,
+            puzzle_code: self.puzzle_code.clone(),
+        };
+        let result = flow_report::BugReport::new(&self.flow_canvas.grid, settings)
+            .to_json()
+            .map_err(|error| error.to_string())
+            .and_then(|json| std::fs::write(&path, json).map_err(|error| error.to_string()));
+        match result {
+            Ok(()) => self.show_bug_report = false,
+            Err(error) => {
+                self.solver_message = Some(format!("Couldn't save the bug report: {error}"))
+            }
+        }
+    }
+
+    /// the bundled packs, each with its levels ticked off as they're finished
+    fn level_browser_ui(&mut self, ctx: &egui::Context) {
+        let mut picked = None;
+        SidePanel::left("level_browser").show(ctx, |ui| {
+            ui.heading("Levels");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (pack_index, pack) in flow_pack::BUNDLED_PACKS.iter().enumerate() {
+                    let levels = &self.bundled_packs[pack_index];
+                    let completed = levels
+                        .iter()
+                        .filter(|level| self.level_progress.is_completed(pack.name, &level.name))
+                        .count();
+                    ui.collapsing(
+                        format!("{} ({completed}/{})", pack.name, levels.len()),
+                        |ui| {
+                            for (level_index, level) in levels.iter().enumerate() {
+                                let label = if self
+                                    .level_progress
+                                    .is_perfect(pack.name, &level.name)
+                                {
+                                    format!("✔ Level {} ★", level_index + 1)
+                                } else if self.level_progress.is_completed(pack.name, &level.name) {
+                                    format!("✔ Level {}", level_index + 1)
+                                } else {
+                                    format!("Level {}", level_index + 1)
+                                };
+                                ui.selectable_label(
+                                    self.browsed_level == Some((pack_index, level_index)),
+                                    label,
+                                )
+                                .on_hover_text(&level.name)
+                                .clicked()
+                                .then(|| picked = Some((pack_index, level_index)));
+                            }
+                        },
+                    );
+                }
+            });
+        });
+        if let Some((pack_index, level_index)) = picked {
+            self.open_bundled_level(pack_index, level_index);
+        }
+    }
+
+    fn open_bundled_level(&mut self, pack_index: usize, level_index: usize) {
+        if let Some(grid) = self.bundled_packs[pack_index][level_index].build_grid() {
+            self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(grid).sources_locked();
+            self.active_lesson = None;
+            self.daily_day = None;
+            self.pack_level = None;
+            self.solver_message = None;
+            self.browsed_level = Some((pack_index, level_index));
+        }
+    }
+
+    /// plays a level of the imported pack
+    fn open_pack_level(&mut self, level_index: usize) {
+        if let Some(grid) = self
+            .imported_pack
+            .as_ref()
+            .and_then(|pack| pack.get(level_index)?.build_grid())
+        {
+            self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(grid).sources_locked();
+            self.active_lesson = None;
+            self.daily_day = None;
+            self.browsed_level = None;
+            self.pack_level = Some(level_index);
+        }
+    }
+
+    /// the level after this one in the pack it came from, or else a new puzzle this size
+    fn next_puzzle(&mut self) {
+        if let Some((pack_index, level_index)) = self.browsed_level
+            && level_index + 1 < self.bundled_packs[pack_index].len()
+        {
+            self.open_bundled_level(pack_index, level_index + 1);
+        } else if let Some(level_index) = self.pack_level
+            && self
+                .imported_pack
+                .as_ref()
+                .is_some_and(|pack| level_index + 1 < pack.len())
+        {
+            self.open_pack_level(level_index + 1);
+        } else {
+            self.generate_puzzle(
+                self.flow_canvas.grid.width,
+                self.flow_canvas.grid.height,
+                flow_generator::new_seed(),
+            );
+        }
+    }
+
+    /// how many levels a pack zen mode can play has, by name, or 0 for one that isn't around
+    fn zen_pack_len(&self) -> impl Fn(&str) -> usize + '_ {
+        |name| {
+            if name == IMPORTED_PACK_NAME {
+                self.imported_pack.as_ref().map_or(0, Vec::len)
+            } else {
+                flow_pack::BUNDLED_PACKS
+                    .iter()
+                    .position(|pack| pack.name == name)
+                    .map_or(0, |pack_index| self.bundled_packs[pack_index].len())
+            }
+        }
+    }
+
+    /// the zen playlist's level to play next, or None once it's been played through
+    fn zen_level(&self) -> Option<(String, usize)> {
+        self.zen_playlist.as_ref()?.current(self.zen_pack_len())
+    }
+
+    /// whether the level is the one on the board
+    fn is_on_board(&self, (pack, level_index): &(String, usize)) -> bool {
+        if pack == IMPORTED_PACK_NAME {
+            self.pack_level == Some(*level_index)
+        } else {
+            self.browsed_level
+                .is_some_and(|(pack_index, browsed_index)| {
+                    flow_pack::BUNDLED_PACKS[pack_index].name == pack
+                        && browsed_index == *level_index
+                })
+        }
+    }
+
+    /// puts the zen playlist's level on the board, or leaves zen mode once it's played through
+    fn open_zen_level(&mut self) {
+        self.zen_solved_at = None;
+        let Some((pack, level_index)) = self.zen_level() else {
+            self.is_zen = false;
+            self.solver_message = Some("That's every level in the playlist.".to_string());
+            return;
+        };
+        self.is_zen = true;
+        if pack == IMPORTED_PACK_NAME {
+            self.open_pack_level(level_index);
+        } else if let Some(pack_index) = flow_pack::BUNDLED_PACKS
+            .iter()
+            .position(|bundled| bundled.name == pack)
+        {
+            self.open_bundled_level(pack_index, level_index);
+        }
+    }
+
+    /// Moves zen mode on to the next level once the one on the board has been celebrated. Zen
+    /// mode stops when another puzzle is opened, but the playlist keeps its place.
+    fn update_zen(&mut self, ctx: &egui::Context) {
+        if !self.is_zen {
+            return;
+        }
+        if !self
+            .zen_level()
+            .is_some_and(|level| self.is_on_board(&level))
+        {
+            self.is_zen = false;
+            self.zen_solved_at = None;
+            return;
+        }
+        let now = ctx.input(|input| input.time);
+        match self.zen_solved_at {
+            Some(solved_at) if now - solved_at >= ZEN_CELEBRATION_SECONDS => {
+                if let Some(playlist) = &mut self.zen_playlist {
+                    playlist.position += 1;
+                }
+                self.open_zen_level();
+            }
+            Some(_) => ctx.request_repaint_after(std::time::Duration::from_millis(50)),
+            None if self.flow_canvas.grid.is_solved() => {
+                self.zen_solved_at = Some(now);
+                ctx.request_repaint_after(std::time::Duration::from_millis(50));
+            }
+            None => {}
+        }
+    }
+
+    /// a moment to enjoy a solved zen level before the next one
+    fn zen_celebration_ui(&self, ctx: &egui::Context) {
+        if self.zen_solved_at.is_none() {
+            return;
+        }
+        egui::Area::new(egui::Id::new("zen_celebration"))
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.heading("✨ Solved ✨");
+                    if self.is_perfect() {
+                        ui.label("★ Perfect");
+                    }
+                });
+            });
+    }
+
+    /// picks the packs for zen mode and how they're ordered, or picks up the saved playlist
+    fn zen_setup_ui(&mut self, ctx: &egui::Context) {
+        if !self.show_zen_setup {
+            return;
+        }
+        let mut is_open = true;
+        let mut is_start_picked = false;
+        let mut is_resume_picked = false;
+        egui::Window::new("Zen")
+            .open(&mut is_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Levels one after another, with no clock.");
+                let mut names: Vec<&str> = flow_pack::BUNDLED_PACKS
+                    .iter()
+                    .map(|pack| pack.name)
+                    .collect();
+                if self.imported_pack.is_some() {
+                    names.push(IMPORTED_PACK_NAME);
+                }
+                for name in names {
+                    let mut is_picked = self.zen_picked_packs.contains(name);
+                    if ui.checkbox(&mut is_picked, name).changed() {
+                        if is_picked {
+                            self.zen_picked_packs.insert(name.to_string());
+                        } else {
+                            self.zen_picked_packs.remove(name);
+                        }
+                    }
+                }
+                ui.checkbox(&mut self.zen_shuffle, "Shuffle");
+                ui.horizontal(|ui| {
+                    is_start_picked = ui
+                        .add_enabled(
+                            !self.zen_picked_packs.is_empty(),
+                            egui::Button::new("Start"),
+                        )
+                        .on_hover_text("Start a new playlist from the first level")
+                        .on_disabled_hover_text("Pick a pack to play")
+                        .clicked();
+                    if let Some(playlist) = &self.zen_playlist {
+                        let pack_len = self.zen_pack_len();
+                        let total = playlist.levels(&pack_len).len();
+                        let can_resume = playlist.position < total
+                            && playlist.packs.iter().all(|pack| pack_len(pack) > 0);
+                        is_resume_picked = ui
+                            .add_enabled(
+                                can_resume,
+                                egui::Button::new(format!(
+                                    "Resume ({} of {total})",
+                                    playlist.position + 1
+                                )),
+                            )
+                            .on_hover_text("Pick up the saved playlist where it was left")
+                            .on_disabled_hover_text(
+                                "The saved playlist is finished, or its pack isn't open",
+                            )
+                            .clicked();
+                    }
+                });
+            });
+        self.show_zen_setup &= is_open && !is_start_picked && !is_resume_picked;
+        if is_start_picked {
+            // the packs in the order they're listed, not the order they were ticked
+            let packs: Vec<String> = flow_pack::BUNDLED_PACKS
+                .iter()
+                .map(|pack| pack.name.to_string())
+                .chain([IMPORTED_PACK_NAME.to_string()])
+                .filter(|name| self.zen_picked_packs.contains(name))
+                .collect();
+            let shuffle_seed = self.zen_shuffle.then(flow_generator::new_seed);
+            self.zen_playlist = Some(flow_zen::ZenPlaylist::new(packs, shuffle_seed));
+        }
+        if is_start_picked || is_resume_picked {
+            self.open_zen_level();
+        }
+    }
+
+    /// Picks the color new sources are placed in, so a puzzle can be copied in any order. Colors
+    /// that already have both sources are ticked.
+    fn source_color_ui(&mut self, ui: &mut egui::Ui) {
+        let next_color = self.flow_canvas.grid.next_color();
+        let selected_text = match self.flow_canvas.source_color {
+            Some(color_id) => self.palette.name(color_id),
+            None => format!("Next free ({})", self.palette.name(next_color)),
+        };
+        egui::ComboBox::from_label("Source color")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut self.flow_canvas.source_color,
+                    None,
+                    format!("0: Next free ({})", self.palette.name(next_color)),
+                );
+                for (color_id, color) in self.palette.colors.iter().enumerate() {
+                    let mark = if self.flow_canvas.grid.get_sources(color_id).len() >= 2 {
+                        " ✔"
+                    } else {
+                        ""
+                    };
+                    ui.selectable_value(
+                        &mut self.flow_canvas.source_color,
+                        Some(color_id),
+                        egui::RichText::new(format!("{}: {}{mark}", color_id + 1, color.name))
+                            .color(Color32::from_rgb(color.rgb[0], color.rgb[1], color.rgb[2])),
+                    );
+                }
+            })
+            .response
+            .on_hover_text("Press 1 to 9 to pick a color, or 0 for the next free one");
+    }
+
+    /// Whether the board is solved in no more strokes than it has colors. A board the solver
+    /// had a hand in doesn't count, since its pipes aren't all the player's strokes.
+    fn is_perfect(&self) -> bool {
+        self.flow_canvas.grid.is_solved()
+            && !self.flow_canvas.is_assisted
+            && (1..=self.flow_canvas.grid.min_strokes()).contains(&self.flow_canvas.strokes)
+    }
+
+    /// how the puzzle went, once it's solved, and a way on to the next one
+    fn win_overlay_ui(&mut self, ctx: &egui::Context) {
+        if !self.show_win_overlay {
+            return;
+        }
+        let mut is_open = true;
+        let mut is_next_picked = false;
+        let mut is_compare_picked = false;
+        egui::Window::new("Solved!")
+            .open(&mut is_open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Moves: {}, Time: {}",
+                    self.flow_canvas.strokes,
+                    format_time(self.flow_canvas.elapsed_seconds)
+                ));
+                if self.is_perfect() {
+                    ui.label("★ Perfect: one stroke per color");
+                } else if self.flow_canvas.is_assisted {
+                    ui.label("Solved with the solver's help");
+                } else {
+                    ui.label(format!(
+                        "A perfect solve takes {} moves",
+                        self.flow_canvas.grid.min_strokes()
+                    ));
+                }
+                ui.horizontal(|ui| {
+                    is_next_picked = ui.button("Next puzzle").clicked();
+                    is_compare_picked = ui
+                        .button("Compare")
+                        .on_hover_text("See where the solver routes the pipes differently")
+                        .clicked();
+                });
+            });
+        self.show_win_overlay &= is_open && !is_next_picked && !is_compare_picked;
+        if is_compare_picked {
+            self.comparison = Some(Comparison::new(&self.flow_canvas.grid, &self.solve_options));
+        }
+        if is_next_picked {
+            self.next_puzzle();
+        }
+    }
+
+    /// says what goes in a bug report before saving one
+    fn bug_report_ui(&mut self, ctx: &egui::Context) {
+        if !self.show_bug_report {
+            return;
+        }
+        let mut is_open = true;
+        egui::Window::new("Report a bug")
+            .open(&mut is_open)
+            .show(ctx, |ui| {
+                ui.set_max_width(320.0);
+                ui.label(flow_report::PRIVACY_SUMMARY);
+                ui.button("Save report")
+                    .on_hover_text("Pick where to save it, then attach it to a GitHub issue")
+                    .clicked()
+                    .then(|| self.save_bug_report());
+            });
+        self.show_bug_report &= is_open;
+    }
+
+    /// Reports how the board differs from the solver's solution, and outlines the cells where it
+    /// does. The solution is drawn faintly under the board, or next to it. Putting a different
+    /// puzzle on the board ends the comparison.
+    fn comparison_ui(&mut self, ctx: &egui::Context) {
+        let grid = &self.flow_canvas.grid;
+        let Some(comparison) = &mut self.comparison else {
+            self.flow_canvas.compare_to = None;
+            return;
+        };
+        if let Some(solve) = &mut comparison.solve {
+            match solve.poll() {
+                Some((outcome, _)) => {
+                    match outcome {
+                        flow_solver::SolveOutcome::Solved(solution) => {
+                            comparison.reference = Some(
+                                flow_canvas::FlowBoardWidget::with_grid(solution)
+                                    .read_only()
+                                    .with_style(self.flow_canvas.style.clone()),
+                            );
+                        }
+                        flow_solver::SolveOutcome::Unsupported => comparison.is_unsupported = true,
+                        flow_solver::SolveOutcome::Unsolvable
+                        | flow_solver::SolveOutcome::TimedOut => {}
+                    }
+                    comparison.solve = None;
+                }
+                None => ctx.request_repaint_after(std::time::Duration::from_millis(100)),
+            }
+        }
+        if comparison
+            .reference
+            .as_ref()
+            .is_some_and(|reference| !reference.grid.has_same_puzzle(grid))
+        {
+            self.comparison = None;
+            self.flow_canvas.compare_to = None;
+            return;
+        }
+
+        let mut is_open = true;
+        egui::Window::new("Compare with the solver")
+            .open(&mut is_open)
+            .show(ctx, |ui| match &mut comparison.reference {
+                None if comparison.solve.is_some() => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Finding a solution…");
+                    });
+                }
+                None if comparison.is_unsupported => {
+                    ui.label(NO_BRIDGES_TEXT);
+                }
+                None => {
+                    ui.label("The solver couldn't find a solution to compare with.");
+                }
+                Some(reference) => {
+                    let num_different = flow_solver::differing_cells(grid, &reference.grid).len();
+                    ui.label(if num_different == 0 {
+                        "You routed every cell the way the solver did.".to_string()
+                    } else {
+                        format!("{num_different} cells are routed differently, outlined.")
+                    });
+                    let (length, reference_length) =
+                        (grid.pipe_length(), reference.grid.pipe_length());
+                    ui.label(match length.cmp(&reference_length) {
+                        std::cmp::Ordering::Greater => format!(
+                            "Your pipes are {} longer: {length} against {reference_length}.",
+                            length - reference_length
+                        ),
+                        std::cmp::Ordering::Less => format!(
+                            "Your pipes are {} shorter: {length} against {reference_length}.",
+                            reference_length - length
+                        ),
+                        std::cmp::Ordering::Equal => {
+                            format!("Your pipes are just as long, at {length}.")
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut comparison.is_side_by_side, false, "Overlay")
+                            .on_hover_text("Draw the solver's pipes faintly under yours");
+                        ui.selectable_value(&mut comparison.is_side_by_side, true, "Side by side");
+                    });
+                    if comparison.is_side_by_side {
+                        reference.compare_to = Some(grid.clone());
+                        ui.add(reference);
+                    }
+                }
+            });
+        match &self.comparison {
+            Some(comparison) if is_open => {
+                self.flow_canvas.show_compared_pipes = !comparison.is_side_by_side;
+                if let Some(reference) = &comparison.reference
+                    && self.flow_canvas.compare_to.is_none()
+                {
+                    self.flow_canvas.compare_to = Some(reference.grid.clone());
+                }
+            }
+            _ => {
+                self.comparison = None;
+                self.flow_canvas.compare_to = None;
+            }
+        }
+    }
+
+    /// Edits the palette: each color's name and RGB, its place in the order, and which colors
+    /// there are at all. Colors are given out in this order, so moving one changes which pipes
+    /// are drawn in it.
+    fn palette_editor_ui(&mut self, ctx: &egui::Context) {
+        if !self.show_palette_editor {
+            return;
+        }
+        let mut is_open = true;
+        egui::Window::new("Colors")
+            .open(&mut is_open)
+            .show(ctx, |ui| {
+                let num_colors = self.palette.colors.len();
+                let mut moved_up = None;
+                let mut removed = None;
+                egui::Grid::new("palette_editor").show(ui, |ui| {
+                    for (color_id, color) in self.palette.colors.iter_mut().enumerate() {
+                        ui.label(format!("{}", color_id + 1));
+                        ui.color_edit_button_srgb(&mut color.rgb);
+                        ui.add(egui::TextEdit::singleline(&mut color.name).desired_width(100.0));
+                        ui.add_enabled(color_id > 0, egui::Button::new("⬆"))
+                            .on_hover_text("Give this color out sooner")
+                            .clicked()
+                            .then(|| moved_up = Some(color_id));
+                        ui.add_enabled(color_id + 1 < num_colors, egui::Button::new("⬇"))
+                            .on_hover_text("Give this color out later")
+                            .clicked()
+                            .then(|| moved_up = Some(color_id + 1));
+                        ui.add_enabled(num_colors > 1, egui::Button::new("🗑"))
+                            .on_hover_text("Remove this color")
+                            .clicked()
+                            .then(|| removed = Some(color_id));
+                        ui.end_row();
+                    }
+                });
+                if let Some(color_id) = moved_up {
+                    self.palette.colors.swap(color_id - 1, color_id);
+                }
+                if let Some(color_id) = removed {
+                    self.palette.colors.remove(color_id);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Add color").clicked() {
+                        self.palette.colors.push(flow_palette::PaletteColor {
+                            name: format!("Color {}", num_colors + 1),
+                            rgb: [128, 128, 128],
+                        });
+                    }
+                    if ui
+                        .button("Reset")
+                        .on_hover_text("Go back to the usual colors")
+                        .clicked()
+                    {
+                        self.palette = flow_palette::Palette::default();
+                    }
+                });
+            });
+        self.show_palette_editor &= is_open;
+    }
+
+    /// asks where to put a picture of the board, and writes it there as a PNG
+    fn export_image(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .set_file_name("board.png")
+            .save_file()
+        else {
+            return;
+        };
+        let result = flow_image::board_to_png(
+            &self.flow_canvas.grid,
+            &self.flow_canvas.style,
+            self.export_cell_size,
+        )
+        .map_err(|error| error.to_string())
+        .and_then(|png| std::fs::write(&path, png).map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            self.solver_message = Some(format!("Couldn't export the image: {error}"));
+        }
+    }
+
+    /// asks for a board saved by save_board, and replaces this one with it
+    fn load_board(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Flow board", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        let result = std::fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|error| error.to_string()));
+        match result {
+            Ok(grid) => {
+                self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(grid);
+                self.active_lesson = None;
+                self.daily_day = None;
+                self.browsed_level = None;
+                self.solver_message = None;
+            }
+            Err(error) => {
+                self.solver_message = Some(format!("Couldn't load {}: {error}", path.display()))
+            }
+        }
+    }
+
+    /// asks for a text file drawing a board shape, and starts an empty board in that shape
+    fn load_shape(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Board shape", &["txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        let grid = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| flow_grid::FlowGrid::parse_mask(&text))
+            .and_then(|mask| flow_grid::FlowGrid::from_mask(&mask));
+        match grid {
+            Some(grid) => {
+                self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(grid);
+                self.active_lesson = None;
+                self.daily_day = None;
+                self.browsed_level = None;
+                self.solver_message = None;
+            }
+            None => {
+                self.solver_message = Some(format!(
+                    "Couldn't read a board shape from {}",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    /// Starts generating a puzzle over the cells of this board that aren't blocked. A puzzle
+    /// code can't describe the shape, so the code is cleared.
+    fn generate_in_shape(&mut self) {
+        let seed = flow_generator::new_seed();
+        let options = self.options_for(self.flow_canvas.grid.width, self.flow_canvas.grid.height);
+        self.start_job("Generating", move |grid| {
+            let new_puzzle = NewPuzzle {
+                code: String::new(),
+                daily_day: None,
+            };
+            let (grid, stats) = flow_generator::generate_with_stats(&grid.mask(), seed, &options);
+            JobResult::Generated(grid.map(Box::new), new_puzzle, stats)
+        });
+    }
+
+    /// asks for a Flow Free level pack and lists its levels
+    fn import_pack(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Flow Free level pack", &["txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => match flow_pack::parse_flow_free(&text) {
+                Some(pack) => {
+                    self.imported_pack = Some(pack);
+                    self.pack_level = None;
+                }
+                None => {
+                    self.solver_message =
+                        Some(format!("{} isn't a Flow Free level pack.", path.display()))
+                }
+            },
+            Err(error) => {
+                self.solver_message = Some(format!("Couldn't read {}: {error}", path.display()))
+            }
+        }
+    }
+
+    /// asks for a screenshot of a board the size of this one, and replaces this one with its
+    /// sources
+    fn import_screenshot(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .pick_file()
+        else {
+            return;
+        };
+        let (width, height) = (self.flow_canvas.grid.width, self.flow_canvas.grid.height);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                self.solver_message = Some(format!("Couldn't read {}: {error}", path.display()));
+                return;
+            }
+        };
+        match flow_screenshot::Screenshot::from_png(&bytes).and_then(|screenshot| {
+            flow_screenshot::grid_from_screenshot(&screenshot, width, height)
+        }) {
+            Some(grid) => {
+                self.flow_canvas = flow_canvas::FlowBoardWidget::with_grid(grid);
+                self.active_lesson = None;
+                self.daily_day = None;
+                self.browsed_level = None;
+                self.solver_message = None;
+            }
+            None => {
+                self.solver_message = Some(format!(
+                    "Couldn't find a {width}x{height} puzzle in {}. Crop it to the board, on a plain background.",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    /// Starts working something out from the board in the background, in place of any job
+    /// already running.
+    fn start_job(
+        &mut self,
+        activity: &'static str,
+        work: impl FnOnce(flow_grid::FlowGrid) -> JobResult + Send + 'static,
+    ) {
+        let grid = self.flow_canvas.grid.clone();
+        self.job = Some(Job {
+            activity,
+            puzzle: grid.clone(),
+            task: flow_solver::run_in_background(move || work(grid)),
+        });
+        self.solver_message = None;
+    }
+
+    /// checks on the running job, and puts its answer on the board once it's done
+    fn update_job(&mut self, ctx: &egui::Context) {
+        let Some(job) = &mut self.job else {
+            return;
+        };
+        let Some(result) = job.task.poll() else {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            return;
+        };
+        let job = self.job.take().expect("the job was just polled");
+        match result {
+            // a new puzzle replaces the board whatever is on it
+            JobResult::Generated(grid, new_puzzle, stats) => {
+                self.generate_stats = Some(stats);
+                self.open_generated(grid, new_puzzle);
+            }
+            // everything else was worked out for the puzzle the job started from
+            _ if !job.puzzle.has_same_puzzle(&self.flow_canvas.grid) => {}
+            JobResult::Hint(hint) => {
+                if hint.is_some_and(|hint| {
+                    self.flow_canvas
+                        .grid
+                        .try_connect(hint.row, hint.col, hint.direction)
+                }) {
+                    self.flow_canvas.is_assisted = true;
+                } else {
+                    self.solver_message = Some("There's no hint that fits your pipes.".to_string());
+                }
+            }
+            JobResult::Rating(difficulty) => {
+                self.solver_message = Some(match difficulty {
+                    Some(difficulty) => format!(
+                        "{:?}: {} forced moves, {} guesses ({} solver moves)",
+                        difficulty.rating,
+                        difficulty.forced_moves,
+                        difficulty.guesses,
+                        difficulty.nodes_explored
+                    ),
+                    None => "Couldn't find a solution to rate.".to_string(),
+                });
+            }
+            JobResult::Count(count) => {
+                self.solver_message = Some(match count {
+                    1 => "Exactly 1 solution".to_string(),
+                    count => format!("Exactly {count} solutions"),
+                });
+            }
+            JobResult::Repairs(solutions, repairs) => self.open_repair_wizard(solutions, repairs),
+            JobResult::Diverse(found) => {
+                let mut browser = SolutionBrowser::with_solutions(found);
+                if let Some(solution) = browser.step_forward() {
+                    self.flow_canvas.grid = solution.clone();
+                    self.flow_canvas.is_assisted = true;
+                }
+                self.solution_browser = Some(browser);
+            }
+            JobResult::Lengths(outcome) => match outcome {
+                flow_solver::SolveOutcome::Solved(solution) => {
+                    self.flow_canvas
+                        .grid
+                        .set_win_condition(flow_rules::ExactLengths::from_solution(&solution));
+                }
+                flow_solver::SolveOutcome::Unsolvable => {
+                    self.solver_message = Some("No solution to take the lengths from".to_string());
+                }
+                flow_solver::SolveOutcome::TimedOut => {
+                    self.solver_message =
+                        Some("Gave up finding a solution to take the lengths from".to_string());
+                }
+                flow_solver::SolveOutcome::Unsupported => {
+                    self.solver_message = Some(NO_BRIDGES_TEXT.to_string());
+                }
+            },
+            JobResult::NextSolution(solutions, outcome) => {
+                let Some(browser) = &mut self.solution_browser else {
+                    return;
+                };
+                browser.solutions = Some(*solutions);
+                match outcome {
+                    flow_solver::SolveOutcome::Solved(solution) => {
+                        browser.found.push(solution);
+                        if let Some(solution) = browser.step_forward() {
+                            self.flow_canvas.grid = solution.clone();
+                            self.flow_canvas.is_assisted = true;
+                        }
+                    }
+                    flow_solver::SolveOutcome::Unsolvable => browser.is_exhausted = true,
+                    flow_solver::SolveOutcome::TimedOut => {
+                        self.solver_message =
+                            Some("Ran out of time looking for another solution.".to_string());
+                    }
+                    flow_solver::SolveOutcome::Unsupported => {
+                        self.solver_message = Some(NO_BRIDGES_TEXT.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Carries on the browser's search for another solution in the background. If a job that
+    /// had the search was dropped, the search starts over and skips the solutions already found.
+    fn start_next_solution(&mut self) {
+        let Some(browser) = &mut self.solution_browser else {
+            return;
+        };
+        let (solutions, skip) = match browser.solutions.take() {
+            Some(solutions) => (Some(solutions), 0),
+            None => (None, browser.found.len()),
+        };
+        let options = self.solve_options.clone();
+        self.start_job("Looking for another solution", move |grid| {
+            let start = web_time::Instant::now();
+            let mut solutions = solutions.unwrap_or_else(|| flow_solver::solutions(&grid));
+            let mut outcome = solutions.next_with_options(&options);
+            for _ in 0..skip {
+                if !matches!(outcome, flow_solver::SolveOutcome::Solved(_)) {
+                    break;
+                }
+                outcome = solutions.next_with_options(&flow_solver::SolveOptions {
+                    timeout: options.timeout.saturating_sub(start.elapsed()),
+                    ..options.clone()
+                });
+            }
+            JobResult::NextSolution(Box::new(solutions), outcome)
+        });
+    }
+
+    /// counts the solutions and looks for source moves that narrow them down
+    fn start_repair(&mut self) {
+        self.start_job("Looking for source moves", |grid| {
+            let solutions = flow_count::count_solutions_exact(&grid);
+            let repairs = if solutions > 1 {
+                flow_repair::propose_repairs(&grid)
+            } else {
+                Vec::new()
+            };
+            JobResult::Repairs(solutions, repairs)
+        });
+    }
+
+    /// opens the repair wizard, or says why there's nothing to repair
+    fn open_repair_wizard(&mut self, solutions: u128, repairs: Vec<flow_repair::Repair>) {
+        match solutions {
+            0 => {
+                self.solver_message = Some("There's no solution to narrow down.".to_string());
+                return;
+            }
+            1 => {
+                self.solver_message = Some("This puzzle already has one solution.".to_string());
+                return;
+            }
+            _ => {}
+        }
+        if repairs.is_empty() {
+            self.solver_message =
+                Some("No single source move or wall keeps it solvable.".to_string());
+            return;
+        }
+        self.repair_wizard = Some(RepairWizard {
+            original: self.flow_canvas.grid.clone(),
+            solutions,
+            repairs,
+            selected: None,
+        });
+    }
+
+    fn repair_wizard_ui(&mut self, ctx: &egui::Context) {
+        let Some(wizard) = &mut self.repair_wizard else {
+            return;
+        };
+        let mut is_done = false;
+        egui::Window::new("Make it unique").show(ctx, |ui| {
+            ui.label(format!(
+                "This puzzle has {} solutions. Pick a source move or wall to preview it.",
+                wizard.solutions
+            ));
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for (index, repair) in wizard.repairs.iter().enumerate() {
+                        if ui
+                            .selectable_label(
+                                wizard.selected == Some(index),
+                                RepairWizard::describe(repair, &self.palette),
+                            )
+                            .clicked()
+                        {
+                            wizard.selected = Some(index);
+                            self.flow_canvas.grid = repair.grid.clone();
+                            self.flow_canvas.highlighted_cell = Some(repair.cell());
+                        }
+                    }
+                });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(wizard.selected.is_some(), egui::Button::new("Apply"))
+                    .clicked()
+                {
+                    is_done = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    self.flow_canvas.grid = wizard.original.clone();
+                    is_done = true;
+                }
+            });
+        });
+        if is_done {
+            self.flow_canvas.highlighted_cell = None;
+            self.repair_wizard = None;
+        }
+    }
+
+    /// puts back the board from before the last change, unless a solve is being played back
+    fn undo(&mut self) {
+        if self.solve_animation.is_none()
+            && let Some(grid) = self.undo_history.undo()
+        {
+            self.flow_canvas.grid = grid;
+            self.solver_message = None;
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.solve_animation.is_none()
+            && let Some(grid) = self.undo_history.redo()
+        {
+            self.flow_canvas.grid = grid;
+            self.solver_message = None;
+        }
+    }
+
+    /// an empty board of this size, of squares or of hexagons, and with edges or wrapping around
+    fn new_board(&mut self, width: usize, height: usize, is_hex: bool, wraps: bool) {
+        self.active_lesson = None;
+        self.daily_day = None;
+        self.browsed_level = None;
+        self.pack_level = None;
+        let grid = if is_hex {
+            flow_grid::FlowGrid::hex_with_size(width, height)
+        } else {
+            flow_grid::FlowGrid::with_size(width, height)
+        };
+        self.flow_canvas =
+            flow_canvas::FlowBoardWidget::with_grid(if wraps { grid.wrapping() } else { grid });
+    }
+
+    /// trades the board on the canvas, and what goes along with it, for the one kept in a tab
+    fn swap_tab(&mut self, tab_index: usize) {
+        let tab = &mut self.tabs[tab_index];
+        std::mem::swap(&mut self.flow_canvas, &mut tab.canvas);
+        std::mem::swap(&mut self.undo_history, &mut tab.undo_history);
+        std::mem::swap(&mut self.active_lesson, &mut tab.active_lesson);
+        std::mem::swap(&mut self.daily_day, &mut tab.daily_day);
+        std::mem::swap(&mut self.browsed_level, &mut tab.browsed_level);
+        std::mem::swap(&mut self.pack_level, &mut tab.pack_level);
+    }
+
+    /// Keeps the ghost solution in step with the board. It's worked out again in the background
+    /// whenever the sources change, and carried over to the canvas, which loses it when it's
+    /// rebuilt.
+    fn update_ghost(&mut self, ctx: &egui::Context) {
+        let grid = &self.flow_canvas.grid;
+        if !self.show_ghost
+            || !self
+                .ghost_puzzle
+                .as_ref()
+                .is_some_and(|puzzle| puzzle.has_same_puzzle(grid))
+        {
+            self.ghost_puzzle = None;
+            self.ghost_solve = None;
+            self.ghost = None;
+            self.flow_canvas.ghost = None;
+        }
+        if self.show_ghost && self.ghost_puzzle.is_none() {
+            self.ghost_solve = Some(flow_solver::solve_in_background(
+                grid,
+                flow_solver::SolveOptions {
+                    keep_pipes: false,
+                    colors: None,
+                    ..self.solve_options.clone()
+                },
+            ));
+            self.ghost_puzzle = Some(grid.clone());
+        }
+        if let Some(ghost_solve) = &mut self.ghost_solve {
+            match ghost_solve.poll() {
+                Some((outcome, _)) => {
+                    match outcome {
+                        flow_solver::SolveOutcome::Solved(solution) => {
+                            self.ghost = Some(solution.clone());
+                            self.flow_canvas.ghost = Some(solution);
+                        }
+                        // no ghost then, and the puzzle isn't tried again until it changes
+                        flow_solver::SolveOutcome::Unsolvable
+                        | flow_solver::SolveOutcome::TimedOut
+                        | flow_solver::SolveOutcome::Unsupported => {}
+                    }
+                    self.ghost_solve = None;
+                }
+                None => ctx.request_repaint_after(std::time::Duration::from_millis(100)),
+            }
+        }
+        if self.flow_canvas.ghost.is_none() {
+            self.flow_canvas.ghost = self.ghost.clone();
+        }
+    }
+
+    /// Puts another tab's board on the canvas. A solve still running is stopped, since its
+    /// answer would land on the wrong board.
+    fn select_tab(&mut self, tab_index: usize) {
+        if tab_index == self.active_tab {
+            return;
+        }
+        self.swap_tab(self.active_tab);
+        self.swap_tab(tab_index);
+        self.active_tab = tab_index;
+        self.background_solve = None;
+        self.job = None;
+        self.solution_browser = None;
+        self.comparison = None;
+        self.repair_wizard = None;
+        self.solver_message = None;
+        self.solve_stats = None;
+        self.show_win_overlay = false;
+        self.was_solved = self.flow_canvas.grid.is_solved();
+    }
+
+    /// opens an empty board the size of this one in a new tab
+    fn add_tab(&mut self) {
+        let name = (1..)
+            .map(|number| format!("Board {number}"))
+            .find(|name| self.tabs.iter().all(|tab| &tab.name != name))
+            .expect("there are always unused numbers");
+        self.tabs.push(BoardTab::new(
+            name,
+            self.flow_canvas.grid.width,
+            self.flow_canvas.grid.height,
+        ));
+        self.select_tab(self.tabs.len() - 1);
+    }
+
+    /// closes a tab, moving to its neighbor if it was on the canvas. The last tab stays open.
+    fn close_tab(&mut self, tab_index: usize) {
+        if self.tabs.len() <= 1 {
+            return;
+        }
+        if tab_index == self.active_tab {
+            self.select_tab(if tab_index + 1 < self.tabs.len() {
+                tab_index + 1
+            } else {
+                tab_index - 1
+            });
+        }
+        self.tabs.remove(tab_index);
+        if self.active_tab > tab_index {
+            self.active_tab -= 1;
+        }
+    }
+
+    /// A tab for each open board, with + to open another. Right-clicking a tab renames it. Tabs
+    /// can't be changed while the solver is playing back, which is partway through the board.
+    fn tabs_ui(&mut self, ui: &mut egui::Ui) {
+        let mut selected = None;
+        let mut closed = None;
+        let num_tabs = self.tabs.len();
+        ui.add_enabled_ui(self.solve_animation.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                for (tab_index, tab) in self.tabs.iter_mut().enumerate() {
+                    let response = ui
+                        .selectable_label(tab_index == self.active_tab, tab.name.as_str())
+                        .on_hover_text("Right-click to rename");
+                    if response.clicked() {
+                        selected = Some(tab_index);
+                    }
+                    response.context_menu(|ui| {
+                        ui.text_edit_singleline(&mut tab.name);
+                    });
+                    if num_tabs > 1
+                        && ui
+                            .small_button("×")
+                            .on_hover_text("Close this board")
+                            .clicked()
+                    {
+                        closed = Some(tab_index);
+                    }
+                }
+                if ui
+                    .button("+")
+                    .on_hover_text("Open another board in a new tab")
+                    .clicked()
+                {
+                    self.add_tab();
+                }
+            });
+        });
+        if let Some(tab_index) = selected {
+            self.select_tab(tab_index);
+        }
+        if let Some(tab_index) = closed {
+            self.close_tab(tab_index);
+        }
+    }
+
+    fn leave_lesson(&mut self) {
+        self.flow_canvas = flow_canvas::FlowBoardWidget::with_size(
+            self.flow_canvas.grid.width,
+            self.flow_canvas.grid.height,
+        );
+        self.active_lesson = None;
+    }
 }
 
 impl App for FlowSolverApp {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(animation) = &mut self.solve_animation {
+            animation.advance(ctx.input(|input| input.stable_dt));
+            if let Some((_, grid)) = animation.current() {
+                self.flow_canvas.grid = grid.clone();
+            }
+            self.flow_canvas.highlighted_cell = animation.changed_cell();
+            if !animation.is_paused {
+                ctx.request_repaint();
+            }
+        }
+        if let Some(background_solve) = &mut self.background_solve {
+            match background_solve.poll() {
+                Some((outcome, stats)) => {
+                    match outcome {
+                        flow_solver::SolveOutcome::Solved(solution) => {
+                            self.flow_canvas.grid = solution;
+                            self.flow_canvas.is_assisted = true;
+                            // the solver's win isn't the player's
+                            self.was_solved = true;
+                        }
+                        flow_solver::SolveOutcome::Unsolvable => {
+                            self.solver_message = Some(if self.solve_options.colors.is_some() {
+                                "Those colors can't all be routed.".to_string()
+                            } else if self.solve_options.keep_pipes {
+                                "Your pipes can't be finished into a solution.".to_string()
+                            } else {
+                                "There's no solution for these sources.".to_string()
+                            });
+                        }
+                        flow_solver::SolveOutcome::TimedOut => {
+                            self.solver_message =
+                                Some("Gave up before finding a solution.".to_string());
+                        }
+                        flow_solver::SolveOutcome::Unsupported => {
+                            self.solver_message = Some(NO_BRIDGES_TEXT.to_string());
+                        }
+                    }
+                    self.solve_stats = Some(stats);
+                    self.background_solve = None;
+                }
+                None => ctx.request_repaint_after(std::time::Duration::from_millis(100)),
+            }
+        }
+        self.update_job(ctx);
+        self.update_ghost(ctx);
+        self.update_blitz(ctx);
+        self.update_zen(ctx);
+        // a drag is recorded once it's let go, and an animation once it's done, so each comes
+        // back in one step
+        if self.solve_animation.is_none() && !ctx.input(|input| input.pointer.any_down()) {
+            self.undo_history.record(&self.flow_canvas.grid);
+        }
+        // only puzzles being played, not ones being built or lessons, and not the solver's
+        // playback
+        let is_solved = self.flow_canvas.grid.is_solved();
+        if is_solved
+            && !self.was_solved
+            && !self.flow_canvas.can_edit_sources
+            && self.active_lesson.is_none()
+            && self.solve_animation.is_none()
+        {
+            // blitz and zen move straight on to their next puzzle instead
+            self.show_win_overlay = self.blitz.is_none() && !self.is_zen;
+            self.sounds.play(flow_sound::Sound::Solve);
+            self.sounds.catch_up(&self.flow_canvas.grid);
+            // the streak and level progress are for the player's own solves
+            if !self.flow_canvas.is_assisted {
+                if let Some(day) = self.daily_day {
+                    self.daily_record.complete(day);
+                }
+                if let Some((pack_index, level_index)) = self.browsed_level {
+                    self.level_progress.complete(
+                        flow_pack::BUNDLED_PACKS[pack_index].name,
+                        &self.bundled_packs[pack_index][level_index].name,
+                        self.is_perfect(),
+                    );
+                }
+            }
+        } else if self.solve_animation.is_some() {
+            self.sounds.catch_up(&self.flow_canvas.grid);
+        } else {
+            self.sounds.follow(&self.flow_canvas.grid);
+        }
+        self.show_win_overlay &= is_solved;
+        self.was_solved = is_solved;
+        if !self.is_viewing && !ctx.wants_keyboard_input() {
+            let shortcut_pressed = |key| {
+                ctx.input_mut(|input| {
+                    input.consume_shortcut(&egui::KeyboardShortcut::new(
+                        egui::Modifiers::COMMAND,
+                        key,
+                    ))
+                })
+            };
+            if shortcut_pressed(egui::Key::Z) {
+                self.undo();
+            } else if shortcut_pressed(egui::Key::Y) {
+                self.redo();
+            }
+            if self.flow_canvas.can_edit_sources {
+                // 0 goes back to the next free color, and the rest pick a color by its number
+                let number_keys = [
+                    egui::Key::Num0,
+                    egui::Key::Num1,
+                    egui::Key::Num2,
+                    egui::Key::Num3,
+                    egui::Key::Num4,
+                    egui::Key::Num5,
+                    egui::Key::Num6,
+                    egui::Key::Num7,
+                    egui::Key::Num8,
+                    egui::Key::Num9,
+                ];
+                if let Some(number) = number_keys.iter().position(|&key| {
+                    ctx.input_mut(|input| input.consume_key(egui::Modifiers::NONE, key))
+                }) {
+                    self.flow_canvas.source_color = number.checked_sub(1);
+                }
+            }
+        }
         TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.heading("Flow Solver");
+                // only puzzles being played are timed, not boards being built
+                if !self.flow_canvas.can_edit_sources {
+                    ui.label(format!(
+                        "Moves: {} (perfect: {})  Time: {}",
+                        self.flow_canvas.strokes,
+                        self.flow_canvas.grid.min_strokes(),
+                        format_time(self.flow_canvas.elapsed_seconds)
+                    ));
+                    if !self.flow_canvas.grid.is_solved() {
+                        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                    }
+                }
+                if let Some(blitz) = &self.blitz {
+                    let time_left = match blitz.puzzle {
+                        Some(_) => blitz.run.time_limit() - self.flow_canvas.elapsed_seconds,
+                        None => blitz.run.time_limit(),
+                    };
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Blitz round {}: {} left  Score: {}  Combo: {}",
+                            blitz.run.round() + 1,
+                            format_time(time_left.max(0.0).ceil()),
+                            blitz.run.score,
+                            blitz.run.combo
+                        ))
+                        .strong(),
+                    );
+                    ui.button("Stop")
+                        .on_hover_text("End the run here and see how it went")
+                        .clicked()
+                        .then(|| self.end_blitz());
+                }
+                if self.is_zen
+                    && let Some(playlist) = &self.zen_playlist
+                {
+                    let total = playlist.levels(self.zen_pack_len()).len();
+                    ui.label(format!("Zen: level {} of {total}", playlist.position + 1));
+                    ui.button("Leave")
+                        .on_hover_text("Stop zen mode here, keeping its place for later")
+                        .clicked()
+                        .then(|| self.is_zen = false);
+                }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.button("Quit").clicked() {
                         let ctx = ctx.clone();
@@ -52,66 +2475,763 @@ impl App for FlowSolverApp {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                         });
                     }
+                    let mut is_streaming = self.spectator_server.is_some();
+                    if ui
+                        .toggle_value(&mut is_streaming, "📡 Stream")
+                        .on_hover_text("Serve the board on a local web page for a browser source")
+                        .changed()
+                    {
+                        self.spectator_server = None;
+                        if is_streaming {
+                            match flow_spectator::SpectatorServer::start(
+                                flow_spectator::SPECTATOR_PORT,
+                            ) {
+                                Ok(server) => self.spectator_server = Some(server),
+                                Err(error) => {
+                                    self.solver_message =
+                                        Some(format!("Couldn't start streaming: {error}"))
+                                }
+                            }
+                        }
+                    }
+                    if let Some(server) = &self.spectator_server {
+                        ui.hyperlink_to(server.url(), server.url());
+                    }
+                    ui.toggle_value(&mut self.show_diagnostics, "Diagnostics");
+                    ui.toggle_value(&mut self.show_palette_editor, "Colors")
+                        .on_hover_text("Change the colors pipes are drawn in");
+                    ui.toggle_value(&mut self.is_viewing, "🔒 View only")
+                        .on_hover_text("Hide the controls and lock the board, for presenting");
+                    if !self.is_viewing {
+                        ui.toggle_value(&mut self.show_lessons, "Lessons");
+                        ui.toggle_value(&mut self.show_level_browser, "Levels")
+                            .on_hover_text("Play the puzzle packs that come with the app");
+                        ui.toggle_value(&mut self.show_zen_setup, "Zen")
+                            .on_hover_text("Play through packs one level after another");
+                        let daily_label = if self.daily_record.is_completed(flow_daily::today()) {
+                            "✔ Daily"
+                        } else {
+                            "Daily"
+                        };
+                        ui.button(daily_label)
+                            .on_hover_text("Today's puzzle, the same for everyone")
+                            .clicked()
+                            .then(|| self.start_daily());
+                        ui.button("Blitz")
+                            .on_hover_text(
+                                "Solve generated puzzles against a clock that gets shorter \
+                                every round. Quick solves build a combo.",
+                            )
+                            .clicked()
+                            .then(|| self.start_blitz());
+                        if ui
+                            .button("Generate")
+                            .on_hover_text("Make a new puzzle with one solution, this size")
+                            .clicked()
+                        {
+                            self.generate_puzzle(
+                                self.flow_canvas.grid.width,
+                                self.flow_canvas.grid.height,
+                                flow_generator::new_seed(),
+                            );
+                        }
+                        self.generate_style_ui(ui);
+                        let code_edit = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.puzzle_code)
+                                    .hint_text("7x7 #482913")
+                                    .desired_width(100.0),
+                            )
+                            .on_hover_text("Share this code, or type one in and press Enter");
+                        if code_edit.lost_focus()
+                            && ui.input(|input| input.key_pressed(egui::Key::Enter))
+                        {
+                            match flow_generator::parse_puzzle_code(&self.puzzle_code) {
+                                Some((width, height, seed, options)) => {
+                                    self.start_generating(width, height, seed, options, None);
+                                }
+                                None => {
+                                    self.solver_message =
+                                        Some("Puzzle codes look like 7x7 #482913, with a style after it if it's not classic.".to_string())
+                                }
+                            }
+                        }
+                    }
                 });
             });
+            if !self.is_viewing {
+                self.tabs_ui(ui);
+            }
         });
-        CentralPanel::default().show(ctx, |ui| {
-            ui.label("Click on the grid to place a flow source. Click and drag to connect them.");
-            ui.horizontal(|ui| {
-                // TODO disable remove row/col if can't remove
-                ui.button("- row")
-                    .clicked()
-                    .then(|| self.flow_canvas.grid.try_remove_row());
-                ui.button("+ row")
-                    .clicked()
-                    .then(|| self.flow_canvas.grid.add_row());
-                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.button("+ column")
-                        .clicked()
-                        .then(|| self.flow_canvas.grid.add_col());
-                    ui.button("- column")
+        if self.show_lessons && !self.is_viewing {
+            SidePanel::left("lessons_panel").show(ctx, |ui| {
+                ui.heading("Strategy course");
+                for (lesson_index, lesson) in flow_lessons::COURSE.iter().enumerate() {
+                    let label = if self.completed_lessons[lesson_index] {
+                        format!("✔ {}", lesson.name)
+                    } else {
+                        lesson.name.to_string()
+                    };
+                    ui.selectable_label(self.active_lesson == Some(lesson_index), label)
                         .clicked()
-                        .then(|| self.flow_canvas.grid.try_remove_col());
-                });
+                        .then(|| self.start_lesson(lesson_index));
+                }
             });
-            ui.add(&mut self.flow_canvas);
-            ui.horizontal(|ui| {
-                ui.label(format!(
-                    "Next color: {}",
-                    COLOR_INDEX
-                        .get(self.flow_canvas.grid.next_color())
-                        .unwrap_or(&("(No Defined color)", Color32::BLACK))
-                        .0,
-                ));
-                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.button("toggle sources locked").clicked().then(|| {
-                        self.flow_canvas.can_edit_sources = !self.flow_canvas.can_edit_sources;
-                    });
+        }
+        if self.show_level_browser && !self.is_viewing {
+            self.level_browser_ui(ctx);
+        }
+        if let Some(pack) = &self.imported_pack
+            && !self.is_viewing
+        {
+            let mut is_closed = false;
+            let mut picked = None;
+            SidePanel::left("pack_panel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Level pack");
+                    is_closed = ui.button("Close").clicked();
+                });
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (level_index, level) in pack.iter().enumerate() {
+                        ui.selectable_label(self.pack_level == Some(level_index), &level.name)
+                            .clicked()
+                            .then(|| picked = Some(level_index));
+                    }
                 });
             });
-            ui.button("Clear")
-                .on_hover_text("Remove all sources and pipes you've placed")
-                .clicked()
-                .then(|| {
-                    self.flow_canvas = flow_canvas::FlowCanvas::with_size(
-                        self.flow_canvas.grid.width,
-                        self.flow_canvas.grid.height,
-                    );
+            if let Some(level_index) = picked {
+                self.open_pack_level(level_index);
+            }
+            if is_closed {
+                self.imported_pack = None;
+            }
+        }
+        self.repair_wizard_ui(ctx);
+        self.bug_report_ui(ctx);
+        self.palette_editor_ui(ctx);
+        self.win_overlay_ui(ctx);
+        self.blitz_results_ui(ctx);
+        self.zen_setup_ui(ctx);
+        self.zen_celebration_ui(ctx);
+        self.comparison_ui(ctx);
+        if self.show_diagnostics && !self.is_viewing {
+            if self.is_diagnostics_detached {
+                ctx.show_viewport_immediate(
+                    egui::ViewportId::from_hash_of("diagnostics"),
+                    ViewportBuilder::default()
+                        .with_title("Flow Solver Diagnostics")
+                        .with_inner_size([300.0, 250.0]),
+                    |ctx, class| {
+                        if class == egui::ViewportClass::Embedded {
+                            // this platform can't open more windows, so float it over the board
+                            egui::Window::new("Diagnostics")
+                                .show(ctx, |ui| self.diagnostics_ui(ui));
+                        } else {
+                            CentralPanel::default().show(ctx, |ui| self.diagnostics_ui(ui));
+                        }
+                        if ctx.input(|input| input.viewport().close_requested()) {
+                            self.is_diagnostics_detached = false;
+                        }
+                    },
+                );
+            } else {
+                SidePanel::right("diagnostics_panel").show(ctx, |ui| self.diagnostics_ui(ui));
+            }
+        }
+        CentralPanel::default().show(ctx, |ui| {
+            // the animation overwrites the board every frame, so edits would only be lost
+            self.flow_canvas.is_interactive = !self.is_viewing && self.solve_animation.is_none();
+            self.flow_canvas.show_dead_ends = self.show_dead_ends && !self.is_viewing;
+            self.flow_canvas.auto_complete = self.auto_complete;
+            self.flow_canvas.hide_sources = self.hide_sources;
+            self.flow_canvas.show_color_labels = self.show_color_labels;
+            self.flow_canvas.style.set_palette(&self.palette);
+            // the controls around the board can outgrow a small window, so they scroll. The board
+            // zooms and pans itself, so the wheel only scrolls when it's not over the board, and
+            // dragging is left to draw pipes.
+            egui::ScrollArea::both()
+                .drag_to_scroll(false)
+                .show(ui, |ui| {
+                    if self.is_viewing {
+                        ui.add(&mut self.flow_canvas);
+                        return;
+                    }
+                    if let Some(lesson_index) = self.active_lesson {
+                        let lesson = &flow_lessons::COURSE[lesson_index];
+                        ui.horizontal(|ui| {
+                            ui.heading(lesson.name);
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| {
+                                    ui.button("Leave lesson")
+                                        .clicked()
+                                        .then(|| self.leave_lesson());
+                                },
+                            );
+                        });
+                        ui.label(lesson.description);
+                    } else if let Some(day) = self.daily_day {
+                        ui.heading(format!("Daily puzzle, {}", flow_daily::Date::of(day)));
+                        self.daily_calendar_ui(ui);
+                    } else {
+                        ui.label(
+                    "Click on the grid to place a flow source, or double-click one to remove its \
+                     color. Click and drag to connect them, and right-click a pipe to remove it. \
+                     On a touch screen, long-press to place or remove a source.",
+                );
+                    }
+                    ui.horizontal(|ui| {
+                        // TODO disable remove row/col if can't remove
+                        ui.button("- row")
+                            .clicked()
+                            .then(|| self.flow_canvas.grid.try_remove_row());
+                        ui.button("+ row")
+                            .clicked()
+                            .then(|| self.flow_canvas.grid.add_row());
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.button("+ column")
+                                .clicked()
+                                .then(|| self.flow_canvas.grid.add_col());
+                            ui.button("- column")
+                                .clicked()
+                                .then(|| self.flow_canvas.grid.try_remove_col());
+                        });
+                    });
+                    ui.add(&mut self.flow_canvas);
+                    let can_solve = !self.flow_canvas.grid.has_bridges();
+                    ui.horizontal(|ui| {
+                        let grid = &self.flow_canvas.grid;
+                        let num_cells = (grid.width * grid.height).max(1);
+                        ui.label(format!(
+                            "Pipes: {}% of cells",
+                            grid.num_filled_cells() * 100 / num_cells
+                        ));
+                        let (connected, flows) = grid.flows_connected();
+                        ui.label(format!("Flows: {connected}/{flows} connected"));
+                    });
+                    if let Some(lesson_index) = self.active_lesson
+                        && flow_lessons::COURSE[lesson_index]
+                            .is_technique_performed(&self.flow_canvas.grid)
+                    {
+                        self.completed_lessons[lesson_index] = true;
+                        ui.horizontal(|ui| {
+                            ui.label("Technique performed!");
+                            if lesson_index + 1 < flow_lessons::COURSE.len() {
+                                ui.button("Next lesson")
+                                    .clicked()
+                                    .then(|| self.start_lesson(lesson_index + 1));
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("Goal")
+                            .selected_text(self.flow_canvas.grid.win_condition().name())
+                            .show_ui(ui, |ui| self.win_condition_ui(ui));
+                        if self.flow_canvas.grid.is_solved() {
+                            ui.label("Solved!");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if self.flow_canvas.can_edit_sources {
+                            self.source_color_ui(ui);
+                        } else {
+                            ui.label(format!(
+                                "Next color: {}",
+                                self.palette.name(self.flow_canvas.grid.next_color())
+                            ));
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.checkbox(&mut self.show_dead_ends, "Show dead ends")
+                                .on_hover_text("Tint cells that can no longer be filled");
+                            ui.checkbox(&mut self.hide_sources, "Hard mode")
+                                .on_hover_text("Sources stay gray until a pipe reaches them");
+                            ui.checkbox(&mut self.show_color_labels, "Color numbers")
+                                .on_hover_text(
+                                    "Number each color's sources and pipes, for colors that \
+                                    are hard to tell apart",
+                                );
+                            ui.checkbox(&mut self.show_ghost, "Ghost").on_hover_text(
+                                "Faintly show a solution under your pipes, to trace over",
+                            );
+                            if self.show_ghost && self.ghost_solve.is_some() {
+                                ui.spinner();
+                            } else if self.show_ghost && self.ghost.is_none() {
+                                ui.label("No solution");
+                            }
+                            ui.checkbox(&mut self.sounds.is_muted, "Mute")
+                                .on_hover_text("No sounds for laying pipe and finishing flows");
+                            ui.checkbox(&mut self.auto_complete, "Assist")
+                        .on_hover_text(
+                            "After each stroke, fill in connections that are the only way to go",
+                        );
+                            ui.add(
+                                egui::DragValue::new(&mut self.export_cell_size)
+                                    .range(10.0..=300.0)
+                                    .suffix(" px/cell"),
+                            )
+                            .on_hover_text("How big each cell is in an exported image");
+                            ui.button("Export image")
+                                .on_hover_text("Save a picture of the board as a PNG")
+                                .clicked()
+                                .then(|| self.export_image());
+                            ui.button("Import pack")
+                                .on_hover_text("Open a Flow Free level pack and pick a level")
+                                .clicked()
+                                .then(|| self.import_pack());
+                            ui.button("Import screenshot")
+                                .on_hover_text(
+                                    "Read the sources out of a PNG screenshot of a board this size",
+                                )
+                                .clicked()
+                                .then(|| self.import_screenshot());
+                            ui.button("Load")
+                                .on_hover_text("Open a board saved with Save")
+                                .clicked()
+                                .then(|| self.load_board());
+                            ui.button("Save")
+                                .on_hover_text("Save the board, pipes and all, to a file")
+                                .clicked()
+                                .then(|| self.save_board());
+                            ui.button("Load shape")
+                                .on_hover_text(
+                                    "Open a text file drawing the board's shape, with '#' or a \
+                                    space off the board and anything else a cell",
+                                )
+                                .clicked()
+                                .then(|| self.load_shape());
+                            ui.button("Fill shape")
+                                .on_hover_text(
+                                    "Make a new puzzle with one solution covering the cells \
+                                    that aren't blocked off",
+                                )
+                                .clicked()
+                                .then(|| self.generate_in_shape());
+                            ui.button("Report a bug")
+                                .on_hover_text("Save the board and settings to attach to an issue")
+                                .clicked()
+                                .then(|| self.show_bug_report = true);
+                            if self.flow_canvas.grid.is_solved() {
+                                ui.button("Copy moves")
+                                    .on_hover_text("Copy each color's pipe as a list of moves")
+                                    .clicked()
+                                    .then(|| {
+                                        ui.ctx().copy_text(move_list(
+                                            &self.flow_canvas.grid,
+                                            &self.palette,
+                                        ))
+                                    });
+                            }
+                            ui.button("Copy as DOT")
+                                .on_hover_text("Copy the grid as a Graphviz graph")
+                                .clicked()
+                                .then(|| ui.ctx().copy_text(self.flow_canvas.grid.to_dot()));
+                            ui.button("toggle sources locked").clicked().then(|| {
+                                self.flow_canvas.can_edit_sources =
+                                    !self.flow_canvas.can_edit_sources;
+                            });
+                            if self.flow_canvas.can_edit_sources {
+                                let tool = &mut self.flow_canvas.edit_tool;
+                                ui.selectable_value(
+                                    tool,
+                                    flow_canvas::EditTool::Sources,
+                                    "Sources",
+                                )
+                                .on_hover_text("Clicking a cell adds or removes a source");
+                                ui.selectable_value(
+                                    tool,
+                                    flow_canvas::EditTool::Bridges,
+                                    "Bridges",
+                                )
+                                .on_hover_text(
+                                    "Clicking a cell adds or removes a bridge, where two \
+                                        pipes can cross",
+                                );
+                                ui.selectable_value(tool, flow_canvas::EditTool::Holes, "Holes")
+                                    .on_hover_text(
+                                        "Clicking a cell blocks it off, so the board doesn't \
+                                        need it filled. Holes along the edge shape the board",
+                                    );
+                                ui.selectable_value(tool, flow_canvas::EditTool::Warps, "Warps")
+                                    .on_hover_text(
+                                        "Clicking two cells links them as warps, where a pipe \
+                                        going into one comes out of the other. Clicking a warp \
+                                        removes the pair",
+                                    );
+                                ui.selectable_value(
+                                    tool,
+                                    flow_canvas::EditTool::ExtraSources,
+                                    "Extra sources",
+                                )
+                                .on_hover_text(
+                                    "Click a source, then click cells to give its color more \
+                                    sources. All of them have to join up, branching only at \
+                                    sources",
+                                );
+                            }
+                        });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.button("Clear")
+                            .on_hover_text("Remove all sources and pipes you've placed")
+                            .clicked()
+                            .then(|| match self.active_lesson {
+                                Some(lesson_index) => self.start_lesson(lesson_index),
+                                None => self.new_board(
+                                    self.flow_canvas.grid.width,
+                                    self.flow_canvas.grid.height,
+                                    self.flow_canvas.grid.is_hex(),
+                                    self.flow_canvas.grid.wraps(),
+                                ),
+                            });
+                        let can_undo =
+                            self.undo_history.can_undo() && self.solve_animation.is_none();
+                        ui.add_enabled(can_undo, egui::Button::new("Undo"))
+                            .on_hover_text("Take back the last change (Ctrl+Z)")
+                            .clicked()
+                            .then(|| self.undo());
+                        let can_redo =
+                            self.undo_history.can_redo() && self.solve_animation.is_none();
+                        ui.add_enabled(can_redo, egui::Button::new("Redo"))
+                            .on_hover_text("Put back the last change you took back (Ctrl+Y)")
+                            .clicked()
+                            .then(|| self.redo());
+                        ui.separator();
+                        let tool = &mut self.flow_canvas.drag_tool;
+                        ui.selectable_value(tool, flow_canvas::DragTool::Draw, "Draw")
+                            .on_hover_text("Dragging lays pipe");
+                        ui.selectable_value(tool, flow_canvas::DragTool::Erase, "Eraser")
+                            .on_hover_text(
+                                "Dragging takes the pipes out of every cell it crosses, leaving \
+                                the sources",
+                            );
+                        ui.separator();
+                        if self.active_lesson.is_none() {
+                            let mut is_hex = self.flow_canvas.grid.is_hex();
+                            let mut wraps = self.flow_canvas.grid.wraps();
+                            let is_hex_changed = ui
+                                .checkbox(&mut is_hex, "Hexes")
+                                .on_hover_text("Start over on a board of hexagons")
+                                .changed();
+                            let wraps_changed = ui
+                                .checkbox(&mut wraps, "Wrap edges")
+                                .on_hover_text(
+                                    "Start over on a board where pipes can leave one edge and \
+                                    come back on the opposite one",
+                                )
+                                .changed();
+                            if is_hex_changed || wraps_changed {
+                                self.new_board(
+                                    self.flow_canvas.grid.width,
+                                    self.flow_canvas.grid.height,
+                                    is_hex,
+                                    wraps,
+                                );
+                            }
+                        }
+                        ui.add(
+                            egui::DragValue::new(&mut self.new_width)
+                                .range(MIN_GRID_SIZE..=MAX_GRID_SIZE)
+                                .suffix(" wide"),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut self.new_height)
+                                .range(MIN_GRID_SIZE..=MAX_GRID_SIZE)
+                                .suffix(" tall"),
+                        );
+                        if ui
+                            .button("New grid")
+                            .on_hover_text("Start over on an empty board of this size")
+                            .clicked()
+                        {
+                            self.new_board(
+                                self.new_width,
+                                self.new_height,
+                                self.flow_canvas.grid.is_hex(),
+                                self.flow_canvas.grid.wraps(),
+                            );
+                            ui.ctx().send_viewport_cmd(egui::ViewportCommand::InnerSize(
+                                window_size(self.new_width, self.new_height),
+                            ));
+                        }
+                        if let Some(background_solve) = &self.background_solve {
+                            ui.spinner();
+                            let progress = background_solve.progress();
+                            ui.label(format!(
+                                "Explored {} moves, {} deep",
+                                progress.nodes_explored, progress.depth
+                            ));
+                            if ui.button("Cancel").clicked() {
+                                self.background_solve = None;
+                            }
+                        } else {
+                            ui.add_enabled(can_solve, egui::Button::new("Solve"))
+                                .on_hover_text("Replace your pipes with a full solution")
+                                .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                                .clicked()
+                                .then(|| {
+                                    self.background_solve = Some(flow_solver::solve_in_background(
+                                        &self.flow_canvas.grid,
+                                        self.solve_options.clone(),
+                                    ));
+                                    self.solver_message = None;
+                                });
+                            let mut timeout_seconds = self.solve_options.timeout.as_secs();
+                            if ui
+                                .add(
+                                    egui::DragValue::new(&mut timeout_seconds)
+                                        .range(1..=3600)
+                                        .suffix(" s limit"),
+                                )
+                                .changed()
+                            {
+                                self.solve_options.timeout =
+                                    std::time::Duration::from_secs(timeout_seconds);
+                            }
+                            ui.checkbox(&mut self.solve_options.keep_pipes, "Keep my pipes")
+                                .on_hover_text(
+                                    "Finish the pipes you've drawn instead of starting over",
+                                );
+                        }
+                        ui.add_enabled(can_solve, egui::Button::new("Hint"))
+                            .on_hover_text("Add one pipe segment from the solution")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| {
+                                let options = self.solve_options.clone();
+                                self.start_job("Finding a hint", move |grid| {
+                                    JobResult::Hint(flow_solver::hint(&grid, &options))
+                                });
+                            });
+                        ui.add_enabled(can_solve, egui::Button::new("Rate"))
+                            .on_hover_text("Estimate how hard these sources are to solve by hand")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| {
+                                let options = self.solve_options.clone();
+                                self.start_job("Rating", move |grid| {
+                                    JobResult::Rating(flow_solver::difficulty(&grid, &options))
+                                });
+                            });
+                        ui.add_enabled(can_solve, egui::Button::new("Count"))
+                            .on_hover_text("Count every solution for these sources exactly")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| {
+                                self.start_job("Counting", |grid| {
+                                    JobResult::Count(flow_count::count_solutions_exact(&grid))
+                                });
+                            });
+                        if let Some(job) = &self.job {
+                            ui.spinner();
+                            ui.label(job.activity);
+                            if ui.button("Cancel").clicked() {
+                                self.job = None;
+                            }
+                        }
+                        if let Some(message) = &self.solver_message {
+                            ui.label(message.as_str());
+                        }
+                    });
+                    ui.collapsing("Solver settings", |ui| {
+                        let config = &mut self.solve_options.config;
+                        ui.checkbox(
+                            &mut config.most_constrained_first,
+                            "Most constrained color first",
+                        )
+                        .on_hover_text("Extend the color with the fewest ways to go");
+                        ui.checkbox(&mut config.longest_pairs_first, "Farthest pairs first")
+                            .on_hover_text(
+                                "Break ties by extending the color farthest from its partner",
+                            );
+                        ui.checkbox(&mut config.corners_first, "Corners first")
+                            .on_hover_text(
+                                "Try moves into tight spots like corners and edges first",
+                            );
+                        self.solve_colors_ui(ui);
+                        ui.checkbox(&mut self.solve_options.parallel, "Use every core")
+                            .on_hover_text(
+                                "Split the search across threads. Finds a solution sooner on \
+                                big boards, but not always the same one.",
+                            );
+                    });
+                    ui.collapsing("Generator settings", |ui| self.generator_settings_ui(ui));
+                    if let Some(stats) = &self.solve_stats {
+                        ui.collapsing("Solver statistics", |ui| {
+                            ui.label(format!("Moves explored: {}", stats.nodes_explored));
+                            ui.label(format!("Deepest search: {} moves", stats.max_depth));
+                            ui.label(format!("Backtracks: {}", stats.backtracks));
+                            ui.label(format!("Propagation steps: {}", stats.propagation_steps));
+                            ui.label(format!("Wall time: {:.3} s", stats.wall_time.as_secs_f64()));
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(can_solve, egui::Button::new("Browse solutions"))
+                            .on_hover_text("Page through every solution for these sources")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| {
+                                self.solution_browser =
+                                    Some(SolutionBrowser::new(&self.flow_canvas.grid));
+                                self.start_next_solution();
+                            });
+                        ui.add_enabled(can_solve, egui::Button::new("Make unique"))
+                            .on_hover_text("Find source moves that leave only one solution")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| self.start_repair());
+                        ui.add_enabled(can_solve, egui::Button::new("Diverse"))
+                            .on_hover_text("Page through a few solutions that differ the most")
+                            .on_disabled_hover_text(NO_BRIDGES_TEXT)
+                            .clicked()
+                            .then(|| {
+                                self.start_job("Picking solutions", |grid| {
+                                    JobResult::Diverse(flow_solver::diverse_solutions(
+                                        &grid,
+                                        DIVERSE_SOLUTIONS,
+                                        DIVERSE_POOL_SIZE,
+                                    ))
+                                });
+                            });
+                        if let Some(browser) = &mut self.solution_browser {
+                            if ui.button("◀").clicked()
+                                && let Some(solution) = browser.step_back()
+                            {
+                                self.flow_canvas.grid = solution.clone();
+                                self.flow_canvas.is_assisted = true;
+                            }
+                            ui.label(browser.describe());
+                            let mut should_search = false;
+                            if ui.button("▶").clicked() {
+                                if browser.needs_search() {
+                                    should_search = true;
+                                } else if let Some(solution) = browser.step_forward() {
+                                    self.flow_canvas.grid = solution.clone();
+                                    self.flow_canvas.is_assisted = true;
+                                }
+                            }
+                            if ui.button("Done").clicked() {
+                                self.solution_browser = None;
+                            } else if should_search {
+                                self.start_next_solution();
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add_enabled(
+                            can_solve && self.solve_animation.is_none(),
+                            egui::Button::new("Watch solve"),
+                        )
+                        .on_hover_text("Animate the solver's search one step at a time")
+                        .on_disabled_hover_text(if can_solve {
+                            "A solve is already playing"
+                        } else {
+                            NO_BRIDGES_TEXT
+                        })
+                        .clicked()
+                        .then(|| {
+                            self.solve_animation =
+                                Some(SolveAnimation::new(&self.flow_canvas.grid));
+                            self.flow_canvas.is_assisted = true;
+                        });
+                        if let Some(animation) = &mut self.solve_animation {
+                            ui.button(if animation.is_paused { "▶" } else { "⏸" })
+                                .clicked()
+                                .then(|| animation.is_paused = !animation.is_paused);
+                            ui.button("⏮")
+                                .on_hover_text("Step back")
+                                .clicked()
+                                .then(|| animation.step_back());
+                            ui.button("⏭")
+                                .on_hover_text("Step forward")
+                                .clicked()
+                                .then(|| animation.step());
+                            ui.add(
+                                egui::Slider::new(&mut animation.steps_per_second, 1.0..=1000.0)
+                                    .logarithmic(true)
+                                    .text("steps/s"),
+                            );
+                            ui.label(animation.describe());
+                            if ui.button("Stop").clicked() {
+                                self.flow_canvas.grid = animation.board_before.clone();
+                                self.solve_animation = None;
+                                self.flow_canvas.highlighted_cell = None;
+                            }
+                        }
+                    });
                 });
         });
+        if let Some(server) = &self.spectator_server {
+            server.update(flow_spectator::board_to_svg(
+                &self.flow_canvas.grid,
+                &self.flow_canvas.style,
+            ));
+        }
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(DAILY_RECORD_KEY, self.daily_record.to_text());
+        storage.set_string(BLITZ_BEST_SCORE_KEY, self.blitz_best_score.to_string());
+        if let Some(playlist) = &self.zen_playlist {
+            storage.set_string(ZEN_PLAYLIST_KEY, playlist.to_text());
+        }
+        storage.set_string(LEVEL_PROGRESS_KEY, self.level_progress.to_text());
+        storage.set_string(PALETTE_KEY, self.palette.to_text());
+        storage.set_string(COLOR_TABLE_KEY, self.color_table.to_text());
+        let session = SavedSession {
+            grid: self.flow_canvas.grid.clone(),
+            can_edit_sources: self.flow_canvas.can_edit_sources,
+            show_color_labels: self.show_color_labels,
+            is_muted: self.sounds.is_muted,
+        };
+        if let Ok(json) = serde_json::to_string(&session) {
+            storage.set_string(SESSION_KEY, json);
+        }
     }
 }
+/// the smallest and largest boards New grid makes
+const MIN_GRID_SIZE: usize = 2;
+const MAX_GRID_SIZE: usize = 30;
+
+/// how big the window needs to be to show a board of this size with the controls around it
+fn window_size(width: usize, height: usize) -> egui::Vec2 {
+    // TODO there's got to be a better way to resize based on rendered contents
+    let cell_size = flow_canvas::BoardMetrics::default().cell_size;
+    egui::vec2(
+        width as f32 * cell_size + 35.0,
+        height as f32 * cell_size + 215.0,
+    )
+}
+
 fn main() -> eframe::Result {
+    let mut args: Vec<String> = std::env::args().collect();
+    let Some(format) = flow_cli::take_format(&mut args) else {
+        eprintln!("Usage: --format text|json");
+        std::process::exit(flow_cli::EXIT_USAGE);
+    };
+    if args.iter().any(|arg| arg == "--bench") {
+        flow_bench::run(format);
+        return Ok(());
+    }
+    if args.iter().any(|arg| arg == "--gym") {
+        flow_gym::run_random_agent(format);
+        return Ok(());
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--pack") {
+        std::process::exit(flow_pack::run(&args[position + 1..], format));
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--solve") {
+        std::process::exit(flow_cli::run_solve(&args[position + 1..], format));
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--dot") {
+        std::process::exit(flow_cli::run_dot(&args[position + 1..]));
+    }
+
     const GRID_HEIGHT: usize = 7;
     const GRID_WIDTH: usize = 7;
 
-    // TODO there's got to be a better way to resize based on rendered contents
-    let ui_width = GRID_WIDTH as f32 * CELL_SIZE + 35.0;
-    let ui_height = GRID_HEIGHT as f32 * CELL_SIZE + 140.0;
-
     let native_options = NativeOptions {
         viewport: ViewportBuilder::default()
-            .with_inner_size([ui_width, ui_height])
+            .with_inner_size(window_size(GRID_WIDTH, GRID_HEIGHT))
             .with_icon(
                 icon_data::from_png_bytes(&include_bytes!("../assets/pipe-512.png")[..])
                     .expect("Failed to load icon"),
@@ -122,6 +3242,10 @@ fn main() -> eframe::Result {
     run_native(
         "Flow Solver",
         native_options,
-        Box::new(|_cc| Ok(Box::new(FlowSolverApp::with_size(GRID_WIDTH, GRID_HEIGHT)))),
+        Box::new(|cc| {
+            Ok(Box::new(
+                FlowSolverApp::with_size(GRID_WIDTH, GRID_HEIGHT).with_storage(cc.storage),
+            ))
+        }),
     )
 }